@@ -0,0 +1,49 @@
+-- Postgres
+-- The records crawl workers share when they are given a --db-url. Every other record stays in the SQLite database of
+-- each worker. The tables are created when a crawl connects, and kept between crawls.
+
+CREATE TABLE IF NOT EXISTS Domain (
+    Id BIGSERIAL PRIMARY KEY,
+    Name TEXT UNIQUE NOT NULL,
+    CrawlDelay DOUBLE PRECISION,
+    RobotsFetched TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS DisallowedPattern (
+    DomainId BIGINT NOT NULL REFERENCES Domain (Id) ON DELETE CASCADE,
+    Pattern TEXT NOT NULL,
+    PRIMARY KEY (DomainId, Pattern)
+);
+
+CREATE TABLE IF NOT EXISTS Page (
+    Id BIGSERIAL PRIMARY KEY,
+    Url TEXT UNIQUE NOT NULL,
+    Hash TEXT NOT NULL,
+    NoFollow BOOLEAN NOT NULL DEFAULT FALSE,
+    Updated TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS PageLink (
+    PageId BIGINT NOT NULL REFERENCES Page (Id) ON DELETE CASCADE,
+    Url TEXT NOT NULL,
+    AnchorText TEXT,
+    Nofollow BOOLEAN NOT NULL DEFAULT FALSE,
+    Rel TEXT,
+    Section TEXT NOT NULL DEFAULT 'body',
+    PRIMARY KEY (PageId, Url)
+);
+-- Tables created by earlier releases lack the rel and section of links
+ALTER TABLE PageLink ADD COLUMN IF NOT EXISTS Rel TEXT;
+ALTER TABLE PageLink ADD COLUMN IF NOT EXISTS Section TEXT NOT NULL DEFAULT 'body';
+
+CREATE TABLE IF NOT EXISTS FrontierUrl (
+    Id BIGSERIAL PRIMARY KEY,
+    Url TEXT UNIQUE NOT NULL,
+    Created TIMESTAMPTZ NOT NULL DEFAULT now(),
+    Status TEXT NOT NULL DEFAULT 'queued',
+    WorkerId TEXT,
+    LeaseExpires TIMESTAMPTZ,
+    Score DOUBLE PRECISION NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS FrontierUrlStatus ON FrontierUrl (Status, Id);
+CREATE INDEX IF NOT EXISTS FrontierUrlScore ON FrontierUrl (Status, Score DESC, Id);
@@ -0,0 +1,291 @@
+-- Sqlite, schema version 1: the initial schema
+
+CREATE TABLE CrawlRun (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ParentRunId INTEGER,
+    PeakMemory INTEGER NOT NULL DEFAULT 0,
+    Started DATETIME DEFAULT CURRENT_TIMESTAMP,
+    Finished DATETIME,
+    Outcome TEXT,
+    Seeds TEXT NOT NULL DEFAULT '[]',
+    Config TEXT NOT NULL DEFAULT '{}',
+    Pages INTEGER NOT NULL DEFAULT 0,
+    Fetches INTEGER NOT NULL DEFAULT 0,
+    Failures INTEGER NOT NULL DEFAULT 0,
+    FrontierLeft INTEGER,
+    FOREIGN KEY (ParentRunId) REFERENCES CrawlRun(Id)
+);
+
+CREATE TABLE Page (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER,
+    Url TEXT UNIQUE NOT NULL,
+    RequestedUrl TEXT NOT NULL,
+    Hash BLOB NOT NULL,
+    HashAlgorithm TEXT NOT NULL DEFAULT 'blake3',
+    DuplicateOf INTEGER,
+    ETag TEXT,
+    LastModified TEXT,
+    WarcFile TEXT,
+    WarcOffset INTEGER,
+    StoredFile TEXT,
+    WordCount INTEGER,
+    TextRatio REAL,
+    ReadingTime INTEGER,
+    NoIndex INTEGER NOT NULL DEFAULT 0,
+    NoFollow INTEGER NOT NULL DEFAULT 0,
+    SuppressedLinks INTEGER NOT NULL DEFAULT 0,
+    Indexed INTEGER NOT NULL DEFAULT 1,
+    ServerAddress TEXT,
+    ServerCountry TEXT,
+    ContentLanguage TEXT,
+    Charset TEXT,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    Updated DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (DuplicateOf) REFERENCES Page(Id)
+);
+
+CREATE TABLE PageFetch (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER NOT NULL,
+    PageId INTEGER,
+    Url TEXT NOT NULL,
+    FinalUrl TEXT NOT NULL,
+    Status INTEGER NOT NULL,
+    ContentType TEXT,
+    ContentLength INTEGER,
+    DurationMs INTEGER NOT NULL,
+    Headers TEXT NOT NULL,
+    Error TEXT,
+    Fetched DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE SET NULL
+);
+
+CREATE TABLE Domain (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Name TEXT UNIQUE NOT NULL,
+    RefusedCount INTEGER NOT NULL DEFAULT 0,
+    BannedUntil DATETIME,
+    CrawlDelay REAL,
+    RobotsFetched DATETIME,
+    Pinned INTEGER NOT NULL DEFAULT 0,
+    Unreachable TEXT,
+    Suspended DATETIME,
+    SuspendedRunId INTEGER,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+Drop TABLE IF EXISTS DisallowedPattern;
+CREATE TABLE DisallowedPattern (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    DomainId INTEGER NOT NULL,
+    Pattern TEXT NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (DomainId, Pattern),
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
+
+CREATE TABLE Redirect (
+    SourceUrl TEXT PRIMARY KEY,
+    TargetUrl TEXT NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE RedirectHop (
+    SourceUrl TEXT NOT NULL,
+    Position INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    Status INTEGER NOT NULL,
+    PRIMARY KEY (SourceUrl, Position)
+);
+
+CREATE TABLE FailedUrl (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER NOT NULL,
+    DomainId INTEGER,
+    Url TEXT NOT NULL UNIQUE,
+    Class TEXT NOT NULL DEFAULT 'other',
+    Error TEXT NOT NULL,
+    Attempts INTEGER NOT NULL DEFAULT 1,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    LastAttempt DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
+
+CREATE TABLE RobotsBlockedUrl (
+    DomainId INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    RunId INTEGER NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (DomainId, Url),
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id)
+);
+
+CREATE TABLE FrontierWait (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER NOT NULL,
+    DomainId INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    Depth INTEGER NOT NULL,
+    WaitMs INTEGER NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
+
+CREATE TABLE FrontierUrl (
+    Url TEXT PRIMARY KEY,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    Status TEXT NOT NULL DEFAULT 'queued',
+    WorkerId TEXT,
+    LeaseExpires DATETIME,
+    Score REAL NOT NULL DEFAULT 0
+);
+CREATE INDEX FrontierUrlStatus ON FrontierUrl (Status, Score DESC);
+
+CREATE TABLE PageLink (
+    PageId INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    AnchorText TEXT,
+    Nofollow INTEGER NOT NULL DEFAULT 0,
+    Element TEXT NOT NULL DEFAULT 'a',
+    Rel TEXT,
+    Section TEXT NOT NULL DEFAULT 'body',
+    External INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (PageId, Url),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+
+CREATE TABLE DomainLink (
+    SourceDomainId INTEGER NOT NULL,
+    TargetDomain TEXT NOT NULL,
+    Links INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (SourceDomainId, TargetDomain),
+    FOREIGN KEY (SourceDomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
+
+CREATE VIRTUAL TABLE LinkAnchor USING fts5 (
+    PageId UNINDEXED,
+    Url UNINDEXED,
+    Text
+);
+
+CREATE TABLE PageMeta (
+    PageId INTEGER PRIMARY KEY,
+    Title TEXT,
+    Description TEXT,
+    Canonical TEXT,
+    OpenGraph TEXT NOT NULL,
+    Language TEXT,
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+CREATE INDEX PageMetaCanonical ON PageMeta (Canonical);
+CREATE INDEX PageMetaLanguage ON PageMeta (Language);
+CREATE INDEX PageHash ON Page (Hash);
+
+CREATE TABLE PageText (
+    PageId INTEGER PRIMARY KEY,
+    Text TEXT NOT NULL,
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+
+CREATE VIRTUAL TABLE PageSearch USING fts5 (
+    PageId UNINDEXED,
+    Url UNINDEXED,
+    Text
+);
+
+CREATE TABLE PageTerm (
+    PageId INTEGER NOT NULL,
+    Term TEXT NOT NULL,
+    Count INTEGER NOT NULL,
+    PRIMARY KEY (PageId, Term),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+CREATE INDEX PageTermTerm ON PageTerm (Term, Count);
+
+CREATE TABLE RunPage (
+    RunId INTEGER NOT NULL,
+    PageId INTEGER NOT NULL,
+    CarriedOver INTEGER NOT NULL,
+    PRIMARY KEY (RunId, PageId),
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+CREATE INDEX RunPagePage ON RunPage (PageId);
+
+CREATE TABLE PageRevision (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    PageId INTEGER NOT NULL,
+    RunId INTEGER,
+    Hash BLOB NOT NULL,
+    HashAlgorithm TEXT NOT NULL,
+    WarcFile TEXT,
+    WarcOffset INTEGER,
+    StoredFile TEXT,
+    Fetched DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id)
+);
+CREATE INDEX PageRevisionPage ON PageRevision (PageId);
+
+CREATE TABLE Asset (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER,
+    Url TEXT UNIQUE NOT NULL,
+    PageId INTEGER,
+    Hash BLOB NOT NULL,
+    HashAlgorithm TEXT NOT NULL,
+    MimeType TEXT NOT NULL,
+    Size INTEGER NOT NULL,
+    StoredFile TEXT NOT NULL,
+    Fetched DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE SET NULL
+);
+CREATE INDEX AssetHash ON Asset (Hash);
+
+CREATE TABLE Image (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER,
+    Url TEXT NOT NULL,
+    Hash BLOB UNIQUE NOT NULL,
+    HashAlgorithm TEXT NOT NULL,
+    MimeType TEXT NOT NULL,
+    Size INTEGER NOT NULL,
+    Width INTEGER,
+    Height INTEGER,
+    StoredFile TEXT NOT NULL,
+    Fetched DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id)
+);
+CREATE INDEX ImageUrl ON Image (Url);
+
+CREATE TABLE PageImage (
+    PageId INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    AltText TEXT,
+    ImageId INTEGER,
+    PRIMARY KEY (PageId, Url),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE,
+    FOREIGN KEY (ImageId) REFERENCES Image(Id) ON DELETE SET NULL
+);
+CREATE INDEX PageImageUrl ON PageImage (Url);
+
+CREATE TABLE PageBody (
+    Hash BLOB PRIMARY KEY,
+    Compression TEXT NOT NULL DEFAULT 'none',
+    Body BLOB NOT NULL
+);
+
+CREATE TABLE PageTag (
+    PageId INTEGER NOT NULL,
+    Tag TEXT NOT NULL,
+    PRIMARY KEY (PageId, Tag),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+CREATE INDEX PageTagTag ON PageTag (Tag);
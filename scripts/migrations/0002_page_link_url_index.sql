@@ -0,0 +1,3 @@
+-- Sqlite, schema version 2: look up the pages linking to a URL, e.g. for orphan pages and URL status reports
+
+CREATE INDEX PageLinkUrl ON PageLink (Url);
@@ -0,0 +1,4 @@
+-- Sqlite, schema version 3: mark the pages recorded by dry runs, which have no stored body and are fetched again by
+-- a later crawl
+
+ALTER TABLE Page ADD COLUMN DryRun INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Sqlite, schema version 4: record the images that are not saved, so that they are not downloaded again
+
+CREATE TABLE RejectedImage (
+    Url TEXT PRIMARY KEY,
+    RunId INTEGER,
+    Reason TEXT NOT NULL,
+    MimeType TEXT,
+    Size INTEGER,
+    Rejected DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id)
+);
@@ -0,0 +1,9 @@
+-- Sqlite, schema version 5: record the priority of the URLs listed in the sitemaps of each domain, for scoring them
+-- when they are queued
+
+CREATE TABLE SitemapUrl (
+    Url TEXT PRIMARY KEY,
+    DomainId INTEGER NOT NULL,
+    Priority REAL NOT NULL,
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Sqlite, schema version 6: drop the counts of links between domains, which are counted from PageLink when they are
+-- read, so they cannot drift from the links that are stored
+
+DROP TABLE DomainLink;
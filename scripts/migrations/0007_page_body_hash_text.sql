@@ -0,0 +1,11 @@
+-- Sqlite, schema version 7: declare the hashes of page bodies as TEXT, the hex digests they hold
+
+CREATE TABLE PageBodyText (
+    Hash TEXT PRIMARY KEY,
+    Compression TEXT NOT NULL DEFAULT 'none',
+    Body BLOB NOT NULL
+);
+INSERT INTO PageBodyText (Hash, Compression, Body)
+SELECT CAST(Hash AS TEXT), Compression, Body FROM PageBody;
+DROP TABLE PageBody;
+ALTER TABLE PageBodyText RENAME TO PageBody;
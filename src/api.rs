@@ -0,0 +1,286 @@
+//! A read-only HTTP API over the crawl database, for dashboards and other services.
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::digest;
+use crate::error::CrawlerError;
+use crate::export::{self, ExportFilter, ExportFormat, ExportTable, Since};
+use crate::preview::PagePreview;
+use crate::stats::CrawlStats;
+
+/// The number of records listed when a request does not give a limit.
+const DEFAULT_LIMIT: usize = 100;
+/// The largest number of records a request may list.
+const MAX_LIMIT: usize = 1000;
+/// The number of lines of text included with a single page.
+const PAGE_TEXT_LINES: usize = 50;
+
+/// The database the API reads from. Every request opens its own read-only connection, so crawls can keep writing.
+struct ApiState {
+    database: PathBuf,
+}
+
+/// An error response, with the message as JSON.
+struct ApiError(StatusCode, String);
+
+impl From<CrawlerError> for ApiError {
+    fn from(error: CrawlerError) -> Self {
+        let status = match error {
+            CrawlerError::Config(_) | CrawlerError::Parse(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// The filters and page of `/pages`.
+#[derive(Deserialize)]
+struct PagesQuery {
+    domain: Option<String>,
+    status: Option<u16>,
+    tag: Option<String>,
+    hash: Option<String>,
+    since: Option<String>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// The page of `/queue`.
+#[derive(Deserialize)]
+struct QueueQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// A link found on a page.
+#[derive(Serialize)]
+struct PageLinkRecord {
+    url: String,
+    anchor_text: Option<String>,
+    nofollow: bool,
+    /// The element the link was found in, e.g. `a` or `img`.
+    element: String,
+    /// The values of the `rel` attribute, e.g. `nofollow noopener`.
+    rel: Option<String>,
+    /// The part of the page the link was found in, e.g. `nav` or `main`.
+    section: String,
+    /// Whether the link points at another host than the page.
+    external: bool,
+    /// The id of the stored page the link points at, if it was crawled.
+    page_id: Option<i64>,
+}
+
+/// A URL saved in the frontier.
+#[derive(Serialize)]
+struct QueuedUrl {
+    url: String,
+    queued: String,
+    /// `queued`, or `claimed` by a worker of a shared frontier.
+    status: String,
+    worker_id: Option<String>,
+}
+
+/// Serves the API until the process is stopped.
+///
+/// Endpoints:
+/// * `GET /pages` - Stored pages, filtered by `domain`, `status`, `tag`, `hash` and `since` as in exports, and paged
+///   with `offset` and `limit`.
+/// * `GET /page/{id}` - The metadata and start of the text of a page.
+/// * `GET /links/{page_id}` - The links found on a page.
+/// * `GET /stats` - The statistics printed by the `stats` subcommand.
+/// * `GET /queue` - The URLs saved in the frontier, paged with `offset` and `limit`.
+///
+/// # Arguments
+/// * `database` - The crawl database.
+/// * `address` - The address to listen on.
+///
+/// # Returns
+/// An error if the address cannot be bound.
+pub async fn serve(database: PathBuf, address: SocketAddr) -> Result<(), CrawlerError> {
+    if !database.exists() {
+        return Err(CrawlerError::Config(format!(
+            "There is no database {}",
+            database.display()
+        )));
+    }
+    let state = Arc::new(ApiState { database });
+    let app = Router::new()
+        .route("/pages", get(pages))
+        .route("/page/{id}", get(page))
+        .route("/links/{page_id}", get(links))
+        .route("/stats", get(stats))
+        .route("/queue", get(queue))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    info!(
+        "Serving the crawl database on http://{}",
+        listener.local_addr()?
+    );
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Runs a query on a read-only connection, away from the async runtime.
+async fn read<T: Send + 'static>(
+    state: &ApiState,
+    query: impl FnOnce(&Connection) -> Result<T, CrawlerError> + Send + 'static,
+) -> Result<T, ApiError> {
+    let database = state.database.clone();
+    tokio::task::spawn_blocking(move || {
+        let connection = Connection::open_with_flags(&database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        query(&connection)
+    })
+    .await
+    .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(ApiError::from)
+}
+
+/// Returns the limit of a request, at most [`MAX_LIMIT`].
+fn limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+}
+
+async fn pages(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<PagesQuery>,
+) -> Result<Response, ApiError> {
+    let body = read(&state, move |connection| {
+        let filter = ExportFilter {
+            since: query
+                .since
+                .map(|since| Since::parse(connection, &since))
+                .transpose()?,
+            domain: query.domain,
+            status: query.status,
+            hash: query
+                .hash
+                .map(|prefix| digest::resolve_short_id(connection, &prefix))
+                .transpose()?,
+            tag: query.tag,
+            offset: query.offset,
+            limit: Some(limit(query.limit)),
+        };
+        // Exported JSON lines are complete objects, so they only need to be joined into an array
+        let mut lines = Vec::new();
+        export::export(
+            connection,
+            ExportTable::Pages,
+            ExportFormat::Jsonl,
+            &filter,
+            &mut lines,
+        )?;
+        let lines = String::from_utf8_lossy(&lines).into_owned();
+        Ok(format!("[{}]", lines.lines().collect::<Vec<_>>().join(",")))
+    })
+    .await?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+async fn page(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<PagePreview>, ApiError> {
+    read(&state, move |connection| {
+        PagePreview::load_id(connection, id, PAGE_TEXT_LINES)
+    })
+    .await?
+    .map(Json)
+    .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("There is no page {}", id)))
+}
+
+async fn links(
+    State(state): State<Arc<ApiState>>,
+    Path(page_id): Path<i64>,
+) -> Result<Json<Vec<PageLinkRecord>>, ApiError> {
+    let links = read(&state, move |connection| {
+        let exists = connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM Page WHERE Id = ?)",
+            [page_id],
+            |row| row.get::<_, bool>(0),
+        )?;
+        if !exists {
+            return Ok(None);
+        }
+        let mut stmt = connection.prepare(
+            "SELECT PageLink.Url, PageLink.AnchorText, PageLink.Nofollow, PageLink.Element, PageLink.Rel,
+                PageLink.Section, PageLink.External, Target.Id
+            FROM PageLink LEFT JOIN Page AS Target ON Target.Url = PageLink.Url
+            WHERE PageLink.PageId = ? ORDER BY PageLink.rowid",
+        )?;
+        let links = stmt
+            .query_map([page_id], |row| {
+                Ok(PageLinkRecord {
+                    url: row.get(0)?,
+                    anchor_text: row.get(1)?,
+                    nofollow: row.get(2)?,
+                    element: row.get(3)?,
+                    rel: row.get(4)?,
+                    section: row.get(5)?,
+                    external: row.get(6)?,
+                    page_id: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(links))
+    })
+    .await?;
+    links.map(Json).ok_or_else(|| {
+        ApiError(
+            StatusCode::NOT_FOUND,
+            format!("There is no page {}", page_id),
+        )
+    })
+}
+
+async fn stats(State(state): State<Arc<ApiState>>) -> Result<Json<CrawlStats>, ApiError> {
+    read(&state, CrawlStats::load).await.map(Json)
+}
+
+async fn queue(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<QueueQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    read(&state, move |connection| {
+        let total: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM FrontierUrl WHERE Status != 'done'",
+            [],
+            |row| row.get(0),
+        )?;
+        let mut stmt = connection.prepare(
+            "SELECT Url, Created, Status, WorkerId FROM FrontierUrl WHERE Status != 'done'
+            ORDER BY rowid LIMIT ? OFFSET ?",
+        )?;
+        let urls = stmt
+            .query_map([limit(query.limit), query.offset], |row| {
+                Ok(QueuedUrl {
+                    url: row.get(0)?,
+                    queued: row.get(1)?,
+                    status: row.get(2)?,
+                    worker_id: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(json!({ "total": total, "urls": urls }))
+    })
+    .await
+    .map(Json)
+}
@@ -0,0 +1,136 @@
+//! Downloading of linked documents, such as PDFs and spreadsheets, as assets rather than pages.
+
+use std::io;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::disk_writer::DiskWriter;
+
+/// The MIME types of common document formats, by file extension.
+const KNOWN_TYPES: [(&str, &str); 16] = [
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("rtf", "application/rtf"),
+    ("csv", "text/csv"),
+    ("epub", "application/epub+zip"),
+    ("zip", "application/zip"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+];
+
+/// A response recognized as an asset.
+pub struct AssetMatch {
+    /// The extension the asset is saved with.
+    pub extension: String,
+    /// The MIME type of the asset.
+    pub mime_type: String,
+}
+
+/// Saves responses of the requested document types to the assets directory, named by the hash of their contents.
+pub struct AssetStore {
+    dir: PathBuf,
+    extensions: Vec<String>,
+    writer: DiskWriter,
+}
+
+impl AssetStore {
+    /// Creates a new `AssetStore`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory assets are saved in.
+    /// * `extensions` - The file extensions of the document types to download, e.g. `pdf`.
+    /// * `writer` - The writer files are written with.
+    pub fn new(dir: impl Into<PathBuf>, extensions: &[String], writer: DiskWriter) -> Self {
+        AssetStore {
+            dir: dir.into(),
+            extensions: extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            writer,
+        }
+    }
+
+    /// Recognizes a response of one of the document types, by its `Content-Type` or else the extension of its URL.
+    ///
+    /// Responses served as HTML are never assets, so error pages served for a document URL are crawled as pages.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the response.
+    /// * `content_type` - The `Content-Type` header of the response, if any.
+    ///
+    /// # Returns
+    /// The extension and MIME type of the asset, or None if the response is not one.
+    pub fn recognize(&self, url: &Url, content_type: Option<&str>) -> Option<AssetMatch> {
+        let mime_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|mime_type| mime_type.trim().to_ascii_lowercase())
+            .filter(|mime_type| !mime_type.is_empty());
+        if mime_type.as_deref() == Some("text/html") {
+            return None;
+        }
+        let by_mime_type = mime_type.as_deref().and_then(|mime_type| {
+            KNOWN_TYPES
+                .iter()
+                .find(|(_, known)| *known == mime_type)
+                .map(|(extension, _)| extension.to_string())
+        });
+        let by_url = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        let extension = by_mime_type
+            .into_iter()
+            .chain(by_url)
+            .find(|extension| self.extensions.contains(extension))?;
+        let mime_type = mime_type
+            .filter(|mime_type| mime_type != "application/octet-stream")
+            .or_else(|| {
+                KNOWN_TYPES
+                    .iter()
+                    .find(|(known, _)| *known == extension)
+                    .map(|(_, mime_type)| mime_type.to_string())
+            })
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        Some(AssetMatch {
+            extension,
+            mime_type,
+        })
+    }
+
+    /// Returns the path an asset is saved at.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of the contents.
+    /// * `extension` - The extension of the asset.
+    pub fn path(&self, hash: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", hash, extension))
+    }
+
+    /// Saves an asset.
+    ///
+    /// # Arguments
+    /// * `path` - The path returned by [`AssetStore::path`].
+    /// * `body` - The contents.
+    pub async fn save(&self, path: PathBuf, body: &[u8]) -> io::Result<()> {
+        self.writer.write(path, body.to_vec()).await
+    }
+}
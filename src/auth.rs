@@ -0,0 +1,163 @@
+//! Credentials sent to the origins that require them, and to no other origin.
+
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::{Origin, Url};
+
+use crate::error::CrawlerError;
+use crate::normalize::normalize_host;
+
+/// How requests to a host are authenticated, as written in the `auth` table of a domain in the configuration file.
+#[derive(Clone, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Credential {
+    /// HTTP Basic authentication.
+    Basic {
+        /// The user name.
+        username: String,
+        /// The password, which may be left out.
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// A bearer token, sent as `Authorization: Bearer <token>`.
+    Bearer {
+        /// The token.
+        bearer: String,
+    },
+    /// Any other header, e.g. an API key.
+    Header {
+        /// The name of the header.
+        header: String,
+        /// The value of the header.
+        value: String,
+    },
+}
+
+impl Credential {
+    /// Returns the header that carries the credential, marked as sensitive so that it is not logged.
+    ///
+    /// # Returns
+    /// The name and value of the header, or an error if they are not a valid header.
+    pub fn header(&self) -> Result<(HeaderName, HeaderValue), CrawlerError> {
+        let (name, value) = match self {
+            Credential::Basic { username, password } => {
+                let pair = format!("{}:{}", username, password.as_deref().unwrap_or_default());
+                let encoded = base64::engine::general_purpose::STANDARD.encode(pair);
+                (AUTHORIZATION, format!("Basic {}", encoded))
+            }
+            Credential::Bearer { bearer } => (AUTHORIZATION, format!("Bearer {}", bearer)),
+            Credential::Header { header, value } => (
+                HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                    CrawlerError::Config(format!("Invalid credential header name \"{}\"", header))
+                })?,
+                value.clone(),
+            ),
+        };
+        let mut value = HeaderValue::from_str(&value).map_err(|_| {
+            CrawlerError::Config(format!("Invalid value of credential header {}", name))
+        })?;
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+
+/// The credentials of origins, by scheme, host and port.
+///
+/// A credential is sent with the requests to URLs of exactly its origin: not to its subdomains, not over plain HTTP
+/// when it was given for HTTPS, and not to another port. As redirects are followed one request at a time, a redirect
+/// to another origin does not carry the credential along.
+#[derive(Clone, Default)]
+pub struct Credentials {
+    headers: HashMap<Origin, (HeaderName, HeaderValue)>,
+}
+
+impl Credentials {
+    /// Adds the credential of a host, sent over HTTPS on the default port, replacing any previous one.
+    ///
+    /// # Arguments
+    /// * `host` - The host name, e.g. `staging.example.com`.
+    /// * `credential` - The credential.
+    ///
+    /// # Returns
+    /// The credentials, or an error if the host or the credential is not valid.
+    pub fn add(self, host: &str, credential: &Credential) -> Result<Self, CrawlerError> {
+        let origin = Url::parse(&format!("https://{}/", normalize_host(host)))?;
+        self.add_origin(&origin, credential)
+    }
+
+    /// Adds the credential of an origin, e.g. a staging site served over plain HTTP on another port, replacing any
+    /// previous one.
+    ///
+    /// # Arguments
+    /// * `origin` - A URL of the origin, e.g. `http://staging.example.com:8080`.
+    /// * `credential` - The credential.
+    ///
+    /// # Returns
+    /// The credentials, or an error if the URL has no origin or the credential is not a valid header.
+    pub fn add_origin(
+        mut self,
+        origin: &Url,
+        credential: &Credential,
+    ) -> Result<Self, CrawlerError> {
+        let origin = origin.origin();
+        if !origin.is_tuple() {
+            return Err(CrawlerError::Config(format!(
+                "{} has no origin to send a credential to",
+                origin.ascii_serialization()
+            )));
+        }
+        self.headers.insert(origin, credential.header()?);
+        Ok(self)
+    }
+
+    /// Returns whether the origin of a URL has a credential.
+    pub fn covers(&self, url: &Url) -> bool {
+        self.headers.contains_key(&url.origin())
+    }
+
+    /// Adds the credential of the origin of a URL to the headers of its request, if it has one.
+    ///
+    /// # Arguments
+    /// * `url` - The URL requested.
+    /// * `headers` - The headers of the request.
+    pub fn apply(&self, url: &Url, headers: &mut HeaderMap) {
+        if let Some((name, value)) = self.headers.get(&url.origin()) {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_credentials_to_their_origin_only() {
+        let credential = Credential::Bearer {
+            bearer: "token".into(),
+        };
+        let credentials = Credentials::default()
+            .add("Staging.Example.com", &credential)
+            .unwrap()
+            .add_origin(&Url::parse("http://localhost:8080/").unwrap(), &credential)
+            .unwrap();
+        let cases = [
+            ("https://staging.example.com/page", true),
+            ("https://staging.example.com:443/robots.txt", true),
+            ("http://staging.example.com/page", false),
+            ("https://staging.example.com:8443/page", false),
+            ("https://www.staging.example.com/page", false),
+            ("https://example.com/page", false),
+            ("http://localhost:8080/", true),
+            ("http://localhost/", false),
+            ("https://localhost:8080/", false),
+        ];
+        for (url, sent) in cases {
+            let mut headers = HeaderMap::new();
+            credentials.apply(&Url::parse(url).unwrap(), &mut headers);
+            assert_eq!(headers.contains_key(AUTHORIZATION), sent, "{}", url);
+        }
+    }
+}
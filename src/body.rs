@@ -0,0 +1,297 @@
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+use reqwest::Response;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// The maximum size of a response body as sent over the network, in bytes.
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+/// The maximum size of a decompressed response body, in bytes.
+const MAX_DECODED_SIZE: u64 = 64 * 1024 * 1024;
+/// The maximum ratio between the decompressed and compressed size of a body.
+const MAX_EXPANSION_RATIO: u64 = 100;
+
+/// The content encodings that are requested from servers, and decoded by [`read_body`].
+pub const ACCEPTED_ENCODINGS: &str = "gzip, deflate";
+
+/// The reason a response body was rejected.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is larger than the maximum size.
+    TooLarge { limit: u64 },
+    /// The body decompresses to more than the maximum size or expansion ratio.
+    DecompressionBomb { compressed: u64 },
+    /// The body is shorter or longer than its `Content-Length`.
+    LengthMismatch { declared: u64, actual: u64 },
+    /// The body could not be read or decoded.
+    Malformed(String),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyError::TooLarge { limit } => write!(f, "body is larger than {} bytes", limit),
+            BodyError::DecompressionBomb { compressed } => write!(
+                f,
+                "body of {} bytes expands beyond the decompression limits",
+                compressed
+            ),
+            BodyError::LengthMismatch { declared, actual } => write!(
+                f,
+                "body is {} bytes but Content-Length is {}",
+                actual, declared
+            ),
+            BodyError::Malformed(reason) => write!(f, "malformed body: {}", reason),
+        }
+    }
+}
+
+impl Error for BodyError {}
+
+/// Reads and decodes the body of a response within size limits.
+///
+/// Bodies with a `Content-Encoding` of gzip or deflate are decompressed, as long as they do not expand too much. A
+/// body whose `Content-Length` is above the size limit is rejected without reading it, and any other body as soon as
+/// more than the limit has been read.
+///
+/// # Arguments
+/// * `response` - The response.
+/// * `max_size` - A limit on the size of the body, both as sent and decoded, if it is lower than the default limits.
+///
+/// # Returns
+/// The decoded body, or the reason it was rejected.
+pub async fn read_body(
+    mut response: Response,
+    max_size: Option<u64>,
+) -> Result<Vec<u8>, BodyError> {
+    let limit = max_size.map_or(MAX_BODY_SIZE, |max_size| max_size.min(MAX_BODY_SIZE));
+    let declared = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return Err(BodyError::TooLarge { limit });
+    }
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase());
+
+    let mut raw = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if (raw.len() + chunk.len()) as u64 > limit {
+                    return Err(BodyError::TooLarge { limit });
+                }
+                raw.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            // Covers invalid chunked encodings and connections closed before the declared length
+            Err(e) => {
+                return Err(match (declared, e.source()) {
+                    (Some(declared), _) if (raw.len() as u64) < declared => {
+                        BodyError::LengthMismatch {
+                            declared,
+                            actual: raw.len() as u64,
+                        }
+                    }
+                    (_, Some(source)) => BodyError::Malformed(source.to_string()),
+                    (_, None) => BodyError::Malformed(e.to_string()),
+                })
+            }
+        }
+    }
+    if let Some(declared) = declared {
+        if declared != raw.len() as u64 {
+            return Err(BodyError::LengthMismatch {
+                declared,
+                actual: raw.len() as u64,
+            });
+        }
+    }
+
+    let decoded = match encoding.as_deref() {
+        None | Some("identity") => return Ok(raw),
+        Some("gzip") | Some("x-gzip") => decode(MultiGzDecoder::new(raw.as_slice()), raw.len())?,
+        Some("deflate") => decode(ZlibDecoder::new(raw.as_slice()), raw.len())?,
+        Some(other) => {
+            return Err(BodyError::Malformed(format!(
+                "unsupported content encoding {}",
+                other
+            )))
+        }
+    };
+    match max_size {
+        Some(max_size) if decoded.len() as u64 > max_size => {
+            Err(BodyError::TooLarge { limit: max_size })
+        }
+        _ => Ok(decoded),
+    }
+}
+
+/// Decompresses a body, stopping as soon as it exceeds the decompression limits.
+///
+/// # Arguments
+/// * `decoder` - The decoder reading the compressed body.
+/// * `compressed` - The size of the compressed body.
+///
+/// # Returns
+/// The decompressed body.
+fn decode(decoder: impl Read, compressed: usize) -> Result<Vec<u8>, BodyError> {
+    let limit = MAX_DECODED_SIZE.min((compressed as u64).max(1) * MAX_EXPANSION_RATIO);
+    let mut decoded = Vec::new();
+    decoder
+        .take(limit + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| BodyError::Malformed(e.to_string()))?;
+    if decoded.len() as u64 > limit {
+        return Err(BodyError::DecompressionBomb {
+            compressed: compressed as u64,
+        });
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Builds a response with a body and headers, as if it was received.
+    fn response(headers: &[(&str, &str)], body: Vec<u8>) -> Response {
+        let mut builder = http::Response::builder();
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        Response::from(builder.body(body).unwrap())
+    }
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Answers a single request with the raw bytes of a response, then closes the connection.
+    async fn serve_once(raw: &'static [u8]) -> Response {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).await;
+            stream.write_all(raw).await.unwrap();
+        });
+        reqwest::get(format!("http://{}/", address)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn decodes_compressed_bodies() {
+        let body = b"<p>Hello</p>".repeat(10);
+        let gzipped = response(&[("content-encoding", "gzip")], gzip(&body));
+        assert_eq!(read_body(gzipped, None).await.unwrap(), body);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let deflated = response(
+            &[("content-encoding", "deflate")],
+            encoder.finish().unwrap(),
+        );
+        assert_eq!(read_body(deflated, None).await.unwrap(), body);
+
+        let plain = response(&[("content-length", "120")], body.clone());
+        assert_eq!(read_body(plain, None).await.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn rejects_decompression_bombs() {
+        let compressed = gzip(&vec![0; 1024 * 1024]);
+        assert!(compressed.len() as u64 * MAX_EXPANSION_RATIO < 1024 * 1024);
+        let length = compressed.len() as u64;
+        let bomb = response(&[("content-encoding", "gzip")], compressed);
+        assert!(matches!(
+            read_body(bomb, None).await,
+            Err(BodyError::DecompressionBomb { compressed }) if compressed == length
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_above_the_size_limit() {
+        let declared = response(&[("content-length", "100")], vec![b'a'; 100]);
+        assert!(matches!(
+            read_body(declared, Some(10)).await,
+            Err(BodyError::TooLarge { limit: 10 })
+        ));
+        let undeclared = response(&[], vec![b'a'; 100]);
+        assert!(matches!(
+            read_body(undeclared, Some(10)).await,
+            Err(BodyError::TooLarge { limit: 10 })
+        ));
+        let expanding = response(&[("content-encoding", "gzip")], gzip(&[b'a'; 100]));
+        assert!(matches!(
+            read_body(expanding, Some(50)).await,
+            Err(BodyError::TooLarge { limit: 50 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_of_another_length() {
+        let short = response(&[("content-length", "10")], vec![b'a'; 5]);
+        assert!(matches!(
+            read_body(short, None).await,
+            Err(BodyError::LengthMismatch {
+                declared: 10,
+                actual: 5
+            })
+        ));
+        let long = response(&[("content-length", "3")], vec![b'a'; 5]);
+        assert!(matches!(
+            read_body(long, None).await,
+            Err(BodyError::LengthMismatch {
+                declared: 3,
+                actual: 5
+            })
+        ));
+
+        let cut_short =
+            serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\nHello")
+                .await;
+        assert!(matches!(
+            read_body(cut_short, None).await,
+            Err(BodyError::LengthMismatch {
+                declared: 100,
+                actual: 5
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_bodies() {
+        let unsupported = response(&[("content-encoding", "br")], b"body".to_vec());
+        assert!(matches!(
+            read_body(unsupported, None).await,
+            Err(BodyError::Malformed(reason)) if reason == "unsupported content encoding br"
+        ));
+        let corrupt = response(&[("content-encoding", "gzip")], b"not gzip".to_vec());
+        assert!(matches!(
+            read_body(corrupt, None).await,
+            Err(BodyError::Malformed(_))
+        ));
+
+        let chunked = serve_once(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nHello\r\nzz\r\n",
+        )
+        .await;
+        assert!(matches!(
+            read_body(chunked, None).await,
+            Err(BodyError::Malformed(_))
+        ));
+    }
+}
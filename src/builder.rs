@@ -0,0 +1,916 @@
+//! Configuration of crawlers, with validation and defaults.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use url::Url;
+
+use crate::auth::Credentials;
+use crate::calendar::DateUrlLimits;
+use crate::crawler::{BanPolicy, CrawlPaths, Crawler};
+use crate::digest::HashAlgorithm;
+use crate::dns::Resolver;
+use crate::error::CrawlerError;
+use crate::fetcher::{local_seed, FetchMode};
+use crate::geoip::GeoIp;
+use crate::handler::PageHandler;
+use crate::images::ImageLimits;
+use crate::links::LinkElement;
+use crate::normalize::{normalize_host, QueryParamRules, TrailingSlashPolicy};
+use crate::object_store::BodyStore;
+use crate::progress::ProgressDisplay;
+use crate::reload::LiveSettings;
+use crate::render::RenderPolicy;
+use crate::routing::StorageRoute;
+use crate::schedule::FreshnessPolicy;
+use crate::scoring::{Scheduling, Scorer, WeightedScorer};
+use crate::shutdown::{Shutdown, DEFAULT_SHUTDOWN_GRACE};
+use crate::storage::{PageCompression, StorageFormat};
+use crate::tagging::PageTagger;
+use crate::tls::TlsConfig;
+use crate::visited::BloomSettings;
+
+/// The time after which a request is abandoned, unless configured otherwise.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The time after which connecting to a server is abandoned, unless configured otherwise.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a URL claimed from a shared frontier is reserved for its worker, unless configured otherwise.
+pub const DEFAULT_FRONTIER_LEASE: Duration = Duration::from_secs(300);
+
+/// Builds a [`Crawler`], see [`Crawler::builder`].
+///
+/// By default the crawler keeps its files in the current directory, follows robots.txt rules, fetches one page at a
+/// time without a depth limit, and saves pages as uncompressed HTML files.
+pub struct CrawlerBuilder {
+    pub(crate) seeds: Vec<String>,
+    pub(crate) paths: CrawlPaths,
+    pub(crate) user_agent: String,
+    pub(crate) ignore_robots: bool,
+    pub(crate) allow_private_networks: bool,
+    pub(crate) tls: TlsConfig,
+    pub(crate) dry_run: bool,
+    pub(crate) shutdown: Shutdown,
+    pub(crate) shutdown_grace: Duration,
+    pub(crate) revalidate: bool,
+    pub(crate) ban_policy: Option<BanPolicy>,
+    pub(crate) error_budget: Option<u32>,
+    pub(crate) event_webhook: Option<Url>,
+    pub(crate) storage_format: StorageFormat,
+    pub(crate) body_store: Option<Arc<dyn BodyStore>>,
+    pub(crate) storage_routes: Vec<StorageRoute>,
+    pub(crate) page_tagger: PageTagger,
+    pub(crate) trailing_slash: TrailingSlashPolicy,
+    pub(crate) query_params: QueryParamRules,
+    pub(crate) link_elements: Vec<LinkElement>,
+    pub(crate) languages: Vec<String>,
+    pub(crate) asset_extensions: Vec<String>,
+    pub(crate) image_limits: Option<ImageLimits>,
+    pub(crate) geoip: Option<GeoIp>,
+    pub(crate) page_handlers: Vec<Box<dyn PageHandler>>,
+    pub(crate) hash_algorithm: HashAlgorithm,
+    pub(crate) memory_cap: Option<usize>,
+    pub(crate) frontier_limit: Option<usize>,
+    pub(crate) visited_filter: Option<BloomSettings>,
+    pub(crate) follow_canonical: bool,
+    pub(crate) skip_canonical_variants: bool,
+    pub(crate) date_limits: DateUrlLimits,
+    pub(crate) fetch_mode: FetchMode,
+    pub(crate) credentials: Credentials,
+    pub(crate) render_policy: RenderPolicy,
+    pub(crate) scorer: Arc<dyn Scorer>,
+    pub(crate) scheduling: Scheduling,
+    pub(crate) request_timeout: Duration,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) max_depth: Option<u32>,
+    pub(crate) max_pages_per_domain: Option<usize>,
+    pub(crate) max_pages: Option<usize>,
+    pub(crate) min_crawl_delay: Option<Duration>,
+    pub(crate) domain_crawl_delays: HashMap<String, Duration>,
+    pub(crate) concurrency: usize,
+    pub(crate) domain_concurrency: usize,
+    pub(crate) domain_concurrency_overrides: HashMap<String, usize>,
+    pub(crate) resume: bool,
+    pub(crate) recrawl: bool,
+    pub(crate) reprocess: bool,
+    pub(crate) refresh: Option<FreshnessPolicy>,
+    pub(crate) worker_id: Option<String>,
+    pub(crate) frontier_lease: Duration,
+    pub(crate) database_url: Option<String>,
+    pub(crate) queue_uncrawled_links: bool,
+    pub(crate) parent_run: Option<i64>,
+    pub(crate) progress: Option<ProgressDisplay>,
+    pub(crate) resolver: Resolver,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+/// Patterns that URLs must, or must not, match to be crawled.
+#[derive(Default)]
+pub(crate) struct UrlFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UrlFilter {
+    /// Compiles include and exclude patterns.
+    ///
+    /// # Returns
+    /// The filter, or an error if a pattern is not a valid regular expression.
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Result<Self, CrawlerError> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|e| {
+                        CrawlerError::Config(format!("Invalid URL pattern \"{}\": {}", pattern, e))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(UrlFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Returns whether a URL matches an include pattern, if there are any, and no exclude pattern.
+    pub(crate) fn allows(&self, url: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(url)))
+            && !self.exclude.iter().any(|pattern| pattern.is_match(url))
+    }
+}
+
+impl CrawlerBuilder {
+    /// Creates a builder with the default configuration and a single seed URL.
+    ///
+    /// # Arguments
+    /// * `start_url` - The URL to start crawling from.
+    pub fn new(start_url: &str) -> Self {
+        Self::with_seeds(vec![start_url.to_string()], false)
+    }
+
+    /// Creates a builder with the default configuration that continues the frontier saved by the previous run,
+    /// see [`Crawler::resume`].
+    pub fn resuming() -> Self {
+        Self::with_seeds(Vec::new(), true)
+    }
+
+    /// Creates a builder with the default configuration that fetches the pages stored by previous runs again,
+    /// see [`Crawler::recrawl`].
+    pub fn recrawling() -> Self {
+        CrawlerBuilder {
+            recrawl: true,
+            ..Self::with_seeds(Vec::new(), false)
+        }
+    }
+
+    /// Creates a builder with the default configuration that parses the pages stored by previous runs again, see
+    /// [`Crawler::reprocess`]. The frontier is kept for a later run.
+    pub fn reprocessing() -> Self {
+        CrawlerBuilder {
+            reprocess: true,
+            ..Self::with_seeds(Vec::new(), true)
+        }
+    }
+
+    fn with_seeds(seeds: Vec<String>, resume: bool) -> Self {
+        CrawlerBuilder {
+            seeds,
+            paths: CrawlPaths::default(),
+            user_agent: env!("CARGO_PKG_NAME").to_string(),
+            ignore_robots: false,
+            allow_private_networks: false,
+            tls: TlsConfig::default(),
+            dry_run: false,
+            shutdown: Shutdown::new(),
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+            revalidate: false,
+            ban_policy: None,
+            error_budget: None,
+            event_webhook: None,
+            storage_format: StorageFormat::Html {
+                compression: PageCompression::None,
+            },
+            body_store: None,
+            storage_routes: Vec::new(),
+            page_tagger: PageTagger::default(),
+            trailing_slash: TrailingSlashPolicy::Keep,
+            query_params: QueryParamRules::default(),
+            link_elements: vec![LinkElement::A],
+            languages: Vec::new(),
+            asset_extensions: Vec::new(),
+            image_limits: None,
+            geoip: None,
+            page_handlers: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+            memory_cap: None,
+            frontier_limit: None,
+            visited_filter: None,
+            follow_canonical: false,
+            skip_canonical_variants: false,
+            date_limits: DateUrlLimits::default(),
+            fetch_mode: FetchMode::Live,
+            credentials: Credentials::default(),
+            render_policy: RenderPolicy::default(),
+            scorer: Arc::new(WeightedScorer::default()),
+            scheduling: Scheduling::Score,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            max_depth: None,
+            max_pages_per_domain: None,
+            max_pages: None,
+            min_crawl_delay: None,
+            domain_crawl_delays: HashMap::new(),
+            concurrency: 1,
+            domain_concurrency: 1,
+            domain_concurrency_overrides: HashMap::new(),
+            resume,
+            recrawl: false,
+            reprocess: false,
+            refresh: None,
+            worker_id: None,
+            frontier_lease: DEFAULT_FRONTIER_LEASE,
+            database_url: None,
+            queue_uncrawled_links: false,
+            parent_run: None,
+            progress: None,
+            resolver: Resolver::system(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Adds another URL to start crawling from. Seeds are crawled in the order they were given.
+    ///
+    /// `file://` seeds crawl the local directory of their file, served as with [`FetchMode::Directory`] under the host
+    /// [`LOCAL_HOST`](crate::LOCAL_HOST), so that links and robots.txt are resolved as on a web server. Seeds that
+    /// cannot be crawled are skipped with a warning by [`build`](Self::build).
+    pub fn seed(mut self, url: &str) -> Self {
+        self.seeds.push(url.to_string());
+        self
+    }
+
+    /// Sets where the database and files are kept.
+    pub fn paths(mut self, paths: CrawlPaths) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Sets the name of the user agent, sent with requests and matched against robots.txt sections.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Sets whether robots.txt rules, meta robots tags and `X-Robots-Tag` headers are ignored.
+    pub fn ignore_robots(mut self, ignore_robots: bool) -> Self {
+        self.ignore_robots = ignore_robots;
+        self
+    }
+
+    /// Sets whether URLs whose host is, or only resolves to, a private, loopback or link-local address are crawled.
+    /// They are refused by default, so that links cannot make the crawler reach internal services.
+    pub fn allow_private_networks(mut self, allow: bool) -> Self {
+        self.allow_private_networks = allow;
+        self
+    }
+
+    /// Trusts the certificates of a PEM file as root certificates, besides the system roots, e.g. for internal sites
+    /// whose certificates are issued by a private CA. Can be called several times.
+    pub fn ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tls.ca_certs.push(path.into());
+        self
+    }
+
+    /// Sets whether invalid TLS certificates are accepted, including expired, self-signed and mismatched ones.
+    ///
+    /// This is dangerous: any server can impersonate the sites crawled. Prefer [`CrawlerBuilder::ca_cert`].
+    pub fn accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.tls.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Sets the TLS settings at once, replacing the CA files and the acceptance of invalid certificates set so far.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Sets whether pages are fetched, parsed and recorded without saving their bodies, assets or images, to estimate
+    /// what a crawl would store. See [`crate::crawler::DryRunTotals`].
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Lets a crawl be stopped gracefully: once the shutdown is requested, no more URLs are taken, and in-flight
+    /// requests are given the grace period to finish before their URLs are put back in the frontier.
+    ///
+    /// # Arguments
+    /// * `shutdown` - The shutdown, e.g. requested on Ctrl+C with [`Shutdown::listen_for_signals`].
+    /// * `grace` - The grace period of in-flight requests.
+    pub fn shutdown(mut self, shutdown: Shutdown, grace: Duration) -> Self {
+        self.shutdown = shutdown;
+        self.shutdown_grace = grace;
+        self
+    }
+
+    /// Shares the frontier with other crawler processes using the same database, as the worker with the given id.
+    /// URLs are claimed from the `FrontierUrl` table for the lease time, so that no two workers crawl the same URL,
+    /// and the frontier is not cleared when the crawl starts.
+    ///
+    /// # Arguments
+    /// * `worker_id` - The id of the worker, or None to keep the frontier in this process.
+    /// * `lease` - How long a claimed URL is reserved before other workers may claim it. It should exceed the time an
+    ///   iteration takes.
+    pub fn worker(mut self, worker_id: Option<String>, lease: Duration) -> Self {
+        self.worker_id = worker_id;
+        self.frontier_lease = lease;
+        self
+    }
+
+    /// Shares robots.txt rules, stored pages, links and the frontier with workers on other machines through a Postgres
+    /// database, see [`crate::store::CrawlStore`]. Every other record is still kept in the crawl database. Workers
+    /// crawling at the same time should also be given a [`CrawlerBuilder::worker`] id.
+    ///
+    /// # Arguments
+    /// * `url` - The connection URL, e.g. `postgres://crawler@db.example.com/crawl`, or None to keep everything in
+    ///   the crawl database.
+    pub fn database_url(mut self, url: Option<String>) -> Self {
+        self.database_url = url;
+        self
+    }
+
+    /// Makes the crawl a later pass of a scheduled crawl: the robots.txt rules of domains that are not pinned are
+    /// fetched again, and stored pages that are stale by the freshness policy are queued and revalidated.
+    pub fn refresh(mut self, freshness: FreshnessPolicy) -> Self {
+        self.refresh = Some(freshness);
+        self
+    }
+
+    /// Sets whether pages stored by previous runs are re-fetched using conditional requests.
+    pub fn revalidate(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    /// Records the run this run is a recrawl of, to follow a site across runs. Resumed and revalidating runs
+    /// continue from the latest run unless another is given.
+    ///
+    /// # Arguments
+    /// * `run_id` - The id of the parent run.
+    pub fn parent_run(mut self, run_id: Option<i64>) -> Self {
+        self.parent_run = run_id;
+        self
+    }
+
+    /// Sets when domains that refuse our requests are banned. Domains are never banned if None.
+    pub fn ban_policy(mut self, ban_policy: Option<BanPolicy>) -> Self {
+        self.ban_policy = ban_policy;
+        self
+    }
+
+    /// Limits the number of errors of a domain during a run, after which the domain is suspended for the rest of the
+    /// run. Server errors, rejected responses and failed requests count. Domains are never suspended if None.
+    pub fn error_budget(mut self, error_budget: Option<u32>) -> Self {
+        self.error_budget = error_budget;
+        self
+    }
+
+    /// Posts an event to a webhook when a page is stored, when a domain is first seen, and when the crawl completes.
+    /// See [`crate::webhook::EventWebhook`].
+    pub fn event_webhook(mut self, url: Option<Url>) -> Self {
+        self.event_webhook = url;
+        self
+    }
+
+    /// Sets the format pages are saved in.
+    pub fn storage_format(mut self, storage_format: StorageFormat) -> Self {
+        self.storage_format = storage_format;
+        self
+    }
+
+    /// Sets the object store page bodies are saved to with [`StorageFormat::Object`], and read back from by
+    /// [`Crawler::reprocess`].
+    ///
+    /// # Arguments
+    /// * `body_store` - The object store, e.g. an [`S3BodyStore`](crate::object_store::S3BodyStore).
+    pub fn body_store(mut self, body_store: impl BodyStore + 'static) -> Self {
+        self.body_store = Some(Arc::new(body_store));
+        self
+    }
+
+    /// Routes the pages matching URL patterns to other storage targets than the storage format.
+    ///
+    /// # Arguments
+    /// * `routes` - The routes, in order of precedence.
+    pub fn storage_routes(mut self, routes: Vec<StorageRoute>) -> Self {
+        self.storage_routes = routes;
+        self
+    }
+
+    /// Tags pages by rules on their URL, markup and text, as they are crawled.
+    ///
+    /// # Arguments
+    /// * `tagger` - The compiled tag rules.
+    pub fn page_tagger(mut self, tagger: PageTagger) -> Self {
+        self.page_tagger = tagger;
+        self
+    }
+
+    /// Sets whether `/dir`, `/dir/` and `/dir/index.html` are crawled as one page, and which form it is stored under.
+    /// By default they are crawled as different pages.
+    pub fn trailing_slash(mut self, policy: TrailingSlashPolicy) -> Self {
+        self.trailing_slash = policy;
+        self
+    }
+
+    /// Sets which query parameters are stripped from URLs. By default tracking parameters such as `utm_source` and
+    /// `fbclid` are, and the others are kept.
+    pub fn query_params(mut self, rules: QueryParamRules) -> Self {
+        self.query_params = rules;
+        self
+    }
+
+    /// Sets the elements links are extracted from. By default only `<a href>` links are.
+    ///
+    /// Links from `<a>`, `<area>` and `<iframe>` are queued, while links to assets from the other elements are only
+    /// recorded.
+    ///
+    /// # Arguments
+    /// * `elements` - The elements.
+    pub fn link_elements(mut self, elements: Vec<LinkElement>) -> Self {
+        self.link_elements = elements;
+        self
+    }
+
+    /// Only crawls pages in the given languages.
+    ///
+    /// The language of every page is detected from its text, or else taken from `<html lang>`, and recorded in
+    /// `PageMeta`. Pages in other languages are recorded without their contents, and their links are not queued.
+    /// Pages whose language cannot be told are crawled. By default every language is crawled.
+    ///
+    /// # Arguments
+    /// * `languages` - The ISO 639-1 codes of the languages, e.g. `en`.
+    pub fn languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = languages
+            .iter()
+            .map(|language| language.trim().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Downloads linked documents of the given types to the assets directory, instead of parsing them as pages.
+    ///
+    /// Responses are recognized by their MIME type, or else the extension of their URL, and recorded in the `Asset`
+    /// table. By default no documents are downloaded.
+    ///
+    /// # Arguments
+    /// * `extensions` - The file extensions of the document types, e.g. `pdf`.
+    pub fn download_assets(mut self, extensions: Vec<String>) -> Self {
+        self.asset_extensions = extensions;
+        self
+    }
+
+    /// Downloads the `<img>` images shown on pages to the images directory, within limits on their type and size.
+    ///
+    /// Images are deduplicated by the hash of their contents and recorded in the `Image` table with their dimensions,
+    /// and linked to the pages they appear on, with their alt text, in the `PageImage` table. By default images are
+    /// not downloaded.
+    ///
+    /// # Arguments
+    /// * `limits` - The MIME types and size of the images that are saved.
+    pub fn download_images(mut self, limits: ImageLimits) -> Self {
+        self.image_limits = Some(limits);
+        self
+    }
+
+    /// Records the country of the server each page was served from, looked up in a GeoIP database.
+    ///
+    /// # Arguments
+    /// * `geoip` - The country database.
+    pub fn geoip(mut self, geoip: GeoIp) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    /// Registers a handler that is called with the responses, links and errors of the crawl. Handlers are called in
+    /// the order they are registered.
+    ///
+    /// # Arguments
+    /// * `handler` - The handler.
+    pub fn page_handler(mut self, handler: impl PageHandler + 'static) -> Self {
+        self.page_handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Sets the algorithm page contents are hashed with, to detect duplicates and name saved files.
+    pub fn hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Sets the approximate memory in bytes above which the frontier is spilled to the database, and fewer pages are
+    /// fetched at a time. The frontier is always kept in memory if None.
+    pub fn memory_cap(mut self, memory_cap: Option<usize>) -> Self {
+        self.memory_cap = memory_cap;
+        self
+    }
+
+    /// Sets how many queued URLs are held in memory. The others are spilled to the database, and read back highest
+    /// score first as the URLs in memory are crawled. The frontier is not limited if None.
+    pub fn frontier_limit(mut self, limit: Option<usize>) -> Self {
+        self.frontier_limit = limit;
+        self
+    }
+
+    /// Sets how pages that declare a different canonical URL are handled.
+    ///
+    /// # Arguments
+    /// * `follow` - Whether to queue the canonical URLs of pages.
+    /// * `skip_variants` - Whether to leave out the contents of variants, and skip URLs known to be variants of an
+    ///   already crawled canonical page.
+    pub fn canonical_policy(mut self, follow: bool, skip_variants: bool) -> Self {
+        self.follow_canonical = follow;
+        self.skip_canonical_variants = skip_variants;
+        self
+    }
+
+    /// Limits how many calendar and date archive URLs, e.g. `/2020/01/02/` or `?month=2020-01`, are crawled per
+    /// directory during a run.
+    pub fn date_limits(mut self, limits: DateUrlLimits) -> Self {
+        self.date_limits = limits;
+        self
+    }
+
+    /// Sets where responses come from, e.g. to record or replay fixtures, or to serve a local directory.
+    pub fn fetch_mode(mut self, mode: FetchMode) -> Self {
+        self.fetch_mode = mode;
+        self
+    }
+
+    /// Sets the credentials sent to the hosts that require them, e.g. for a staging site behind HTTP Basic
+    /// authentication. Each credential is only sent to its own host, including after redirects.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Renders the HTML pages of every domain, or of chosen domains, in a headless Chromium browser, so the DOM after
+    /// their scripts ran is stored and links are extracted from it.
+    ///
+    /// Rendered pages are requested twice, once to read the status and headers and once by the browser. Pages that
+    /// cannot be rendered are stored as they were served. Requires the `headless` feature, and
+    /// [`CrawlerBuilder::allow_private_networks`], as the browser makes requests of its own that the crawler does not
+    /// check.
+    ///
+    /// # Arguments
+    /// * `policy` - Which pages are rendered, and how.
+    pub fn render_policy(mut self, policy: RenderPolicy) -> Self {
+        self.render_policy = policy;
+        self
+    }
+
+    /// Sets how queued URLs are scored. URLs with higher scores are crawled first. Default is a
+    /// [`WeightedScorer`] with its default weights, which crawls pages close to the seeds first.
+    ///
+    /// # Arguments
+    /// * `scorer` - The scorer.
+    pub fn scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.scorer = Arc::new(scorer);
+        self
+    }
+
+    /// Sets the order queued URLs are crawled in. By default the URL with the highest score is crawled first, while
+    /// with [`Scheduling::RoundRobin`] hosts take turns, each with its URL scoring highest. A frontier shared between
+    /// workers always hands out the URL with the highest score.
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Keeps the URLs visited during a run in a Bloom filter of fixed size, instead of a set that grows with every URL.
+    /// The URLs are also written to a temporary database on disk, which is only looked up for the URLs the filter
+    /// reports as visited, so that none is skipped by mistake. Unless revalidating, the filter holds the stored pages
+    /// and downloaded assets too, and URLs it has not seen are not looked up in the crawl database either. The visited
+    /// URLs are kept exactly in memory if None.
+    ///
+    /// # Arguments
+    /// * `settings` - The number of URLs the filter is sized for, and its false positive rate at that number.
+    pub fn visited_filter(mut self, settings: Option<BloomSettings>) -> Self {
+        self.visited_filter = settings;
+        self
+    }
+
+    /// Sets how long requests may take, and how long connecting to a server may take.
+    ///
+    /// # Arguments
+    /// * `request` - The time after which a request, including reading the response, is abandoned.
+    /// * `connect` - The time after which connecting is abandoned.
+    pub fn timeouts(mut self, request: Duration, connect: Duration) -> Self {
+        self.request_timeout = request;
+        self.connect_timeout = connect;
+        self
+    }
+
+    /// Limits how many links away from the start URL pages are crawled. Links are followed without limit if None.
+    pub fn max_depth(mut self, max_depth: Option<u32>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Limits the number of pages stored per domain, counting the pages of previous runs. Once a domain has as many,
+    /// its new URLs are no longer queued, and those already queued are skipped. Pages are stored without limit if
+    /// None.
+    pub fn max_pages_per_domain(mut self, max_pages: Option<usize>) -> Self {
+        self.max_pages_per_domain = max_pages;
+        self
+    }
+
+    /// Sets whether the links recorded on stored pages that were never crawled are queued when the crawl starts, to
+    /// deepen an interrupted or shallow crawl. They are queued as seeds, after the URLs left in the frontier.
+    pub fn queue_uncrawled_links(mut self, queue: bool) -> Self {
+        self.queue_uncrawled_links = queue;
+        self
+    }
+
+    /// Stops the crawl once this many pages have been stored during the run. Pages whose contents are not saved, and
+    /// failed requests, do not count. The crawl runs until the frontier is empty if None.
+    pub fn max_pages(mut self, max_pages: Option<usize>) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Sets the resolver host names are looked up with, instead of the resolver of the operating system.
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Shows the progress of the crawl on a display, updated as URLs are fetched.
+    pub fn progress(mut self, progress: ProgressDisplay) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Sets the minimum time between requests to a domain. Longer robots.txt crawl delays still apply.
+    pub fn crawl_delay(mut self, crawl_delay: Option<Duration>) -> Self {
+        self.min_crawl_delay = crawl_delay;
+        self
+    }
+
+    /// Sets the minimum time between requests to a single domain, instead of the general one.
+    ///
+    /// # Arguments
+    /// * `domain` - The domain name, e.g. `example.com`.
+    /// * `crawl_delay` - The minimum time between requests.
+    pub fn domain_crawl_delay(mut self, domain: &str, crawl_delay: Duration) -> Self {
+        self.domain_crawl_delays
+            .insert(normalize_host(domain), crawl_delay);
+        self
+    }
+
+    /// Sets how many pages are fetched at the same time. Only [`CrawlerBuilder::domain_concurrency`] pages of each
+    /// domain are fetched at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets how many pages of a single domain are fetched at the same time, within the concurrency. Default is 1.
+    pub fn domain_concurrency(mut self, concurrency: usize) -> Self {
+        self.domain_concurrency = concurrency;
+        self
+    }
+
+    /// Sets how many pages of a single domain are fetched at the same time, instead of the general limit.
+    ///
+    /// # Arguments
+    /// * `domain` - The domain name, e.g. `example.com`.
+    /// * `concurrency` - The number of pages.
+    pub fn domain_concurrency_override(mut self, domain: &str, concurrency: usize) -> Self {
+        self.domain_concurrency_overrides
+            .insert(normalize_host(domain), concurrency);
+        self
+    }
+
+    /// Only crawls URLs that match at least one of the include patterns. May be given several times.
+    ///
+    /// # Arguments
+    /// * `pattern` - A regular expression, matched anywhere in the URL.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Does not crawl URLs that match an exclude pattern. May be given several times.
+    ///
+    /// # Arguments
+    /// * `pattern` - A regular expression, matched anywhere in the URL.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Applies the settings that can also be changed while crawling, see [`Crawler::apply_settings`]. URL patterns
+    /// are added to those already given.
+    ///
+    /// # Arguments
+    /// * `settings` - The settings.
+    pub fn live_settings(mut self, settings: &LiveSettings) -> Self {
+        self.min_crawl_delay = settings.crawl_delay;
+        self.domain_crawl_delays = settings
+            .domain_crawl_delays
+            .iter()
+            .map(|(domain, delay)| (domain.clone(), *delay))
+            .collect();
+        self.ban_policy = settings.ban_policy;
+        self.error_budget = settings.error_budget;
+        self.include.extend(settings.include.iter().cloned());
+        self.exclude.extend(settings.exclude.iter().cloned());
+        self.max_pages = settings.max_pages;
+        self.max_pages_per_domain = settings.max_pages_per_domain;
+        self
+    }
+
+    /// Validates the configuration and creates the crawler, starting a new crawl run in the database.
+    ///
+    /// # Returns
+    /// The crawler, or an error if the configuration is invalid or the database cannot be opened.
+    pub fn build(mut self) -> Result<Crawler, CrawlerError> {
+        self.serve_file_seeds()?;
+        if self.user_agent.trim().is_empty() {
+            return Err(CrawlerError::Config(
+                "The user agent must not be empty".into(),
+            ));
+        }
+        if let Some(settings) = &self.visited_filter {
+            if settings.capacity == 0
+                || !(settings.false_positive_rate > 0.0 && settings.false_positive_rate < 1.0)
+            {
+                return Err(CrawlerError::Config(
+                    "The visited filter needs a capacity of at least 1 and a false positive rate between 0 and 1"
+                        .into(),
+                ));
+            }
+        }
+        if matches!(self.storage_format, StorageFormat::Object { .. }) && self.body_store.is_none()
+        {
+            return Err(CrawlerError::Config(
+                "Saving pages as objects needs an object store".into(),
+            ));
+        }
+        // The browser resolves and requests hosts itself, out of reach of the checks of the resolver
+        if self.render_policy.is_enabled() && !self.allow_private_networks {
+            return Err(CrawlerError::Config(
+                "Rendering pages lets the browser reach private networks, and requires allowing them".into(),
+            ));
+        }
+        if self.memory_cap == Some(0) {
+            return Err(CrawlerError::Config(
+                "The memory cap must be at least 1 MiB".into(),
+            ));
+        }
+        if self.frontier_limit == Some(0) {
+            return Err(CrawlerError::Config(
+                "The frontier limit must be at least 1".into(),
+            ));
+        }
+        if self.concurrency == 0 {
+            return Err(CrawlerError::Config(
+                "The concurrency must be at least 1".into(),
+            ));
+        }
+        if self.domain_concurrency == 0
+            || self.domain_concurrency_overrides.values().any(|n| *n == 0)
+        {
+            return Err(CrawlerError::Config(
+                "The concurrency per domain must be at least 1".into(),
+            ));
+        }
+        if self.request_timeout.is_zero() || self.connect_timeout.is_zero() {
+            return Err(CrawlerError::Config(
+                "Timeouts must be longer than zero".into(),
+            ));
+        }
+        if self.error_budget == Some(0) {
+            return Err(CrawlerError::Config(
+                "The error budget must be at least 1".into(),
+            ));
+        }
+        let url_filter = UrlFilter::new(&self.include, &self.exclude)?;
+        self.skip_invalid_seeds(&url_filter)?;
+        Crawler::from_builder(self, url_filter)
+    }
+
+    /// Drops the seeds that cannot be crawled, logging a warning for each: those that are not valid URLs, use
+    /// another scheme than HTTP(S), or are excluded by the URL patterns.
+    ///
+    /// # Arguments
+    /// * `url_filter` - The URL patterns.
+    ///
+    /// # Returns
+    /// A Result indicating success, or an error if seeds were given and none of them can be crawled.
+    fn skip_invalid_seeds(&mut self, url_filter: &UrlFilter) -> Result<(), CrawlerError> {
+        let given = self.seeds.len();
+        self.seeds.retain(|seed| {
+            let problem = match Url::parse(seed) {
+                Err(e) => e.to_string(),
+                Ok(url) if url.scheme() != "http" && url.scheme() != "https" => {
+                    format!("unsupported scheme {}", url.scheme())
+                }
+                Ok(url) if !url_filter.allows(url.as_str()) => {
+                    "excluded by the URL patterns".to_string()
+                }
+                Ok(_) => return true,
+            };
+            warn!("Skipping seed URL \"{}\": {}", seed, problem);
+            false
+        });
+        if given > 0 && self.seeds.is_empty() {
+            return Err(CrawlerError::Config(format!(
+                "None of the {} seed URLs can be crawled",
+                given
+            )));
+        }
+        Ok(())
+    }
+
+    /// Replaces `file://` seeds by their URLs under the local host, and serves their directory.
+    ///
+    /// # Returns
+    /// A Result indicating success, or an error if the seeds are in different directories or responses come from
+    /// elsewhere.
+    fn serve_file_seeds(&mut self) -> Result<(), CrawlerError> {
+        let mut root: Option<PathBuf> = None;
+        for seed in &mut self.seeds {
+            let Some(url) = Url::parse(seed).ok().filter(|url| url.scheme() == "file") else {
+                continue;
+            };
+            let (dir, local) = local_seed(&url)?;
+            if root.as_ref().is_some_and(|root| *root != dir) {
+                return Err(CrawlerError::Config(
+                    "All file:// seeds must be in the same directory".into(),
+                ));
+            }
+            root = Some(dir);
+            *seed = local.to_string();
+        }
+        if let Some(root) = root {
+            if !matches!(self.fetch_mode, FetchMode::Live) {
+                return Err(CrawlerError::Config(
+                    "file:// seeds cannot be combined with recording, replaying or a fixture directory".into(),
+                ));
+            }
+            self.fetch_mode = FetchMode::Directory(root);
+        }
+        Ok(())
+    }
+
+    /// Returns the settings that decide what a run crawls, as recorded in the `Config` column of `CrawlRun`.
+    pub(crate) fn run_config(&self) -> serde_json::Value {
+        let kind = match (self.recrawl, self.refresh.is_some(), self.resume) {
+            _ if self.reprocess => "reprocess",
+            (true, _, _) => "recrawl",
+            (_, true, _) => "refresh",
+            (_, _, true) => "resume",
+            _ => "crawl",
+        };
+        let storage = match self.storage_format {
+            StorageFormat::Html { .. } => "html",
+            StorageFormat::Warc { .. } => "warc",
+            StorageFormat::Blob { .. } => "blob",
+            StorageFormat::Object { .. } => "object",
+            StorageFormat::Discard => "none",
+        };
+        serde_json::json!({
+            "kind": kind,
+            "user_agent": self.user_agent,
+            "max_depth": self.max_depth,
+            "max_pages": self.max_pages,
+            "max_pages_per_domain": self.max_pages_per_domain,
+            "concurrency": self.concurrency,
+            "domain_concurrency": self.domain_concurrency,
+            "min_crawl_delay_secs": self.min_crawl_delay.map(|delay| delay.as_secs_f64()),
+            "include": self.include,
+            "exclude": self.exclude,
+            "ignore_robots": self.ignore_robots,
+            "allow_private_networks": self.allow_private_networks,
+            "tls": {
+                "ca_certs": self.tls.ca_certs,
+                "insecure": self.tls.accept_invalid_certs,
+            },
+            "revalidate": self.revalidate,
+            "dry_run": self.dry_run,
+            "storage": storage,
+            "trailing_slash": self.trailing_slash.name(),
+            "scheduling": self.scheduling.name(),
+            "languages": self.languages,
+            "worker_id": self.worker_id,
+        })
+    }
+}
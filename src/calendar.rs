@@ -0,0 +1,160 @@
+//! Recognition of calendar and date archive URLs, which can expand without bound.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use url::Url;
+
+/// A path segment holding a full date, e.g. `2020-01-02` or `20200102`.
+static DATE_SEGMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(19|20)\d{2}[-_]?(0[1-9]|1[0-2])([-_]?(0[1-9]|[12]\d|3[01]))?$").unwrap()
+});
+/// A path segment holding a year.
+static YEAR_SEGMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(19|20)\d{2}$").unwrap());
+/// A path segment holding a month or day number.
+static MONTH_SEGMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(0?[1-9]|1[0-2])$").unwrap());
+/// Query parameters that select a calendar page.
+const DATE_PARAMETERS: [&str; 7] = ["year", "month", "day", "date", "week", "cal", "calendar"];
+
+/// Limits on how many date-pattern URLs are crawled per directory.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateUrlLimits {
+    /// The limit for every domain without its own, or no limit if None.
+    #[serde(default)]
+    pub max_per_directory: Option<usize>,
+    /// The limits of single domains, which take precedence. A limit of 0 excludes the date URLs of a domain.
+    #[serde(default)]
+    pub domains: HashMap<String, usize>,
+}
+
+impl DateUrlLimits {
+    /// Returns the limit that applies to a host, if any.
+    ///
+    /// # Arguments
+    /// * `host` - The host name of a URL.
+    pub fn limit_for(&self, host: &str) -> Option<usize> {
+        self.domains.get(host).copied().or(self.max_per_directory)
+    }
+}
+
+/// Recognizes a date-pattern URL, such as `/blog/2020/01/02/` or `/events?month=2020-01`.
+///
+/// # Arguments
+/// * `url` - The URL.
+///
+/// # Returns
+/// The directory the URL expands, e.g. `example.com/blog/`, which the limits are counted per, or None if the URL
+/// does not follow a date pattern.
+pub fn date_directory(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    for (index, segment) in segments.iter().enumerate() {
+        let next = segments.get(index + 1);
+        let is_date = DATE_SEGMENT.is_match(segment)
+            || (YEAR_SEGMENT.is_match(segment)
+                && next.is_none_or(|next| MONTH_SEGMENT.is_match(next)));
+        if is_date {
+            return Some(format!("{}/{}", host, prefix(&segments[..index])));
+        }
+    }
+
+    let mut parameters: Vec<String> = url
+        .query_pairs()
+        .map(|(name, _)| name.to_ascii_lowercase())
+        .filter(|name| DATE_PARAMETERS.contains(&name.as_str()))
+        .collect();
+    if parameters.is_empty() {
+        return None;
+    }
+    parameters.sort();
+    parameters.dedup();
+    Some(format!(
+        "{}/{}?{}",
+        host,
+        prefix(&segments),
+        parameters.join("&")
+    ))
+}
+
+/// Joins path segments into a directory path with a trailing slash, or an empty string for the root.
+fn prefix(segments: &[&str]) -> String {
+    segments
+        .iter()
+        .map(|segment| format!("{}/", segment))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_date_directories() {
+        let cases = [
+            (
+                "https://example.com/blog/2020/01/02/",
+                Some("example.com/blog/"),
+            ),
+            (
+                "https://example.com/blog/2020/01/",
+                Some("example.com/blog/"),
+            ),
+            ("https://example.com/blog/2020/", Some("example.com/blog/")),
+            (
+                "https://example.com/archive/2020-01-02",
+                Some("example.com/archive/"),
+            ),
+            (
+                "https://example.com/archive/20200102/post",
+                Some("example.com/archive/"),
+            ),
+            (
+                "https://example.com/archive/2020_01",
+                Some("example.com/archive/"),
+            ),
+            ("https://example.com/2020/05/post", Some("example.com/")),
+            (
+                "https://example.com/events?month=2020-01",
+                Some("example.com/events/?month"),
+            ),
+            (
+                "https://example.com/cal?Year=2020&month=1&Month=2",
+                Some("example.com/cal/?month&year"),
+            ),
+            // A year followed by something that is no month is e.g. a product or a page number
+            ("https://example.com/models/2020/specs", None),
+            ("https://example.com/blog/2020/13/", None),
+            ("https://example.com/blog/1850/01/", None),
+            ("https://example.com/items/12345678", None),
+            ("https://example.com/search?q=2020", None),
+            ("https://example.com/", None),
+        ];
+        for (url, directory) in cases {
+            assert_eq!(
+                date_directory(&Url::parse(url).unwrap()).as_deref(),
+                directory,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn prefers_the_limits_of_domains() {
+        let limits: DateUrlLimits = serde_json::from_str(
+            r#"{"max_per_directory": 10, "domains": {"news.example.com": 0}}"#,
+        )
+        .unwrap();
+        assert_eq!(limits.limit_for("news.example.com"), Some(0));
+        assert_eq!(limits.limit_for("example.com"), Some(10));
+        assert_eq!(DateUrlLimits::default().limit_for("example.com"), None);
+        assert!(serde_json::from_str::<DateUrlLimits>(r#"{"max": 10}"#).is_err());
+    }
+}
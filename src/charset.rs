@@ -0,0 +1,181 @@
+//! Detection of the character encoding of pages, and decoding of their bodies to text.
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+use url::Url;
+
+/// The number of bytes at the start of a body searched for a `<meta>` charset declaration, as browsers do.
+const META_PRESCAN_SIZE: usize = 1024;
+
+/// A `charset` parameter of a `Content-Type` header, or of the `content` of a `<meta http-equiv>` element.
+static CHARSET_PARAMETER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)charset\s*=\s*["']?([\w.:-]+)"#).unwrap());
+
+/// A `<meta>` element declaring a charset, in either form.
+static META_CHARSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<meta\s[^>]*?charset\s*=\s*["']?([\w.:-]+)"#).unwrap());
+
+/// Detects the encoding of a body and decodes it.
+///
+/// A byte order mark takes precedence, then the charset of the `Content-Type` header, then a `<meta>` declaration
+/// near the start of the body. Bodies declaring neither are sniffed, with the top-level domain of the URL as a hint.
+/// Malformed sequences are replaced with U+FFFD.
+///
+/// # Arguments
+/// * `body` - The body of the page.
+/// * `content_type` - The `Content-Type` header of the response, if any.
+/// * `url` - The URL of the page.
+///
+/// # Returns
+/// The decoded body, and the encoding it was decoded with.
+pub fn decode<'a>(
+    body: &'a [u8],
+    content_type: Option<&str>,
+    url: &Url,
+) -> (Cow<'a, str>, &'static Encoding) {
+    let encoding = content_type
+        .and_then(|content_type| charset_label(&CHARSET_PARAMETER, content_type))
+        .or_else(|| meta_charset(body))
+        .unwrap_or_else(|| sniff(body, url));
+    let (text, encoding, _) = encoding.decode(body);
+    (text, encoding)
+}
+
+/// Decodes a saved body with the encoding recorded for its page.
+///
+/// # Arguments
+/// * `body` - The body of the page.
+/// * `charset` - The name of the encoding recorded for the page. Pages recorded without one are read as UTF-8.
+///
+/// # Returns
+/// The decoded body.
+pub fn decode_saved<'a>(body: &'a [u8], charset: Option<&str>) -> Cow<'a, str> {
+    let encoding = charset
+        .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(body).0
+}
+
+/// Returns the encoding named by the first match of a pattern, if it is known.
+fn charset_label(pattern: &Regex, text: &str) -> Option<&'static Encoding> {
+    let label = pattern.captures(text)?.get(1)?.as_str();
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Returns the encoding declared by a `<meta>` element near the start of a body.
+///
+/// A declared UTF-16 encoding is read as UTF-8, since a body that could be searched as ASCII is not UTF-16.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(META_PRESCAN_SIZE)]);
+    charset_label(&META_CHARSET, &head).map(Encoding::output_encoding)
+}
+
+/// Guesses the encoding of a body from its contents.
+fn sniff(body: &[u8], url: &Url) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(body, true);
+    let tld = url
+        .host_str()
+        .and_then(|host| host.rsplit('.').next())
+        .filter(|tld| !tld.is_empty() && tld.bytes().all(|byte| byte.is_ascii_alphabetic()));
+    detector.guess(tld.map(str::as_bytes), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    #[test]
+    fn detects_declared_encodings() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let latin = b"<p>caf\xe9</p>";
+        let meta = b"<html><head><meta charset=\"ISO-8859-1\"></head><p>caf\xe9</p>";
+        let http_equiv =
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\"><p>caf\xe9</p>";
+        let cases: [(&[u8], Option<&str>, &str, &str); 7] = [
+            (
+                latin,
+                Some("text/html; charset=ISO-8859-1"),
+                "windows-1252",
+                "<p>café</p>",
+            ),
+            (
+                latin,
+                Some("text/html;charset=\"latin1\""),
+                "windows-1252",
+                "<p>café</p>",
+            ),
+            (meta, None, "windows-1252", "café"),
+            (http_equiv, Some("text/html"), "windows-1252", "café"),
+            // The header takes precedence over the document, and a byte order mark over both
+            (
+                "<meta charset=\"latin1\"><p>café</p>".as_bytes(),
+                Some("text/html; charset=utf-8"),
+                "UTF-8",
+                "café",
+            ),
+            (
+                b"\xef\xbb\xbf<p>caf\xc3\xa9</p>",
+                Some("text/html; charset=latin1"),
+                "UTF-8",
+                "<p>café</p>",
+            ),
+            // A document declaring UTF-16 is read as UTF-8, as it could not be searched if it were UTF-16
+            (
+                "<meta charset=\"utf-16\"><p>café</p>".as_bytes(),
+                None,
+                "UTF-8",
+                "café",
+            ),
+        ];
+        for (body, content_type, encoding, text) in cases {
+            let (decoded, detected) = decode(body, content_type, &url);
+            assert_eq!(detected.name(), encoding, "{:?}", content_type);
+            assert!(decoded.contains(text), "{}", decoded);
+        }
+    }
+
+    #[test]
+    fn only_reads_declarations_near_the_start() {
+        let mut body = format!("<html><head><!--{}-->", "x".repeat(META_PRESCAN_SIZE)).into_bytes();
+        body.extend_from_slice(b"<meta charset=\"shift_jis\"></head><p>plain</p>");
+        let url = Url::parse("https://example.com/").unwrap();
+        assert_ne!(decode(&body, None, &url).1, SHIFT_JIS);
+    }
+
+    #[test]
+    fn sniffs_undeclared_encodings() {
+        let japanese = "<p>日本語のページです。今日は天気がいいですね。ここに文章があります。</p>";
+        let (encoded, _, _) = SHIFT_JIS.encode(japanese);
+        let url = Url::parse("https://example.jp/").unwrap();
+        assert_eq!(
+            decode(&encoded, Some("text/html"), &url),
+            (japanese.into(), SHIFT_JIS)
+        );
+
+        let french = "<p>Le café était très fermé, déjà à midi, où l'été commençait.</p>";
+        let (encoded, _, _) = WINDOWS_1252.encode(french);
+        let url = Url::parse("https://example.fr/").unwrap();
+        assert_eq!(decode(&encoded, None, &url), (french.into(), WINDOWS_1252));
+
+        let url = Url::parse("https://example.com/").unwrap();
+        assert_eq!(decode("<p>café</p>".as_bytes(), None, &url).1, UTF_8);
+        // Malformed sequences of a declared encoding are replaced
+        let (text, _) = decode(b"a\xffb", Some("text/plain; charset=utf-8"), &url);
+        assert_eq!(text, "a\u{fffd}b");
+    }
+
+    #[test]
+    fn decodes_saved_bodies() {
+        assert_eq!(decode_saved(b"caf\xe9", Some("windows-1252")), "café");
+        assert_eq!(decode_saved("café".as_bytes(), None), "café");
+        assert_eq!(
+            decode_saved("café".as_bytes(), Some("no-such-charset")),
+            "café"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! The TOML configuration file of crawls, given with `--config`.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::auth::Credential;
+use crate::crawler::CrawlPaths;
+use crate::digest::HashAlgorithm;
+use crate::error::CrawlerError;
+use crate::links::LinkElement;
+use crate::normalize::{normalize_host, TrailingSlashPolicy};
+use crate::object_store::S3Settings;
+use crate::scoring::Scheduling;
+use crate::storage::PageCompression;
+
+/// The settings of a crawl. Every setting is optional, and command line arguments take precedence.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    /// The URLs to start crawling from.
+    pub seeds: Vec<String>,
+    /// The name of the user agent.
+    pub user_agent: Option<String>,
+    /// The number of crawl iterations.
+    pub depth: Option<u32>,
+    /// When the passes of a long-running crawl start, e.g. `every 6h` or a cron expression.
+    pub schedule: Option<String>,
+    /// The number of hours after which scheduled passes fetch stored pages again.
+    pub refresh_hours: Option<f64>,
+    /// How many links away from the seeds pages are crawled.
+    pub max_link_depth: Option<u32>,
+    /// The number of stored pages after which the crawl stops.
+    pub max_pages: Option<usize>,
+    /// How the URLs of directories are treated.
+    pub trailing_slash: Option<TrailingSlashPolicy>,
+    /// The order queued URLs are crawled in.
+    pub scheduling: Option<Scheduling>,
+    /// Query parameters stripped from URLs besides the tracking parameters, as names or prefixes ending in `*`.
+    pub strip_params: Vec<String>,
+    /// Query parameters kept in URLs, even tracking parameters.
+    pub keep_params: Vec<String>,
+    /// The elements links are extracted from.
+    pub extract: Option<Vec<LinkElement>>,
+    /// The ISO 639-1 codes of the languages crawled.
+    pub languages: Vec<String>,
+    /// The file extensions of the linked documents downloaded as assets.
+    pub download_assets: Vec<String>,
+    /// Whether the images shown on pages are downloaded.
+    pub images: Option<bool>,
+    /// The MIME types of the images downloaded.
+    pub image_types: Vec<String>,
+    /// The size in KiB of the largest image downloaded.
+    pub max_image_size: Option<u64>,
+    /// The maximum number of pages stored per domain.
+    pub max_pages_per_domain: Option<usize>,
+    /// The number of pages fetched at the same time.
+    pub concurrency: Option<usize>,
+    /// The approximate memory in MiB above which queued URLs are spilled and fewer pages are fetched at a time.
+    pub memory_cap: Option<u64>,
+    /// The number of queued URLs held in memory.
+    pub frontier_limit: Option<usize>,
+    /// The seconds in-flight requests are given to finish after Ctrl+C.
+    pub shutdown_grace: Option<u64>,
+    /// The number of URLs the Bloom filter of visited URLs is sized for.
+    pub visited_filter: Option<usize>,
+    /// The false positive rate of the Bloom filter of visited URLs.
+    pub visited_fp_rate: Option<f64>,
+    /// The number of seconds after which a request is abandoned.
+    pub timeout: Option<u64>,
+    /// Regular expressions that URLs must match to be crawled.
+    pub include: Vec<String>,
+    /// Regular expressions of URLs that are not crawled.
+    pub exclude: Vec<String>,
+    /// The maximum number of date-pattern URLs crawled per directory.
+    pub max_date_urls: Option<usize>,
+    /// A JSON rules file routing pages to storage targets, and tagging pages.
+    pub rules: Option<PathBuf>,
+    /// A MaxMind database the countries of servers are looked up in.
+    pub geoip: Option<PathBuf>,
+    /// Whether HTML pages are rendered in a headless browser.
+    pub render: Option<bool>,
+    /// The Chromium executable pages are rendered with.
+    pub chrome: Option<PathBuf>,
+    /// The URL crawl events are posted to.
+    pub event_webhook: Option<String>,
+    /// The Postgres database shared with workers on other machines.
+    pub db_url: Option<String>,
+    /// The SMTP server report emails are sent through.
+    pub smtp_url: Option<String>,
+    /// The password of the SMTP server, instead of the `SMTP_PASSWORD` environment variable.
+    pub smtp_password: Option<String>,
+    /// Whether hosts on private, loopback and link-local addresses are crawled.
+    pub allow_private_networks: Option<bool>,
+    /// PEM files of CA certificates trusted besides the system roots.
+    pub ca_certs: Vec<PathBuf>,
+    /// Whether invalid TLS certificates are accepted.
+    pub insecure: Option<bool>,
+    /// How hard domains are crawled.
+    pub politeness: Politeness,
+    /// Where and how pages are saved.
+    pub storage: StorageConfig,
+    /// Settings of single domains, by domain name.
+    pub domains: HashMap<String, DomainOverride>,
+    /// Where host names are looked up.
+    pub dns: DnsConfig,
+}
+
+/// Where host names are looked up, instead of the resolver of the operating system.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsConfig {
+    /// DNS servers, as `ip` or `ip:port`.
+    pub servers: Vec<String>,
+    /// The URL of a DNS-over-HTTPS server, whose host is looked up with `servers` if it is not an IP address.
+    pub doh: Option<String>,
+    /// Fixed addresses of hosts, as `host:port:addr[,addr]` like curl or `host:addr`, which are used instead of looking
+    /// the hosts up.
+    pub resolve: Vec<String>,
+}
+
+/// How hard domains are crawled.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Politeness {
+    /// Whether robots.txt rules and robots directives are ignored.
+    pub ignore_robots: Option<bool>,
+    /// The minimum number of seconds between requests to a domain. Longer robots.txt crawl delays still apply.
+    pub crawl_delay: Option<f64>,
+    /// The number of pages of a domain fetched at the same time.
+    pub domain_concurrency: Option<usize>,
+    /// The number of consecutive 403/429 responses after which a domain is banned.
+    pub ban_after: Option<u32>,
+    /// The number of hours a banned domain is skipped for.
+    pub ban_hours: Option<u32>,
+    /// The number of errors after which a domain is suspended for the rest of a run.
+    pub error_budget: Option<u32>,
+}
+
+/// Where and how pages are saved.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// The directory the database, pages and events file are kept in, under their default names.
+    pub dir: Option<PathBuf>,
+    /// The database, overriding `dir`.
+    pub database: Option<PathBuf>,
+    /// The directory pages are saved in, overriding `dir`.
+    pub pages: Option<PathBuf>,
+    /// The directory downloaded document assets are saved in, overriding `dir`.
+    pub assets: Option<PathBuf>,
+    /// The directory downloaded images are saved in, overriding `dir`.
+    pub images: Option<PathBuf>,
+    /// The events file, overriding `dir`.
+    pub events: Option<PathBuf>,
+    /// The format pages are saved in, `html` or `warc`.
+    pub format: Option<String>,
+    /// Where page bodies are saved, `files`, `blob` or `s3`.
+    pub store: Option<String>,
+    /// The bucket page bodies are saved to with `store = "s3"`, and read back from.
+    pub s3: Option<S3Settings>,
+    /// The compression of saved HTML files or BLOBs.
+    pub compress: Option<PageCompression>,
+    /// The size in MiB after which a new WARC file is started.
+    pub warc_max_size: Option<u64>,
+    /// The algorithm page contents are hashed with.
+    pub hash: Option<HashAlgorithm>,
+}
+
+/// Settings of a single domain, taking precedence over the general ones.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DomainOverride {
+    /// The minimum number of seconds between requests to the domain.
+    pub crawl_delay: Option<f64>,
+    /// The number of pages of the domain fetched at the same time.
+    pub concurrency: Option<usize>,
+    /// The maximum number of date-pattern URLs crawled per directory of the domain.
+    pub max_date_urls: Option<usize>,
+    /// The number of hours after which scheduled passes fetch pages of the domain again.
+    pub refresh_hours: Option<f64>,
+    /// Whether the domain is not crawled at all.
+    pub skip: bool,
+    /// Whether the HTML pages of the domain are rendered in a headless browser.
+    pub render: Option<bool>,
+    /// Query parameters stripped from the URLs of the domain.
+    pub strip_params: Vec<String>,
+    /// Query parameters kept in the URLs of the domain.
+    pub keep_params: Vec<String>,
+    /// The credential sent with every request to the domain over HTTPS on the default port, and to no other origin.
+    pub auth: Option<Credential>,
+    /// The origin the credential is sent to instead, e.g. `http://staging.example.com:8080`.
+    pub auth_origin: Option<String>,
+}
+
+impl CrawlConfig {
+    /// Reads and checks a configuration file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the TOML file.
+    ///
+    /// # Returns
+    /// The configuration, or an error if the file cannot be read or is invalid.
+    pub fn load(path: &Path) -> Result<Self, CrawlerError> {
+        let mut config: CrawlConfig = toml::from_str(&fs::read_to_string(path)?).map_err(|e| {
+            CrawlerError::Config(format!(
+                "Invalid configuration file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        if let Some(format) = config
+            .storage
+            .format
+            .as_deref()
+            .filter(|format| !["html", "warc"].contains(format))
+        {
+            return Err(CrawlerError::Config(format!(
+                "Invalid configuration file {}: unknown storage format \"{}\"",
+                path.display(),
+                format
+            )));
+        }
+        if let Some(store) = config
+            .storage
+            .store
+            .as_deref()
+            .filter(|store| !["files", "blob", "s3"].contains(store))
+        {
+            return Err(CrawlerError::Config(format!(
+                "Invalid configuration file {}: unknown page store \"{}\"",
+                path.display(),
+                store
+            )));
+        }
+        let delays = config
+            .domains
+            .values()
+            .filter_map(|domain| domain.crawl_delay)
+            .chain(config.politeness.crawl_delay);
+        for delay in delays {
+            if !delay.is_finite() || delay < 0.0 {
+                return Err(CrawlerError::Config(format!(
+                    "Invalid configuration file {}: crawl delay {} is not a number of seconds",
+                    path.display(),
+                    delay
+                )));
+            }
+        }
+        for (domain, settings) in &config.domains {
+            if let Some(Err(e)) = settings.auth.as_ref().map(Credential::header) {
+                return Err(CrawlerError::Config(format!(
+                    "Invalid configuration file {}: credential of {}: {}",
+                    path.display(),
+                    domain,
+                    e
+                )));
+            }
+            if let Some(origin) = &settings.auth_origin {
+                let host = Url::parse(origin)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string));
+                if host.as_deref() != Some(normalize_host(domain).as_str()) {
+                    return Err(CrawlerError::Config(format!(
+                        "Invalid configuration file {}: auth_origin {} is not an origin of {}",
+                        path.display(),
+                        origin,
+                        domain
+                    )));
+                }
+            }
+        }
+        config.domains = config
+            .domains
+            .into_iter()
+            .map(|(domain, settings)| (normalize_host(&domain), settings))
+            .collect();
+        Ok(config)
+    }
+
+    /// Returns where the database and files are kept.
+    pub fn paths(&self) -> CrawlPaths {
+        let storage = &self.storage;
+        let defaults = storage
+            .dir
+            .as_ref()
+            .map_or_else(CrawlPaths::default, CrawlPaths::in_dir);
+        CrawlPaths {
+            database: storage.database.clone().unwrap_or(defaults.database),
+            save_dir: storage.pages.clone().unwrap_or(defaults.save_dir),
+            assets_dir: storage.assets.clone().unwrap_or(defaults.assets_dir),
+            images_dir: storage.images.clone().unwrap_or(defaults.images_dir),
+            events_file: storage.events.clone().unwrap_or(defaults.events_file),
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Extraction of the readable text of pages, conversion of pages to Markdown, and statistics about the text.
+
+use ego_tree::iter::Edge;
+use ego_tree::NodeRef;
+use scraper::{Html, Node};
+use url::Url;
+
+use crate::meta::base_url;
+
+/// Average adult silent reading speed, in words per minute.
+const WORDS_PER_MINUTE: usize = 238;
+/// Elements whose text is not shown to readers.
+const HIDDEN_ELEMENTS: [&str; 4] = ["script", "style", "noscript", "template"];
+/// Elements left out of the plain text rendition of a page, besides hidden ones.
+const BOILERPLATE_ELEMENTS: [&str; 1] = ["nav"];
+/// Elements that start a new line in the plain text rendition of a page.
+const BLOCK_ELEMENTS: [&str; 25] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "p",
+    "pre",
+    "section",
+    "tr",
+];
+/// Pages with fewer words than this are reported as thin content.
+pub const THIN_CONTENT_WORDS: usize = 300;
+
+/// Simple statistics about the readable text of a page.
+pub struct ContentStats {
+    /// The number of words of visible text.
+    pub word_count: usize,
+    /// The size of the visible text relative to the size of the HTML.
+    pub text_ratio: f64,
+    /// The estimated time to read the visible text, in seconds.
+    pub reading_time: usize,
+}
+
+impl ContentStats {
+    /// Computes the statistics of a page.
+    ///
+    /// # Arguments
+    /// * `text` - The visible text of the page, see [`visible_text`].
+    /// * `html_size` - The size of the raw HTML in bytes.
+    pub fn from_text(text: &str, html_size: usize) -> Self {
+        let word_count = text.split_whitespace().count();
+        ContentStats {
+            word_count,
+            text_ratio: if html_size == 0 {
+                0.0
+            } else {
+                text.len() as f64 / html_size as f64
+            },
+            reading_time: (word_count * 60).div_ceil(WORDS_PER_MINUTE),
+        }
+    }
+}
+
+/// Extracts the text of a parsed page that is shown to readers, with whitespace collapsed to single spaces.
+///
+/// # Arguments
+/// * `document` - The parsed page.
+pub fn visible_text(document: &Html) -> String {
+    let mut words = Vec::new();
+    for node in document.tree.root().descendants() {
+        let Node::Text(text) = node.value() else {
+            continue;
+        };
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor
+                .value()
+                .as_element()
+                .is_some_and(|element| HIDDEN_ELEMENTS.contains(&element.name()))
+        });
+        if !hidden {
+            words.extend(text.split_whitespace());
+        }
+    }
+    words.join(" ")
+}
+
+/// Extracts a plain text rendition of a parsed page for text processing.
+///
+/// Hidden elements and navigation are left out. Block elements such as paragraphs and headings are put on separate
+/// lines, with whitespace within lines collapsed to single spaces.
+///
+/// # Arguments
+/// * `document` - The parsed page.
+pub fn plain_text(document: &Html) -> String {
+    let mut lines = Vec::new();
+    let mut line: Vec<&str> = Vec::new();
+    let mut skipped_depth = 0;
+    for edge in document.tree.root().traverse() {
+        let (node, opening) = match edge {
+            Edge::Open(node) => (node, true),
+            Edge::Close(node) => (node, false),
+        };
+        match node.value() {
+            Node::Element(element) => {
+                let name = element.name();
+                if HIDDEN_ELEMENTS.contains(&name) || BOILERPLATE_ELEMENTS.contains(&name) {
+                    skipped_depth = if opening {
+                        skipped_depth + 1
+                    } else {
+                        skipped_depth - 1
+                    };
+                } else if skipped_depth == 0 && BLOCK_ELEMENTS.contains(&name) && !line.is_empty() {
+                    lines.push(line.join(" "));
+                    line.clear();
+                }
+            }
+            Node::Text(text) if opening && skipped_depth == 0 => {
+                line.extend(text.split_whitespace());
+            }
+            _ => {}
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line.join(" "));
+    }
+    lines.join("\n")
+}
+
+/// The lists a Markdown rendition is inside of, innermost last, with the next number of ordered ones.
+struct MarkdownContext<'a> {
+    base: &'a Url,
+    lists: Vec<Option<usize>>,
+    in_pre: bool,
+}
+
+/// Converts a parsed page to Markdown, e.g. to add documentation pages to a knowledge base.
+///
+/// Hidden elements, navigation and the head are left out like in [`plain_text`]. Headings, paragraphs, lists, block
+/// quotes, code, links, images, emphasis and rules are converted, and other elements are reduced to their text. Links
+/// and images are resolved against the `<base href>` of the page, or else its URL.
+///
+/// # Arguments
+/// * `document` - The parsed page.
+/// * `url` - The URL of the page.
+pub fn markdown(document: &Html, url: &Url) -> String {
+    let base = base_url(document, url);
+    let mut context = MarkdownContext {
+        base: &base,
+        lists: Vec::new(),
+        in_pre: false,
+    };
+    let mut out = String::new();
+    for child in document.tree.root().children() {
+        render_markdown(child, &mut context, &mut out);
+    }
+    let mut markdown = String::new();
+    let mut blank_lines = 0;
+    for line in out.trim().lines() {
+        let line = line.trim_end_matches(' ');
+        blank_lines = if line.is_empty() { blank_lines + 1 } else { 0 };
+        if blank_lines < 2 {
+            markdown.push_str(line);
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+/// Appends the Markdown rendition of a node and its descendants.
+fn render_markdown(node: NodeRef<Node>, context: &mut MarkdownContext, out: &mut String) {
+    let element = match node.value() {
+        Node::Text(text) if context.in_pre => {
+            out.push_str(text);
+            return;
+        }
+        Node::Text(text) => {
+            push_collapsed(out, text);
+            return;
+        }
+        Node::Element(element) => element,
+        _ => {
+            for child in node.children() {
+                render_markdown(child, context, out);
+            }
+            return;
+        }
+    };
+
+    let name = element.name();
+    if HIDDEN_ELEMENTS.contains(&name) || BOILERPLATE_ELEMENTS.contains(&name) || name == "head" {
+        return;
+    }
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse().unwrap_or(1);
+            let text = render_inline(node, context);
+            if !text.is_empty() {
+                block_break(out);
+                out.push_str(&format!("{} {}", "#".repeat(level), text));
+                block_break(out);
+            }
+        }
+        "br" => line_break(out),
+        "hr" => {
+            block_break(out);
+            out.push_str("---");
+            block_break(out);
+        }
+        "ul" | "ol" => {
+            if context.lists.is_empty() {
+                block_break(out);
+            } else {
+                line_break(out);
+            }
+            context.lists.push((name == "ol").then_some(1));
+            for child in node.children() {
+                render_markdown(child, context, out);
+            }
+            context.lists.pop();
+            if context.lists.is_empty() {
+                block_break(out);
+            }
+        }
+        "li" => {
+            line_break(out);
+            let indent = "  ".repeat(context.lists.len().saturating_sub(1));
+            let marker = match context.lists.last_mut() {
+                Some(Some(number)) => {
+                    *number += 1;
+                    format!("{}. ", *number - 1)
+                }
+                _ => "- ".to_string(),
+            };
+            out.push_str(&indent);
+            out.push_str(&marker);
+            for child in node.children() {
+                render_markdown(child, context, out);
+            }
+        }
+        "blockquote" => {
+            let mut quote = String::new();
+            for child in node.children() {
+                render_markdown(child, context, &mut quote);
+            }
+            block_break(out);
+            for line in quote.trim().lines() {
+                out.push_str(format!("> {}", line).trim_end());
+                out.push('\n');
+            }
+            block_break(out);
+        }
+        "pre" => {
+            let mut code = String::new();
+            context.in_pre = true;
+            for child in node.children() {
+                render_markdown(child, context, &mut code);
+            }
+            context.in_pre = false;
+            block_break(out);
+            out.push_str("```\n");
+            out.push_str(code.trim_matches('\n'));
+            out.push_str("\n```");
+            block_break(out);
+        }
+        "code" if !context.in_pre => {
+            let code = node
+                .descendants()
+                .filter_map(|descendant| descendant.value().as_text().map(|text| &**text))
+                .collect::<String>();
+            let code = code.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !code.is_empty() {
+                out.push_str(&format!("`{}`", code));
+            }
+        }
+        "strong" | "b" | "em" | "i" if !context.in_pre => {
+            let marker = if matches!(name, "strong" | "b") {
+                "**"
+            } else {
+                "*"
+            };
+            let text = render_inline(node, context);
+            if !text.is_empty() {
+                out.push_str(&format!("{}{}{}", marker, text, marker));
+            }
+        }
+        "a" if !context.in_pre => {
+            let text = render_inline(node, context);
+            let target = element
+                .attr("href")
+                .filter(|href| !href.trim_start().starts_with("javascript:"))
+                .and_then(|href| context.base.join(href.trim()).ok());
+            match target {
+                Some(target) if !text.is_empty() => {
+                    out.push_str(&format!("[{}]({})", text, target))
+                }
+                _ => out.push_str(&text),
+            }
+        }
+        "img" => {
+            if let Some(source) = element
+                .attr("src")
+                .and_then(|src| context.base.join(src.trim()).ok())
+            {
+                let alt = element.attr("alt").unwrap_or_default().trim();
+                out.push_str(&format!("![{}]({})", alt, source));
+            }
+        }
+        "td" | "th" => {
+            if !out.ends_with('\n') && !out.is_empty() {
+                out.push_str(" | ");
+            }
+            out.push_str(&render_inline(node, context));
+        }
+        _ => {
+            let block = BLOCK_ELEMENTS.contains(&name) || matches!(name, "table" | "figure");
+            if block {
+                if name == "tr" || name == "dt" || name == "dd" {
+                    line_break(out);
+                } else {
+                    block_break(out);
+                }
+            }
+            for child in node.children() {
+                render_markdown(child, context, out);
+            }
+            if block && name != "tr" && name != "dt" && name != "dd" {
+                block_break(out);
+            }
+        }
+    }
+}
+
+/// Returns the Markdown rendition of the children of a node on a single line.
+fn render_inline(node: NodeRef<Node>, context: &mut MarkdownContext) -> String {
+    let mut text = String::new();
+    for child in node.children() {
+        render_markdown(child, context, &mut text);
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Appends text with its whitespace collapsed to single spaces.
+fn push_collapsed(out: &mut String, text: &str) {
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let at_space = out.is_empty() || out.ends_with([' ', '\n']);
+    if text.starts_with(char::is_whitespace) && !at_space {
+        out.push(' ');
+    }
+    out.push_str(&words);
+    if !words.is_empty() && text.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+/// Ends the current line.
+fn line_break(out: &mut String) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Ends the current block with an empty line.
+fn block_break(out: &mut String) {
+    line_break(out);
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(html: &str) -> String {
+        let url = Url::parse("https://example.com/docs/guide.html").unwrap();
+        markdown(&Html::parse_document(html), &url)
+    }
+
+    #[test]
+    fn converts_pages_to_markdown() {
+        let cases = [
+            ("<h1>Title</h1><h3>Part</h3>", "# Title\n\n### Part\n"),
+            (
+                "<p>One <b>bold</b> and <em>soft</em> word</p><p>Two</p>",
+                "One **bold** and *soft* word\n\nTwo\n",
+            ),
+            ("<ul><li>a</li><li>b</li></ul>", "- a\n- b\n"),
+            ("<ol><li>a</li><li>b</li></ol>", "1. a\n2. b\n"),
+            ("<ul><li>a<ol><li>b</li></ol></li></ul>", "- a\n  1. b\n"),
+            (
+                "<blockquote><p>Quoted</p><p>Twice</p></blockquote>",
+                "> Quoted\n>\n> Twice\n",
+            ),
+            (
+                "<pre><code>fn main() {\n    run();\n}</code></pre>",
+                "```\nfn main() {\n    run();\n}\n```\n",
+            ),
+            (
+                "<p>Run <code> cargo   test </code></p>",
+                "Run `cargo test`\n",
+            ),
+            ("<p>A<br>B</p><hr><p>C</p>", "A\nB\n\n---\n\nC\n"),
+            (
+                "<table><tr><th>Name</th><th>Size</th></tr><tr><td>a</td><td>1</td></tr></table>",
+                "Name | Size\na | 1\n",
+            ),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(convert(html), expected, "{}", html);
+        }
+    }
+
+    #[test]
+    fn resolves_links_and_leaves_out_boilerplate() {
+        let cases = [
+            (
+                r#"<p><a href="install.html">Install</a> <a href="/">Home</a></p>"#,
+                "[Install](https://example.com/docs/install.html) [Home](https://example.com/)\n",
+            ),
+            (
+                r#"<head><base href="https://docs.example.com/v2/"></head><a href="api">API</a>"#,
+                "[API](https://docs.example.com/v2/api)\n",
+            ),
+            (
+                r#"<img src="logo.png" alt=" Logo ">"#,
+                "![Logo](https://example.com/docs/logo.png)\n",
+            ),
+            (
+                r#"<a href="javascript:void(0)">Menu</a> <a href="/x"><img src="/x.png" alt=""></a>"#,
+                "Menu [![](https://example.com/x.png)](https://example.com/x)\n",
+            ),
+            (
+                "<nav><a href=\"/\">Home</a></nav><script>var a;</script><style>p{}</style><p>Text</p>",
+                "Text\n",
+            ),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(convert(html), expected, "{}", html);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! What became of given URLs in a crawl, for checking the coverage of curated URL lists.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use url::Url;
+
+use crate::crawler::ROBOTS_DISALLOWED;
+use crate::error::CrawlerError;
+
+/// What became of a URL.
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UrlStatus {
+    /// The URL was fetched and its page stored.
+    Crawled {
+        /// The URL the page is stored under, which differs from the URL if it redirected.
+        final_url: String,
+        /// The status of the last response.
+        http_status: Option<u16>,
+    },
+    /// The URL redirects to a URL that was crawled or queued instead.
+    Redirected {
+        /// The URL the redirects end at.
+        target: String,
+    },
+    /// The URL was dequeued but not fetched.
+    Skipped {
+        /// Why the URL was not fetched.
+        reason: String,
+    },
+    /// Fetching the URL failed, its response was rejected, or it had an error status.
+    Failed {
+        /// The latest error.
+        error: String,
+    },
+    /// The URL is waiting in the frontier.
+    Queued,
+    /// The URL was linked to by a stored page, but never queued or crawled.
+    Linked,
+    /// The URL does not appear in the crawl.
+    Unseen,
+}
+
+impl UrlStatus {
+    /// Returns the name of the status, as printed in the first column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UrlStatus::Crawled { .. } => "crawled",
+            UrlStatus::Redirected { .. } => "redirected",
+            UrlStatus::Skipped { .. } => "skipped",
+            UrlStatus::Failed { .. } => "failed",
+            UrlStatus::Queued => "queued",
+            UrlStatus::Linked => "linked",
+            UrlStatus::Unseen => "unseen",
+        }
+    }
+
+    /// Returns the detail of the status, e.g. the reason a URL was skipped.
+    fn detail(&self) -> String {
+        match self {
+            UrlStatus::Crawled {
+                final_url,
+                http_status,
+            } => {
+                let status = http_status.map_or("-".to_string(), |status| status.to_string());
+                format!("{} {}", status, final_url)
+            }
+            UrlStatus::Redirected { target } => target.clone(),
+            UrlStatus::Skipped { reason } => reason.clone(),
+            UrlStatus::Failed { error } => error.clone(),
+            UrlStatus::Queued | UrlStatus::Linked | UrlStatus::Unseen => String::new(),
+        }
+    }
+}
+
+/// The status of a URL.
+#[derive(Serialize)]
+pub struct UrlReport {
+    /// The URL as it was given.
+    pub url: String,
+    /// What became of it.
+    #[serde(flatten)]
+    pub status: UrlStatus,
+}
+
+/// Looks up what became of URLs in the crawl database and the event log.
+pub struct UrlStatusLookup<'a> {
+    connection: &'a Connection,
+    skip_reasons: HashMap<String, String>,
+}
+
+impl<'a> UrlStatusLookup<'a> {
+    /// Creates a lookup, reading the reasons dequeued URLs were skipped from the event log.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `events_file` - The event log. A missing file is treated as empty.
+    ///
+    /// # Returns
+    /// The lookup, or an error if the event log cannot be read.
+    pub fn new(connection: &'a Connection, events_file: &Path) -> Result<Self, CrawlerError> {
+        let mut skip_reasons = HashMap::new();
+        let file = match File::open(events_file) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        for line in file
+            .into_iter()
+            .flat_map(|file| BufReader::new(file).lines())
+        {
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&line?) else {
+                continue;
+            };
+            if event["event"] != "skip" {
+                continue;
+            }
+            if let (Some(url), Some(reason)) = (event["url"].as_str(), event["reason"].as_str()) {
+                skip_reasons.insert(url.to_string(), reason.to_string());
+            }
+        }
+        Ok(UrlStatusLookup {
+            connection,
+            skip_reasons,
+        })
+    }
+
+    /// Looks up what became of a URL.
+    ///
+    /// A stored page takes precedence over earlier failures and skips of its URL. Otherwise the latest failure is
+    /// reported before skips, queued URLs and links.
+    ///
+    /// # Arguments
+    /// * `url` - The URL, normalized the way the crawler records URLs before it is looked up.
+    ///
+    /// # Returns
+    /// The status of the URL.
+    pub fn status(&self, url: &str) -> Result<UrlStatus, CrawlerError> {
+        let url = Url::parse(url).map_or_else(|_| url.to_string(), String::from);
+        let crawled = self
+            .connection
+            .query_row(
+                "SELECT Page.Url, (SELECT Status FROM PageFetch WHERE PageId = Page.Id ORDER BY Id DESC LIMIT 1)
+                FROM Page WHERE Url = ?1 OR RequestedUrl = ?1
+                ORDER BY Url = ?1 DESC LIMIT 1",
+                [&url],
+                |row| {
+                    Ok(UrlStatus::Crawled {
+                        final_url: row.get(0)?,
+                        http_status: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        if let Some(crawled) = crawled {
+            return Ok(crawled);
+        }
+        let target = self
+            .connection
+            .query_row(
+                "SELECT TargetUrl FROM Redirect WHERE SourceUrl = ?",
+                [&url],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(target) = target {
+            return Ok(UrlStatus::Redirected { target });
+        }
+        let error = self
+            .connection
+            .query_row(
+                "SELECT Error FROM (
+                    SELECT Error, LastAttempt FROM FailedUrl WHERE Url = ?1
+                    UNION ALL
+                    SELECT COALESCE(Error, 'HTTP status ' || Status), Fetched FROM PageFetch
+                    WHERE Url = ?1 AND PageId IS NULL AND (Error IS NOT NULL OR Status >= 400)
+                ) ORDER BY Created DESC LIMIT 1",
+                [&url],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(error) = error {
+            return Ok(UrlStatus::Failed { error });
+        }
+        if let Some(reason) = self.skip_reasons.get(&url) {
+            return Ok(UrlStatus::Skipped {
+                reason: reason.clone(),
+            });
+        }
+        if self.exists("SELECT 1 FROM RobotsBlockedUrl WHERE Url = ?", &url)? {
+            return Ok(UrlStatus::Skipped {
+                reason: ROBOTS_DISALLOWED.to_string(),
+            });
+        }
+        if self.exists(
+            "SELECT 1 FROM FrontierUrl WHERE Url = ? AND Status != 'done'",
+            &url,
+        )? {
+            return Ok(UrlStatus::Queued);
+        }
+        if self.exists("SELECT 1 FROM PageLink WHERE Url = ?", &url)? {
+            return Ok(UrlStatus::Linked);
+        }
+        Ok(UrlStatus::Unseen)
+    }
+
+    /// Returns whether a query for a URL returns a row.
+    fn exists(&self, query: &str, url: &str) -> Result<bool, CrawlerError> {
+        Ok(self
+            .connection
+            .query_row(query, [url], |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
+}
+
+/// Reads a list of URLs, one per line. Blank lines and lines starting with `#` are ignored.
+///
+/// # Arguments
+/// * `reader` - The list.
+///
+/// # Returns
+/// The URLs, in the order they were listed.
+pub fn read_url_list(reader: impl BufRead) -> Result<Vec<String>, CrawlerError> {
+    let mut urls = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let url = line.trim();
+        if !url.is_empty() && !url.starts_with('#') {
+            urls.push(url.to_string());
+        }
+    }
+    Ok(urls)
+}
+
+/// Writes one tab-separated line per URL with its status, the URL, and the detail of the status, followed by the
+/// number of URLs with each status.
+///
+/// # Arguments
+/// * `reports` - The statuses of the URLs.
+/// * `writer` - Where the lines are written.
+pub fn write_reports(reports: &[UrlReport], writer: &mut impl Write) -> Result<(), CrawlerError> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for report in reports {
+        let name = report.status.name();
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            name,
+            report.url,
+            report.status.detail()
+        )?;
+        match counts.iter_mut().find(|(counted, _)| *counted == name) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((name, 1)),
+        }
+    }
+    let summary = counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(writer, "# {} URLs: {}", reports.len(), summary)?;
+    Ok(())
+}
@@ -1,49 +1,746 @@
-use blake3::Hasher;
-use hex::encode;
-use itertools::Itertools;
-use log::{error, info};
-use regex::Regex;
-use rusqlite::Connection;
-use scraper::{Html, Selector};
-use std::fs;
+//! The crawler, which fetches batches of queued URLs and records what it finds.
+
+use chrono::Utc;
+use futures::future::join_all;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, CONTENT_LANGUAGE, CONTENT_LENGTH,
+    CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, RETRY_AFTER,
+};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Response, StatusCode, Version};
+use rusqlite::{params, Connection, OptionalExtension};
+use scraper::Html;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncReadExt;
+use tracing::{error, info, warn};
 use url::Url;
 
-use crate::unique_queue::UniqueQueue;
+use crate::assets::{AssetMatch, AssetStore};
+use crate::body::{read_body, BodyError, ACCEPTED_ENCODINGS};
+use crate::builder::{CrawlerBuilder, UrlFilter};
+use crate::calendar::{date_directory, DateUrlLimits};
+use crate::charset::{self, decode_saved};
+use crate::content::{plain_text, visible_text, ContentStats};
+use crate::digest::HashAlgorithm;
+use crate::disk_writer::{DiskWriter, DiskWriterHandle};
+use crate::dns::Resolver;
+use crate::error::CrawlerError;
+use crate::events::{CrawlEvent, EventLog};
+use crate::fetcher::Fetcher;
+use crate::frontier::Frontier;
+use crate::geoip::GeoIp;
+use crate::handler::{PageFetch, PageHandler};
+use crate::images::{self, ImageRejection, ImageStore};
+use crate::language;
+use crate::links::{extract_links, FoundLink, LinkElement};
+use crate::memory::MemoryUsage;
+use crate::meta::{base_url, PageMeta, RobotsDirectives};
+use crate::normalize::{display_host, display_url, is_same_site, resolve_href, UrlNormalizer};
+use crate::reachability::{check_host, CHECK_TIMEOUT};
+use crate::reader::BodyReader;
+use crate::redirect_map::RedirectMap;
+use crate::reload::LiveSettings;
+use crate::render::Renderer;
+use crate::retry::FailureClass;
+use crate::robots::{self, disallowed_patterns, is_disallowed};
+use crate::schema;
+use crate::scoring::{Scheduling, ScoreContext, Scorer};
+use crate::shutdown::Shutdown;
+use crate::sitemap::{decompress_sitemap, parse_sitemap, ParsedSitemap, MAX_SITEMAP_BYTES};
+use crate::storage::{PageStore, StoredLocation, StoredResponse};
+use crate::store::{CrawlStore, PostgresStore};
+use crate::tagging::PageTagger;
+use crate::terms;
+use crate::throttle::{parse_retry_after, Throttle, MAX_THROTTLE_RETRIES};
+use crate::visited::VisitedSet;
+use crate::webhook::{EventWebhook, EventWebhookHandle, WebhookEvent};
 
-const DB_NAME: &str = "web_crawler.db";
-const SAVE_DIR: &str = "pages";
-const DISALLOWED_ROBOTS_REGEX: &str = r"(?i)Disallow:\s*(\S+*)";
+const MAX_REDIRECTS: usize = 10;
+const WRITE_QUEUE_SIZE: usize = 64;
+const X_ROBOTS_TAG: &str = "x-robots-tag";
+pub(crate) const ROBOTS_DISALLOWED: &str = "Disallowed by robots.txt";
+/// The reason URLs of domains with as many stored pages as allowed are not crawlable.
+const PAGE_CAP_REACHED: &str = "Domain has reached the maximum number of pages";
+/// How long a worker sharing the frontier waits for a locked database before an error.
+const SHARED_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a worker sharing the frontier waits when other workers hold every queued URL.
+const SHARED_CLAIM_WAIT: Duration = Duration::from_secs(1);
+/// The most sitemap files read for a domain, sitemap indexes included.
+const MAX_SITEMAP_FILES: usize = 10;
+/// How many prepared statements the database connection keeps, enough for every statement run for each link.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/// The connection settings of the crawl database. WAL lets readers, such as the API, work alongside the crawl, and
+/// makes commits cheap enough to write every page in a transaction of its own.
+const DATABASE_PRAGMAS: &str = "PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    PRAGMA temp_store = MEMORY;
+    PRAGMA cache_size = -16384;";
 
 /// A web crawler that follows links on webpages and stores their contents to SQLite database.
 pub struct Crawler {
+    /// The name of the user agent, sent with requests and matched against robots.txt sections.
     pub user_agent: String,
+    /// The connection to the crawl database.
     pub db_connection: Connection,
+    /// The Postgres database shared with workers on other machines, if any, see [`crawl_store`].
+    shared: Option<PostgresStore>,
 
-    url_queue: UniqueQueue<String>,
-    hasher: Hasher,
+    url_queue: Frontier,
+    scorer: Arc<dyn Scorer>,
+    visited: VisitedSet,
+    /// How many links away from the start URL queued URLs were found, until they are taken from the queue.
+    depths: HashMap<String, u32>,
+    last_requests: HashMap<i64, Instant>,
+    /// The domains that throttled the crawler with 429 or 503 responses during this run.
+    throttles: HashMap<i64, Throttle>,
+    /// The number of times each throttled URL was queued again.
+    throttle_retries: HashMap<String, u32>,
+    /// The URLs of the current batch that are queued again, because they were throttled or a shutdown cut their
+    /// requests short.
+    requeued_urls: HashSet<String>,
+    /// The URLs taken from the queue for domains that are paused, by domain, which are put back once their pause ends.
+    parked: HashMap<i64, Vec<(String, SystemTime, f64)>>,
+    /// Asks the crawler to stop taking URLs.
+    shutdown: Shutdown,
+    /// The time in-flight requests are given to finish once a shutdown is requested.
+    shutdown_grace: Duration,
+    /// The hosts and ports checked for reachability during this run, with the reason they are unreachable, if any.
+    host_checks: HashMap<String, Option<String>>,
+    resolver: Resolver,
+    memory: MemoryUsage,
+    peak_memory: usize,
+    memory_cap: Option<usize>,
+    /// The highest memory usage seen since the current batch started.
+    batch_memory: usize,
+    /// The number of pages fetched at a time, lowered from the concurrency while batches use more than the memory cap.
+    batch_concurrency: usize,
+    redirects: RedirectMap,
+    store: PageStore,
+    /// Where documents of the types to download are saved, if any are.
+    assets: Option<AssetStore>,
+    /// Where the images shown on pages are saved, if they are downloaded.
+    images: Option<ImageStore>,
+    /// The images shown on pages that are still to be downloaded, with the depth they were found at. They are queued
+    /// apart from the pages, so that they take no part of the page budget.
+    image_queue: VecDeque<(String, u32)>,
+    /// The browser pages are rendered in, if any are.
+    renderer: Option<Renderer>,
+    events: EventLog,
+    writer_handle: DiskWriterHandle,
+    webhook: Option<EventWebhook>,
+    webhook_handle: Option<EventWebhookHandle>,
+    fetcher: Fetcher,
+    paths: CrawlPaths,
+    hash_algorithm: HashAlgorithm,
+    url_filter: UrlFilter,
     ignore_robots: bool,
+    revalidate: bool,
+    /// Whether the crawler reprocesses stored pages, making no requests.
+    reprocess: bool,
+    follow_canonical: bool,
+    skip_canonical_variants: bool,
+    date_limits: DateUrlLimits,
+    /// The number of date-pattern URLs crawled during this run, per directory.
+    date_counts: HashMap<String, usize>,
+    max_depth: Option<u32>,
+    max_pages_per_domain: Option<usize>,
+    /// The number of stored pages of each host, by any run, counted while a cap on them is set.
+    domain_pages: Option<HashMap<String, usize>>,
+    /// The number of pages after which the crawl stops.
+    max_pages: Option<usize>,
+    /// The number of pages stored during this run, duplicates included, but not pages whose contents were not saved.
+    stored_pages: usize,
+    /// What would have been saved, if this is a dry run that saves no bodies, assets or images.
+    dry_run: Option<DryRunTotals>,
+    changed_pages: usize,
+    /// The minimum time between requests to a domain, unless it has its own.
+    min_crawl_delay: Option<Duration>,
+    domain_crawl_delays: HashMap<String, Duration>,
+    concurrency: usize,
+    /// The number of pages of a domain fetched at the same time, unless it has its own.
+    domain_concurrency: usize,
+    domain_concurrency_overrides: HashMap<String, usize>,
+    ban_policy: Option<BanPolicy>,
+    error_budget: Option<u32>,
+    /// The number of errors of each domain during this run.
+    domain_errors: HashMap<i64, u32>,
+    tagger: PageTagger,
+    normalizer: UrlNormalizer,
+    /// The elements links are extracted from.
+    link_elements: Vec<LinkElement>,
+    /// The ISO 639-1 codes of the languages crawled, or empty to crawl every language.
+    languages: Vec<String>,
+    /// The country database servers are looked up in, if any.
+    geoip: Option<GeoIp>,
+    handlers: Vec<Box<dyn PageHandler>>,
+    run_id: i64,
+}
+
+/// What the crawler takes from the contents of a page.
+struct ParsedPage {
+    document: Html,
+    meta: PageMeta,
+    /// The robots directives that are followed.
+    directives: RobotsDirectives,
+    text: String,
+    /// Whether the page is in one of the languages crawled.
+    in_language: bool,
+}
+
+/// The response to a single page request.
+struct FetchRecord<'a> {
+    url: &'a Url,
+    final_url: &'a Url,
+    version: Version,
+    status: StatusCode,
+    headers: &'a HeaderMap,
+    content_length: Option<u64>,
+    duration: Duration,
+    depth: u32,
+    /// The address of the server the response came from, unless it was replayed.
+    server_address: Option<IpAddr>,
+    error: Option<String>,
+}
+
+/// A page request prepared for a batch, with everything needed to send it without access to the database.
+struct PageRequest {
+    url: Url,
+    /// When the URL was queued.
+    queued: SystemTime,
+    /// The score the URL was queued with.
+    score: f64,
+    domain_id: i64,
+    depth: u32,
+    /// Whether the URL is of an image shown on a page, taken from the image queue rather than the frontier.
+    image: bool,
+    /// The time to wait for the crawl delay of the domain before sending the request.
+    delay: Duration,
+    previous: Option<PageValidators>,
+    headers: HeaderMap,
+}
+
+/// Why a redirect chain was not followed to its end.
+enum RedirectStop {
+    /// The next URL is on another host, so it is queued to be checked and requested like any URL of that host.
+    OtherHost(Url),
+    /// The next URL is not crawlable, for the given reason.
+    NotCrawlable(Url, String),
+}
+
+/// The response to a page request, with its body if it was successful.
+struct FetchedPage {
+    response_headers: HeaderMap,
+    version: Version,
+    status: StatusCode,
+    final_url: Url,
+    hops: Vec<(Url, StatusCode)>,
+    /// Where the redirect chain was left, if it was not followed to its end.
+    stopped: Option<RedirectStop>,
+    body: Option<Result<Vec<u8>, BodyError>>,
+    duration: Duration,
+    server_address: Option<IpAddr>,
+}
+
+/// Where a crawler keeps its database and files.
+#[derive(Clone)]
+pub struct CrawlPaths {
+    /// The SQLite database, created or upgraded to the latest schema when a crawler opens it.
+    pub database: PathBuf,
+    /// The directory pages are saved in.
+    pub save_dir: PathBuf,
+    /// The directory downloaded document assets are saved in.
+    pub assets_dir: PathBuf,
+    /// The directory downloaded images are saved in.
+    pub images_dir: PathBuf,
+    /// The file crawl events are appended to.
+    pub events_file: PathBuf,
+}
+
+impl CrawlPaths {
+    /// Places the database, pages, assets and images directories and events file in a directory, under their default
+    /// names.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory.
+    pub fn in_dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        CrawlPaths {
+            database: dir.join("web_crawler.db"),
+            save_dir: dir.join("pages"),
+            assets_dir: dir.join("assets"),
+            images_dir: dir.join("images"),
+            events_file: dir.join("events.ndjson"),
+        }
+    }
+}
+
+impl Default for CrawlPaths {
+    /// Places the files in the current directory.
+    fn default() -> Self {
+        CrawlPaths::in_dir("")
+    }
+}
+
+/// Determines when a domain that keeps refusing our requests is banned from crawling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BanPolicy {
+    /// The number of consecutive 403/429 responses after which a domain is banned.
+    pub threshold: u32,
+    /// How long a banned domain is skipped for, in hours.
+    pub quarantine_hours: u32,
+}
+
+/// What a dry run would have saved, had it been a real crawl.
+#[derive(Clone, Default)]
+pub struct DryRunTotals {
+    /// The number of distinct page bodies, not counting duplicates and pages whose contents are not saved.
+    pub pages: usize,
+    /// The size of the page bodies, in bytes, before compression.
+    pub page_bytes: u64,
+    /// The number of distinct downloaded assets and images.
+    pub files: usize,
+    /// The size of the assets and images, in bytes.
+    pub file_bytes: u64,
+    /// The hashes of the assets and images counted, as files with identical contents are saved once.
+    file_hashes: HashSet<String>,
+}
+
+impl DryRunTotals {
+    /// Counts an asset or image, unless one with the same contents was counted.
+    fn add_file(&mut self, hash: String, size: usize) {
+        if self.file_hashes.insert(hash) {
+            self.files += 1;
+            self.file_bytes += size as u64;
+        }
+    }
+}
+
+/// Cache validators recorded from a previous fetch of a page.
+struct PageValidators {
+    page_id: i64,
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
 impl Crawler {
-    /// Creates a new Crawler instance.
+    /// Starts configuring a crawler, see [`CrawlerBuilder`].
     ///
     /// # Arguments
     /// * `start_url` - The URL to start crawling from.
-    /// * `user_agent` - The name of the user agent string to.
-    /// * `ignore_robots` - Whether to ignore robots.txt rules. Default is false.
-    pub fn new(start_url: &str, user_agent: &str, ignore_robots: Option<bool>) -> Self {
-        let db_connection = Connection::open(DB_NAME).unwrap();
+    pub fn builder(start_url: &str) -> CrawlerBuilder {
+        CrawlerBuilder::new(start_url)
+    }
+
+    /// Starts configuring a crawler that continues with the URLs left in the frontier when the previous run
+    /// finished, see [`CrawlerBuilder`]. Seeds may still be added.
+    pub fn resume() -> CrawlerBuilder {
+        CrawlerBuilder::resuming()
+    }
+
+    /// Starts configuring a crawler that fetches every page stored by previous runs again, without following links to
+    /// new pages, see [`CrawlerBuilder`]. Pages whose contents changed keep their earlier versions in the
+    /// `PageRevision` table.
+    pub fn recrawl() -> CrawlerBuilder {
+        CrawlerBuilder::recrawling()
+    }
+
+    /// Starts configuring a crawler that parses the pages stored by previous runs again without fetching them, see
+    /// [`CrawlerBuilder`] and [`Crawler::reprocess_pages`].
+    pub fn reprocess() -> CrawlerBuilder {
+        CrawlerBuilder::reprocessing()
+    }
+
+    /// Creates a crawler from a validated configuration, starting a new crawl run in the database.
+    pub(crate) fn from_builder(
+        builder: CrawlerBuilder,
+        url_filter: UrlFilter,
+    ) -> Result<Self, CrawlerError> {
+        let client = builder.tls.configure(Client::builder())?;
+        if builder.tls.accept_invalid_certs {
+            warn!("Accepting invalid TLS certificates: responses may come from impostor servers");
+        }
+        let renderer = builder
+            .render_policy
+            .is_enabled()
+            .then(|| Renderer::new(builder.render_policy.clone(), &builder.tls))
+            .transpose()?;
+        let mut db_connection = Connection::open(&builder.paths.database)?;
+        db_connection.execute_batch(DATABASE_PRAGMAS)?;
+        schema::migrate(&mut db_connection)?;
+        db_connection.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        let parent_run = match builder.parent_run {
+            Some(id) => {
+                db_connection
+                    .query_row("SELECT Id FROM CrawlRun WHERE Id = ?", [id], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                    .optional()?
+                    .ok_or_else(|| CrawlerError::Config(format!("There is no crawl run {}", id)))?;
+                Some(id)
+            }
+            None if builder.resume || builder.revalidate || builder.recrawl => {
+                db_connection.query_row("SELECT MAX(Id) FROM CrawlRun", [], |row| row.get(0))?
+            }
+            None => None,
+        };
+        db_connection.execute(
+            "INSERT INTO CrawlRun (ParentRunId, Seeds, Config) VALUES (?, ?, ?)",
+            params![
+                parent_run,
+                serde_json::to_string(&builder.seeds)?,
+                builder.run_config().to_string()
+            ],
+        )?;
+        let run_id = db_connection.last_insert_rowid();
+        if let Some(parent_run) = parent_run {
+            info!("Run {} continues from run {}", run_id, parent_run);
+        }
+
+        let mut redirects = RedirectMap::new();
+        db_connection
+            .prepare("SELECT SourceUrl, TargetUrl FROM Redirect")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .try_for_each(|redirect| {
+                        let (source, target) = redirect?;
+                        redirects.insert(source, target);
+                        Ok(())
+                    })
+            })?;
+
+        let (writer, writer_handle) = DiskWriter::start(WRITE_QUEUE_SIZE);
+        let shared = builder
+            .database_url
+            .as_deref()
+            .map(PostgresStore::connect)
+            .transpose()?;
+        if shared.is_some() {
+            info!("Sharing robots.txt rules, pages, links and the frontier through Postgres");
+        }
+        let store = crawl_store(&db_connection, &shared);
+        let scorer = builder.scorer.clone();
+        let seed_score = |url: &str| {
+            Url::parse(url).map_or(0.0, |url| {
+                scorer.score(
+                    &url,
+                    &ScoreContext {
+                        depth: 0,
+                        source: None,
+                        sitemap_priority: None,
+                    },
+                )
+            })
+        };
+        let mut url_queue = match &builder.worker_id {
+            Some(worker_id) => {
+                db_connection.busy_timeout(SHARED_BUSY_TIMEOUT)?;
+                info!("Worker {} shares the frontier in the database", worker_id);
+                Frontier::shared(worker_id.clone(), builder.frontier_lease)
+            }
+            None => Frontier::new(),
+        };
+        url_queue.set_limit(builder.frontier_limit);
+        url_queue.set_scheduling(builder.scheduling);
+        if builder.resume || url_queue.is_shared() {
+            let saved = url_queue.restore(store)?;
+            info!("Resuming with {} URLs left by the previous run", saved);
+        } else {
+            store.frontier_clear()?;
+        }
+        if builder.queue_uncrawled_links {
+            let added = url_queue.add_uncrawled_links(store, builder.ignore_robots)?;
+            info!("Queued {} links that were found but never crawled", added);
+        }
+        let mut fresh_pages = Vec::new();
+        if let Some(freshness) = &builder.refresh {
+            let expired = db_connection.execute(
+                "UPDATE Domain SET RobotsFetched = NULL WHERE Pinned = 0",
+                [],
+            )?;
+            let expired = expired.max(store.expire_robots_rules()?);
+            info!("Refreshing the robots.txt rules of {} domains", expired);
+            let mut stmt = db_connection.prepare(
+                "SELECT Page.Url, (julianday('now') - julianday(COALESCE(MAX(PageFetch.Fetched), Page.Updated))) * 86400,
+                    Page.DryRun
+                FROM Page LEFT JOIN PageFetch ON PageFetch.PageId = Page.Id
+                GROUP BY Page.Id ORDER BY Page.Id DESC",
+            )?;
+            let pages = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, f64>(1)?,
+                        row.get::<_, bool>(2)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(stmt);
+            let mut stale = 0;
+            for (url, age, dry_run) in pages {
+                let domain = Url::parse(&url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_default();
+                // Pages recorded by a dry run have no stored body, however recent they are
+                if dry_run || freshness.is_stale(&domain, Duration::from_secs_f64(age.max(0.0))) {
+                    let score = seed_score(&url);
+                    url_queue.push(store, url, score)?;
+                    stale += 1;
+                } else {
+                    fresh_pages.push(url);
+                }
+            }
+            info!("Refreshing {} stale pages", stale);
+        }
+        if builder.recrawl {
+            let mut stmt = db_connection.prepare("SELECT Url FROM Page ORDER BY Id DESC")?;
+            let stored = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(stmt);
+            info!("Recrawling {} stored pages", stored.len());
+            for url in stored {
+                let score = seed_score(&url);
+                url_queue.push(store, url, score)?;
+            }
+        }
+        // The frontier pops the URL pushed last, so push the seeds in reverse to crawl them in order
+        let normalizer =
+            UrlNormalizer::new(builder.trailing_slash).query_params(builder.query_params.clone());
+        let mut seeds = builder.seeds.iter().rev().collect::<Vec<_>>();
+        if builder.scheduling == Scheduling::RoundRobin {
+            // Hosts take turns in the order they were first queued, so queue the hosts in the order of their first seed
+            let host = |seed: &str| Url::parse(seed).ok()?.host_str().map(str::to_string);
+            let mut first_seed = HashMap::new();
+            for (index, seed) in builder.seeds.iter().enumerate() {
+                first_seed.entry(host(seed)).or_insert(index);
+            }
+            seeds.sort_by_key(|seed| first_seed[&host(seed)]);
+        }
+        for seed in seeds {
+            let seed = Url::parse(seed).map_or_else(
+                |_| seed.clone(),
+                |seed| normalizer.normalize(&seed).to_string(),
+            );
+            let score = seed_score(&seed);
+            url_queue.push(store, seed, score)?;
+        }
 
-        let mut url_queue = UniqueQueue::new();
-        url_queue.push(start_url.to_string());
+        let mut store = PageStore::new(
+            builder.paths.save_dir.clone(),
+            builder.storage_format,
+            run_id,
+            writer.clone(),
+        );
+        store.set_routes(builder.storage_routes);
+        store.set_body_store(builder.body_store.clone());
+        let assets = (!builder.asset_extensions.is_empty()).then(|| {
+            AssetStore::new(
+                builder.paths.assets_dir.clone(),
+                &builder.asset_extensions,
+                writer.clone(),
+            )
+        });
+        let images = builder.image_limits.clone().map(|limits| {
+            ImageStore::new(builder.paths.images_dir.clone(), limits, writer.clone())
+        });
+        // Requests and reachability checks are guarded, but not the webhook, whose URL is trusted
+        let resolver = builder
+            .resolver
+            .clone()
+            .block_private_networks(!builder.allow_private_networks);
+        let client = resolver
+            .configure(client)
+            .user_agent(&builder.user_agent)
+            .redirect(Policy::none())
+            .timeout(builder.request_timeout)
+            .connect_timeout(builder.connect_timeout)
+            .build()?;
+        // Reprocessing makes no requests at all
+        let event_webhook = builder.event_webhook.filter(|_| !builder.reprocess);
+        let (webhook, webhook_handle) = match event_webhook {
+            Some(url) => {
+                let client = builder
+                    .tls
+                    .configure(builder.resolver.configure(Client::builder()))?
+                    .user_agent(&builder.user_agent)
+                    .timeout(builder.request_timeout)
+                    .build()?;
+                let (webhook, handle) = EventWebhook::start(client, url, run_id);
+                (Some(webhook), Some(handle))
+            }
+            None => (None, None),
+        };
+        let domain_pages = builder
+            .max_pages_per_domain
+            .map(|_| count_domain_pages(&db_connection))
+            .transpose()?;
 
-        Crawler {
-            user_agent: user_agent.to_string(),
+        let mut crawler = Crawler {
+            user_agent: builder.user_agent,
             db_connection,
+            shared,
             url_queue,
-            hasher: Hasher::new(),
-            ignore_robots: ignore_robots.unwrap_or(false),
+            scorer,
+            visited: VisitedSet::new(builder.visited_filter)?,
+            depths: HashMap::new(),
+            last_requests: HashMap::new(),
+            throttles: HashMap::new(),
+            throttle_retries: HashMap::new(),
+            requeued_urls: HashSet::new(),
+            parked: HashMap::new(),
+            shutdown: builder.shutdown.clone(),
+            shutdown_grace: builder.shutdown_grace,
+            host_checks: HashMap::new(),
+            resolver,
+            memory: MemoryUsage::default(),
+            peak_memory: 0,
+            memory_cap: builder.memory_cap,
+            batch_memory: 0,
+            batch_concurrency: builder.concurrency,
+            redirects,
+            store,
+            assets,
+            images,
+            image_queue: VecDeque::new(),
+            renderer,
+            events: EventLog::new(
+                builder.paths.events_file.clone(),
+                run_id,
+                writer,
+                builder.progress,
+            ),
+            writer_handle,
+            webhook,
+            webhook_handle,
+            fetcher: Fetcher::new(client, builder.fetch_mode)?
+                .block_private_networks(!builder.allow_private_networks)
+                .credentials(builder.credentials),
+            paths: builder.paths.clone(),
+            hash_algorithm: builder.hash_algorithm,
+            url_filter,
+            ignore_robots: builder.ignore_robots,
+            revalidate: builder.revalidate || builder.recrawl || builder.refresh.is_some(),
+            reprocess: builder.reprocess,
+            follow_canonical: builder.follow_canonical,
+            skip_canonical_variants: builder.skip_canonical_variants,
+            date_limits: builder.date_limits,
+            date_counts: HashMap::new(),
+            max_depth: if builder.recrawl {
+                Some(0)
+            } else {
+                builder.max_depth
+            },
+            max_pages_per_domain: builder.max_pages_per_domain,
+            domain_pages,
+            max_pages: builder.max_pages,
+            stored_pages: 0,
+            dry_run: builder.dry_run.then(DryRunTotals::default),
+            changed_pages: 0,
+            min_crawl_delay: builder.min_crawl_delay,
+            domain_crawl_delays: builder.domain_crawl_delays,
+            concurrency: builder.concurrency,
+            domain_concurrency: builder.domain_concurrency,
+            domain_concurrency_overrides: builder.domain_concurrency_overrides,
+            ban_policy: builder.ban_policy,
+            error_budget: builder.error_budget,
+            domain_errors: HashMap::new(),
+            tagger: builder.page_tagger,
+            normalizer,
+            link_elements: builder.link_elements,
+            languages: builder.languages,
+            geoip: builder.geoip,
+            handlers: builder.page_handlers,
+            run_id,
+        };
+        // Fresh pages count as crawled, so that links do not lead back to them while stale pages are revalidated
+        for url in fresh_pages {
+            crawler.mark_visited(url)?;
+        }
+        if crawler.shared.is_none() && !crawler.revalidate {
+            // The stored pages of other workers are not known in advance, so only a local database is covered
+            let mut stmt = crawler
+                .db_connection
+                .prepare("SELECT Url FROM Page WHERE DryRun = 0 UNION ALL SELECT Url FROM Asset")?;
+            let stored = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            crawler.visited.add_stored(stored.filter_map(Result::ok));
+        }
+        if crawler.images.is_some() {
+            crawler.queue_pending_images()?;
+        }
+        Ok(crawler)
+    }
+
+    /// Queues the images shown on stored pages that were neither downloaded nor rejected, e.g. because the previous
+    /// run stopped before their turn. The depth they were found at is not recorded, so they are queued as found on
+    /// the start page.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn queue_pending_images(&mut self) -> Result<(), CrawlerError> {
+        let urls = self
+            .db_connection
+            .prepare(
+                "SELECT Url FROM PageImage
+                WHERE ImageId IS NULL AND (Url LIKE 'http://%' OR Url LIKE 'https://%')
+                    AND Url NOT IN (SELECT Url FROM Image) AND Url NOT IN (SELECT Url FROM FailedUrl)
+                GROUP BY Url ORDER BY MIN(rowid)",
+            )?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for url in urls {
+            if !self.is_image_rejected(&url)? {
+                self.image_queue.push_back((url, 1));
+            }
         }
+        if !self.image_queue.is_empty() {
+            info!(
+                "Queued {} images of stored pages that were not downloaded yet",
+                self.image_queue.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the date directory of a URL if it is limited, and how many URLs of it have been crawled.
+    fn date_limit(&self, url: &Url) -> Option<(String, usize, usize)> {
+        let limit = self.date_limits.limit_for(url.host_str()?)?;
+        let directory = date_directory(url)?;
+        let count = self.date_counts.get(&directory).copied().unwrap_or(0);
+        Some((directory, count, limit))
+    }
+
+    /// Queues a URL, remembering the smallest depth it was found at, for the depth limit and frontier statistics.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `depth` - How many links away from the start URL it was found.
+    /// * `source` - The page it was found on.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn queue_url(&mut self, url: String, depth: u32, source: &Url) -> Result<(), CrawlerError> {
+        self.depths
+            .entry(url.clone())
+            .and_modify(|known| *known = (*known).min(depth))
+            .or_insert(depth);
+        let sitemap_priority = self
+            .db_connection
+            .prepare_cached("SELECT Priority FROM SitemapUrl WHERE Url = ?")?
+            .query_row([&url], |row| row.get(0))
+            .optional()?;
+        let context = ScoreContext {
+            depth,
+            source: Some(source),
+            sitemap_priority,
+        };
+        let score = Url::parse(&url).map_or(0.0, |url| self.scorer.score(&url, &context));
+        self.url_queue
+            .push(crawl_store(&self.db_connection, &self.shared), url, score)
     }
 
     /// Fetches the domain id from the database.
@@ -53,8 +750,10 @@ impl Crawler {
     ///
     /// # Returns
     /// The id of the domain entity.
-    fn get_domain_id(&self, url: &Url) -> Result<i64, Box<dyn std::error::Error>> {
-        let domain_name = url.domain().ok_or("Invalid URL")?;
+    fn get_domain_id(&self, url: &Url) -> Result<i64, CrawlerError> {
+        let domain_name = url
+            .host_str()
+            .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
         let id: i64 = self.db_connection.query_row(
             "SELECT Id FROM Domain WHERE Name = ?",
             [domain_name],
@@ -63,13 +762,173 @@ impl Crawler {
         Ok(id)
     }
 
+    /// Fetches the domain id from the database, if the domain has been recorded.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    ///
+    /// # Returns
+    /// The id of the domain entity, or None if the domain is not known.
+    fn find_domain_id(&self, url: &Url) -> Result<Option<i64>, CrawlerError> {
+        let Some(domain_name) = url.host_str() else {
+            return Ok(None);
+        };
+        let id = self
+            .db_connection
+            .prepare_cached("SELECT Id FROM Domain WHERE Name = ?")?
+            .query_row([domain_name], |row| row.get(0))
+            .optional()?;
+        Ok(id)
+    }
+
+    /// Fetches the cache validators stored for a previously crawled page.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    ///
+    /// # Returns
+    /// The validators of the page, or None if the page has not been crawled before.
+    fn get_page_validators(&self, url: &Url) -> Result<Option<PageValidators>, CrawlerError> {
+        let validators = self
+            .db_connection
+            .query_row(
+                "SELECT Id, ETag, LastModified FROM Page WHERE Url = ?",
+                [url.as_str()],
+                |row| {
+                    Ok(PageValidators {
+                        page_id: row.get(0)?,
+                        etag: row.get(1)?,
+                        last_modified: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(validators)
+    }
+
+    /// Checks if the domain of a URL is currently banned.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to check.
+    ///
+    /// # Returns
+    /// `true` if the domain's quarantine period has not ended yet.
+    fn is_domain_banned(&self, url: &Url) -> Result<bool, CrawlerError> {
+        let domain_name = url
+            .host_str()
+            .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
+        let banned = self
+            .db_connection
+            .prepare_cached("SELECT BannedUntil > CURRENT_TIMESTAMP FROM Domain WHERE Name = ?")?
+            .query_row([domain_name], |row| row.get::<_, Option<bool>>(0))
+            .optional()?;
+        Ok(banned.flatten().unwrap_or(false))
+    }
+
+    /// Checks if the domain of a URL has been suspended during this run, see [`Crawler::record_domain_error`].
+    ///
+    /// # Arguments
+    /// * `url` - The URL to check.
+    ///
+    /// # Returns
+    /// `true` if the domain is suspended.
+    fn is_domain_suspended(&self, url: &Url) -> Result<bool, CrawlerError> {
+        let domain_name = url
+            .host_str()
+            .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
+        let suspended = self
+            .db_connection
+            .prepare_cached("SELECT SuspendedRunId = ? FROM Domain WHERE Name = ?")?
+            .query_row(params![self.run_id, domain_name], |row| {
+                row.get::<_, Option<bool>>(0)
+            })
+            .optional()?;
+        Ok(suspended.flatten().unwrap_or(false))
+    }
+
+    /// Checks if a page has already been crawled.
+    ///
+    /// Pages visited during this run are crawled. Unless revalidating, so are pages and downloaded assets that are
+    /// already in the database, as assets have no `Page` row of their own. With a Bloom filter of visited URLs, which
+    /// also holds the stored pages and assets, the database is only looked up for URLs the filter reports as seen.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    ///
+    /// # Returns
+    /// `true` if the page should not be fetched again.
+    fn is_page_crawled(&self, url: &str) -> Result<bool, CrawlerError> {
+        if self.visited.contains(url)? {
+            return Ok(true);
+        }
+        if self.revalidate || !self.visited.may_be_stored(url) {
+            return Ok(false);
+        }
+        if crawl_store(&self.db_connection, &self.shared).is_page_stored(url)? {
+            return Ok(true);
+        }
+        let asset = self
+            .db_connection
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM Asset WHERE Url = ?)")?
+            .query_row([url], |row| row.get(0))?;
+        Ok(asset)
+    }
+
+    /// Checks whether a URL is known to be a variant of another, already crawled, canonical page.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to check.
+    ///
+    /// # Returns
+    /// `true` if a previous fetch of the URL declared a different canonical URL that has been crawled.
+    fn is_canonical_crawled(&self, url: &str) -> Result<bool, CrawlerError> {
+        let covered = self.db_connection.query_row(
+            "SELECT EXISTS (
+                SELECT 1 FROM Page Variant
+                JOIN PageMeta ON PageMeta.PageId = Variant.Id
+                JOIN Page Canonical ON Canonical.Url = PageMeta.Canonical
+                WHERE Variant.Url = ? AND Canonical.Id != Variant.Id
+            )",
+            [url],
+            |row| row.get(0),
+        )?;
+        Ok(covered)
+    }
+
+    /// Returns whether a domain has as many stored pages as allowed, by any run, and a URL of it is not one of them.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    fn is_page_cap_reached(&self, url: &Url) -> Result<bool, CrawlerError> {
+        let (Some(cap), Some(counts), Some(domain)) = (
+            self.max_pages_per_domain,
+            &self.domain_pages,
+            url.host_str(),
+        ) else {
+            return Ok(false);
+        };
+        if counts.get(domain).copied().unwrap_or(0) < cap {
+            return Ok(false);
+        }
+        let known = self
+            .db_connection
+            .query_row("SELECT 1 FROM Page WHERE Url = ?", [url.as_str()], |_| {
+                Ok(())
+            })
+            .optional()?
+            .is_some();
+        Ok(!known)
+    }
+
     /// Checks if the URL is crawlable based on the robots.txt rules and if it has already been crawled.
     ///
-    /// URLs that are already in the database are not crawlable.
+    /// URLs that have already been crawled, or are known to redirect to a crawled page, are not crawlable. Neither
+    /// are URLs on banned domains or domains with the maximum number of stored pages, or URLs left out by the include
+    /// and exclude patterns.
     ///
     /// # Arguments
     /// * `url` - The URL to check.
-    /// * `domain_id` - The id of the domain entity.
+    /// * `domain_id` - The id of the domain entity, looked up if None.
     ///
     /// # Returns
     /// A tuple containing a boolean indicating if the URL is crawlable and an optional reason why it is not.
@@ -77,44 +936,85 @@ impl Crawler {
         &self,
         url: &Url,
         domain_id: Option<i64>,
-    ) -> Result<(bool, Option<&str>), Box<dyn std::error::Error>> {
-        let exists = self.db_connection.query_row(
-            "SELECT COUNT(*) FROM Page WHERE Url = ?",
-            [url.as_str()],
-            |row| row.get::<_, i32>(0),
-        )? > 0;
-        if exists {
+    ) -> Result<(bool, Option<&str>), CrawlerError> {
+        if !self.url_filter.allows(url.as_str()) {
+            return Ok((false, Some("Excluded by the URL patterns")));
+        }
+        if self.is_page_crawled(url.as_str())? {
             return Ok((false, Some("Already crawled")));
         }
+        let target = self.redirects.resolve(url.as_str());
+        if target != url.as_str() && self.is_page_crawled(target)? {
+            return Ok((false, Some("Redirects to an already crawled page")));
+        }
+        if let Some((_, count, limit)) = self.date_limit(url) {
+            if count >= limit {
+                return Ok((false, Some("Too many date-pattern URLs in its directory")));
+            }
+        }
+        if self.skip_canonical_variants && self.is_canonical_crawled(url.as_str())? {
+            return Ok((false, Some("Variant of an already crawled canonical page")));
+        }
+
+        if self.is_domain_banned(url)? {
+            return Ok((false, Some("Domain is banned")));
+        }
+        if self.is_domain_suspended(url)? {
+            return Ok((false, Some("Domain is suspended after too many errors")));
+        }
+        if self.is_page_cap_reached(url)? {
+            return Ok((false, Some(PAGE_CAP_REACHED)));
+        }
 
         if self.ignore_robots {
             return Ok((true, None));
         }
 
-        // Check if the URL is crawlable based on robots.txt rules
+        // Check if the URL is crawlable based on robots.txt rules. The rules of a domain not seen yet are unknown, and
+        // its URLs are checked again when they are dequeued, after its robots.txt file has been fetched.
         let domain_id = match domain_id {
             Some(id) => id,
-            None => self.get_domain_id(url)?,
+            None => match self.find_domain_id(url)? {
+                Some(id) => id,
+                None => return Ok((true, None)),
+            },
         };
-        let mut stmt = self
-            .db_connection
-            .prepare("SELECT Pattern FROM DisallowedPattern WHERE DomainId = ?")?;
-        let disallowed_patterns = stmt
-            .query_map([domain_id], |row| row.get::<_, String>(0))?
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-
-        // Check URL path against disallowed patterns
-        let path = url.path();
-        for pattern in disallowed_patterns {
-            if path.starts_with(&pattern) || pattern == "*" {
-                return Ok((false, Some("Disallowed by robots.txt")));
-            }
+        if is_disallowed(
+            &disallowed_patterns(&self.db_connection, domain_id)?,
+            url.path(),
+        ) {
+            return Ok((false, Some(ROBOTS_DISALLOWED)));
         }
         Ok((true, None))
     }
 
-    /// Resolves the href attribute of an anchor tag and returns a Url object.
+    /// Checks if the URL is crawlable, like [`Crawler::is_url_crawlable`], as a `Result`.
+    ///
+    /// # Returns
+    /// `CrawlerError::Policy` with the reason if the URL is not crawlable, e.g. [`ROBOTS_DISALLOWED`].
+    fn check_crawlable(&self, url: &Url, domain_id: Option<i64>) -> Result<(), CrawlerError> {
+        match self.is_url_crawlable(url, domain_id)? {
+            (true, _) => Ok(()),
+            (false, reason) => Err(CrawlerError::Policy(reason.unwrap_or_default().to_string())),
+        }
+    }
+
+    /// Checks whether a URL would be crawled, fetching the robots.txt file of its domain first if needed.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    ///
+    /// # Returns
+    /// `CrawlerError::Policy` with the reason if the URL is refused, e.g. by robots.txt or the URL patterns, or
+    /// another error if the check failed.
+    pub async fn check_url(&mut self, url: &str) -> Result<(), CrawlerError> {
+        let url = self.normalizer.normalize(&Url::parse(url)?);
+        let domain_id = self.record_domain(&url)?;
+        self.record_robots_txt(&url, Some(domain_id)).await?;
+        self.check_crawlable(&url, Some(domain_id))
+    }
+
+    /// Resolves the href attribute of an anchor tag and returns it in normalized form.
     ///
     /// # Arguments
     /// * `href` - The href attribute value.
@@ -123,21 +1023,7 @@ impl Crawler {
     /// # Returns
     /// An Option containing the resolved URL if successful, None otherwise.
     fn parse_href(&self, href: &str, base_url: &Url) -> Option<Url> {
-        let mut new_url: Url;
-        if let Ok(parsed_url) = Url::parse(href) {
-            new_url = parsed_url;
-        } else if href.starts_with("//") {
-            let scheme = base_url.scheme();
-            new_url = Url::parse(&format!("{}:{}", scheme, href)).ok()?;
-        } else if href.starts_with('/') {
-            new_url = base_url.clone();
-            new_url.set_path(href);
-        } else {
-            new_url = base_url.clone();
-        }
-        new_url.set_query(None);
-        new_url.set_fragment(None);
-        Some(new_url)
+        resolve_href(href, base_url).map(|url| self.normalizer.normalize(&url))
     }
 
     /// Records the url domain in the database, and returns the domain id.
@@ -147,225 +1033,4056 @@ impl Crawler {
     ///
     /// # Returns
     /// The id of the created domain entity.
-    fn record_domain(&self, url: &Url) -> Result<i64, Box<dyn std::error::Error>> {
-        let domain_name = url.domain().ok_or("Invalid URL")?;
-        self.db_connection.execute(
+    fn record_domain(&self, url: &Url) -> Result<i64, CrawlerError> {
+        let domain_name = url
+            .host_str()
+            .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
+        let inserted = self.db_connection.execute(
             "INSERT OR IGNORE INTO Domain (Name) VALUES (?)",
             [domain_name],
-        )?;
+        )? > 0;
         let id: i64 = self.db_connection.query_row(
             "SELECT Id FROM Domain WHERE Name = ?",
             [domain_name],
             |row| row.get(0),
         )?;
+        if inserted {
+            self.send_webhook_event(WebhookEvent::DomainDiscovered {
+                domain: domain_name.to_string(),
+                domain_id: id,
+            });
+        }
         Ok(id)
     }
 
-    /// Parses a html page and records the links found in the database.
+    /// Parses a html page and records the links found in the database, replacing those of a previous fetch.
+    ///
+    /// Relative links are resolved against the `<base href>` of the page if it declares one. Every link is recorded
+    /// with its anchor text and the element it was found in, but only crawlable links to pages are queued. Links
+    /// marked `rel="nofollow"` are not queued, and none of the links of a page marked `nofollow` are, unless robots
+    /// rules are ignored. The number of links held back by the page-level directive is recorded with the page. Links
+    /// of pages at the maximum depth, or not to be queued, e.g. because the page is in a language that is not crawled,
+    /// are recorded but not queued.
     ///
     /// # Arguments
     /// * `url` - The URL of the page.
-    /// * `body` - The contents of the page.
+    /// * `document` - The parsed contents of the page.
     /// * `page_id` - The id of the page entity.
     /// * `domain_id` - The id of the domain entity.
+    /// * `directives` - The robots directives of the page that are followed.
+    /// * `depth` - How many links away from the start URL the page was found.
+    /// * `queue_links` - Whether the crawlable links are queued.
     ///
     /// # Returns
-    /// A Result indicating success or failure.
+    /// The URLs of the links on the page, in document order.
+    #[allow(clippy::too_many_arguments)]
     fn record_page_links(
         &mut self,
         url: &Url,
-        body: &str,
+        document: &Html,
         page_id: i64,
         domain_id: Option<i64>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        directives: RobotsDirectives,
+        depth: u32,
+        queue_links: bool,
+    ) -> Result<Vec<Url>, CrawlerError> {
         // Fetch the id here, before iteration
         let domain_id = match domain_id {
             Some(id) => id,
             None => self.get_domain_id(url)?,
         };
 
-        let document = Html::parse_document(body);
-        let selector = Selector::parse("a")?;
-        let urls: Vec<String> = document
-            .select(&selector)
-            .filter_map(|element| element.value().attr("href"))
-            .filter_map(|href| self.parse_href(href, url))
-            .filter(|url| {
-                self.is_url_crawlable(url, Some(domain_id))
-                    .unwrap_or((false, None))
-                    .0
-            })
-            .map(|url| url.to_string())
+        self.db_connection
+            .execute("DELETE FROM PageLink WHERE PageId = ?", [page_id])?;
+        self.db_connection
+            .execute("DELETE FROM LinkAnchor WHERE PageId = ?", [page_id])?;
+
+        let base = base_url(document, url);
+        let links: Vec<(Url, FoundLink)> = extract_links(document, &self.link_elements)
+            .into_iter()
+            .filter_map(|link| Some((self.parse_href(link.href, &base)?, link)))
             .collect();
 
-        info!("Found {} links on page {}", urls.len(), url);
+        // Only links to pages are shared, as workers queue the shared links that were never crawled
+        let navigable: Vec<(Url, &FoundLink)> = links
+            .iter()
+            .filter(|(_, link)| link.element.is_navigable())
+            .map(|(link_url, link)| (link_url.clone(), link))
+            .collect();
+        crawl_store(&self.db_connection, &self.shared).save_links(url.as_str(), &navigable)?;
 
-        for url in urls {
-            self.url_queue.push(url.clone());
-            self.db_connection.execute(
-                "INSERT OR IGNORE INTO PageLink (PageId, Url) VALUES (?, ?)",
-                [page_id.to_string(), url.clone()],
-            )?;
+        let at_max_depth =
+            self.max_depth.is_some_and(|max_depth| depth >= max_depth) || !queue_links;
+        let (mut queued, mut suppressed) = (0, 0);
+        for (link_url, link) in &links {
+            let external = !is_same_site(link_url, url);
+            // A page is linked to once: by its first link to it from an element that leads to pages, else by its first
+            // reference, so that an image shown before a link to its URL does not hide the link
+            self.db_connection
+                .prepare_cached(&format!(
+                    "INSERT INTO PageLink (PageId, Url, AnchorText, Nofollow, Element, Rel, Section, External)
+                    VALUES (?, ?, NULLIF(?, ''), ?, ?, NULLIF(?, ''), ?, ?)
+                    ON CONFLICT (PageId, Url) DO UPDATE SET
+                        AnchorText = excluded.AnchorText,
+                        Nofollow = excluded.Nofollow,
+                        Element = excluded.Element,
+                        Rel = excluded.Rel,
+                        Section = excluded.Section
+                    WHERE {} AND NOT {}",
+                    LinkElement::navigable_sql("excluded.Element"),
+                    LinkElement::navigable_sql("PageLink.Element")
+                ))?
+                .execute(params![
+                    page_id,
+                    link_url.as_str(),
+                    link.anchor_text,
+                    link.nofollow,
+                    link.element.name(),
+                    link.rel,
+                    link.section.name(),
+                    external
+                ])?;
+            if !link.anchor_text.is_empty() {
+                self.db_connection
+                    .prepare_cached("INSERT INTO LinkAnchor (PageId, Url, Text) VALUES (?, ?, ?)")?
+                    .execute(params![page_id, link_url.as_str(), link.anchor_text])?;
+            }
+
+            if !link.element.is_navigable() {
+                continue;
+            }
+            if directives.nofollow {
+                suppressed += 1;
+                continue;
+            }
+            if at_max_depth || (link.nofollow && !self.ignore_robots) {
+                continue;
+            }
+            // Links to other domains are checked against the robots.txt rules of their own domain
+            let link_domain_id = match link_url.host_str() == url.host_str() {
+                true => Some(domain_id),
+                false => self.find_domain_id(link_url)?,
+            };
+            let (crawlable, reason) = self
+                .is_url_crawlable(link_url, link_domain_id)
+                .unwrap_or((false, None));
+            if let (Some(ROBOTS_DISALLOWED), Some(link_domain_id)) = (reason, link_domain_id) {
+                self.record_robots_blocked(link_url, link_domain_id)?;
+            }
+            if reason == Some(PAGE_CAP_REACHED) {
+                info!(url = %link_url, reason, "URL {} is not queued: {}", link_url, PAGE_CAP_REACHED);
+            }
+            if crawlable {
+                // Queue the final target of known redirects, so the frontier does not hold several aliases of one page
+                let target = self.redirects.resolve(link_url.as_str()).to_string();
+                self.queue_url(target, depth + 1, url)?;
+                queued += 1;
+            }
         }
-        Ok(())
+
+        self.db_connection.execute(
+            "UPDATE Page SET SuppressedLinks = ? WHERE Id = ?",
+            params![suppressed, page_id],
+        )?;
+
+        if directives.nofollow {
+            info!(
+                "Found {} links on page {}, none queued as the page is marked nofollow",
+                links.len(),
+                display_url(url)
+            );
+        } else {
+            info!(
+                url = %url,
+                links = links.len(),
+                queued,
+                "Found {} links on page {}, {} queued",
+                links.len(),
+                display_url(url),
+                queued
+            );
+        }
+        Ok(links.into_iter().map(|(link_url, ..)| link_url).collect())
     }
 
-    /// Records the page contents in the database and saves it to a file.
+    /// Records how long a URL waited in the frontier before it was taken to be crawled.
     ///
     /// # Arguments
-    /// * `url` - The URL of the page.
-    /// * `body` - The contents of the page.
+    /// * `request` - The request prepared for the URL.
+    /// * `queued` - When the URL was queued.
+    ///
     /// # Returns
-    /// The id of the created page entity.
-    fn record_page_contents(
-        &mut self,
-        url: &Url,
-        body: &str,
-    ) -> Result<i64, Box<dyn std::error::Error>> {
-        self.hasher.reset();
-        self.hasher.update(body.as_bytes());
-        let hash = encode(self.hasher.finalize().as_bytes());
-        let filename = format!("{}.html", hash);
-        let filepath = format!("{}/{}", SAVE_DIR, filename);
-        fs::write(filepath, body)?;
+    /// A Result indicating success or failure.
+    fn record_frontier_wait(
+        &self,
+        request: &PageRequest,
+        queued: SystemTime,
+    ) -> Result<(), CrawlerError> {
+        let wait = SystemTime::now().duration_since(queued).unwrap_or_default();
         self.db_connection.execute(
-            "INSERT INTO Page (Url, Hash) VALUES (?, ?)",
-            &[url.as_str(), &hash],
+            "INSERT INTO FrontierWait (RunId, DomainId, Url, Depth, WaitMs) VALUES (?, ?, ?, ?, ?)",
+            params![
+                self.run_id,
+                request.domain_id,
+                request.url.as_str(),
+                request.depth,
+                wait.as_millis() as i64
+            ],
         )?;
-        let page_id = self.db_connection.last_insert_rowid();
-        Ok(page_id)
+        Ok(())
     }
 
-    /// Fetches the robots.txt file for an existing domain in the database and records the disallowed patterns.
+    /// Records that robots.txt disallows crawling a URL, once per URL.
     ///
-    /// Will return if the robots.txt file is not found.
+    /// # Arguments
+    /// * `url` - The disallowed URL.
+    /// * `domain_id` - The id of the domain entity whose rules disallow the URL.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_robots_blocked(&self, url: &Url, domain_id: i64) -> Result<(), CrawlerError> {
+        self.db_connection.execute(
+            "INSERT OR IGNORE INTO RobotsBlockedUrl (DomainId, Url, RunId) VALUES (?, ?, ?)",
+            params![domain_id, url.as_str(), self.run_id],
+        )?;
+        Ok(())
+    }
+
+    /// Records the word count, text to HTML ratio and reading time of a page.
     ///
     /// # Arguments
-    /// * `url` - The URL of the page.
-    /// * `domain_id` - The id of the domain entity.
+    /// * `document` - The parsed contents of the page.
+    /// * `html_size` - The size of the raw HTML in bytes.
+    /// * `page_id` - The id of the page entity.
     ///
     /// # Returns
     /// A Result indicating success or failure.
-    async fn record_robots_txt(
+    fn record_content_stats(
         &self,
-        url: &Url,
-        domain_id: Option<i64>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let domain_id = match domain_id {
-            Some(id) => id,
-            None => self.get_domain_id(url)?,
-        };
+        document: &Html,
+        html_size: usize,
+        page_id: i64,
+    ) -> Result<(), CrawlerError> {
+        let text = visible_text(document);
+        let stats = ContentStats::from_text(&text, html_size);
+        self.db_connection.execute(
+            "UPDATE Page SET WordCount = ?, TextRatio = ?, ReadingTime = ? WHERE Id = ?",
+            params![
+                stats.word_count,
+                stats.text_ratio,
+                stats.reading_time,
+                page_id
+            ],
+        )?;
+        Ok(())
+    }
 
-        // Fetch the robots.txt file
-        let domain_name = url.domain().ok_or("Invalid URL")?;
-        let robots_url = format!("{}://{}/robots.txt", url.scheme(), domain_name);
-        let response = reqwest::get(&robots_url).await?;
+    /// Records the title, description, canonical URL and Open Graph properties of a page.
+    ///
+    /// # Arguments
+    /// * `meta` - The metadata of the page.
+    /// * `page_id` - The id of the page entity.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_page_meta(&self, meta: &PageMeta, page_id: i64) -> Result<(), CrawlerError> {
+        self.db_connection.execute(
+            "INSERT OR REPLACE INTO PageMeta (PageId, Title, Description, Canonical, OpenGraph, Language)
+            VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                page_id,
+                meta.title,
+                meta.description,
+                meta.canonical.as_ref().map(Url::as_str),
+                serde_json::to_string(&meta.open_graph)?,
+                meta.language
+            ],
+        )?;
+        Ok(())
+    }
 
-        // Return if the robots.txt file is not found
-        let status = response.status();
-        if !status.is_success() {
-            info!("No robots.txt found for {}", domain_name);
+    /// Records the plain text rendition of a page, and indexes it for search and by its most frequent terms.
+    ///
+    /// Duplicate pages are skipped, as their text is stored for the page they duplicate, and so are pages whose
+    /// contents are not saved.
+    ///
+    /// # Arguments
+    /// * `text` - The plain text of the page.
+    /// * `language` - The ISO 639-1 code of the language of the page.
+    /// * `page_id` - The id of the page entity.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_page_text(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        page_id: i64,
+    ) -> Result<(), CrawlerError> {
+        self.db_connection
+            .execute("DELETE FROM PageText WHERE PageId = ?", [page_id])?;
+        self.db_connection
+            .execute("DELETE FROM PageSearch WHERE PageId = ?", [page_id])?;
+        self.db_connection.execute(
+            "INSERT INTO PageText (PageId, Text)
+            SELECT Id, ? FROM Page WHERE Id = ? AND DuplicateOf IS NULL AND Indexed = 1",
+            params![text, page_id],
+        )?;
+        self.db_connection.execute(
+            "INSERT INTO PageSearch (PageId, Url, Text)
+            SELECT Id, Url, ? FROM Page WHERE Id = ? AND DuplicateOf IS NULL AND Indexed = 1",
+            params![text, page_id],
+        )?;
+        terms::record_terms(&self.db_connection, page_id, text, language)?;
+        Ok(())
+    }
+
+    /// Records the tags the tag rules assign to a page, replacing those of a previous fetch.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    /// * `document` - The parsed contents of the page.
+    /// * `text` - The plain text of the page.
+    /// * `page_id` - The id of the page entity.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_page_tags(
+        &self,
+        url: &Url,
+        document: &Html,
+        text: &str,
+        page_id: i64,
+    ) -> Result<(), CrawlerError> {
+        if self.tagger.is_empty() {
             return Ok(());
         }
+        self.db_connection
+            .execute("DELETE FROM PageTag WHERE PageId = ?", [page_id])?;
+        for tag in self.tagger.tags(url, document, text) {
+            self.db_connection.execute(
+                "INSERT INTO PageTag (PageId, Tag) VALUES (?, ?)",
+                params![page_id, tag],
+            )?;
+        }
+        Ok(())
+    }
 
-        // Parse the robots.txt file
-        let robots_txt = response.text().await?;
-
-        // Split the file into "user-agent" sections
-        let user_agent_regex = Regex::new(r"(?i)User-agent:\s*(\S+*)")?;
-        let disallowed_regex = Regex::new(DISALLOWED_ROBOTS_REGEX)?;
-        let mut user_agent_matches = user_agent_regex
-            .find_iter(&robots_txt)
-            .map(|m| m.start())
+    /// Queues the links recorded for a page by a previous fetch, unless the page is at the maximum depth. Links marked
+    /// nofollow, by themselves or by the page, are not queued unless robots rules are ignored.
+    ///
+    /// # Arguments
+    /// * `page_id` - The id of the page entity.
+    /// * `url` - The URL of the page.
+    /// * `depth` - How many links away from the start URL the page was found.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn queue_recorded_links(
+        &mut self,
+        page_id: i64,
+        url: &Url,
+        depth: u32,
+    ) -> Result<(), CrawlerError> {
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Ok(());
+        }
+        let mut stmt = self.db_connection.prepare(&format!(
+            "SELECT PageLink.Url FROM PageLink JOIN Page ON Page.Id = PageLink.PageId
+            WHERE PageLink.PageId = ?1 AND {}
+                AND (?2 OR (Page.NoFollow = 0 AND PageLink.Nofollow = 0))",
+            LinkElement::navigable_sql("PageLink.Element")
+        ))?;
+        let links = stmt
+            .query_map(params![page_id, self.ignore_robots], |row| {
+                row.get::<_, String>(0)
+            })?
+            .filter_map(Result::ok)
             .collect::<Vec<_>>();
-        user_agent_matches.push(robots_txt.len());
-
-        // Iterate over the user-agent sections and record disallowed patterns if the user-agent matches
-        for (first_match, last_match) in user_agent_matches.iter().tuple_windows() {
-            let section = &robots_txt[*first_match..*last_match];
-            let user_agent = user_agent_regex
-                .captures(section)
-                .and_then(|cap| cap.get(1))
-                .map(|m| m.as_str())
-                .unwrap_or("");
-
-            if user_agent != "*" && user_agent != self.user_agent {
-                continue;
+        drop(stmt);
+
+        for link in links {
+            if !self.visited.contains(&link)? {
+                self.queue_url(link, depth + 1, url)?;
             }
+        }
+        Ok(())
+    }
 
-            // Record disallowed patterns
-            for disallowed in disallowed_regex.captures_iter(section) {
-                if let Some(disallowed_pattern) = disallowed.get(1) {
-                    self.db_connection.execute(
-                        "INSERT OR IGNORE INTO DisallowedPattern (DomainId, Pattern) VALUES (?, ?)",
-                        &[
-                            &domain_id.to_string().as_str(),
-                            &disallowed_pattern.as_str(),
-                        ],
-                    )?;
-                }
+    /// Records a redirect chain, in memory and in the database.
+    ///
+    /// Every URL of the chain is recorded as redirecting to the final target, and each hop is stored with its status.
+    ///
+    /// # Arguments
+    /// * `hops` - The redirected URLs in the order they were requested, with their response status.
+    /// * `target` - The URL the chain ended at.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_redirect_chain(
+        &mut self,
+        hops: &[(Url, StatusCode)],
+        target: &Url,
+    ) -> Result<(), CrawlerError> {
+        let Some((source, _)) = hops.first() else {
+            return Ok(());
+        };
+        self.db_connection.execute(
+            "DELETE FROM RedirectHop WHERE SourceUrl = ?",
+            [source.as_str()],
+        )?;
+        for (position, (hop_url, status)) in hops.iter().enumerate() {
+            self.db_connection.execute(
+                "INSERT INTO RedirectHop (SourceUrl, Position, Url, Status) VALUES (?, ?, ?, ?)",
+                params![source.as_str(), position, hop_url.as_str(), status.as_u16()],
+            )?;
+            self.db_connection.execute(
+                "INSERT OR REPLACE INTO Redirect (SourceUrl, TargetUrl) VALUES (?, ?)",
+                [hop_url.as_str(), target.as_str()],
+            )?;
+            self.redirects
+                .insert(hop_url.to_string(), target.to_string());
+        }
+        Ok(())
+    }
+
+    /// Records whether a domain refused a request, banning it according to the ban policy.
+    ///
+    /// Refusals are counted while they are consecutive; any other response resets the count, except for 429, which is
+    /// handled by throttling the domain and leaves the count as it is.
+    ///
+    /// # Arguments
+    /// * `domain_id` - The id of the domain entity.
+    /// * `status` - The status of the response.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_domain_response(
+        &self,
+        domain_id: i64,
+        status: StatusCode,
+    ) -> Result<(), CrawlerError> {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Ok(());
+        }
+        if status != StatusCode::FORBIDDEN {
+            self.db_connection.execute(
+                "UPDATE Domain SET RefusedCount = 0 WHERE Id = ?",
+                [domain_id],
+            )?;
+            return Ok(());
+        }
+
+        let refused_count: u32 = self.db_connection.query_row(
+            "UPDATE Domain SET RefusedCount = RefusedCount + 1 WHERE Id = ? RETURNING RefusedCount",
+            [domain_id],
+            |row| row.get(0),
+        )?;
+        if let Some(policy) = self.ban_policy {
+            if refused_count >= policy.threshold {
+                let name: String = self.db_connection.query_row(
+                    "UPDATE Domain SET BannedUntil = datetime('now', ?), RefusedCount = 0 WHERE Id = ? RETURNING Name",
+                    params![format!("+{} hours", policy.quarantine_hours), domain_id],
+                    |row| row.get(0),
+                )?;
+                warn!(
+                    "Banning domain {} for {} hours after {} refused requests",
+                    name, policy.quarantine_hours, refused_count
+                );
             }
         }
         Ok(())
     }
 
-    /// Fetches the page contents and records them in the database.
+    /// Throttles a domain that responded with 429 or 503, and queues the URL again unless it was throttled too often.
     ///
-    /// Records any links found on the page.
+    /// See [`Throttle::next`] for how long the domain is paused, and how its crawl delay grows.
+    ///
+    /// # Arguments
+    /// * `request` - The request of the throttled page.
+    /// * `final_url` - The URL of the response, after any redirects.
+    /// * `headers` - The headers of the response.
+    ///
+    /// # Returns
+    /// `true` if the URL is queued again, `false` if its response should be recorded as a failure.
+    fn throttle_domain(
+        &mut self,
+        request: &PageRequest,
+        final_url: &Url,
+        headers: &HeaderMap,
+    ) -> Result<bool, CrawlerError> {
+        let retry_after = headers
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| parse_retry_after(v, Utc::now()));
+        let crawl_delay = self.crawl_delay_of(request.domain_id)?;
+        let throttle = Throttle::next(
+            self.throttles.get(&request.domain_id),
+            crawl_delay,
+            retry_after,
+        );
+        self.throttles.insert(request.domain_id, throttle);
+
+        let url = request.url.as_str();
+        let retries = self.throttle_retries.entry(url.to_string()).or_default();
+        if *retries >= MAX_THROTTLE_RETRIES {
+            return Ok(false);
+        }
+        *retries += 1;
+        let pause = throttle.remaining().unwrap_or_default();
+        warn!(
+            url,
+            domain = request.url.host_str(),
+            pause_ms = pause.as_millis() as u64,
+            "Throttled by {}, pausing the domain for {:.1}s and queueing {} again",
+            display_host(request.url.host_str().unwrap_or_default()),
+            pause.as_secs_f64(),
+            url
+        );
+        // The URL and its redirect target are not crawled yet, so they must not be skipped when the URL is taken
+        for visited in [url, final_url.as_str()] {
+            self.visited.remove(visited)?;
+        }
+        self.memory.visited = self.visited.memory_bytes();
+        self.requeued_urls.insert(url.to_string());
+        Ok(true)
+    }
+
+    /// Counts an error of a domain, suspending the domain for the rest of the run once its error budget is spent.
+    ///
+    /// The suspension is recorded in the `Suspended` and `SuspendedRunId` columns of the domain. Its queued URLs are
+    /// skipped when they are dequeued.
     ///
     /// # Arguments
-    /// * `url` - The URL of the page.
     /// * `domain_id` - The id of the domain entity.
     ///
     /// # Returns
     /// A Result indicating success or failure.
-    async fn process_page(
+    fn record_domain_error(&mut self, domain_id: i64) -> Result<(), CrawlerError> {
+        let Some(budget) = self.error_budget else {
+            return Ok(());
+        };
+        let errors = self.domain_errors.entry(domain_id).or_default();
+        *errors += 1;
+        if *errors == budget {
+            let name: String = self.db_connection.query_row(
+                "UPDATE Domain SET Suspended = CURRENT_TIMESTAMP, SuspendedRunId = ? WHERE Id = ? RETURNING Name",
+                params![self.run_id, domain_id],
+                |row| row.get(0),
+            )?;
+            warn!(
+                domain = name,
+                errors = budget,
+                "Suspending domain {} for the rest of the run after {} errors",
+                name,
+                budget
+            );
+        }
+        Ok(())
+    }
+
+    /// Records the response to a page request in the database and the events log.
+    ///
+    /// Stored pages are also recorded as part of this run, carried over if an earlier run fetched them already.
+    ///
+    /// # Arguments
+    /// * `fetch` - The response.
+    /// * `page_id` - The id of the page entity, if the response was stored.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn record_fetch(
+        &self,
+        fetch: &FetchRecord<'_>,
+        page_id: Option<i64>,
+    ) -> Result<(), CrawlerError> {
+        self.insert_fetch(fetch, page_id)?;
+        self.report_fetch(fetch, page_id).await
+    }
+
+    /// Records the response to a page request in the database, see [`Crawler::record_fetch`].
+    ///
+    /// # Arguments
+    /// * `fetch` - The response.
+    /// * `page_id` - The id of the page entity, if the response was stored.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn insert_fetch(
+        &self,
+        fetch: &FetchRecord<'_>,
+        page_id: Option<i64>,
+    ) -> Result<(), CrawlerError> {
+        let content_type = fetch
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+        let headers = fetch
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into(),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        if let Some(page_id) = page_id {
+            self.db_connection.execute(
+                "INSERT OR IGNORE INTO RunPage (RunId, PageId, CarriedOver)
+                VALUES (?1, ?2, EXISTS (SELECT 1 FROM PageFetch WHERE PageId = ?2 AND RunId < ?1))",
+                params![self.run_id, page_id],
+            )?;
+        }
+        self.db_connection.execute(
+            "INSERT INTO PageFetch (RunId, PageId, Url, FinalUrl, Status, ContentType, ContentLength, DurationMs, Headers, Error)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                self.run_id,
+                page_id,
+                fetch.url.as_str(),
+                fetch.final_url.as_str(),
+                fetch.status.as_u16(),
+                content_type,
+                fetch.content_length,
+                fetch.duration.as_millis() as i64,
+                serde_json::Value::Object(headers).to_string(),
+                fetch.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Reports the response to a page request in the log and the events log, see [`Crawler::record_fetch`].
+    ///
+    /// # Arguments
+    /// * `fetch` - The response.
+    /// * `page_id` - The id of the page entity, if the response was stored.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn report_fetch(
+        &self,
+        fetch: &FetchRecord<'_>,
+        page_id: Option<i64>,
+    ) -> Result<(), CrawlerError> {
+        let content_type = fetch
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+        let duration_ms = fetch.duration.as_millis() as u64;
+        info!(
+            url = %fetch.url,
+            domain = fetch.url.host_str(),
+            status = fetch.status.as_u16(),
+            duration_ms,
+            depth = fetch.depth,
+            "Fetched {} ({}) in {} ms",
+            display_url(fetch.url),
+            fetch.status.as_u16(),
+            duration_ms
+        );
+        self.events
+            .emit(&CrawlEvent::Fetch {
+                url: fetch.url.as_str(),
+                final_url: fetch.final_url.as_str(),
+                status: fetch.status.as_u16(),
+                content_type,
+                content_length: fetch.content_length,
+                duration_ms,
+                page_id,
+                error: fetch.error.as_deref(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Saves a downloaded document to the assets directory and records it, instead of parsing it as a page.
+    ///
+    /// Assets with identical contents are saved once. The page the asset was first linked from is recorded as the
+    /// page it originates from.
+    ///
+    /// # Arguments
+    /// * `fetch` - The response to the asset request.
+    /// * `body` - The contents of the asset.
+    /// * `asset` - The type of the asset.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn record_asset(
         &mut self,
-        url: &Url,
-        domain_id: Option<i64>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let response = reqwest::get(url.as_str()).await?;
-        let status = response.status();
-        if !status.is_success() {
-            error!("Failed to fetch page ({}): {}", status.as_str(), url);
+        fetch: &FetchRecord<'_>,
+        body: &[u8],
+        asset: AssetMatch,
+    ) -> Result<(), CrawlerError> {
+        let Some(assets) = &self.assets else {
+            return Ok(());
+        };
+        let hash = self.hash_algorithm.digest(body);
+        if let Some(totals) = &mut self.dry_run {
+            totals.add_file(hash, body.len());
+            return Ok(());
+        }
+        let path = assets.path(&hash, &asset.extension);
+        let saved = self.db_connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM Asset WHERE StoredFile = ?)",
+            [path.display().to_string()],
+            |row| row.get::<_, bool>(0),
+        )?;
+        if !saved {
+            assets.save(path.clone(), body).await?;
+        }
+        self.db_connection.execute(
+            "INSERT INTO Asset (RunId, Url, PageId, Hash, HashAlgorithm, MimeType, Size, StoredFile)
+            VALUES (?1, ?2, (SELECT PageId FROM PageLink WHERE Url IN (?2, ?3) ORDER BY rowid LIMIT 1), ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (Url) DO UPDATE SET
+                RunId = excluded.RunId,
+                Hash = excluded.Hash,
+                HashAlgorithm = excluded.HashAlgorithm,
+                MimeType = excluded.MimeType,
+                Size = excluded.Size,
+                StoredFile = excluded.StoredFile,
+                Fetched = CURRENT_TIMESTAMP",
+            params![
+                self.run_id,
+                fetch.final_url.as_str(),
+                fetch.url.as_str(),
+                hash,
+                self.hash_algorithm.name(),
+                asset.mime_type,
+                body.len(),
+                path.display().to_string(),
+            ],
+        )?;
+        info!(
+            url = %fetch.final_url,
+            mime_type = asset.mime_type,
+            "Saved asset {} ({}, {} bytes)",
+            fetch.final_url,
+            asset.mime_type,
+            body.len()
+        );
+        Ok(())
+    }
+
+    /// Saves a downloaded image to the images directory and links it to the pages it appears on.
+    ///
+    /// Images outside the MIME type and size limits are not saved. Images with identical contents are saved and
+    /// recorded once, whichever URL they were downloaded from.
+    ///
+    /// # Arguments
+    /// * `fetch` - The response to the image request.
+    /// * `body` - The contents of the image.
+    /// * `mime_type` - The MIME type of the image.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn record_image(
+        &mut self,
+        fetch: &FetchRecord<'_>,
+        body: &[u8],
+        mime_type: &str,
+    ) -> Result<(), CrawlerError> {
+        let Some(images) = &self.images else {
+            return Ok(());
+        };
+        if let Some(rejection) = images.check(mime_type, body.len()) {
+            return self.record_image_rejection(
+                fetch.final_url,
+                rejection,
+                mime_type,
+                body.len() as u64,
+            );
+        }
+        let hash = self.hash_algorithm.digest(body);
+        if let Some(totals) = &mut self.dry_run {
+            totals.add_file(hash, body.len());
             return Ok(());
         }
-        let body = response.text().await?;
+        let known = self
+            .db_connection
+            .query_row(
+                "SELECT Id FROM Image WHERE Hash = ? AND HashAlgorithm = ?",
+                [hash.as_str(), self.hash_algorithm.name()],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        let image_id = match known {
+            Some(id) => id,
+            None => {
+                let path = images.path(&hash, mime_type);
+                images.save(path.clone(), body).await?;
+                let (width, height) = images::dimensions(body).unzip();
+                self.db_connection.execute(
+                    "INSERT INTO Image (RunId, Url, Hash, HashAlgorithm, MimeType, Size, Width, Height, StoredFile)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![
+                        self.run_id,
+                        fetch.final_url.as_str(),
+                        hash,
+                        self.hash_algorithm.name(),
+                        mime_type,
+                        body.len(),
+                        width,
+                        height,
+                        path.display().to_string(),
+                    ],
+                )?;
+                info!(
+                    url = %fetch.final_url,
+                    mime_type,
+                    "Saved image {} ({}, {} bytes)",
+                    fetch.final_url,
+                    mime_type,
+                    body.len()
+                );
+                self.db_connection.last_insert_rowid()
+            }
+        };
+        self.db_connection.execute(
+            "UPDATE PageImage SET ImageId = ? WHERE Url IN (?, ?)",
+            params![image_id, fetch.url.as_str(), fetch.final_url.as_str()],
+        )?;
+        Ok(())
+    }
+
+    /// Records an image that is not saved, so that it is not downloaded again while the limits would reject it.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the image.
+    /// * `rejection` - Why the image is not saved.
+    /// * `mime_type` - The MIME type of the image.
+    /// * `size` - The size of the image in bytes, or more than the size limit if its download was stopped there.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_image_rejection(
+        &self,
+        url: &Url,
+        rejection: ImageRejection,
+        mime_type: &str,
+        size: u64,
+    ) -> Result<(), CrawlerError> {
+        let reason = match rejection {
+            ImageRejection::MimeType => format!("{} images are not saved", mime_type),
+            ImageRejection::TooLarge => format!("{} bytes is above the size limit", size),
+        };
+        info!(url = %url, reason, "Image {} is not saved: {}", url, reason);
+        self.db_connection.execute(
+            "INSERT OR REPLACE INTO RejectedImage (Url, RunId, Reason, MimeType, Size) VALUES (?, ?, ?, ?, ?)",
+            params![
+                url.as_str(),
+                self.run_id,
+                rejection.name(),
+                mime_type,
+                size
+            ],
+        )?;
+        Ok(())
+    }
 
-        let page_id = self.record_page_contents(url, &body)?;
-        self.record_page_links(url, &body, page_id, domain_id)?;
+    /// Checks whether an image was rejected before, and would still be rejected with the current limits.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the image.
+    ///
+    /// # Returns
+    /// `true` if the image should not be downloaded again.
+    fn is_image_rejected(&self, url: &str) -> Result<bool, CrawlerError> {
+        let Some(images) = &self.images else {
+            return Ok(false);
+        };
+        let rejected = self
+            .db_connection
+            .prepare_cached("SELECT MimeType, Size FROM RejectedImage WHERE Url = ?")?
+            .query_row([url], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })
+            .optional()?;
+        Ok(rejected
+            .is_some_and(|(mime_type, size)| images.check(&mime_type, size as usize).is_some()))
+    }
 
+    /// Records the images shown on a page with their alt text, and queues those that were not downloaded yet.
+    ///
+    /// Images are queued even from pages at the maximum depth, as they are part of the page rather than pages of
+    /// their own. Images already downloaded from the same URL are linked to the page without fetching them again, and
+    /// images rejected before are not fetched again unless the limits changed.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    /// * `document` - The parsed contents of the page.
+    /// * `page_id` - The id of the page entity.
+    /// * `depth` - How many links away from the start URL the page was found.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_page_images(
+        &mut self,
+        url: &Url,
+        document: &Html,
+        page_id: i64,
+        depth: u32,
+    ) -> Result<(), CrawlerError> {
+        self.db_connection
+            .execute("DELETE FROM PageImage WHERE PageId = ?", [page_id])?;
+        let base = base_url(document, url);
+        let images: Vec<(Url, String)> = extract_links(document, &[LinkElement::Img])
+            .into_iter()
+            .filter_map(|link| Some((self.parse_href(link.href, &base)?, link.anchor_text)))
+            .collect();
+        for (image_url, alt) in &images {
+            let image_id: Option<i64> = self
+                .db_connection
+                .prepare_cached(
+                    "SELECT Id FROM Image WHERE Url = ?1
+                    UNION SELECT ImageId FROM PageImage WHERE Url = ?1 AND ImageId IS NOT NULL LIMIT 1",
+                )?
+                .query_row([image_url.as_str()], |row| row.get(0))
+                .optional()?;
+            self.db_connection
+                .prepare_cached(
+                    "INSERT OR IGNORE INTO PageImage (PageId, Url, AltText, ImageId) VALUES (?, ?, NULLIF(?, ''), ?)",
+                )?
+                .execute(params![page_id, image_url.as_str(), alt, image_id])?;
+            if image_id.is_some()
+                || !matches!(image_url.scheme(), "http" | "https")
+                || self.is_image_rejected(image_url.as_str())?
+                || self
+                    .image_queue
+                    .iter()
+                    .any(|(queued, _)| queued == image_url.as_str())
+            {
+                continue;
+            }
+            if self.is_url_crawlable(image_url, None)?.0 {
+                self.image_queue
+                    .push_back((image_url.to_string(), depth + 1));
+            }
+        }
         Ok(())
     }
 
-    /// Perform a single crawl iteration.
+    /// Records the page contents in the database and saves them in the storage format.
     ///
-    /// An iteration consists of processing the next URL in a queue.
+    /// If the page already exists, its validators are replaced, and its hash, run and update time are only changed
+    /// if the contents differ. If another page with identical contents is stored, the contents are not saved again
+    /// and the page is recorded as a duplicate of it. The contents of pages that are not indexed, e.g. because they
+    /// are marked `noindex`, are not saved.
     ///
+    /// # Arguments
+    /// * `fetch` - The response to the page request.
+    /// * `request_headers` - The extra headers the page was requested with.
+    /// * `body` - The contents of the page.
+    /// * `charset` - The name of the encoding the contents were decoded with.
+    /// * `directives` - The robots directives of the page that are followed.
+    /// * `skip_reason` - Why the page is not indexed, or None if it is.
     /// # Returns
-    /// `true` if there are more URLs to crawl, `false` otherwise.
-    pub async fn crawl(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        let next_url = self.url_queue.pop();
-        match next_url {
-            Some(url) => {
-                info!("Crawling URL: {}", url);
-                let url = Url::parse(&url)?;
-                let domain_id = self.record_domain(&url)?;
-                self.record_robots_txt(&url, Some(domain_id)).await?;
+    /// The id of the created page entity.
+    async fn record_page_contents(
+        &mut self,
+        fetch: &FetchRecord<'_>,
+        request_headers: &HeaderMap,
+        body: &[u8],
+        charset: &str,
+        directives: RobotsDirectives,
+        skip_reason: Option<&str>,
+    ) -> Result<i64, CrawlerError> {
+        let url = fetch.final_url;
+        let headers = fetch.headers;
+        let hash = self.hash_algorithm.digest(body);
 
-                if let (false, reason) = self.is_url_crawlable(&url, Some(domain_id))? {
-                    info!("URL {} is not crawlable: {}", url, reason.unwrap_or(""));
-                } else {
-                    self.process_page(&url, Some(domain_id)).await?;
-                }
-                if self.url_queue.is_empty() {
-                    return Ok(false);
+        let duplicate_of: Option<(i64, String)> = self
+            .db_connection
+            .query_row(
+                "SELECT Id, Url FROM Page WHERE Hash = ? AND HashAlgorithm = ? AND Url != ? AND DuplicateOf IS NULL AND Indexed = 1
+                ORDER BY Id LIMIT 1",
+                [hash.as_str(), self.hash_algorithm.name(), url.as_str()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        // Another URL may map to the same file of a mirror, e.g. `/a` and `/a.html`
+        let mirror_taken = match self.store.mirror_file(url) {
+            Some(path) => self.db_connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM Page WHERE StoredFile = ? AND Url != ?)",
+                [path.display().to_string(), url.to_string()],
+                |row| row.get(0),
+            )?,
+            None => false,
+        };
+        let response = StoredResponse {
+            url,
+            user_agent: &self.user_agent,
+            request_headers,
+            version: fetch.version,
+            status: fetch.status,
+            headers,
+            body,
+            hash: &hash,
+            mirror_taken,
+        };
+        let location = match &duplicate_of {
+            _ if skip_reason.is_some() => {
+                info!(
+                    "Contents of page {} are not saved: {}",
+                    url,
+                    skip_reason.unwrap_or_default()
+                );
+                None
+            }
+            Some((original_id, original_url)) => {
+                info!("Page {} duplicates page {}", url, original_id);
+                match self.dry_run {
+                    Some(_) => None,
+                    None => self.store.store_duplicate(&response, original_url).await?,
                 }
             }
-            None => {
-                return Ok(false);
+            None => match &mut self.dry_run {
+                Some(totals) => {
+                    totals.pages += 1;
+                    totals.page_bytes += body.len() as u64;
+                    None
+                }
+                None => self.store.store(&response).await?,
+            },
+        };
+        let duplicate_of = duplicate_of
+            .filter(|_| skip_reason.is_none())
+            .map(|(id, _)| id);
+        let (warc_file, warc_offset, stored_file) = match location {
+            Some(StoredLocation::Warc { file, offset }) => (Some(file), Some(offset), None),
+            Some(StoredLocation::File(path)) => (None, None, Some(path.display().to_string())),
+            Some(StoredLocation::Object(location)) => (None, None, Some(location)),
+            Some(StoredLocation::Blob {
+                compression,
+                contents,
+            }) => {
+                self.db_connection
+                    .prepare_cached(
+                        "INSERT OR IGNORE INTO PageBody (Hash, Compression, Body) VALUES (?, ?, ?)",
+                    )?
+                    .execute(params![hash, compression, contents])?;
+                (None, None, None)
             }
+            None => (None, None, None),
+        };
+
+        let etag = headers.get(ETAG).and_then(|v| v.to_str().ok());
+        let last_modified = headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok());
+        let content_language = headers.get(CONTENT_LANGUAGE).and_then(|v| v.to_str().ok());
+        let server_country = self
+            .geoip
+            .as_ref()
+            .zip(fetch.server_address)
+            .and_then(|(geoip, ip)| geoip.country(ip));
+        let existing = self
+            .db_connection
+            .query_row(
+                "SELECT Hash != ?1 AND HashAlgorithm = ?2 AND DryRun = 0 FROM Page WHERE Url = ?3",
+                params![hash, self.hash_algorithm.name(), url.as_str()],
+                |row| row.get::<_, bool>(0),
+            )
+            .optional()?;
+        let changed = existing.unwrap_or(false);
+        if changed {
+            // The first change of a page also keeps the version stored before it
+            self.db_connection.execute(
+                "INSERT INTO PageRevision (PageId, RunId, Hash, HashAlgorithm, WarcFile, WarcOffset, StoredFile, Fetched)
+                SELECT Id, RunId, Hash, HashAlgorithm, WarcFile, WarcOffset, StoredFile, Updated FROM Page
+                WHERE Url = ? AND NOT EXISTS (SELECT 1 FROM PageRevision WHERE PageId = Page.Id)",
+                [url.as_str()],
+            )?;
         }
-        Ok(true)
+        self.db_connection.execute(
+            "INSERT INTO Page (RunId, Url, RequestedUrl, Hash, HashAlgorithm, DuplicateOf, ETag, LastModified, WarcFile, WarcOffset, StoredFile, NoIndex, NoFollow, Indexed,
+                ServerAddress, ServerCountry, ContentLanguage, Charset, DryRun)
+            VALUES (?1, ?2, ?3, ?4, ?14, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?15, ?16, ?17, ?18, ?19)
+            ON CONFLICT (Url) DO UPDATE SET
+                RunId = CASE WHEN Hash != excluded.Hash THEN excluded.RunId ELSE RunId END,
+                Updated = CASE WHEN Hash != excluded.Hash THEN CURRENT_TIMESTAMP ELSE Updated END,
+                RequestedUrl = excluded.RequestedUrl,
+                Hash = excluded.Hash,
+                HashAlgorithm = excluded.HashAlgorithm,
+                DuplicateOf = excluded.DuplicateOf,
+                ETag = excluded.ETag,
+                LastModified = excluded.LastModified,
+                WarcFile = excluded.WarcFile,
+                WarcOffset = excluded.WarcOffset,
+                StoredFile = excluded.StoredFile,
+                NoIndex = excluded.NoIndex,
+                NoFollow = excluded.NoFollow,
+                Indexed = excluded.Indexed,
+                ServerAddress = excluded.ServerAddress,
+                ServerCountry = excluded.ServerCountry,
+                ContentLanguage = excluded.ContentLanguage,
+                Charset = excluded.Charset,
+                DryRun = excluded.DryRun
+            WHERE excluded.DryRun = 0 OR DryRun = 1",
+            params![
+                self.run_id,
+                url.as_str(),
+                fetch.url.as_str(),
+                hash,
+                duplicate_of,
+                etag,
+                last_modified,
+                warc_file,
+                warc_offset,
+                stored_file,
+                directives.noindex,
+                directives.nofollow,
+                skip_reason.is_none(),
+                self.hash_algorithm.name(),
+                fetch.server_address.map(|ip| ip.to_string()),
+                server_country,
+                content_language,
+                charset,
+                self.dry_run.is_some()
+            ],
+        )?;
+        let page_id = self.db_connection.query_row(
+            "SELECT Id FROM Page WHERE Url = ?",
+            [url.as_str()],
+            |row| row.get(0),
+        )?;
+        if let (None, Some(counts), Some(host)) = (existing, &mut self.domain_pages, url.host_str())
+        {
+            *counts.entry(host.to_string()).or_default() += 1;
+        }
+        // A URL that failed before and now succeeded is no longer retried
+        self.db_connection.execute(
+            "DELETE FROM FailedUrl WHERE Url IN (?, ?)",
+            [url.as_str(), fetch.url.as_str()],
+        )?;
+        if self.dry_run.is_none() {
+            crawl_store(&self.db_connection, &self.shared).save_page(
+                url.as_str(),
+                &hash,
+                directives.nofollow,
+            )?;
+        }
+        if skip_reason.is_none() {
+            self.stored_pages += 1;
+        }
+        if changed {
+            info!("Contents of page {} changed", url);
+            self.db_connection.execute(
+                "INSERT INTO PageRevision (PageId, RunId, Hash, HashAlgorithm, WarcFile, WarcOffset, StoredFile)
+                SELECT Id, RunId, Hash, HashAlgorithm, WarcFile, WarcOffset, StoredFile FROM Page WHERE Id = ?",
+                [page_id],
+            )?;
+            self.changed_pages += 1;
+        }
+        self.send_webhook_event(WebhookEvent::PageStored {
+            url: url.to_string(),
+            page_id,
+            hash,
+            duplicate_of,
+            indexed: skip_reason.is_none(),
+        });
+        Ok(page_id)
+    }
+
+    /// Fetches the robots.txt file for an existing domain in the database and records its rules.
+    ///
+    /// URLs of the domain may have been queued before its rules were known, or while older rules applied. When the
+    /// file is fetched, the queued URLs of the domain that its rules disallow are dropped from the frontier and
+    /// recorded as blocked, instead of waiting for each of them to be dequeued. See [`robots::record_robots_txt`].
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    /// * `domain_id` - The id of the domain entity.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn record_robots_txt(
+        &mut self,
+        url: &Url,
+        domain_id: Option<i64>,
+    ) -> Result<(), CrawlerError> {
+        let domain_id = match domain_id {
+            Some(id) => id,
+            None => self.get_domain_id(url)?,
+        };
+        let fetched = robots::record_robots_txt(
+            &self.db_connection,
+            crawl_store(&self.db_connection, &self.shared),
+            &self.fetcher,
+            &self.user_agent,
+            url,
+            domain_id,
+        )
+        .await?;
+        let Some(rules) = fetched else {
+            return Ok(());
+        };
+        self.read_sitemaps(url, domain_id, &rules.sitemaps).await?;
+        if self.ignore_robots {
+            return Ok(());
+        }
+
+        let patterns = disallowed_patterns(&self.db_connection, domain_id)?;
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        let removed = self.url_queue.remove_matching(
+            crawl_store(&self.db_connection, &self.shared),
+            url.host_str().unwrap_or_default(),
+            |queued| {
+                Url::parse(queued).is_ok_and(|queued| {
+                    queued.host_str() == url.host_str() && is_disallowed(&patterns, queued.path())
+                })
+            },
+        )?;
+        if !removed.is_empty() {
+            info!(
+                "Dropped {} queued URLs of {} disallowed by its robots.txt",
+                removed.len(),
+                display_host(url.host_str().unwrap_or_default())
+            );
+        }
+        for removed_url in removed {
+            self.record_robots_blocked(&Url::parse(&removed_url)?, domain_id)?;
+            self.events
+                .emit(&CrawlEvent::Skip {
+                    url: &removed_url,
+                    reason: ROBOTS_DISALLOWED,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the sitemaps a domain lists in its robots.txt, and records the priority of the URLs of the domain they
+    /// list, which the scorer gets for URLs queued afterwards.
+    ///
+    /// Only sitemaps on the host of the domain are read. Sitemap indexes are followed, up to [`MAX_SITEMAP_FILES`]
+    /// files in all, and sitemaps that cannot be read are skipped.
+    ///
+    /// # Arguments
+    /// * `url` - A URL of the domain.
+    /// * `domain_id` - The id of the domain entity.
+    /// * `sitemaps` - The URLs of the sitemaps listed in its robots.txt.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn read_sitemaps(
+        &mut self,
+        url: &Url,
+        domain_id: i64,
+        sitemaps: &[String],
+    ) -> Result<(), CrawlerError> {
+        let on_host = |listed: &str| {
+            Url::parse(listed)
+                .ok()
+                .filter(|listed| listed.host_str() == url.host_str())
+        };
+        let mut pending = sitemaps
+            .iter()
+            .filter_map(|sitemap| on_host(sitemap))
+            .collect::<VecDeque<_>>();
+        let (mut read, mut listed) = (0, 0);
+        while let Some(sitemap_url) = pending.pop_front() {
+            if read == MAX_SITEMAP_FILES {
+                warn!(
+                    "Reading only {} sitemaps of {}",
+                    MAX_SITEMAP_FILES,
+                    display_host(url.host_str().unwrap_or_default())
+                );
+                break;
+            }
+            read += 1;
+            let sitemap = match self.fetch_sitemap(&sitemap_url).await {
+                Ok(sitemap) => sitemap,
+                Err(e) => {
+                    warn!(url = %sitemap_url, error = %e, "Skipping sitemap {}: {}", sitemap_url, e);
+                    continue;
+                }
+            };
+            pending.extend(
+                sitemap
+                    .sitemaps
+                    .iter()
+                    .filter_map(|sitemap| on_host(sitemap)),
+            );
+            let transaction = self.db_connection.unchecked_transaction()?;
+            {
+                let mut insert = transaction.prepare_cached(
+                    "INSERT OR REPLACE INTO SitemapUrl (Url, DomainId, Priority) VALUES (?, ?, ?)",
+                )?;
+                for (listed_url, priority) in &sitemap.urls {
+                    if let Some(listed_url) = on_host(listed_url) {
+                        let listed_url = self.normalizer.normalize(&listed_url);
+                        insert.execute(params![listed_url.as_str(), domain_id, priority])?;
+                        listed += 1;
+                    }
+                }
+            }
+            transaction.commit()?;
+        }
+        if read > 0 {
+            info!(
+                "Read the priority of {} URLs from {} sitemaps of {}",
+                listed,
+                read,
+                display_host(url.host_str().unwrap_or_default())
+            );
+        }
+        Ok(())
+    }
+
+    /// Fetches and parses a sitemap, decompressing it if it is gzipped.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the sitemap.
+    ///
+    /// # Returns
+    /// What the sitemap lists, or an error if it could not be fetched.
+    async fn fetch_sitemap(&self, url: &Url) -> Result<ParsedSitemap, CrawlerError> {
+        let response = self.fetcher.get(url, HeaderMap::new()).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(CrawlerError::Fetch(format!("HTTP status {}", status)));
+        }
+        let body = read_body(response, Some(MAX_SITEMAP_BYTES as u64))
+            .await
+            .map_err(|e| CrawlerError::Fetch(e.to_string()))?;
+        let body = decompress_sitemap(body)?;
+        Ok(parse_sitemap(&String::from_utf8_lossy(&body)))
+    }
+
+    /// Checks whether the host of a URL can be reached, once per host, port and run.
+    ///
+    /// When a host is found dead, it is marked unreachable in the database and all of its queued URLs are recorded as
+    /// failed and removed from the frontier, instead of timing out on each one. URLs of a dead host found later are
+    /// failed when they are dequeued. Hosts are not checked when replaying fixtures or serving local files.
+    ///
+    /// # Arguments
+    /// * `url` - The URL about to be crawled.
+    /// * `domain_id` - The id of the domain entity.
+    ///
+    /// # Returns
+    /// Whether the host is reachable. If not, the URL has been recorded as failed.
+    async fn check_reachable(&mut self, url: &Url, domain_id: i64) -> Result<bool, CrawlerError> {
+        if self.fetcher.is_offline() {
+            return Ok(true);
+        }
+        let host = host_and_port(url)
+            .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
+        let problem = match self.host_checks.get(&host) {
+            Some(problem) => problem.clone(),
+            None => {
+                let problem = check_host(url, CHECK_TIMEOUT, &self.resolver).await;
+                // Domains do not distinguish ports, so keep a failure of another port of the domain from this run
+                let failed_on_other_port = self.host_checks.iter().any(|(checked, problem)| {
+                    problem.is_some()
+                        && checked.rsplit_once(':').map(|(name, _)| name) == url.host_str()
+                });
+                if problem.is_some() || !failed_on_other_port {
+                    self.db_connection.execute(
+                        "UPDATE Domain SET Unreachable = ? WHERE Id = ?",
+                        params![problem, domain_id],
+                    )?;
+                }
+                self.host_checks.insert(host.clone(), problem.clone());
+                if let Some(problem) = &problem {
+                    warn!("Host {} is unreachable: {}", host, problem);
+                    let removed = self.url_queue.remove_matching(
+                        crawl_store(&self.db_connection, &self.shared),
+                        url.host_str().unwrap_or_default(),
+                        |queued| {
+                            Url::parse(queued).is_ok_and(|queued| {
+                                host_and_port(&queued).is_some_and(|queued| queued == host)
+                            })
+                        },
+                    )?;
+                    info!("Dropped {} queued URLs of {}", removed.len(), host);
+                    for removed_url in removed {
+                        self.record_failed_url(&removed_url, domain_id, problem)
+                            .await?;
+                    }
+                }
+                problem
+            }
+        };
+        match problem {
+            Some(problem) => {
+                self.record_failed_url(url.as_str(), domain_id, &problem)
+                    .await?;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Records a URL that could not be crawled in the database and the events log.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `domain_id` - The id of the domain entity.
+    /// * `error` - Why the URL could not be crawled.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn record_failed_url(
+        &self,
+        url: &str,
+        domain_id: i64,
+        error: &str,
+    ) -> Result<(), CrawlerError> {
+        self.record_failure(url, Some(domain_id), FailureClass::Unreachable, error)?;
+        self.events.emit(&CrawlEvent::Error { url, error }).await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt to crawl a URL in the `FailedUrl` table, counting the attempts of URLs that failed
+    /// before, so that `retry` can queue them again.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `domain_id` - The id of the domain entity, if known.
+    /// * `class` - The class of the failure.
+    /// * `error` - Why the URL could not be crawled.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn record_failure(
+        &self,
+        url: &str,
+        domain_id: Option<i64>,
+        class: FailureClass,
+        error: &str,
+    ) -> Result<(), CrawlerError> {
+        self.db_connection
+            .prepare_cached(
+                "INSERT INTO FailedUrl (RunId, DomainId, Url, Class, Error) VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT (Url) DO UPDATE SET
+                    RunId = excluded.RunId,
+                    DomainId = COALESCE(excluded.DomainId, DomainId),
+                    Class = excluded.Class,
+                    Error = excluded.Error,
+                    Attempts = Attempts + 1,
+                    LastAttempt = CURRENT_TIMESTAMP",
+            )?
+            .execute(params![self.run_id, domain_id, url, class.name(), error])?;
+        Ok(())
+    }
+
+    /// Returns the crawl delay of a domain: the longer of the robots.txt crawl delay and the configured delay of the
+    /// domain.
+    ///
+    /// # Arguments
+    /// * `domain_id` - The id of the domain entity.
+    ///
+    /// # Returns
+    /// The crawl delay, or None if requests to the domain are not delayed.
+    fn crawl_delay_of(&self, domain_id: i64) -> Result<Option<Duration>, CrawlerError> {
+        let (robots_delay, name): (Option<f64>, String) = self.db_connection.query_row(
+            "SELECT CrawlDelay, Name FROM Domain WHERE Id = ?",
+            [domain_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let configured_delay = self
+            .domain_crawl_delays
+            .get(&name)
+            .copied()
+            .or(self.min_crawl_delay);
+        Ok(robots_delay
+            .map(Duration::from_secs_f64)
+            .max(configured_delay))
+    }
+
+    /// Schedules the next request to a domain once its crawl delay has passed since its last request.
+    ///
+    /// The crawl delay of a domain that throttled the crawler is raised to its throttle delay.
+    ///
+    /// # Arguments
+    /// * `domain_id` - The id of the domain entity.
+    ///
+    /// # Returns
+    /// The time to wait before sending the request.
+    fn schedule_request(&mut self, domain_id: i64) -> Result<Duration, CrawlerError> {
+        let crawl_delay = self.crawl_delay_of(domain_id)?.max(
+            self.throttles
+                .get(&domain_id)
+                .map(|throttle| throttle.delay),
+        );
+        let remaining = match (crawl_delay, self.last_requests.get(&domain_id)) {
+            (Some(crawl_delay), Some(last_request)) => {
+                (*last_request + crawl_delay).saturating_duration_since(Instant::now())
+            }
+            _ => Duration::ZERO,
+        };
+        self.last_requests
+            .insert(domain_id, Instant::now() + remaining);
+        Ok(remaining)
+    }
+
+    /// Requests a URL, following redirects manually so that the chain can be recorded.
+    ///
+    /// Every URL the chain leads to is checked like a dequeued URL before it is requested. A redirect to another host
+    /// is not followed, as that host has robots.txt rules, a crawl delay and a ban status of its own; its target is
+    /// left to be queued instead. A redirect to a URL that is not crawlable is not followed either.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to request.
+    /// * `headers` - Additional headers to send with every request of the chain. Cache validators are only sent with
+    ///   the request of the URL itself.
+    ///
+    /// # Returns
+    /// The last response and its URL, the redirected URLs in the order they were requested, with their response
+    /// status, and where the chain was left if it was not followed to its end.
+    async fn fetch(
+        &self,
+        url: &Url,
+        mut headers: HeaderMap,
+    ) -> Result<(Response, Url, Vec<(Url, StatusCode)>, Option<RedirectStop>), CrawlerError> {
+        let mut current = url.clone();
+        let mut hops = Vec::new();
+        loop {
+            let response = self.fetcher.get(&current, headers.clone()).await?;
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok());
+            let location = match location {
+                Some(location) if status.is_redirection() && status != StatusCode::NOT_MODIFIED => {
+                    location
+                }
+                _ => return Ok((response, self.normalizer.normalize(&current), hops, None)),
+            };
+
+            let next = current.join(location)?;
+            hops.push((current.clone(), status));
+            if hops.len() > MAX_REDIRECTS {
+                return Err(CrawlerError::Fetch(format!(
+                    "Too many redirects for {}",
+                    url
+                )));
+            }
+            let normalized = self.normalizer.normalize(&next);
+            let stop = if next.host_str() != url.host_str() {
+                Some(RedirectStop::OtherHost(normalized))
+            } else if normalized == *url {
+                // A redirect between variants of the requested URL, e.g. from `/docs` to `/docs/`
+                None
+            } else {
+                match self.is_url_crawlable(&normalized, None)? {
+                    (false, reason) => Some(RedirectStop::NotCrawlable(
+                        normalized,
+                        reason.unwrap_or_default().to_string(),
+                    )),
+                    (true, _) => None,
+                }
+            };
+            if stop.is_some() {
+                return Ok((response, current, hops, stop));
+            }
+            headers.remove(IF_NONE_MATCH);
+            headers.remove(IF_MODIFIED_SINCE);
+            current = next;
+        }
+    }
+
+    /// Checks whether a dequeued URL can be crawled, and prepares its request.
+    ///
+    /// Fetches the robots.txt rules of the domain if necessary, and marks the URL as visited. Pages stored by a
+    /// previous run are requested conditionally when revalidating.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    /// * `queued` - When the URL was queued.
+    /// * `score` - The score the URL was queued with.
+    /// * `domain_id` - The id of the domain entity.
+    ///
+    /// # Returns
+    /// The request, or None if the URL is not crawlable.
+    async fn prepare_request(
+        &mut self,
+        url: Url,
+        queued: SystemTime,
+        score: f64,
+        domain_id: i64,
+    ) -> Result<Option<PageRequest>, CrawlerError> {
+        // The depth goes with the request from here on, and back into the map if the URL is queued again
+        let depth = self.depths.remove(url.as_str()).unwrap_or(0);
+        info!(
+            url = %url,
+            domain = url.host_str(),
+            depth,
+            "Crawling URL: {}",
+            display_url(&url)
+        );
+        if !self.check_reachable(&url, domain_id).await? {
+            info!(url = %url, reason = "host is unreachable", "URL {} is not crawlable: host is unreachable", url);
+            return Ok(None);
+        }
+        self.record_robots_txt(&url, Some(domain_id)).await?;
+        match self.check_crawlable(&url, Some(domain_id)) {
+            Ok(()) => {}
+            Err(CrawlerError::Policy(reason)) => {
+                if reason == ROBOTS_DISALLOWED {
+                    self.record_robots_blocked(&url, domain_id)?;
+                }
+                info!(url = %url, reason, "URL {} is not crawlable: {}", url, reason);
+                self.events
+                    .emit(&CrawlEvent::Skip {
+                        url: url.as_str(),
+                        reason: &reason,
+                    })
+                    .await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.mark_visited(url.to_string())?;
+        if let Some((directory, count, _)) = self.date_limit(&url) {
+            self.date_counts.insert(directory, count + 1);
+        }
+        let previous = if self.revalidate {
+            let known_url = Url::parse(self.redirects.resolve(url.as_str()))?;
+            self.get_page_validators(&known_url)?
+        } else {
+            None
+        };
+
+        let mut headers = HeaderMap::new();
+        if let Some(validators) = &previous {
+            if let Some(etag) = &validators.etag {
+                headers.insert(IF_NONE_MATCH, etag.parse()?);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                headers.insert(IF_MODIFIED_SINCE, last_modified.parse()?);
+            }
+        }
+        headers.insert(ACCEPT_ENCODING, ACCEPTED_ENCODINGS.parse()?);
+        Ok(Some(PageRequest {
+            depth,
+            delay: self.schedule_request(domain_id)?,
+            url,
+            queued,
+            score,
+            domain_id,
+            image: false,
+            previous,
+            headers,
+        }))
+    }
+
+    /// Sends a prepared request once the crawl delay of its domain has passed, and reads the body of a successful
+    /// response.
+    ///
+    /// # Arguments
+    /// * `request` - The request.
+    ///
+    /// # Returns
+    /// The response, or an error if no response was received.
+    async fn fetch_page(&self, request: &PageRequest) -> Result<FetchedPage, CrawlerError> {
+        if !request.delay.is_zero() {
+            tokio::time::sleep(request.delay).await;
+        }
+        let started = Instant::now();
+        let (response, final_url, hops, stopped) =
+            self.fetch(&request.url, request.headers.clone()).await?;
+        let status = response.status();
+        let response_headers = response.headers().clone();
+        let version = response.version();
+        let server_address = response.remote_addr().map(|address| address.ip());
+        // Images above the size limit are not read further than the limit, or at all if their length is declared
+        let max_size = self
+            .images
+            .as_ref()
+            .filter(|_| self.image_type(&response_headers).is_some())
+            .map(ImageStore::max_size);
+        let body = if status.is_success() {
+            Some(read_body(response, max_size).await)
+        } else {
+            None
+        };
+        Ok(FetchedPage {
+            response_headers,
+            version,
+            status,
+            final_url,
+            hops,
+            stopped,
+            body,
+            duration: started.elapsed(),
+            server_address,
+        })
+    }
+
+    /// Records a fetched page in the database.
+    ///
+    /// Records any links found on the page. Pages that have not been modified since a previous run are left
+    /// untouched, and their recorded links are queued again. Redirected pages are stored under their final URL.
+    ///
+    /// # Arguments
+    /// * `request` - The request of the page.
+    /// * `page` - The response.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn process_page(
+        &mut self,
+        request: &PageRequest,
+        page: FetchedPage,
+    ) -> Result<(), CrawlerError> {
+        let PageRequest {
+            url,
+            domain_id,
+            depth,
+            previous,
+            headers: request_headers,
+            ..
+        } = request;
+        let (url, domain_id, depth) = (url, *domain_id, *depth);
+        let FetchedPage {
+            response_headers: headers,
+            version,
+            status,
+            final_url,
+            hops,
+            stopped,
+            body,
+            duration,
+            server_address,
+        } = page;
+        if let Some(stop) = stopped {
+            return self.process_redirect_stop(url, depth, &hops, stop).await;
+        }
+        // A redirect between variants of the requested URL, e.g. from `/docs` to `/docs/`, ends where it started
+        if !hops.is_empty() && final_url != *url {
+            self.record_redirect_chain(&hops, &final_url)?;
+            if self.is_page_crawled(final_url.as_str())? {
+                info!(
+                    "URL {} redirects to already crawled page {}",
+                    url, final_url
+                );
+                self.events
+                    .emit(&CrawlEvent::Skip {
+                        url: url.as_str(),
+                        reason: &format!("Redirects to already crawled page {}", final_url),
+                    })
+                    .await?;
+                return Ok(());
+            }
+            self.mark_visited(final_url.to_string())?;
+        }
+
+        self.record_domain_response(domain_id, status)?;
+        if matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ) && self.throttle_domain(request, &final_url, &headers)?
+        {
+            return Ok(());
+        }
+        if status.is_server_error() {
+            self.record_domain_error(domain_id)?;
+        }
+        let declared_length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        let bytes = match body {
+            None => {
+                let fetch = FetchRecord {
+                    url,
+                    final_url: &final_url,
+                    version,
+                    status,
+                    headers: &headers,
+                    content_length: declared_length,
+                    duration,
+                    depth,
+                    server_address,
+                    error: None,
+                };
+                self.record_fetch(&fetch, previous.as_ref().map(|v| v.page_id))
+                    .await?;
+                self.call_fetch_handlers(&fetch, None).await;
+
+                match previous {
+                    Some(validators) if status == StatusCode::NOT_MODIFIED => {
+                        info!("Page not modified: {}", url);
+                        self.queue_recorded_links(validators.page_id, url, depth)?;
+                    }
+                    _ => {
+                        error!(
+                            url = %url,
+                            status = status.as_u16(),
+                            "Failed to fetch page ({}): {}",
+                            status.as_str(),
+                            url
+                        );
+                        self.record_failure(
+                            url.as_str(),
+                            Some(domain_id),
+                            FailureClass::of_status(status),
+                            &format!("HTTP status {}", status),
+                        )?;
+                    }
+                }
+                return Ok(());
+            }
+            Some(Err(e)) => {
+                let image_type = self.image_type(&headers);
+                match (&e, image_type) {
+                    (BodyError::TooLarge { limit }, Some(mime_type)) => {
+                        let size = declared_length.unwrap_or_default().max(limit + 1);
+                        self.record_image_rejection(
+                            &final_url,
+                            ImageRejection::TooLarge,
+                            &mime_type,
+                            size,
+                        )?;
+                    }
+                    _ => {
+                        let message = e.to_string();
+                        error!(url = %url, error = message, "Rejected response from {}: {}", url, e);
+                        self.record_domain_error(domain_id)?;
+                        self.record_failure(
+                            url.as_str(),
+                            Some(domain_id),
+                            FailureClass::Body,
+                            &message,
+                        )?;
+                        for handler in &self.handlers {
+                            handler.on_error(url, &message).await;
+                        }
+                    }
+                }
+                let fetch = FetchRecord {
+                    url,
+                    final_url: &final_url,
+                    version,
+                    status,
+                    headers: &headers,
+                    content_length: declared_length,
+                    duration,
+                    depth,
+                    server_address,
+                    error: Some(e.to_string()),
+                };
+                self.record_fetch(&fetch, None).await?;
+                return Ok(());
+            }
+            Some(Ok(bytes)) => bytes,
+        };
+        let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        let fetch = FetchRecord {
+            url,
+            final_url: &final_url,
+            version,
+            status,
+            headers: &headers,
+            content_length: Some(bytes.len() as u64),
+            duration,
+            depth,
+            server_address,
+            error: None,
+        };
+        let asset = self
+            .assets
+            .as_ref()
+            .and_then(|assets| assets.recognize(&final_url, content_type));
+        if let Some(asset) = asset {
+            self.record_fetch(&fetch, None).await?;
+            self.call_fetch_handlers(&fetch, Some(&bytes)).await;
+            return self.record_asset(&fetch, &bytes, asset).await;
+        }
+        if let Some(mime_type) = self.image_type(&headers) {
+            self.record_fetch(&fetch, None).await?;
+            self.call_fetch_handlers(&fetch, Some(&bytes)).await;
+            return self.record_image(&fetch, &bytes, &mime_type).await;
+        }
+        // The browser cannot send credentials, so it would render the login page of a protected site instead
+        let rendered = match &self.renderer {
+            Some(renderer)
+                if renderer.renders(&final_url) && !self.fetcher.has_credential(&final_url) =>
+            {
+                match renderer.render(&final_url).await {
+                    Ok(html) => Some(html),
+                    Err(e) => {
+                        warn!(url = %final_url, error = %e, "Storing {} as served: {}", final_url, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        // The browser serializes the rendered DOM as UTF-8, whatever the charset of the response
+        let (bytes, declared_type) = match rendered {
+            Some(html) => (html.into_bytes(), Some("text/html; charset=utf-8")),
+            None => (bytes, content_type),
+        };
+        let (body, encoding) = charset::decode(&bytes, declared_type, &final_url);
+        self.memory.parse_buffers = MemoryUsage::parse_size(&body);
+        self.update_memory_usage()?;
+
+        let page = self.parse_page(&body, &final_url, &headers);
+        let directives = page.directives;
+        let in_language = page.in_language;
+        let canonical = page
+            .meta
+            .canonical
+            .as_ref()
+            .filter(|canonical| canonical.as_str() != final_url.as_str());
+        let skip_reason = self.skip_reason(directives.noindex, in_language, canonical.is_some());
+        // The page and everything found on it are written in one transaction, rather than one per statement. Handlers
+        // and events are only called once it is committed, so that they do not hold the write lock of the database
+        self.db_connection.execute_batch("BEGIN IMMEDIATE")?;
+        let recorded = async {
+            let page_id = self
+                .record_page_contents(
+                    &fetch,
+                    request_headers,
+                    &bytes,
+                    encoding.name(),
+                    directives,
+                    skip_reason,
+                )
+                .await?;
+            self.insert_fetch(&fetch, Some(page_id))?;
+            let links = self.record_parsed_page(
+                &final_url,
+                &page,
+                bytes.len(),
+                page_id,
+                Some(domain_id),
+                depth,
+                in_language,
+            )?;
+            if self.images.is_some() {
+                self.record_page_images(&final_url, &page.document, page_id, depth)?;
+            }
+            if let Some(canonical) = canonical.filter(|_| self.follow_canonical) {
+                if self.is_url_crawlable(canonical, None)?.0 {
+                    info!("Queueing canonical URL {} of {}", canonical, final_url);
+                    self.queue_url(canonical.to_string(), depth, &final_url)?;
+                }
+            }
+            Ok::<_, CrawlerError>((page_id, links))
+        }
+        .await;
+        let (page_id, links) = match recorded {
+            Ok(recorded) => {
+                self.db_connection.execute_batch("COMMIT")?;
+                recorded
+            }
+            Err(e) => {
+                self.db_connection.execute_batch("ROLLBACK")?;
+                return Err(e);
+            }
+        };
+        self.report_fetch(&fetch, Some(page_id)).await?;
+        self.call_fetch_handlers(&fetch, Some(&bytes)).await;
+        for handler in &self.handlers {
+            handler.on_links_extracted(&final_url, &links).await;
+        }
+
+        Ok(())
+    }
+
+    /// Records a redirect chain that was not followed to its end, queueing its next URL if it is on another host.
+    ///
+    /// # Arguments
+    /// * `url` - The requested URL.
+    /// * `depth` - How many links away from the start URL the requested URL was found.
+    /// * `hops` - The redirected URLs in the order they were requested, with their response status.
+    /// * `stop` - Where the chain was left.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    async fn process_redirect_stop(
+        &mut self,
+        url: &Url,
+        depth: u32,
+        hops: &[(Url, StatusCode)],
+        stop: RedirectStop,
+    ) -> Result<(), CrawlerError> {
+        match stop {
+            RedirectStop::OtherHost(target) => {
+                self.record_redirect_chain(hops, &target)?;
+                if self.is_page_crawled(target.as_str())? {
+                    info!("URL {} redirects to already crawled page {}", url, target);
+                } else {
+                    info!("URL {} redirects to {}, which is queued", url, target);
+                    self.queue_url(target.to_string(), depth, url)?;
+                }
+            }
+            RedirectStop::NotCrawlable(target, reason) => {
+                self.record_redirect_chain(hops, &target)?;
+                if reason == ROBOTS_DISALLOWED {
+                    let domain_id = self.get_domain_id(&target)?;
+                    self.record_robots_blocked(&target, domain_id)?;
+                }
+                info!(url = %url, reason, "URL {} redirects to {}, which is not crawlable: {}", url, target, reason);
+                self.events
+                    .emit(&CrawlEvent::Skip {
+                        url: url.as_str(),
+                        reason: &format!(
+                            "Redirects to {}, which is not crawlable: {}",
+                            target, reason
+                        ),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the contents of a page, finding its metadata, robots directives, text and language.
+    ///
+    /// # Arguments
+    /// * `body` - The decoded body of the page.
+    /// * `url` - The final URL of the page.
+    /// * `headers` - The response headers, for the `X-Robots-Tag` directives.
+    ///
+    /// # Returns
+    /// The parsed page.
+    fn parse_page(&self, body: &str, url: &Url, headers: &HeaderMap) -> ParsedPage {
+        let document = Html::parse_document(body);
+        let mut meta = PageMeta::from_document(&document, url);
+        meta.canonical = meta
+            .canonical
+            .map(|canonical| self.normalizer.normalize(&canonical));
+        let directives = if self.ignore_robots {
+            RobotsDirectives::default()
+        } else {
+            let header = RobotsDirectives::from_header(
+                headers
+                    .get_all(X_ROBOTS_TAG)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok()),
+                &self.user_agent,
+            );
+            meta.robots.union(header)
+        };
+        let text = plain_text(&document);
+        meta.language = language::detect(&text, meta.language.as_deref());
+        let in_language = self.is_in_language(meta.language.as_deref());
+        ParsedPage {
+            document,
+            meta,
+            directives,
+            text,
+            in_language,
+        }
+    }
+
+    /// Returns whether a page in a language is crawled. Pages whose language is unknown are.
+    fn is_in_language(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty()
+            || language
+                .is_none_or(|language| self.languages.iter().any(|crawled| crawled == language))
+    }
+
+    /// Returns why the contents of a page are not saved or indexed, or None if they are.
+    ///
+    /// # Arguments
+    /// * `noindex` - Whether the page is marked `noindex`.
+    /// * `in_language` - Whether the page is in one of the languages crawled.
+    /// * `is_variant` - Whether the page declares another page as canonical.
+    fn skip_reason(
+        &self,
+        noindex: bool,
+        in_language: bool,
+        is_variant: bool,
+    ) -> Option<&'static str> {
+        if noindex {
+            Some("marked noindex")
+        } else if !in_language {
+            Some("in a language that is not crawled")
+        } else if self.skip_canonical_variants && is_variant {
+            Some("variant of a canonical page")
+        } else {
+            None
+        }
+    }
+
+    /// Records what was parsed from a page: its content statistics, text, tags, metadata and links.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    /// * `page` - The parsed page.
+    /// * `html_size` - The size of the body in bytes.
+    /// * `page_id` - The id of the page entity.
+    /// * `domain_id` - The id of the domain entity.
+    /// * `depth` - How many links away from the start URL the page was found.
+    /// * `queue_links` - Whether the crawlable links of the page are queued.
+    ///
+    /// # Returns
+    /// The URLs of the links on the page, in document order.
+    #[allow(clippy::too_many_arguments)]
+    fn record_parsed_page(
+        &mut self,
+        url: &Url,
+        page: &ParsedPage,
+        html_size: usize,
+        page_id: i64,
+        domain_id: Option<i64>,
+        depth: u32,
+        queue_links: bool,
+    ) -> Result<Vec<Url>, CrawlerError> {
+        self.record_content_stats(&page.document, html_size, page_id)?;
+        self.record_page_text(&page.text, page.meta.language.as_deref(), page_id)?;
+        self.record_page_tags(url, &page.document, &page.text, page_id)?;
+        self.record_page_meta(&page.meta, page_id)?;
+        self.record_page_links(
+            url,
+            &page.document,
+            page_id,
+            domain_id,
+            page.directives,
+            depth,
+            queue_links,
+        )
+    }
+
+    /// Parses the stored bodies of pages again and records what is found on them, replacing their text, search
+    /// index, term counts, tags, metadata and links, without any request.
+    ///
+    /// This applies changes to link extraction, normalization, tag rules or languages to pages already crawled. The
+    /// robots directives of `X-Robots-Tag` headers are read from the latest fetch of each page. Links are recorded
+    /// but not queued; `resume --from-links` queues those never crawled. Pages are indexed or not by the current
+    /// rules. The contents of pages skipped as `noindex`, in another language or as canonical variants were not
+    /// saved, so these pages are reconsidered by their recorded language and directives: those no longer skipped are
+    /// queued with their validators cleared, for `resume --revalidate` to fetch them again in full. Other pages whose
+    /// contents were not saved are left as they are.
+    ///
+    /// # Returns
+    /// The number of pages reprocessed or queued, and the number whose body could not be read.
+    pub async fn reprocess_pages(&mut self) -> Result<(usize, usize), CrawlerError> {
+        let pages = self
+            .db_connection
+            .prepare(
+                "SELECT Page.Id, Page.Url, Page.Charset,
+                    (SELECT Headers FROM PageFetch WHERE PageId = Page.Id ORDER BY Id DESC LIMIT 1),
+                    Page.Indexed, Page.NoIndex, PageMeta.Language, PageMeta.Canonical
+                FROM Page LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+                WHERE Page.DryRun = 0 ORDER BY Page.Id",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    (
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ),
+                    (
+                        row.get::<_, bool>(4)?,
+                        row.get::<_, bool>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                        row.get::<_, Option<String>>(7)?,
+                    ),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let reader = BodyReader::open(&self.paths)?.with_body_store(self.store.body_store());
+
+        let (mut reprocessed, mut missing) = (0, 0);
+        for ((page_id, url, charset, headers), (indexed, noindex, language, canonical)) in pages {
+            if self.shutdown.is_requested() {
+                break;
+            }
+            let mut body = Vec::new();
+            let read = match reader.open_url(&url).await {
+                Ok(mut stream) => stream.read_to_end(&mut body).await.map_err(Into::into),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = read {
+                if indexed {
+                    warn!("Failed to read the stored body of {}: {}", url, e);
+                    missing += 1;
+                    continue;
+                }
+                let is_variant = canonical.is_some_and(|canonical| canonical != url);
+                let in_language = self.is_in_language(language.as_deref());
+                if self.skip_reason(noindex, in_language, is_variant).is_none() {
+                    info!("Queueing {}, which is no longer skipped", url);
+                    self.db_connection.execute(
+                        "UPDATE Page SET ETag = NULL, LastModified = NULL WHERE Id = ?",
+                        [page_id],
+                    )?;
+                    let parsed = Url::parse(&url)?;
+                    self.queue_url(url, 0, &parsed)?;
+                    reprocessed += 1;
+                }
+                continue;
+            }
+            let url = Url::parse(&url)?;
+            let headers = stored_headers(headers.as_deref());
+            let page = self.parse_page(&decode_saved(&body, charset.as_deref()), &url, &headers);
+            let is_variant = page
+                .meta
+                .canonical
+                .as_ref()
+                .is_some_and(|canonical| canonical != &url);
+            let skip_reason =
+                self.skip_reason(page.directives.noindex, page.in_language, is_variant);
+            self.db_connection.execute_batch("BEGIN IMMEDIATE")?;
+            let recorded = self
+                .db_connection
+                .execute(
+                    "UPDATE Page SET NoIndex = ?, NoFollow = ?, Indexed = ? WHERE Id = ?",
+                    params![
+                        page.directives.noindex,
+                        page.directives.nofollow,
+                        skip_reason.is_none(),
+                        page_id
+                    ],
+                )
+                .map_err(CrawlerError::from)
+                .and_then(|_| {
+                    self.record_parsed_page(&url, &page, body.len(), page_id, None, 0, false)
+                });
+            match recorded {
+                Ok(_) => self.db_connection.execute_batch("COMMIT")?,
+                Err(e) => {
+                    self.db_connection.execute_batch("ROLLBACK")?;
+                    return Err(e);
+                }
+            }
+            reprocessed += 1;
+        }
+        Ok((reprocessed, missing))
+    }
+
+    /// Passes a response to the `on_fetch` method of every page handler.
+    ///
+    /// # Arguments
+    /// * `fetch` - The response.
+    /// * `body` - The body of the response, if it was read.
+    async fn call_fetch_handlers(&self, fetch: &FetchRecord<'_>, body: Option<&[u8]>) {
+        if self.handlers.is_empty() {
+            return;
+        }
+        let page = PageFetch {
+            url: fetch.url,
+            final_url: fetch.final_url,
+            status: fetch.status,
+            headers: fetch.headers,
+            body,
+            depth: fetch.depth,
+        };
+        for handler in &self.handlers {
+            handler.on_fetch(&page).await;
+        }
+    }
+
+    /// Marks a URL as visited during this run.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn mark_visited(&mut self, url: String) -> Result<(), CrawlerError> {
+        self.visited.insert(url)?;
+        self.memory.visited = self.visited.memory_bytes();
+        Ok(())
+    }
+
+    /// Refreshes the tracked memory usage, records new peaks, and spills the frontier while above the memory cap.
+    ///
+    /// Spilling stops once usage falls below 80% of the cap.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn update_memory_usage(&mut self) -> Result<(), CrawlerError> {
+        self.memory.frontier = self.url_queue.memory_bytes();
+        let total = self.memory.total();
+        self.batch_memory = self.batch_memory.max(total);
+        if total > self.peak_memory {
+            self.peak_memory = total;
+            self.db_connection.execute(
+                "UPDATE CrawlRun SET PeakMemory = ? WHERE Id = ?",
+                params![total, self.run_id],
+            )?;
+        }
+
+        // A shared frontier is always kept in the database
+        if let (Some(cap), false) = (self.memory_cap, self.url_queue.is_shared()) {
+            if total > cap && !self.url_queue.is_spilling() {
+                warn!(
+                    "Memory usage of {} bytes exceeds the cap, spilling the frontier to the database",
+                    total
+                );
+                self.url_queue.set_spilling(true);
+            } else if total < cap / 10 * 8 && self.url_queue.is_spilling() {
+                info!(
+                    "Memory usage of {} bytes is below the cap, no longer spilling",
+                    total
+                );
+                self.url_queue.set_spilling(false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Halves the number of pages fetched at a time after a batch used more memory than the cap, and doubles it
+    /// again, up to the concurrency, after a batch used less than 80% of the cap.
+    fn adjust_batch_concurrency(&mut self) {
+        if let Some(cap) = self.memory_cap {
+            if self.batch_memory > cap && self.batch_concurrency > 1 {
+                self.batch_concurrency /= 2;
+                warn!(
+                    "Memory usage of {} bytes exceeds the cap, fetching {} pages at a time",
+                    self.batch_memory, self.batch_concurrency
+                );
+            } else if self.batch_memory < cap / 10 * 8 && self.batch_concurrency < self.concurrency
+            {
+                self.batch_concurrency = (self.batch_concurrency * 2).min(self.concurrency);
+                info!(
+                    "Memory usage of {} bytes is below the cap, fetching {} pages at a time",
+                    self.batch_memory, self.batch_concurrency
+                );
+            }
+        }
+        self.batch_memory = self.memory.total();
+    }
+
+    /// Switches to new rate limits, URL patterns and budgets, between iterations of a running crawl. Queued URLs
+    /// are checked against the new URL patterns when they are dequeued.
+    ///
+    /// # Arguments
+    /// * `settings` - The new settings, replacing all of the previous ones.
+    ///
+    /// # Returns
+    /// A Result indicating success, or an error if a URL pattern is invalid, in which case nothing is changed.
+    pub fn apply_settings(&mut self, settings: &LiveSettings) -> Result<(), CrawlerError> {
+        if settings.error_budget == Some(0) {
+            return Err(CrawlerError::Config(
+                "The error budget must be at least 1".into(),
+            ));
+        }
+        self.url_filter = UrlFilter::new(&settings.include, &settings.exclude)?;
+        self.min_crawl_delay = settings.crawl_delay;
+        self.domain_crawl_delays = settings
+            .domain_crawl_delays
+            .iter()
+            .map(|(domain, delay)| (domain.clone(), *delay))
+            .collect();
+        self.ban_policy = settings.ban_policy;
+        self.error_budget = settings.error_budget;
+        self.max_pages = settings.max_pages;
+        self.max_pages_per_domain = settings.max_pages_per_domain;
+        self.domain_pages = match (self.max_pages_per_domain, self.domain_pages.take()) {
+            (None, _) => None,
+            (Some(_), Some(counts)) => Some(counts),
+            (Some(_), None) => Some(count_domain_pages(&self.db_connection)?),
+        };
+        Ok(())
+    }
+
+    /// Returns the number of pages stored during this run, not counting pages whose contents were not saved.
+    pub fn stored_pages(&self) -> usize {
+        self.stored_pages
+    }
+
+    /// Returns what a dry run would have saved, or None if this is not a dry run.
+    pub fn dry_run_totals(&self) -> Option<&DryRunTotals> {
+        self.dry_run.as_ref()
+    }
+
+    /// Returns the number of pages stored by a previous run whose contents changed during this run.
+    pub fn changed_pages(&self) -> usize {
+        self.changed_pages
+    }
+
+    /// Returns whether as many pages are stored as the page budget allows, which ends the crawl.
+    pub fn is_page_budget_spent(&self) -> bool {
+        self.max_pages
+            .is_some_and(|max_pages| self.stored_pages >= max_pages)
+    }
+
+    /// Returns the highest approximate memory usage seen during this run, in bytes.
+    pub fn peak_memory(&self) -> usize {
+        self.peak_memory
+    }
+
+    /// Saves the URLs left in the frontier for a later run to resume, and waits for the queued page files to be written
+    /// to disk. Must be called before the program exits.
+    ///
+    /// # Returns
+    /// An error if the frontier could not be saved or any page file could not be written.
+    pub async fn finish(mut self) -> Result<(), CrawlerError> {
+        self.release_parked(true)?;
+        let left = self
+            .url_queue
+            .save(crawl_store(&self.db_connection, &self.shared))?;
+        if left > 0 {
+            info!(
+                "{} URLs are left in the frontier, continue with resume",
+                left
+            );
+        }
+        if !self.image_queue.is_empty() {
+            info!(
+                "{} images are left to download, continue with resume",
+                self.image_queue.len()
+            );
+        }
+        if let Some(progress) = self.events.progress() {
+            progress.finish();
+        }
+        let count = |table: &str| {
+            self.db_connection.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE RunId = ?", table),
+                [self.run_id],
+                |row| row.get::<_, i64>(0),
+            )
+        };
+        // Reprocessing does not take URLs from the frontier, so those left in it do not make it incomplete
+        let outcome = match (self.shutdown.is_requested(), left) {
+            (true, _) => "stopped",
+            (false, 0) => "completed",
+            (false, _) if self.reprocess => "completed",
+            (false, _) => "incomplete",
+        };
+        self.db_connection.execute(
+            "UPDATE CrawlRun SET Finished = CURRENT_TIMESTAMP, Outcome = ?, Pages = ?, Fetches = ?, Failures = ?,
+                FrontierLeft = ?
+            WHERE Id = ?",
+            params![
+                outcome,
+                count("RunPage")?,
+                count("PageFetch")?,
+                count("FailedUrl")?,
+                left,
+                self.run_id
+            ],
+        )?;
+        if let Some(webhook) = &self.webhook {
+            webhook.send(WebhookEvent::CrawlCompleted {
+                pages: count("Page")?,
+                fetches: count("PageFetch")?,
+                failed: count("FailedUrl")?,
+                frontier: left,
+            });
+        }
+        let Crawler {
+            store,
+            assets,
+            images,
+            events,
+            writer_handle,
+            webhook,
+            webhook_handle,
+            ..
+        } = self;
+        drop(store);
+        drop(assets);
+        drop(images);
+        drop(events);
+        drop(webhook);
+        if let Some(webhook_handle) = webhook_handle {
+            webhook_handle.finish().await;
+        }
+        writer_handle.finish().await?;
+        Ok(())
+    }
+
+    /// Queues an event to be posted to the event webhook, if there is one.
+    fn send_webhook_event(&self, event: WebhookEvent) {
+        if let Some(webhook) = &self.webhook {
+            webhook.send(event);
+        }
+    }
+
+    /// Returns the id of the crawl run this instance records pages under.
+    pub fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    /// Perform a single crawl iteration.
+    ///
+    /// An iteration takes a batch of URLs from the queue, up to the concurrency and the concurrency per domain, fetches
+    /// the crawlable ones at the same time, and records the responses one by one. A URL that fails does not stop the
+    /// others of its batch. With a page budget, a batch holds no more URLs than there are pages left to store, so the
+    /// requests in flight when the budget is spent are finished, but no more are sent. Images shown on pages fill the
+    /// rest of the batch from their own queue, and are still downloaded once the page budget is spent. When the
+    /// frontier is shared and other workers hold every URL left, the iteration waits until one can be claimed or none
+    /// are left.
+    ///
+    /// # Returns
+    /// `true` if there are more URLs or images to crawl, `false` if there are none or the page budget is spent and no
+    /// images are left, or the first fatal error of the batch, see [`CrawlerError::is_fatal`]. Other errors only stop
+    /// the crawl of their URL, which is recorded in `FailedUrl` and the events log. An error is also returned once a
+    /// body could not be written.
+    pub async fn crawl(&mut self) -> Result<bool, CrawlerError> {
+        // Bodies are written in the background, so pages are recorded before a failed write shows. Stopping at once
+        // keeps the pages that refer to unwritten bodies to those of the batches written since
+        let failed_writes = self.writer_handle.failures();
+        if failed_writes > 0 {
+            return Err(CrawlerError::Io(std::io::Error::other(format!(
+                "{} file writes failed, the latest pages may refer to bodies that were not saved",
+                failed_writes
+            ))));
+        }
+        if (self.is_page_budget_spent() && self.image_queue.is_empty())
+            || self.shutdown.is_requested()
+        {
+            return Ok(false);
+        }
+        self.adjust_batch_concurrency();
+        let batch_size = match self.max_pages {
+            Some(max_pages) => self
+                .batch_concurrency
+                .min(max_pages.saturating_sub(self.stored_pages)),
+            None => self.batch_concurrency,
+        };
+        self.release_parked(false)?;
+        let mut requests = Vec::new();
+        let mut deferred = Vec::new();
+        let mut parked = HashSet::new();
+        let mut popped = Vec::new();
+        let mut domains: HashMap<i64, usize> = HashMap::new();
+        let mut first_error = None;
+        loop {
+            while requests.len() < batch_size && deferred.len() < self.concurrency {
+                let Some((url, queued, score)) = self
+                    .url_queue
+                    .pop(crawl_store(&self.db_connection, &self.shared))?
+                else {
+                    break;
+                };
+                popped.push(url.clone());
+                let result = match Url::parse(&url) {
+                    Ok(parsed) => match self.record_domain(&parsed) {
+                        Ok(domain_id) if self.is_domain_paused(domain_id) => {
+                            parked.insert(url.clone());
+                            self.parked.entry(domain_id).or_default().push((
+                                url.clone(),
+                                queued,
+                                score,
+                            ));
+                            Ok(None)
+                        }
+                        Ok(domain_id)
+                            if domains.get(&domain_id).copied().unwrap_or(0)
+                                >= self.concurrency_of(&parsed) =>
+                        {
+                            deferred.push((url.clone(), queued, score));
+                            Ok(None)
+                        }
+                        Ok(domain_id) => {
+                            *domains.entry(domain_id).or_default() += 1;
+                            let request =
+                                self.prepare_request(parsed, queued, score, domain_id).await;
+                            if let Ok(Some(request)) = &request {
+                                self.record_frontier_wait(request, queued)?;
+                            }
+                            request
+                        }
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e.into()),
+                };
+                match result {
+                    Ok(request) => requests.extend(request),
+                    Err(e) => {
+                        self.depths.remove(&url);
+                        self.record_crawl_error(&url, e, &mut first_error).await?
+                    }
+                }
+            }
+            // Other workers hold the remaining URLs, and may queue more once they are crawled, so wait for them
+            // within this iteration rather than spend iterations on empty batches
+            if !popped.is_empty()
+                || !self.url_queue.is_shared()
+                || self.url_queue.is_empty()
+                || !self.image_queue.is_empty()
+                || self.shutdown.is_requested()
+            {
+                break;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(SHARED_CLAIM_WAIT) => {}
+                _ = self.shutdown.requested() => {}
+            }
+        }
+        // Put back the URLs of domains that already have as many requests in this batch as they allow, so they are taken
+        // first next time
+        for (url, queued, score) in deferred.iter().rev() {
+            self.url_queue.push_at(
+                crawl_store(&self.db_connection, &self.shared),
+                url.clone(),
+                *queued,
+                *score,
+            )?;
+        }
+        let mut waiting_images = Vec::new();
+        while requests.len() < self.batch_concurrency {
+            let Some((url, depth)) = self.image_queue.pop_front() else {
+                break;
+            };
+            let result = match Url::parse(&url) {
+                Ok(parsed) => match self.record_domain(&parsed) {
+                    Ok(domain_id)
+                        if self.is_domain_paused(domain_id)
+                            || domains.get(&domain_id).copied().unwrap_or(0)
+                                >= self.concurrency_of(&parsed) =>
+                    {
+                        waiting_images.push((url.clone(), depth));
+                        Ok(None)
+                    }
+                    Ok(domain_id) => {
+                        *domains.entry(domain_id).or_default() += 1;
+                        self.prepare_request(parsed, SystemTime::now(), 0.0, domain_id)
+                            .await
+                            .map(|request| {
+                                request.map(|request| PageRequest {
+                                    depth,
+                                    image: true,
+                                    ..request
+                                })
+                            })
+                    }
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(request) => requests.extend(request),
+                Err(e) => self.record_crawl_error(&url, e, &mut first_error).await?,
+            }
+        }
+        for image in waiting_images.into_iter().rev() {
+            self.image_queue.push_front(image);
+        }
+        if requests.is_empty()
+            && deferred.is_empty()
+            && self.url_queue.is_empty()
+            && (!self.parked.is_empty() || !self.image_queue.is_empty())
+        {
+            // Only domains that throttled the crawler have URLs or images left, so wait until the first of them may be
+            // requested again
+            if let Some(pause) = self
+                .throttles
+                .values()
+                .filter_map(Throttle::remaining)
+                .min()
+            {
+                tokio::select! {
+                    _ = tokio::time::sleep(pause) => {}
+                    _ = self.shutdown.requested() => {}
+                }
+            }
+        }
+
+        let fetches = join_all(requests.iter().map(|request| self.fetch_page(request)));
+        let pages = tokio::select! {
+            pages = fetches => pages,
+            _ = async {
+                self.shutdown.requested().await;
+                tokio::time::sleep(self.shutdown_grace).await;
+            } => Vec::new(),
+        };
+        if pages.len() < requests.len() {
+            warn!(
+                "Putting back {} URLs whose requests did not finish before the shutdown",
+                requests.len()
+            );
+            for request in &requests {
+                self.visited.remove(request.url.as_str())?;
+                self.requeued_urls.insert(request.url.to_string());
+            }
+        }
+        self.memory.bodies = pages
+            .iter()
+            .filter_map(|page| match page {
+                Ok(FetchedPage {
+                    body: Some(Ok(bytes)),
+                    ..
+                }) => Some(bytes.len()),
+                _ => None,
+            })
+            .sum();
+        for (request, page) in requests.iter().zip(pages) {
+            let result = match page {
+                Ok(page) => self.process_page(request, page).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                self.record_crawl_error(request.url.as_str(), e, &mut first_error)
+                    .await?;
+            }
+        }
+        // Put back the throttled URLs, which are taken once their domain may be requested again, and those cut short
+        let requeued_urls = std::mem::take(&mut self.requeued_urls);
+        for request in requests
+            .iter()
+            .filter(|request| requeued_urls.contains(request.url.as_str()))
+        {
+            if request.image {
+                self.image_queue
+                    .push_front((request.url.to_string(), request.depth));
+                continue;
+            }
+            self.depths.insert(request.url.to_string(), request.depth);
+            self.url_queue.push_at(
+                crawl_store(&self.db_connection, &self.shared),
+                request.url.to_string(),
+                request.queued,
+                request.score,
+            )?;
+        }
+        for url in popped.iter().filter(|url| {
+            !deferred.iter().any(|(deferred, _, _)| deferred == *url)
+                && !parked.contains(*url)
+                && !requeued_urls.contains(*url)
+        }) {
+            self.url_queue
+                .ack(crawl_store(&self.db_connection, &self.shared), url)?;
+        }
+        self.memory.bodies = 0;
+        self.memory.parse_buffers = 0;
+        self.update_memory_usage()?;
+        if let Some(progress) = self.events.progress() {
+            progress.set_queued(
+                self.url_queue.len() + self.parked.values().map(Vec::len).sum::<usize>(),
+            );
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok((((!self.url_queue.is_empty() || !self.parked.is_empty())
+                && !self.is_page_budget_spent())
+                || !self.image_queue.is_empty())
+                && !self.shutdown.is_requested()),
+        }
+    }
+
+    /// Puts the URLs parked for paused domains back into the queue, in the order they were taken.
+    ///
+    /// # Arguments
+    /// * `all` - Whether the URLs of domains that are still paused are put back as well, as when the crawl ends.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    fn release_parked(&mut self, all: bool) -> Result<(), CrawlerError> {
+        let domain_ids = self
+            .parked
+            .keys()
+            .copied()
+            .filter(|domain_id| all || !self.is_domain_paused(*domain_id))
+            .collect::<Vec<_>>();
+        for domain_id in domain_ids {
+            let urls = self.parked.remove(&domain_id).unwrap_or_default();
+            for (url, queued, score) in urls.into_iter().rev() {
+                self.url_queue.push_at(
+                    crawl_store(&self.db_connection, &self.shared),
+                    url,
+                    queued,
+                    score,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether a domain that throttled the crawler is paused.
+    fn is_domain_paused(&self, domain_id: i64) -> bool {
+        self.throttles
+            .get(&domain_id)
+            .is_some_and(|throttle| throttle.remaining().is_some())
+    }
+
+    /// Returns the MIME type of a response, without parameters, if it is an image and images are downloaded.
+    fn image_type(&self, headers: &HeaderMap) -> Option<String> {
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|mime_type| mime_type.trim().to_ascii_lowercase())
+            .filter(|mime_type| self.images.is_some() && mime_type.starts_with("image/"))
+    }
+
+    /// Returns how many pages of the domain of a URL are fetched at the same time.
+    fn concurrency_of(&self, url: &Url) -> usize {
+        url.host_str()
+            .and_then(|host| self.domain_concurrency_overrides.get(host))
+            .copied()
+            .unwrap_or(self.domain_concurrency)
+    }
+
+    /// Reports an error that stopped the crawl of a URL in the events log, keeping the first fatal error of a batch.
+    async fn record_crawl_error(
+        &mut self,
+        url: &str,
+        error: CrawlerError,
+        first_error: &mut Option<CrawlerError>,
+    ) -> Result<(), CrawlerError> {
+        warn!(url, error = %error, "Failed to crawl {}: {}", url, error);
+        let message = error.to_string();
+        let class = FailureClass::of_error(&error);
+        if let Ok(parsed) = Url::parse(url) {
+            let domain_id = self.find_domain_id(&parsed).ok().flatten();
+            if let Some(domain_id) = domain_id {
+                self.record_domain_error(domain_id)?;
+            }
+            if let Some(class) = class {
+                self.record_failure(url, domain_id, class, &message)?;
+            }
+            for handler in &self.handlers {
+                handler.on_error(&parsed, &message).await;
+            }
+        }
+        self.events
+            .emit(&CrawlEvent::Error {
+                url,
+                error: &message,
+            })
+            .await?;
+        if error.is_fatal() {
+            first_error.get_or_insert(error);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the response headers recorded for a fetch in the `Headers` column of `PageFetch`.
+fn stored_headers(json: Option<&str>) -> HeaderMap {
+    let headers: serde_json::Map<String, serde_json::Value> = json
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default();
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(value.as_str()?).ok()?,
+            ))
+        })
+        .collect()
+}
+
+/// Returns the host and port of a URL, e.g. `example.com:443`.
+fn host_and_port(url: &Url) -> Option<String> {
+    Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ))
+}
+
+/// Counts the stored pages of each host, by any run.
+fn count_domain_pages(connection: &Connection) -> Result<HashMap<String, usize>, CrawlerError> {
+    let mut counts = HashMap::new();
+    let mut stmt = connection.prepare("SELECT Url FROM Page")?;
+    let urls = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    for url in urls {
+        if let Some(host) = Url::parse(&url?)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        {
+            *counts.entry(host).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Returns where the records shared with other workers are kept: the Postgres database if there is one, otherwise the
+/// crawl database itself. Takes the two fields rather than the crawler, so that the frontier can be borrowed mutably
+/// alongside.
+fn crawl_store<'a>(
+    connection: &'a Connection,
+    shared: &'a Option<PostgresStore>,
+) -> &'a dyn CrawlStore {
+    match shared {
+        Some(shared) => shared,
+        None => connection,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::FetchMode;
+    use crate::images::ImageLimits;
+    use crate::render::RenderPolicy;
+    use crate::routing::RulesFile;
+    use crate::scoring::WeightedScorer;
+    use crate::throttle::{MAX_THROTTLE_DELAY, MAX_THROTTLE_PAUSE};
+    use crate::visited::BloomSettings;
+    use std::fs;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay");
+    const SITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/site");
+    const REDIRECT_FIXTURES: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/redirects");
+    const IMAGE_SITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/images");
+    const ASSET_SITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/assets");
+    const SITEMAP_SITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sitemap");
+    const LANGUAGE_SITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/languages");
+
+    /// Creates a fresh directory for a test crawl, in which the crawler creates the database.
+    fn test_paths(name: &str) -> CrawlPaths {
+        let dir = std::env::temp_dir().join(format!("web_crawler_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let paths = CrawlPaths::in_dir(&dir);
+        fs::create_dir_all(&paths.save_dir).unwrap();
+        paths
+    }
+
+    /// Returns the URLs of the stored pages, and whether each is a duplicate.
+    fn stored_pages(connection: &Connection) -> Vec<(String, bool)> {
+        connection
+            .prepare("SELECT Url, DuplicateOf IS NOT NULL FROM Page ORDER BY Id")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    /// Crawls the recorded site in a fresh directory.
+    #[tokio::test]
+    async fn replays_recorded_crawl() {
+        let paths = test_paths("replay");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Replay(FIXTURES.into()))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let connection = &crawler.db_connection;
+        assert_eq!(
+            stored_pages(connection),
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/copy".to_string(), false),
+                ("http://example.com/about".to_string(), true),
+            ]
+        );
+        let redirect: String = connection
+            .query_row(
+                "SELECT TargetUrl FROM Redirect WHERE SourceUrl = 'http://example.com/old'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(redirect, "http://example.com/about");
+        let links: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM PageLink JOIN Page ON Page.Id = PageId WHERE Page.Url = 'http://example.com/'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(links, 4);
+        let title: String = connection
+            .query_row("SELECT Title FROM PageMeta WHERE PageId = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(title, "Home");
+
+        crawler.finish().await.unwrap();
+        assert_eq!(fs::read_dir(&paths.save_dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Records a URL that fails as failed, and goes on crawling the other URLs.
+    #[tokio::test]
+    async fn keeps_crawling_after_a_failed_url() {
+        let paths = test_paths("failed_url");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let fixtures = dir.join("fixtures");
+        fs::create_dir_all(&fixtures).unwrap();
+        let responses = [
+            (
+                "",
+                "text/html",
+                r#"<a href="/a">A</a> <a href="/missing">Missing</a> <a href="/b">B</a>"#,
+            ),
+            ("a", "text/html", "<p>A</p>"),
+            ("b", "text/html", "<p>B</p>"),
+            ("robots.txt", "text/plain", "User-agent: *\nAllow: /\n"),
+        ];
+        for (path, content_type, body) in responses {
+            let fixture = serde_json::json!({
+                "url": format!("http://example.com/{}", path),
+                "status": 200,
+                "headers": [["content-type", content_type]],
+                "body": body,
+            });
+            fs::write(
+                fixtures.join(format!("example_com_{}.json", path.replace('.', "_"))),
+                fixture.to_string(),
+            )
+            .unwrap();
+        }
+
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Replay(fixtures))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let mut pages = stored_pages(&crawler.db_connection);
+        pages.sort();
+        assert_eq!(
+            pages,
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/a".to_string(), false),
+                ("http://example.com/b".to_string(), false),
+            ]
+        );
+        let failed: String = crawler
+            .db_connection
+            .query_row("SELECT Url FROM FailedUrl", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(failed, "http://example.com/missing");
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Checks the URLs redirects lead to before requesting them, queueing those on other hosts.
+    #[tokio::test]
+    async fn checks_redirect_targets() {
+        let paths = test_paths("redirects");
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Replay(REDIRECT_FIXTURES.into()))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let mut pages = stored_pages(&crawler.db_connection);
+        pages.sort();
+        assert_eq!(
+            pages,
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/new".to_string(), false),
+                ("http://other.test/landing".to_string(), false),
+            ]
+        );
+        let blocked: String = crawler
+            .db_connection
+            .query_row("SELECT Url FROM RobotsBlockedUrl", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(blocked, "http://example.com/private/page");
+        let redirect: String = crawler
+            .db_connection
+            .query_row(
+                "SELECT TargetUrl FROM Redirect WHERE SourceUrl = 'http://example.com/away'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(redirect, "http://other.test/landing");
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Sends a credential to its origin only, and not to the other host or the plain HTTP origin a redirect leads to.
+    #[tokio::test]
+    async fn keeps_credentials_on_their_origin() {
+        use crate::auth::{Credential, Credentials};
+        use std::sync::Mutex;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                    let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                    let host = request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("host: "))
+                        .unwrap_or_default()
+                        .to_string();
+                    let authorized = request.contains("\r\nauthorization: ");
+                    let response = match path.as_str() {
+                        "/" => format!(
+                            "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/next\r\nContent-Length: 0\r\n",
+                            port
+                        ),
+                        "/next" => "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 13\r\n".into(),
+                        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n".into(),
+                    };
+                    let body = if path == "/next" {
+                        "<p>Next</p>\r\n"
+                    } else {
+                        ""
+                    };
+                    seen.lock().unwrap().push((host, path, authorized));
+                    let response = format!("{}Connection: close\r\n\r\n{}", response, body);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        let credential = Credential::Bearer {
+            bearer: "secret".into(),
+        };
+        let origin = Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        let credentials = Credentials::default()
+            .add_origin(&origin, &credential)
+            .unwrap()
+            .add("localhost", &credential)
+            .unwrap();
+        let paths = test_paths("credentials");
+        let mut crawler = Crawler::builder(origin.as_str())
+            .paths(paths.clone())
+            .user_agent("test")
+            .allow_private_networks(true)
+            .credentials(credentials)
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        crawler.finish().await.unwrap();
+
+        let requests = requests.lock().unwrap().clone();
+        let authorized = |host: &str, path: &str| {
+            requests
+                .iter()
+                .find(|(seen_host, seen_path, _)| {
+                    seen_host.split(':').next() == Some(host) && seen_path == path
+                })
+                .map(|(_, _, authorized)| *authorized)
+        };
+        assert_eq!(authorized("127.0.0.1", "/robots.txt"), Some(true));
+        assert_eq!(authorized("127.0.0.1", "/"), Some(true));
+        assert_eq!(authorized("localhost", "/robots.txt"), Some(false));
+        assert_eq!(authorized("localhost", "/next"), Some(false));
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Crawls the other domains while one is paused, and keeps the URLs of the paused domain for a later run.
+    #[tokio::test]
+    async fn parks_urls_of_paused_domains() {
+        let paths = test_paths("paused");
+        let mut crawler = Crawler::builder("http://other.test/landing")
+            .seed("http://example.com/new")
+            .paths(paths.clone())
+            .user_agent("test")
+            .concurrency(1)
+            .fetch_mode(FetchMode::Replay(REDIRECT_FIXTURES.into()))
+            .build()
+            .unwrap();
+        let paused = crawler
+            .record_domain(&Url::parse("http://other.test/").unwrap())
+            .unwrap();
+        crawler.throttles.insert(
+            paused,
+            Throttle {
+                delay: MAX_THROTTLE_DELAY,
+                paused_until: Instant::now() + MAX_THROTTLE_PAUSE,
+            },
+        );
+        // Waiting for the paused domain would take an hour
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while crawler.stored_pages() < 1 {
+                assert!(crawler.crawl().await.unwrap());
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            stored_pages(&crawler.db_connection),
+            [("http://example.com/new".to_string(), false)]
+        );
+        assert_eq!(crawler.parked[&paused].len(), 1);
+        let database = paths.database.clone();
+        crawler.finish().await.unwrap();
+        let connection = Connection::open(&database).unwrap();
+        let left: String = connection
+            .query_row("SELECT Url FROM FrontierUrl", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, "http://other.test/landing");
+        drop(connection);
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Crawls the fixture site served from its directory, following links, redirects and robots.txt rules as on a
+    /// web server.
+    #[tokio::test]
+    async fn crawls_fixture_directory() {
+        let paths = test_paths("fixture_dir");
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(SITE.into()))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        assert!(crawler.depths.is_empty());
+
+        let connection = &crawler.db_connection;
+        let mut pages = stored_pages(connection);
+        pages.sort();
+        assert_eq!(
+            pages,
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/docs/".to_string(), false),
+                (
+                    "http://example.com/docs/guide.html?ref=docs".to_string(),
+                    false
+                ),
+                ("http://example.com/index.html".to_string(), true),
+            ]
+        );
+        let redirect: String = connection
+            .query_row(
+                "SELECT TargetUrl FROM Redirect WHERE SourceUrl = 'http://example.com/docs'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(redirect, "http://example.com/docs/");
+        let blocked: String = connection
+            .query_row("SELECT Url FROM RobotsBlockedUrl", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(blocked, "http://example.com/private/secret.html");
+        let failed: (String, String) = connection
+            .query_row("SELECT Url, Class FROM FailedUrl", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(
+            failed,
+            (
+                "http://example.com/missing.html".to_string(),
+                "client_error".to_string()
+            )
+        );
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Tells the URLs refused by robots.txt or the URL patterns apart with a policy error.
+    #[tokio::test]
+    async fn refuses_urls_by_policy() {
+        let paths = test_paths("check_url");
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(SITE.into()))
+            .exclude(r"\.pdf$")
+            .build()
+            .unwrap();
+
+        crawler.check_url("http://example.com/docs/").await.unwrap();
+        for (url, refusal) in [
+            ("http://example.com/private/secret.html", ROBOTS_DISALLOWED),
+            (
+                "http://example.com/guide.pdf",
+                "Excluded by the URL patterns",
+            ),
+        ] {
+            match crawler.check_url(url).await {
+                Err(CrawlerError::Policy(reason)) => assert_eq!(reason, refusal),
+                other => panic!("{} was not refused: {:?}", url, other.err()),
+            }
+        }
+        assert!(matches!(
+            crawler.check_url("not a url").await,
+            Err(CrawlerError::Parse(_))
+        ));
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Crawls the fixture site in a dry run, then for real in the same database, which stores every page.
+    #[tokio::test]
+    async fn crawls_after_dry_run() {
+        let paths = test_paths("after_dry_run");
+        let mut dry_run = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(SITE.into()))
+            .dry_run(true)
+            .build()
+            .unwrap();
+        while dry_run.crawl().await.unwrap() {}
+        let recorded = stored_pages(&dry_run.db_connection);
+        assert_eq!(dry_run.dry_run_totals().unwrap().pages, 3);
+        dry_run.finish().await.unwrap();
+
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(SITE.into()))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(stored_pages(&crawler.db_connection), recorded);
+        assert_eq!(crawler.stored_pages(), recorded.len());
+        let marked: i64 = crawler
+            .db_connection
+            .query_row("SELECT COUNT(*) FROM Page WHERE DryRun = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(marked, 0);
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Only takes a page for a duplicate of another whose body was hashed with the same algorithm.
+    #[tokio::test]
+    async fn compares_hashes_of_one_algorithm() {
+        let paths = test_paths("hash_algorithms");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(&site).unwrap();
+        let body = "<p>Same page</p>";
+        fs::write(site.join("a.html"), body).unwrap();
+        fs::write(site.join("b.html"), body).unwrap();
+        fs::write(
+            site.join("index.html"),
+            r#"<a href="/a.html">A</a><a href="/b.html">B</a>"#,
+        )
+        .unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site))
+            .build()
+            .unwrap();
+        crawler
+            .db_connection
+            .execute(
+                "INSERT INTO Page (Url, RequestedUrl, Hash, HashAlgorithm) VALUES (?1, ?1, ?2, 'sha256')",
+                [
+                    "http://other.test/".to_string(),
+                    HashAlgorithm::Blake3.digest(body.as_bytes()),
+                ],
+            )
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let mut duplicates: Vec<String> = crawler
+            .db_connection
+            .prepare(
+                "SELECT Page.Url, Original.Url FROM Page JOIN Page AS Original ON Original.Id = Page.DuplicateOf",
+            )
+            .unwrap()
+            .query_map([], |row| Ok(vec![row.get(0)?, row.get(1)?]))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        duplicates.sort();
+        assert_eq!(
+            duplicates,
+            ["http://example.com/a.html", "http://example.com/b.html"]
+        );
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Fetches the stored pages again without following new links, keeping the versions of the pages that changed.
+    #[tokio::test]
+    async fn recrawls_stored_pages() {
+        let paths = test_paths("recrawl");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(&site).unwrap();
+        fs::write(site.join("index.html"), r#"<a href="/a.html">A</a>"#).unwrap();
+        fs::write(site.join("a.html"), "<p>First</p>").unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site.clone()))
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        crawler.finish().await.unwrap();
+
+        fs::write(
+            site.join("a.html"),
+            r#"<p>Second</p><a href="/new.html">New</a>"#,
+        )
+        .unwrap();
+        fs::write(site.join("new.html"), "<p>New</p>").unwrap();
+        let recrawl = || {
+            Crawler::recrawl()
+                .paths(paths.clone())
+                .user_agent("test")
+                .fetch_mode(FetchMode::Directory(site.clone()))
+                .build()
+                .unwrap()
+        };
+        let revisions = |crawler: &Crawler| {
+            crawler
+                .db_connection
+                .prepare(
+                    "SELECT Page.Url, COUNT(*) FROM PageRevision JOIN Page ON Page.Id = PageRevision.PageId
+                    GROUP BY Page.Url ORDER BY Page.Url",
+                )
+                .unwrap()
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        let mut crawler = recrawl();
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(crawler.changed_pages(), 1);
+        assert_eq!(
+            stored_pages(&crawler.db_connection),
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/a.html".to_string(), false),
+            ]
+        );
+        // The version stored before the first change is kept with the new one
+        assert_eq!(
+            revisions(&crawler),
+            [("http://example.com/a.html".to_string(), 2)]
+        );
+        crawler.finish().await.unwrap();
+
+        let mut crawler = recrawl();
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(crawler.changed_pages(), 0);
+        assert_eq!(
+            revisions(&crawler),
+            [("http://example.com/a.html".to_string(), 2)]
+        );
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Fetches no more pages of a domain at the same time than it allows, leaving the others for the next batches.
+    #[tokio::test]
+    async fn limits_requests_per_domain() {
+        let paths = test_paths("domain_concurrency");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(&site).unwrap();
+        let mut index = String::new();
+        for page in ["a", "b", "c", "d"] {
+            fs::write(site.join(format!("{}.html", page)), "<p>Page</p>").unwrap();
+            index.push_str(&format!(r#"<a href="/{}.html">{}</a>"#, page, page));
+        }
+        for page in ["a", "b"] {
+            index.push_str(&format!(
+                r#"<a href="http://other.test/{}.html">{}</a>"#,
+                page, page
+            ));
+        }
+        fs::write(site.join("index.html"), index).unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site))
+            .concurrency(4)
+            .domain_concurrency(2)
+            .domain_concurrency_override("other.test", 1)
+            .build()
+            .unwrap();
+        let hosts = |crawler: &Crawler| {
+            let mut hosts = HashMap::new();
+            for (url, _) in stored_pages(&crawler.db_connection) {
+                let host = Url::parse(&url).unwrap().host_str().unwrap().to_string();
+                *hosts.entry(host).or_insert(0) += 1;
+            }
+            hosts
+        };
+
+        assert!(crawler.crawl().await.unwrap());
+        assert!(crawler.crawl().await.unwrap());
+        assert_eq!(
+            hosts(&crawler),
+            HashMap::from([
+                ("example.com".to_string(), 3),
+                ("other.test".to_string(), 1)
+            ])
+        );
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(
+            hosts(&crawler),
+            HashMap::from([
+                ("example.com".to_string(), 5),
+                ("other.test".to_string(), 2)
+            ])
+        );
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Fetches as many pages at a time as the concurrency allows, but no more than the pages left of the budget.
+    #[tokio::test]
+    async fn fetches_pages_in_batches() {
+        let paths = test_paths("batches");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(&site).unwrap();
+        let mut index = String::new();
+        for page in ["a", "b", "c", "d", "e", "f"] {
+            fs::write(site.join(format!("{}.html", page)), "<p>Page</p>").unwrap();
+            index.push_str(&format!(r#"<a href="/{}.html">{}</a>"#, page, page));
+        }
+        fs::write(site.join("index.html"), index).unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site))
+            .concurrency(3)
+            .domain_concurrency(3)
+            .max_pages(Some(5))
+            .build()
+            .unwrap();
+
+        assert!(crawler.crawl().await.unwrap());
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 1);
+        assert!(crawler.crawl().await.unwrap());
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 4);
+        crawler.crawl().await.unwrap();
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 5);
+        assert!(!crawler.crawl().await.unwrap());
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 5);
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Fetches fewer pages at a time after a batch used more memory than the cap.
+    #[tokio::test]
+    async fn reduces_concurrency_above_the_memory_cap() {
+        let paths = test_paths("memory_cap");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(&site).unwrap();
+        let mut index = String::new();
+        for page in ["a", "b", "c", "d", "e", "f"] {
+            fs::write(site.join(format!("{}.html", page)), "<p>Page</p>").unwrap();
+            index.push_str(&format!(r#"<a href="/{}.html">{}</a>"#, page, page));
+        }
+        fs::write(site.join("index.html"), index).unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site))
+            .concurrency(4)
+            .domain_concurrency(4)
+            .memory_cap(Some(1))
+            .build()
+            .unwrap();
+
+        for stored in [1, 3, 4, 5] {
+            assert!(crawler.crawl().await.unwrap());
+            assert_eq!(stored_pages(&crawler.db_connection).len(), stored);
+        }
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Saves URLs that map to the same file of a mirror to files of their own.
+    #[tokio::test]
+    async fn keeps_mirror_files_apart() {
+        let paths = test_paths("mirror_files");
+        let dir = paths.database.parent().unwrap().to_path_buf();
+        let site = dir.join("site");
+        fs::create_dir_all(site.join("a")).unwrap();
+        fs::write(site.join("a").join("index.html"), "<p>Page</p>").unwrap();
+        fs::write(
+            site.join("index.html"),
+            r#"<a href="/a/">A</a><a href="/a/index.html">Index</a>"#,
+        )
+        .unwrap();
+        let rules: RulesFile = serde_json::from_value(serde_json::json!({
+            "storage": [{"pattern": "/a/**", "format": "mirror", "dir": dir.join("mirror")}]
+        }))
+        .unwrap();
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(site))
+            .storage_routes(rules.storage_routes().unwrap())
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let files: Vec<String> = crawler
+            .db_connection
+            .prepare("SELECT StoredFile FROM Page WHERE StoredFile IS NOT NULL ORDER BY Id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        crawler.finish().await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_ne!(files[0], files[1]);
+        for file in &files {
+            assert_eq!(fs::read_to_string(file).unwrap(), "<p>Page</p>");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Records a link to a page by the anchor leading to it, even after another element referred to its URL.
+    #[tokio::test]
+    async fn prefers_navigable_link_elements() {
+        let paths = test_paths("navigable_links");
+        let mut crawler = Crawler::builder("http://example.com/docs/guide.html")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(SITE.into()))
+            .link_elements(vec![LinkElement::A, LinkElement::Link])
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let link: (String, String, String) = crawler
+            .db_connection
+            .query_row(
+                "SELECT PageLink.Element, PageLink.AnchorText, PageLink.Section FROM PageLink
+                JOIN Page ON Page.Id = PageLink.PageId
+                WHERE Page.Url = 'http://example.com/docs/guide.html' AND PageLink.Url = 'http://example.com/'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            link,
+            ("a".to_string(), "Home".to_string(), "body".to_string())
+        );
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Crawls the fixture site from a `file://` seed, under the local host.
+    #[tokio::test]
+    async fn crawls_file_seed() {
+        let paths = test_paths("file_seed");
+        let seed = Url::from_file_path(format!("{}/docs/guide.html", SITE)).unwrap();
+        let mut crawler = Crawler::builder(seed.as_str())
+            .paths(paths.clone())
+            .user_agent("test")
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+
+        let pages = stored_pages(&crawler.db_connection);
+        assert_eq!(pages[0].0, "http://localhost/guide.html");
+        assert_eq!(pages[1].0, "http://localhost/");
+        assert!(pages
+            .iter()
+            .all(|(url, _)| url.starts_with("http://localhost/")));
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn refuses_render_without_private_networks() {
+        let paths = test_paths("render_private");
+        let builder = |allow| {
+            Crawler::builder("http://example.com/")
+                .paths(paths.clone())
+                .user_agent("test")
+                .render_policy(RenderPolicy {
+                    domains: HashMap::from([("app.example.com".to_string(), true)]),
+                    ..RenderPolicy::default()
+                })
+                .allow_private_networks(allow)
+        };
+        match builder(false).build() {
+            Err(CrawlerError::Config(message)) => assert!(message.contains("private networks")),
+            _ => panic!("rendering was allowed without private networks"),
+        }
+        if cfg!(not(feature = "headless")) {
+            match builder(true).build() {
+                Err(CrawlerError::Config(message)) => assert!(message.contains("headless")),
+                _ => panic!("rendering was allowed without the headless feature"),
+            }
+        }
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn skips_invalid_seeds() {
+        let paths = test_paths("invalid_seeds");
+        let builder = || {
+            Crawler::builder("not a url")
+                .seed("ftp://example.com/")
+                .paths(paths.clone())
+                .fetch_mode(FetchMode::Directory(SITE.into()))
+                .user_agent("test")
+        };
+        assert!(matches!(builder().build(), Err(CrawlerError::Config(_))));
+
+        let mut crawler = builder()
+            .seed("http://example.com/docs/guide.html")
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        assert!(stored_pages(&crawler.db_connection)
+            .iter()
+            .any(|(url, _)| url == "http://example.com/docs/guide.html"));
+
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Stops storing the pages of a domain at the cap, counting the pages stored by earlier runs.
+    #[tokio::test]
+    async fn caps_stored_pages_per_domain() {
+        let paths = test_paths("domain_cap");
+        let crawl = |seed: &str, cap| {
+            Crawler::builder(seed)
+                .paths(paths.clone())
+                .user_agent("test")
+                .fetch_mode(FetchMode::Directory(SITE.into()))
+                .max_pages_per_domain(Some(cap))
+                .build()
+                .unwrap()
+        };
+        let mut crawler = crawl("http://example.com/", 2);
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 2);
+        crawler.finish().await.unwrap();
+
+        let mut crawler = crawl("http://example.com/docs/guide.html?ref=docs", 2);
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 2);
+        crawler.finish().await.unwrap();
+
+        let mut crawler = crawl("http://example.com/docs/guide.html?ref=docs", 3);
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(stored_pages(&crawler.db_connection).len(), 3);
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn crawls_ip_hosts() {
+        for (name, seed, host) in [
+            ("ipv4_host", "http://192.0.2.1/", "192.0.2.1"),
+            ("ipv6_host", "http://[2001:db8::1]/", "[2001:db8::1]"),
+        ] {
+            let paths = test_paths(name);
+            let mut crawler = Crawler::builder(seed)
+                .paths(paths.clone())
+                .user_agent("test")
+                .fetch_mode(FetchMode::Directory(SITE.into()))
+                .max_pages_per_domain(Some(10))
+                .build()
+                .unwrap();
+            while crawler.crawl().await.unwrap() {}
+
+            let domains = crawler
+                .db_connection
+                .prepare("SELECT Name FROM Domain")
+                .unwrap()
+                .query_map([], |row| row.get::<_, String>(0))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(domains, [host]);
+            let pages = stored_pages(&crawler.db_connection);
+            assert!(pages.len() > 1);
+            assert!(!pages.iter().any(|(url, _)| url.contains("/private/")));
+
+            crawler.finish().await.unwrap();
+            fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+        }
+    }
+
+    /// Downloads images outside the page budget, and records those above the size limit so that they are not
+    /// downloaded again.
+    #[tokio::test]
+    async fn downloads_images_apart_from_pages() {
+        let paths = test_paths("images");
+        let limits = ImageLimits {
+            mime_types: vec!["image/png".into()],
+            max_size: 1024,
+        };
+        let fetches = |connection: &Connection, url: &str| -> i64 {
+            connection
+                .query_row(
+                    "SELECT COUNT(*) FROM PageFetch WHERE Url = ?",
+                    [url],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(IMAGE_SITE.into()))
+            .max_pages(Some(1))
+            .download_images(limits.clone())
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        let connection = &crawler.db_connection;
+        assert_eq!(stored_pages(connection).len(), 1);
+        let saved: (String, i64, i64) = connection
+            .query_row("SELECT Url, Width, Height FROM Image", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(saved, ("http://example.com/small.png".to_string(), 2, 3));
+        let rejected: (String, String, u64) = connection
+            .query_row("SELECT Url, Reason, Size FROM RejectedImage", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        let large_size = fs::metadata(format!("{}/large.png", IMAGE_SITE))
+            .unwrap()
+            .len();
+        assert_eq!(
+            rejected,
+            (
+                "http://example.com/large.png".to_string(),
+                "too_large".to_string(),
+                large_size
+            )
+        );
+        crawler.finish().await.unwrap();
+
+        // The next page shows the same images, which are linked to it without requesting them again
+        let mut crawler = Crawler::resume()
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(IMAGE_SITE.into()))
+            .download_images(limits.clone())
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        let connection = &crawler.db_connection;
+        assert_eq!(stored_pages(connection).len(), 2);
+        assert_eq!(fetches(connection, "http://example.com/small.png"), 1);
+        assert_eq!(fetches(connection, "http://example.com/large.png"), 1);
+        let linked: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM PageImage WHERE Url LIKE '%small.png' AND ImageId IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(linked, 2);
+        crawler.finish().await.unwrap();
+
+        // A higher size limit lets the rejected image be downloaded
+        let mut crawler = Crawler::resume()
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(IMAGE_SITE.into()))
+            .download_images(ImageLimits {
+                max_size: 4096,
+                ..limits
+            })
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        assert_eq!(
+            fetches(&crawler.db_connection, "http://example.com/large.png"),
+            2
+        );
+        crawler.finish().await.unwrap();
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+    }
+
+    /// Does not download an asset again when a later run finds another link to it, with or without a Bloom filter of
+    /// the visited URLs.
+    #[tokio::test]
+    async fn skips_downloaded_assets() {
+        for (name, filter) in [
+            ("assets", None),
+            (
+                "assets_bloom",
+                Some(BloomSettings {
+                    capacity: 1000,
+                    false_positive_rate: 0.01,
+                }),
+            ),
+        ] {
+            let paths = test_paths(name);
+            for seed in ["http://example.com/", "http://example.com/other.html"] {
+                let mut crawler = Crawler::builder(seed)
+                    .paths(paths.clone())
+                    .user_agent("test")
+                    .fetch_mode(FetchMode::Directory(ASSET_SITE.into()))
+                    .download_assets(vec!["pdf".into()])
+                    .visited_filter(filter)
+                    .build()
+                    .unwrap();
+                while crawler.crawl().await.unwrap() {}
+                crawler.finish().await.unwrap();
+            }
+
+            let connection = Connection::open(&paths.database).unwrap();
+            assert_eq!(stored_pages(&connection).len(), 2, "{}", name);
+            let (assets, fetches): (i64, i64) = connection
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM Asset),
+                        (SELECT COUNT(*) FROM PageFetch WHERE Url = 'http://example.com/report.pdf')",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((assets, fetches), (1, 1), "{}", name);
+            fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+        }
+    }
+
+    /// Reads the sitemaps listed in robots.txt, through a sitemap index to a gzipped sitemap, and crawls the URLs with
+    /// a higher sitemap priority first, while URLs of equal score are crawled last queued first.
+    #[tokio::test]
+    async fn scores_urls_by_sitemap_priority() {
+        for (sitemap_weight, second_page) in [(1.0, "a.html"), (0.0, "b.html")] {
+            let paths = test_paths("sitemap");
+            let mut crawler = Crawler::builder("http://example.com/")
+                .paths(paths.clone())
+                .user_agent("test")
+                .fetch_mode(FetchMode::Directory(SITEMAP_SITE.into()))
+                .scorer(WeightedScorer {
+                    sitemap_weight,
+                    ..WeightedScorer::default()
+                })
+                .max_pages(Some(2))
+                .build()
+                .unwrap();
+            while crawler.crawl().await.unwrap() {}
+
+            let priorities = crawler
+                .db_connection
+                .prepare("SELECT Url, Priority FROM SitemapUrl ORDER BY Url")
+                .unwrap()
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+                })
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                priorities,
+                [
+                    ("http://example.com/".to_string(), 1.0),
+                    ("http://example.com/a.html".to_string(), 0.9),
+                    ("http://example.com/b.html".to_string(), 0.1),
+                ]
+            );
+            assert_eq!(
+                stored_pages(&crawler.db_connection),
+                [
+                    ("http://example.com/".to_string(), false),
+                    (format!("http://example.com/{}", second_page), false),
+                ]
+            );
+            crawler.finish().await.unwrap();
+            fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
+        }
+    }
+
+    /// Reprocesses pages with other languages: stored pages no longer crawled are no longer indexed, and pages skipped
+    /// before are queued to be fetched again, without any request or notification.
+    #[tokio::test]
+    async fn reprocesses_skipped_pages() {
+        let paths = test_paths("reprocess");
+        let mut crawler = Crawler::builder("http://example.com/")
+            .paths(paths.clone())
+            .user_agent("test")
+            .fetch_mode(FetchMode::Directory(LANGUAGE_SITE.into()))
+            .languages(vec!["en".into()])
+            .build()
+            .unwrap();
+        while crawler.crawl().await.unwrap() {}
+        crawler.finish().await.unwrap();
+        let indexed = |connection: &Connection| {
+            connection
+                .prepare("SELECT Url, Indexed FROM Page ORDER BY Id")
+                .unwrap()
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?))
+                })
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        let connection = Connection::open(&paths.database).unwrap();
+        assert_eq!(
+            indexed(&connection),
+            [
+                ("http://example.com/".to_string(), true),
+                ("http://example.com/de.html".to_string(), false),
+            ]
+        );
+
+        let mut crawler = Crawler::reprocess()
+            .paths(paths.clone())
+            .languages(vec!["de".into()])
+            .build()
+            .unwrap();
+        assert_eq!(crawler.reprocess_pages().await.unwrap(), (2, 0));
+        crawler.finish().await.unwrap();
+        assert_eq!(
+            indexed(&connection),
+            [
+                ("http://example.com/".to_string(), false),
+                ("http://example.com/de.html".to_string(), false),
+            ]
+        );
+        let (queued, outcome, fetches): (Vec<String>, String, i64) = (
+            connection
+                .prepare("SELECT Url FROM FrontierUrl")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap(),
+            connection
+                .query_row(
+                    "SELECT Outcome FROM CrawlRun ORDER BY Id DESC LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap(),
+            connection
+                .query_row("SELECT COUNT(*) FROM PageFetch", [], |row| row.get(0))
+                .unwrap(),
+        );
+        assert_eq!(queued, ["http://example.com/de.html"]);
+        assert_eq!(outcome, "completed");
+        assert_eq!(fetches, 2);
+        fs::remove_dir_all(paths.database.parent().unwrap()).unwrap();
     }
 }
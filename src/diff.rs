@@ -0,0 +1,304 @@
+//! Reports of what changed between the two latest stored versions of a page, for change monitoring.
+
+use rusqlite::{Connection, OptionalExtension};
+use scraper::Html;
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::io::Write;
+use tokio::io::AsyncReadExt;
+use url::Url;
+
+use crate::charset::decode_saved;
+use crate::content::plain_text;
+use crate::error::CrawlerError;
+use crate::links::{extract_links, LinkElement};
+use crate::meta::base_url;
+use crate::normalize::resolve_href;
+use crate::reader::BodyReader;
+
+/// A stored version of a page, as recorded in `PageRevision`.
+pub struct Revision {
+    /// The id of the revision.
+    pub id: i64,
+    /// The crawl run that stored the version.
+    pub run_id: Option<i64>,
+    /// The content hash of the version.
+    pub hash: String,
+    /// When the version was fetched.
+    pub fetched: Option<String>,
+}
+
+impl Revision {
+    /// Returns the label of the version in the headers of diffs.
+    fn label(&self) -> String {
+        format!(
+            "revision {} ({})",
+            self.id,
+            self.fetched.as_deref().unwrap_or("unknown time")
+        )
+    }
+}
+
+/// The changes between two versions of a page: a line diff of their plain text, optionally of their HTML, and the
+/// links to pages that were added or removed.
+pub struct PageDiff {
+    /// The final URL of the page.
+    pub url: String,
+    /// The earlier version.
+    pub old: Revision,
+    /// The later version.
+    pub new: Revision,
+    /// The unified diff of the plain text, empty if the text is the same.
+    pub text: String,
+    /// The unified diff of the HTML, if requested, empty if the HTML is the same.
+    pub html: Option<String>,
+    /// The links to pages found only in the later version, in order.
+    pub added_links: Vec<String>,
+    /// The links to pages found only in the earlier version, in order.
+    pub removed_links: Vec<String>,
+}
+
+impl PageDiff {
+    /// Compares the two latest stored versions of a page.
+    ///
+    /// Pages get a second version when `recrawl`, a revalidating crawl or a scheduled pass finds that their contents
+    /// changed. Both bodies are decoded with the charset recorded for the page.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `reader` - Reads the stored bodies of the versions.
+    /// * `url` - The final or requested URL of the page.
+    /// * `with_html` - Whether the HTML is compared as well as the text.
+    /// * `context` - The number of unchanged lines shown around each change.
+    ///
+    /// # Returns
+    /// The changes, or an error if no page has the URL, it has a single version or a body cannot be read.
+    pub async fn load(
+        connection: &Connection,
+        reader: &BodyReader,
+        url: &str,
+        with_html: bool,
+        context: usize,
+    ) -> Result<Self, CrawlerError> {
+        let page = connection
+            .query_row(
+                "SELECT Id, Url, Charset FROM Page
+                WHERE Url = ?1 OR RequestedUrl = ?1 ORDER BY Url = ?1 DESC LIMIT 1",
+                [url],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((page_id, page_url, charset)) = page else {
+            return Err(CrawlerError::Config(format!(
+                "No stored page has the URL {}",
+                url
+            )));
+        };
+        let revisions = connection
+            .prepare(
+                "SELECT Id, RunId, Hash, Fetched FROM PageRevision WHERE PageId = ? ORDER BY Id DESC LIMIT 2",
+            )?
+            .query_map([page_id], |row| {
+                Ok(Revision {
+                    id: row.get(0)?,
+                    run_id: row.get(1)?,
+                    hash: row.get(2)?,
+                    fetched: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut revisions = revisions.into_iter();
+        let (Some(new), Some(old)) = (revisions.next(), revisions.next()) else {
+            return Err(CrawlerError::Config(format!(
+                "The contents of page {} have not changed since it was first stored",
+                page_url
+            )));
+        };
+        let mut bodies = Vec::new();
+        for revision in [&old, &new] {
+            let mut body = Vec::new();
+            reader
+                .open_revision(revision.id)
+                .await?
+                .read_to_end(&mut body)
+                .await?;
+            bodies.push(decode_saved(&body, charset.as_deref()).into_owned());
+        }
+        let parsed_url = Url::parse(&page_url)?;
+        Ok(PageDiff::between(
+            &parsed_url,
+            (old, &bodies[0]),
+            (new, &bodies[1]),
+            with_html,
+            context,
+        ))
+    }
+
+    /// Compares two versions of a page.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page, which links are resolved against.
+    /// * `old` - The earlier version and its HTML.
+    /// * `new` - The later version and its HTML.
+    /// * `with_html` - Whether the HTML is compared as well as the text.
+    /// * `context` - The number of unchanged lines shown around each change.
+    pub fn between(
+        url: &Url,
+        old: (Revision, &str),
+        new: (Revision, &str),
+        with_html: bool,
+        context: usize,
+    ) -> Self {
+        let (old, old_html) = old;
+        let (new, new_html) = new;
+        let old_document = Html::parse_document(old_html);
+        let new_document = Html::parse_document(new_html);
+        let old_links = page_links(&old_document, url);
+        let new_links = page_links(&new_document, url);
+        let text = unified_diff(
+            &plain_text(&old_document),
+            &plain_text(&new_document),
+            (&old, &new),
+            context,
+        );
+        let html = with_html.then(|| unified_diff(old_html, new_html, (&old, &new), context));
+        PageDiff {
+            url: url.to_string(),
+            added_links: new_links
+                .iter()
+                .filter(|link| !old_links.contains(*link))
+                .cloned()
+                .collect(),
+            removed_links: old_links
+                .iter()
+                .filter(|link| !new_links.contains(*link))
+                .cloned()
+                .collect(),
+            old,
+            new,
+            text,
+            html,
+        }
+    }
+
+    /// Writes the report: the two versions, the links added and removed, and the diffs.
+    ///
+    /// # Arguments
+    /// * `writer` - Where the report is written.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), CrawlerError> {
+        writeln!(writer, "URL:         {}", self.url)?;
+        for (name, revision) in [("Old:", &self.old), ("New:", &self.new)] {
+            writeln!(
+                writer,
+                "{:<13}revision {}, fetched {}{}, hash {}",
+                name,
+                revision.id,
+                revision.fetched.as_deref().unwrap_or("at an unknown time"),
+                revision
+                    .run_id
+                    .map(|run_id| format!(" by run {}", run_id))
+                    .unwrap_or_default(),
+                revision.hash
+            )?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "Links added: {}", self.added_links.len())?;
+        for link in &self.added_links {
+            writeln!(writer, "  + {}", link)?;
+        }
+        writeln!(writer, "Links removed: {}", self.removed_links.len())?;
+        for link in &self.removed_links {
+            writeln!(writer, "  - {}", link)?;
+        }
+        writeln!(writer)?;
+        if self.text.is_empty() {
+            writeln!(writer, "The text did not change")?;
+        } else {
+            writeln!(writer, "Text:")?;
+            writer.write_all(self.text.as_bytes())?;
+        }
+        if let Some(html) = &self.html {
+            writeln!(writer)?;
+            if html.is_empty() {
+                writeln!(writer, "The HTML did not change")?;
+            } else {
+                writeln!(writer, "HTML:")?;
+                writer.write_all(html.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the distinct links of a page to other pages, resolved, in document order.
+fn page_links(document: &Html, url: &Url) -> Vec<String> {
+    let base = base_url(document, url);
+    let navigable = LinkElement::ALL
+        .into_iter()
+        .filter(|element| element.is_navigable())
+        .collect::<Vec<_>>();
+    let mut seen = BTreeSet::new();
+    extract_links(document, &navigable)
+        .into_iter()
+        .filter_map(|link| resolve_href(link.href, &base))
+        .map(String::from)
+        .filter(|link| seen.insert(link.clone()))
+        .collect()
+}
+
+/// Returns the unified diff of the lines of two texts, or an empty string if they are the same.
+fn unified_diff(old: &str, new: &str, labels: (&Revision, &Revision), context: usize) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(context)
+        .missing_newline_hint(false)
+        .header(&labels.0.label(), &labels.1.label())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revision(id: i64) -> Revision {
+        Revision {
+            id,
+            run_id: Some(id),
+            hash: format!("hash{}", id),
+            fetched: None,
+        }
+    }
+
+    #[test]
+    fn compares_text_and_links() {
+        let url = Url::parse("https://example.com/news/").unwrap();
+        let old = r#"<html><body><h1>News</h1><p>Old story</p>
+            <a href="/a">A</a><a href="b#top">B</a><link rel="stylesheet" href="/style.css"></body></html>"#;
+        let new = r#"<html><body><h1>News</h1><p>New story</p>
+            <a href="/a">A</a><a href="c">C</a><a href="mailto:news@example.com">Mail</a></body></html>"#;
+        let diff = PageDiff::between(&url, (revision(1), old), (revision(2), new), false, 3);
+
+        assert_eq!(diff.added_links, ["https://example.com/news/c"]);
+        assert_eq!(diff.removed_links, ["https://example.com/news/b"]);
+        assert!(diff.text.contains("-Old story\n"));
+        assert!(diff.text.contains("+New story\n"));
+        assert!(diff.text.contains(" News\n"));
+        assert!(diff.html.is_none());
+
+        let same = PageDiff::between(&url, (revision(1), old), (revision(2), old), true, 3);
+        assert!(same.text.is_empty());
+        assert_eq!(same.html.as_deref(), Some(""));
+    }
+}
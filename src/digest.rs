@@ -0,0 +1,127 @@
+//! Content hashes of pages, and the short hash prefixes that reference them.
+
+use rusqlite::Connection;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::CrawlerError;
+
+/// The shortest hash prefix used as the short id of a page.
+pub const MIN_SHORT_ID_LENGTH: usize = 8;
+
+/// The algorithm page contents are hashed with.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// BLAKE3, fast on large pages.
+    #[default]
+    Blake3,
+    /// SHA-256, as used by most archive tooling.
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Every algorithm.
+    pub const ALL: [HashAlgorithm; 2] = [HashAlgorithm::Blake3, HashAlgorithm::Sha256];
+
+    /// Returns the name of the algorithm, as stored in the `HashAlgorithm` column of `Page`.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Finds an algorithm by its name.
+    ///
+    /// # Arguments
+    /// * `name` - The name, e.g. `sha256`.
+    ///
+    /// # Returns
+    /// The algorithm, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Hashes some bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - The bytes to hash.
+    ///
+    /// # Returns
+    /// The lowercase hex encoded digest.
+    pub fn digest(self, bytes: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+            HashAlgorithm::Sha256 => hex::encode(Sha256::digest(bytes)),
+        }
+    }
+}
+
+/// Returns the shortest prefix of a stored page hash that no other stored hash starts with, and at least
+/// [`MIN_SHORT_ID_LENGTH`] characters long.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `hash` - The full hash of a stored page.
+///
+/// # Returns
+/// The short id of the page.
+pub fn short_id(connection: &Connection, hash: &str) -> rusqlite::Result<String> {
+    let neighbours: (Option<String>, Option<String>) = connection.query_row(
+        "SELECT (SELECT MAX(Hash) FROM Page WHERE Hash < ?1), (SELECT MIN(Hash) FROM Page WHERE Hash > ?1)",
+        [hash],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let shared = [neighbours.0, neighbours.1]
+        .iter()
+        .flatten()
+        .map(|neighbour| {
+            neighbour
+                .bytes()
+                .zip(hash.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+    let length = (shared + 1).max(MIN_SHORT_ID_LENGTH).min(hash.len());
+    Ok(hash[..length].to_string())
+}
+
+/// Finds the full hash of the stored pages that a short id refers to.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `prefix` - A prefix of a page hash, e.g. a short id printed by `search`.
+///
+/// # Returns
+/// The full hash, or an error if no stored hash, or more than one, starts with the prefix.
+pub fn resolve_short_id(connection: &Connection, prefix: &str) -> Result<String, CrawlerError> {
+    let prefix = prefix.to_ascii_lowercase();
+    if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(CrawlerError::Config(format!(
+            "\"{}\" is not a hash prefix",
+            prefix
+        )));
+    }
+    // Hex digits sort before 'g', so this range holds exactly the hashes starting with the prefix
+    let mut stmt = connection
+        .prepare("SELECT DISTINCT Hash FROM Page WHERE Hash >= ?1 AND Hash < ?1 || 'g' LIMIT 2")?;
+    let hashes = stmt
+        .query_map([&prefix], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    match hashes.as_slice() {
+        [hash] => Ok(hash.clone()),
+        [] => Err(CrawlerError::Config(format!(
+            "No stored page has a hash starting with {}",
+            prefix
+        ))),
+        _ => Err(CrawlerError::Config(format!(
+            "Hash prefix {} is ambiguous, use more characters",
+            prefix
+        ))),
+    }
+}
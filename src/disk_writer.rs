@@ -0,0 +1,122 @@
+//! File writes on a dedicated thread.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::error;
+
+/// A pending write to a file.
+struct WriteJob {
+    path: PathBuf,
+    contents: Vec<u8>,
+    append: bool,
+}
+
+/// Hands file writes to a dedicated thread, so slow disks do not stall the async runtime.
+///
+/// Writes are queued in order, and queueing waits while the queue is full. Missing parent directories are created.
+#[derive(Clone)]
+pub struct DiskWriter {
+    sender: Sender<WriteJob>,
+}
+
+/// Owns the thread of a `DiskWriter`, and waits for it to finish the queued writes.
+pub struct DiskWriterHandle {
+    thread: JoinHandle<()>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl DiskWriter {
+    /// Starts the writer thread.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of queued writes.
+    ///
+    /// # Returns
+    /// The writer, and the handle used to wait for its thread once every clone of the writer is dropped.
+    pub fn start(capacity: usize) -> (Self, DiskWriterHandle) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let failures = Arc::new(AtomicUsize::new(0));
+        let thread_failures = failures.clone();
+        let thread = thread::spawn(move || Self::run(receiver, &thread_failures));
+        (DiskWriter { sender }, DiskWriterHandle { thread, failures })
+    }
+
+    /// Replaces the contents of a file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    /// * `contents` - The new contents.
+    pub async fn write(&self, path: PathBuf, contents: Vec<u8>) -> io::Result<()> {
+        self.queue(path, contents, false).await
+    }
+
+    /// Appends to a file, creating it if it does not exist.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    /// * `contents` - The data to append.
+    pub async fn append(&self, path: PathBuf, contents: Vec<u8>) -> io::Result<()> {
+        self.queue(path, contents, true).await
+    }
+
+    async fn queue(&self, path: PathBuf, contents: Vec<u8>, append: bool) -> io::Result<()> {
+        self.sender
+            .send(WriteJob {
+                path,
+                contents,
+                append,
+            })
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Disk writer has stopped"))
+    }
+
+    /// Performs queued writes until every sender is dropped, counting the writes that fail.
+    fn run(mut receiver: Receiver<WriteJob>, failures: &AtomicUsize) {
+        while let Some(job) = receiver.blocking_recv() {
+            let parent = job.path.parent().filter(|parent| !parent.exists());
+            let result = parent
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| {
+                    OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .append(job.append)
+                        .truncate(!job.append)
+                        .open(&job.path)
+                })
+                .and_then(|mut file| file.write_all(&job.contents));
+            if let Err(e) = result {
+                error!("Failed to write {}: {}", job.path.display(), e);
+                failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl DiskWriterHandle {
+    /// Returns the number of writes that failed so far. Writes still queued are not counted yet.
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the queued writes to finish. Every `DiskWriter` must be dropped first.
+    ///
+    /// # Returns
+    /// An error if any write failed.
+    pub async fn finish(self) -> io::Result<()> {
+        let failures = self.failures;
+        tokio::task::spawn_blocking(move || self.thread.join())
+            .await
+            .map_err(io::Error::other)?
+            .map_err(|_| io::Error::other("Disk writer thread panicked"))?;
+        match failures.load(Ordering::Relaxed) {
+            0 => Ok(()),
+            n => Err(io::Error::other(format!("{} file writes failed", n))),
+        }
+    }
+}
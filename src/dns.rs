@@ -0,0 +1,431 @@
+//! Resolution of host names, with the system resolver or with configured DNS servers, and a cache of their answers.
+
+use hickory_resolver::config::{
+    NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+};
+use hickory_resolver::TokioAsyncResolver;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::ClientBuilder;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::normalize::normalize_host;
+
+/// The port of plain DNS servers given without one.
+const DNS_PORT: u16 = 53;
+/// The only path DNS-over-HTTPS queries can be sent to.
+const DOH_PATH: &str = "/dns-query";
+/// The number of answers kept in the cache of a resolver, each until its TTL runs out.
+const DNS_CACHE_SIZE: usize = 4096;
+
+/// Looks up the addresses of hosts, for requests and reachability checks alike.
+///
+/// The default resolver is that of the operating system, which follows its configuration, e.g. the hosts file,
+/// nsswitch and mDNS. A resolver can instead send its queries to other DNS servers, over UDP and TCP, or to a
+/// DNS-over-HTTPS server, and then caches the answers for as long as their TTL allows, so a host is not looked up
+/// again for every request.
+///
+/// Hosts can be given fixed addresses, like `--resolve` of curl, which are used without looking them up.
+///
+/// A resolver can leave out private, loopback and link-local addresses, so that requests cannot reach internal
+/// services through host names that resolve to them, including names that change their addresses between lookups.
+#[derive(Clone, Default)]
+pub struct Resolver {
+    custom: Option<Arc<TokioAsyncResolver>>,
+    overrides: Arc<HashMap<String, Vec<IpAddr>>>,
+    block_private: bool,
+}
+
+impl Resolver {
+    /// Creates a `Resolver` that looks hosts up with the resolver of the operating system, without a cache.
+    pub fn system() -> Self {
+        Resolver::default()
+    }
+
+    /// Creates a `Resolver` that sends queries to DNS servers, in order.
+    ///
+    /// # Arguments
+    /// * `servers` - The addresses of the servers, as `ip` or `ip:port`. IPv6 addresses with a port are written
+    ///   `[ip]:port`.
+    pub fn servers(servers: &[String]) -> Result<Self, CrawlerError> {
+        let mut group = NameServerConfigGroup::new();
+        for server in servers {
+            let address = parse_server(server)?;
+            group.push(NameServerConfig::new(address, Protocol::Udp));
+            group.push(NameServerConfig::new(address, Protocol::Tcp));
+        }
+        Ok(Resolver::from_group(group))
+    }
+
+    /// Creates a `Resolver` that sends queries to a DNS-over-HTTPS server.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the server, e.g. `https://1.1.1.1/dns-query`. Its path must be `/dns-query`.
+    /// * `bootstrap` - The resolver the host of the server is looked up with, when it is not an IP address.
+    pub async fn doh(url: &str, bootstrap: &Resolver) -> Result<Self, CrawlerError> {
+        let url = Url::parse(url)?;
+        if url.scheme() != "https" {
+            return Err(CrawlerError::Config(format!(
+                "DNS-over-HTTPS URL {} must use https",
+                url
+            )));
+        }
+        if url.path() != DOH_PATH && url.path() != "/" {
+            return Err(CrawlerError::Config(format!(
+                "DNS-over-HTTPS queries can only be sent to {}",
+                DOH_PATH
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| CrawlerError::Config(format!("DNS-over-HTTPS URL {} has no host", url)))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap();
+        let ips: Vec<IpAddr> = match host.parse() {
+            Ok(ip) => vec![ip],
+            Err(_) => bootstrap
+                .lookup(host, port)
+                .await?
+                .into_iter()
+                .map(|address| address.ip())
+                .collect(),
+        };
+        let group = NameServerConfigGroup::from_ips_https(&ips, port, host.to_string(), true);
+        Ok(Resolver::from_group(group))
+    }
+
+    fn from_group(group: NameServerConfigGroup) -> Self {
+        let config = ResolverConfig::from_parts(None, Vec::new(), group);
+        let mut options = ResolverOpts::default();
+        options.cache_size = DNS_CACHE_SIZE;
+        Resolver {
+            custom: Some(Arc::new(TokioAsyncResolver::tokio(config, options))),
+            overrides: Arc::default(),
+            block_private: false,
+        }
+    }
+
+    /// Gives hosts fixed addresses, which are used instead of looking the hosts up. A host given several times gets
+    /// all of its addresses. Fixed addresses are never left out as private network addresses.
+    ///
+    /// # Arguments
+    /// * `overrides` - The hosts and their addresses, as `host:port:addr[,addr]...` like `--resolve` of curl, or as
+    ///   `host:addr`. IPv6 addresses are written `[ip]`. As the port of a request is not known when its host is
+    ///   looked up, the addresses are used for every port.
+    pub fn overrides(mut self, overrides: &[String]) -> Result<Self, CrawlerError> {
+        let mut hosts: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        for entry in overrides {
+            let (host, ips) = parse_override(entry)?;
+            hosts.entry(host).or_default().extend(ips);
+        }
+        self.overrides = Arc::new(hosts);
+        Ok(self)
+    }
+
+    /// Sets whether private network addresses are left out of lookups. See [`is_private_address`].
+    ///
+    /// # Arguments
+    /// * `block` - Whether the addresses are left out.
+    pub fn block_private_networks(mut self, block: bool) -> Self {
+        self.block_private = block;
+        self
+    }
+
+    /// Looks up the addresses of a host.
+    ///
+    /// # Arguments
+    /// * `host` - The host name or IP address.
+    /// * `port` - The port of the returned addresses.
+    ///
+    /// # Returns
+    /// The addresses, or a `PermissionDenied` error if private networks are blocked and the host only has private
+    /// addresses.
+    pub async fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let fixed = normalize_host(host.trim_end_matches('.'));
+        if let Some(ips) = self.overrides.get(&fixed) {
+            return Ok(ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect());
+        }
+        let addresses: Vec<SocketAddr> = match &self.custom {
+            Some(resolver) => resolver
+                .lookup_ip(host)
+                .await?
+                .iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect(),
+            None => tokio::net::lookup_host((host, port)).await?.collect(),
+        };
+        if !self.block_private || addresses.is_empty() {
+            return Ok(addresses);
+        }
+        let public: Vec<SocketAddr> = addresses
+            .into_iter()
+            .filter(|address| !is_private_address(address.ip()))
+            .collect();
+        if public.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} resolves to a private network address", host),
+            ));
+        }
+        Ok(public)
+    }
+
+    /// Makes an HTTP client resolve hosts with this resolver.
+    ///
+    /// # Arguments
+    /// * `builder` - The builder of the client.
+    pub fn configure(&self, builder: ClientBuilder) -> ClientBuilder {
+        match self.custom.is_some() || self.block_private || !self.overrides.is_empty() {
+            true => builder.dns_resolver(Arc::new(self.clone())),
+            false => builder,
+        }
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            // The port is replaced by the port of the request
+            let addresses: Addrs = Box::new(resolver.lookup(name.as_str(), 0).await?.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+/// Returns whether an address belongs to a private network, which crawled links must not reach.
+///
+/// These are the loopback, unspecified and broadcast addresses, the RFC 1918 private ranges, the shared range of
+/// carrier-grade NAT, the benchmarking range, link-local addresses (which include the `169.254.169.254` metadata
+/// service of cloud providers), multicast and reserved addresses, and unique local IPv6 addresses. IPv4 addresses
+/// mapped into IPv6, or embedded in the NAT64 prefix `64:ff9b::/96`, are checked as IPv4.
+///
+/// # Arguments
+/// * `ip` - The address.
+pub fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_private_v4(mapped);
+            }
+            let segments = ip.segments();
+            // NAT64 gateways translate 64:ff9b::/96 to the IPv4 address in its last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                return is_private_v4(Ipv4Addr::from(((high as u32) << 16) | low as u32));
+            }
+            let first = segments[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || first == 0
+        || first >= 224
+        || (first == 100 && (second & 0xc0) == 64)
+        || (first == 198 && (second & 0xfe) == 18)
+}
+
+/// Parses the address of a DNS server, given as `ip` or `ip:port`.
+fn parse_server(server: &str) -> Result<SocketAddr, CrawlerError> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DNS_PORT));
+    }
+    server
+        .parse()
+        .map_err(|_| CrawlerError::Config(format!("Invalid DNS server address {}", server)))
+}
+
+/// Parses the fixed addresses of a host, given as `host:port:addr[,addr]...` or `host:addr`.
+///
+/// # Returns
+/// The host, in lowercase with internationalized labels in punycode, and its addresses.
+fn parse_override(entry: &str) -> Result<(String, Vec<IpAddr>), CrawlerError> {
+    let invalid = || {
+        CrawlerError::Config(format!(
+            "Invalid --resolve entry {}, expected host:port:addr",
+            entry
+        ))
+    };
+    let parse_ip = |ip: &str| {
+        ip.trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())
+    };
+    let (host, rest) = entry.split_once(':').ok_or_else(invalid)?;
+    let host = host.trim().trim_end_matches('.');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let ips = match parse_ip(rest) {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let (port, addresses) = rest.split_once(':').ok_or_else(invalid)?;
+            port.parse::<u16>().map_err(|_| invalid())?;
+            addresses
+                .split(',')
+                .map(parse_ip)
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    Ok((normalize_host(host), ips))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fixed_addresses() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        let cases = [
+            (
+                "example.com:443:10.0.0.7",
+                "example.com",
+                vec![ip("10.0.0.7")],
+            ),
+            (
+                "Example.COM.:80:10.0.0.7,[2001:db8::1]",
+                "example.com",
+                vec![ip("10.0.0.7"), ip("2001:db8::1")],
+            ),
+            ("example.com:10.0.0.7", "example.com", vec![ip("10.0.0.7")]),
+            ("example.com:[::1]", "example.com", vec![ip("::1")]),
+            (
+                "bücher.de:443:10.0.0.8",
+                "xn--bcher-kva.de",
+                vec![ip("10.0.0.8")],
+            ),
+        ];
+        for (entry, host, ips) in cases {
+            assert_eq!(
+                parse_override(entry).unwrap(),
+                (host.to_string(), ips),
+                "{}",
+                entry
+            );
+        }
+        for entry in [
+            "example.com",
+            ":443:10.0.0.7",
+            "example.com:443",
+            "example.com:https:10.0.0.7",
+            "example.com:443:10.0.0.7,nowhere",
+            "example.com:70000:10.0.0.7",
+        ] {
+            assert!(parse_override(entry).is_err(), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn parses_server_addresses() {
+        let cases = [
+            ("1.1.1.1", Some("1.1.1.1:53")),
+            ("1.1.1.1:5353", Some("1.1.1.1:5353")),
+            ("2606:4700::1111", Some("[2606:4700::1111]:53")),
+            ("[2606:4700::1111]:5353", Some("[2606:4700::1111]:5353")),
+            ("dns.example.com", None),
+            ("1.1.1.1:dns", None),
+            ("", None),
+        ];
+        for (server, address) in cases {
+            assert_eq!(
+                parse_server(server).ok(),
+                address.map(|address| address.parse().unwrap()),
+                "{}",
+                server
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_doh_urls() {
+        let system = Resolver::system();
+        for url in [
+            "https://1.1.1.1/dns-query",
+            "https://1.1.1.1/",
+            "https://[2606:4700::1111]:8443/dns-query",
+        ] {
+            let resolver = Resolver::doh(url, &system).await;
+            assert!(
+                resolver.is_ok_and(|resolver| resolver.custom.is_some()),
+                "{}",
+                url
+            );
+        }
+        for url in [
+            "http://1.1.1.1/dns-query",
+            "https://1.1.1.1/resolve",
+            "dns-query",
+        ] {
+            assert!(Resolver::doh(url, &system).await.is_err(), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn looks_up_fixed_addresses() {
+        let resolver = Resolver::system()
+            .overrides(&["bücher.de:443:10.0.0.8".to_string()])
+            .unwrap();
+        let addresses = resolver.lookup("xn--bcher-kva.de.", 8080).await.unwrap();
+        assert_eq!(addresses, ["10.0.0.8:8080".parse::<SocketAddr>().unwrap()]);
+        assert!(resolver.custom.is_none());
+    }
+
+    #[test]
+    fn detects_private_addresses() {
+        let cases = [
+            ("127.0.0.1", true),
+            ("10.1.2.3", true),
+            ("172.16.0.1", true),
+            ("192.168.1.1", true),
+            ("100.64.0.1", true),
+            ("169.254.169.254", true),
+            ("0.0.0.0", true),
+            ("255.255.255.255", true),
+            ("224.0.0.251", true),
+            ("239.255.255.250", true),
+            ("240.0.0.1", true),
+            ("198.18.0.1", true),
+            ("198.19.255.255", true),
+            ("198.20.0.1", false),
+            ("100.128.0.1", false),
+            ("93.184.216.34", false),
+            ("8.8.8.8", false),
+            ("::1", true),
+            ("::", true),
+            ("fc00::1", true),
+            ("fd12:3456::1", true),
+            ("fe80::1", true),
+            ("ff02::1", true),
+            ("::ffff:127.0.0.1", true),
+            ("::ffff:93.184.216.34", false),
+            ("64:ff9b::7f00:1", true),
+            ("64:ff9b::a9fe:a9fe", true),
+            ("64:ff9b::5db8:d822", false),
+            ("2606:2800:220:1::1", false),
+        ];
+        for (ip, private) in cases {
+            assert_eq!(is_private_address(ip.parse().unwrap()), private, "{}", ip);
+        }
+    }
+}
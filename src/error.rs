@@ -0,0 +1,73 @@
+//! The error type of the library.
+
+use std::io;
+use thiserror::Error;
+
+/// An error of the crawler, telling callers which part of a crawl failed.
+#[derive(Debug, Error)]
+pub enum CrawlerError {
+    /// An HTTP request failed, e.g. it timed out or the server could not be connected to.
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    /// A page could not be fetched for another reason, e.g. its host could not be resolved or it redirects too often.
+    #[error("{0}")]
+    Fetch(String),
+    /// A URL, document, pattern or file is malformed.
+    #[error("{0}")]
+    Parse(String),
+    /// The database could not be read or written, e.g. because it is locked by another process.
+    #[error(transparent)]
+    Database(#[from] rusqlite::Error),
+    /// The Postgres database shared with other workers could not be connected to, read or written.
+    #[error(transparent)]
+    Postgres(#[from] tokio_postgres::Error),
+    /// A file could not be read or written.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A URL is refused by robots.txt rules or the crawl policy.
+    #[error("{0}")]
+    Policy(String),
+    /// The configuration or the arguments are invalid.
+    #[error("{0}")]
+    Config(String),
+    /// A notification could not be delivered.
+    #[error("{0}")]
+    Delivery(String),
+}
+
+impl CrawlerError {
+    /// Returns whether the error stops the crawl, rather than only the crawl of the URL it happened for.
+    ///
+    /// Errors of the database and of writing files are fatal, as every later page would fail the same way or be
+    /// recorded without its body. Errors of requests, documents and policies only concern their URL.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            CrawlerError::Database(_) | CrawlerError::Postgres(_) | CrawlerError::Io(_)
+        )
+    }
+}
+
+impl From<url::ParseError> for CrawlerError {
+    fn from(error: url::ParseError) -> Self {
+        CrawlerError::Parse(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CrawlerError {
+    fn from(error: serde_json::Error) -> Self {
+        CrawlerError::Parse(error.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for CrawlerError {
+    fn from(error: reqwest::header::InvalidHeaderValue) -> Self {
+        CrawlerError::Parse(error.to_string())
+    }
+}
+
+impl From<regex::Error> for CrawlerError {
+    fn from(error: regex::Error) -> Self {
+        CrawlerError::Parse(error.to_string())
+    }
+}
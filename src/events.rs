@@ -0,0 +1,92 @@
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+
+use crate::disk_writer::DiskWriter;
+use crate::progress::ProgressDisplay;
+
+/// Something that happened to a URL during a crawl.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CrawlEvent<'a> {
+    /// A response was received, whether or not its page was stored.
+    Fetch {
+        url: &'a str,
+        final_url: &'a str,
+        status: u16,
+        content_type: Option<&'a str>,
+        content_length: Option<u64>,
+        duration_ms: u64,
+        page_id: Option<i64>,
+        /// Why the response was rejected, if it was.
+        error: Option<&'a str>,
+    },
+    /// A dequeued URL was not fetched.
+    Skip { url: &'a str, reason: &'a str },
+    /// Crawling a URL failed.
+    Error { url: &'a str, error: &'a str },
+}
+
+/// An event with the context shared by every event.
+#[derive(Serialize)]
+struct EventLine<'a> {
+    time: String,
+    run_id: i64,
+    #[serde(flatten)]
+    event: &'a CrawlEvent<'a>,
+}
+
+/// Appends crawl events to a newline-delimited JSON file, as an audit trail that does not depend on the database.
+pub struct EventLog {
+    path: PathBuf,
+    run_id: i64,
+    writer: DiskWriter,
+    progress: Option<ProgressDisplay>,
+}
+
+impl EventLog {
+    /// Creates a new `EventLog`. Events of every run are appended to the same file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    /// * `run_id` - The id of the crawl run, included in every event.
+    /// * `writer` - The writer the events are handed to.
+    /// * `progress` - The display updated with every event, if any.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        run_id: i64,
+        writer: DiskWriter,
+        progress: Option<ProgressDisplay>,
+    ) -> Self {
+        EventLog {
+            path: path.into(),
+            run_id,
+            writer,
+            progress,
+        }
+    }
+
+    /// Returns the display updated with every event, if any.
+    pub fn progress(&self) -> Option<&ProgressDisplay> {
+        self.progress.as_ref()
+    }
+
+    /// Queues an event to be appended to the file.
+    ///
+    /// # Arguments
+    /// * `event` - The event.
+    pub async fn emit(&self, event: &CrawlEvent<'_>) -> io::Result<()> {
+        if let Some(progress) = &self.progress {
+            progress.record(event);
+        }
+        let line = EventLine {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            run_id: self.run_id,
+            event,
+        };
+        let mut json = serde_json::to_vec(&line)?;
+        json.push(b'\n');
+        self.writer.append(self.path.clone(), json).await
+    }
+}
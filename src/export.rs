@@ -0,0 +1,442 @@
+//! Export of the crawl database as JSON Lines or CSV.
+
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use scraper::Html;
+use serde_json::{Map, Number};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tracing::warn;
+use url::Url;
+
+use crate::charset::decode_saved;
+use crate::content::{markdown, plain_text};
+use crate::error::CrawlerError;
+use crate::normalize::normalize_host;
+use crate::reader::BodyReader;
+use crate::routing::{mirror_path, unique_mirror_path};
+
+/// The status of the last fetch of a page, as an SQL expression.
+const PAGE_STATUS: &str =
+    "(SELECT Status FROM PageFetch WHERE PageId = Page.Id ORDER BY Id DESC LIMIT 1)";
+
+/// A point in the crawl history to export changes from.
+pub enum Since {
+    /// Only pages added or changed by runs after the given run id.
+    Run(i64),
+    /// Only pages added or changed after the given timestamp.
+    Timestamp(String),
+}
+
+impl Since {
+    /// Parses a `--since` argument.
+    ///
+    /// Plain integers are treated as run ids, anything else as a timestamp understood by SQLite's `datetime()`.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection, used to normalise timestamps.
+    /// * `value` - The argument value.
+    ///
+    /// # Returns
+    /// The parsed point in time, or an error if the value is neither a run id nor a valid timestamp.
+    pub fn parse(connection: &Connection, value: &str) -> Result<Self, CrawlerError> {
+        if let Ok(run_id) = value.parse::<i64>() {
+            return Ok(Since::Run(run_id));
+        }
+        let timestamp: Option<String> =
+            connection.query_row("SELECT datetime(?)", [value], |row| row.get(0))?;
+        match timestamp {
+            Some(timestamp) => Ok(Since::Timestamp(timestamp)),
+            None => Err(CrawlerError::Config(format!(
+                "\"{}\" is not a run id or timestamp",
+                value
+            ))),
+        }
+    }
+}
+
+/// The records to export.
+#[derive(Clone, Copy)]
+pub enum ExportTable {
+    /// Crawled pages, with their metadata.
+    Pages,
+    /// Links found on crawled pages.
+    Links,
+    /// Known domains, with their crawl policy.
+    Domains,
+}
+
+/// The format records are exported in.
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma separated values with a header row.
+    Csv,
+}
+
+/// The format page bodies are exported in.
+#[derive(Clone, Copy)]
+pub enum BodyFormat {
+    /// Markdown converted from the HTML, with the URL and title as front matter.
+    Markdown,
+    /// The plain text of the page, as indexed for search.
+    Text,
+    /// The HTML as it was saved.
+    Html,
+}
+
+impl BodyFormat {
+    /// Returns the extension of exported files.
+    fn extension(self) -> &'static str {
+        match self {
+            BodyFormat::Markdown => "md",
+            BodyFormat::Text => "txt",
+            BodyFormat::Html => "html",
+        }
+    }
+}
+
+/// Restricts the exported records.
+#[derive(Default)]
+pub struct ExportFilter {
+    /// Only records added or changed after this point.
+    pub since: Option<Since>,
+    /// Only records of pages on this domain, or the domain itself.
+    pub domain: Option<String>,
+    /// Only records of pages whose last fetch had this status.
+    pub status: Option<u16>,
+    /// Only records of pages with this content hash.
+    pub hash: Option<String>,
+    /// Only records of pages with this tag.
+    pub tag: Option<String>,
+    /// The number of matching records skipped before the first one exported.
+    pub offset: usize,
+    /// The maximum number of records exported.
+    pub limit: Option<usize>,
+}
+
+/// Writes the stored pages, links or domains in the given format.
+///
+/// Links are filtered by the page they were found on.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `table` - The records to export.
+/// * `format` - The format to write the records in.
+/// * `filter` - The records to include.
+/// * `writer` - The destination of the exported records.
+///
+/// # Returns
+/// The number of records exported.
+pub fn export(
+    connection: &Connection,
+    table: ExportTable,
+    format: ExportFormat,
+    filter: &ExportFilter,
+    writer: &mut impl Write,
+) -> Result<usize, CrawlerError> {
+    let (mut query, url_column) = match table {
+        ExportTable::Pages => (
+            format!(
+                "SELECT Id AS id, RunId AS run_id, Url AS url, RequestedUrl AS requested_url, {} AS status,
+                    Hash AS hash, HashAlgorithm AS hash_algorithm, DuplicateOf AS duplicate_of, WarcFile AS warc_file, WarcOffset AS warc_offset,
+                    WordCount AS word_count, TextRatio AS text_ratio, ReadingTime AS reading_time, NoIndex AS noindex, NoFollow AS nofollow, SuppressedLinks AS suppressed_links, Indexed AS indexed,
+                    ServerAddress AS server_address, ServerCountry AS server_country, ContentLanguage AS content_language, Charset AS charset,
+                    Created AS created, Updated AS updated, Title AS title, Description AS description, Canonical AS canonical, Language AS language,
+                    (SELECT group_concat(Tag, ' ') FROM PageTag WHERE PageTag.PageId = Page.Id) AS tags
+                FROM Page LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id",
+                PAGE_STATUS
+            ),
+            Some("url"),
+        ),
+        ExportTable::Links => (
+            "SELECT PageLink.PageId AS page_id, Page.Url AS page_url, PageLink.Url AS url,
+                PageLink.AnchorText AS anchor_text, PageLink.Nofollow AS nofollow, PageLink.Element AS element,
+                PageLink.Rel AS rel, PageLink.Section AS section, PageLink.External AS external
+            FROM PageLink JOIN Page ON Page.Id = PageLink.PageId"
+                .to_string(),
+            Some("page_url"),
+        ),
+        ExportTable::Domains => (
+            "SELECT Id AS id, Name AS name, RefusedCount AS refused_count, BannedUntil AS banned_until,
+                Unreachable AS unreachable, CrawlDelay AS crawl_delay, RobotsFetched AS robots_fetched, Pinned AS pinned, Created AS created
+            FROM Domain"
+                .to_string(),
+            None,
+        ),
+    };
+
+    let (conditions, args) = filter_conditions(table, filter)?;
+    if !conditions.is_empty() {
+        query = format!("{} WHERE {}", query, conditions.join(" AND "));
+    }
+    let mut stmt = connection.prepare(&format!("{} ORDER BY 1", query))?;
+    let columns = stmt
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let url_index = url_column.and_then(|name| columns.iter().position(|c| c == name));
+    let mut rows = stmt.query(params_from_iter(args))?;
+
+    if let ExportFormat::Csv = format {
+        write_csv_row(writer, columns.iter().map(|c| Value::Text(c.clone())))?;
+    }
+    let (mut matched, mut count) = (0, 0);
+    while let Some(row) = rows.next()? {
+        if filter.limit.is_some_and(|limit| count >= limit) {
+            break;
+        }
+        let values = (0..columns.len())
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(index) = url_index {
+            let url = match &values[index] {
+                Value::Text(url) => Some(url.as_str()),
+                _ => None,
+            };
+            if !matches_domain(filter, url) {
+                continue;
+            }
+        }
+        matched += 1;
+        if matched <= filter.offset {
+            continue;
+        }
+        match format {
+            ExportFormat::Jsonl => {
+                let object = columns
+                    .iter()
+                    .cloned()
+                    .zip(values.into_iter().map(to_json))
+                    .collect::<Map<_, _>>();
+                writeln!(writer, "{}", serde_json::Value::Object(object))?;
+            }
+            ExportFormat::Csv => write_csv_row(writer, values.into_iter())?,
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Writes the saved body of every distinct stored page to a file per page, converted to Markdown or plain text on the
+/// way out.
+///
+/// Files are laid out like a mirror, in a tree of hosts and paths, with the extension of the format. Pages saved as
+/// objects are read from the object store of the reader. Pages saved in WARC or JSONL files, or whose contents were
+/// not saved, are left out.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `reader` - Reads the saved bodies of the pages.
+/// * `format` - The format to write the bodies in.
+/// * `filter` - The pages to include.
+/// * `output_dir` - The directory to write the files to.
+///
+/// # Returns
+/// The number of pages exported, and the number whose saved file could not be read.
+pub async fn export_bodies(
+    connection: &Connection,
+    reader: &BodyReader,
+    format: BodyFormat,
+    filter: &ExportFilter,
+    output_dir: &Path,
+) -> Result<(usize, usize), CrawlerError> {
+    let (mut conditions, args) = filter_conditions(ExportTable::Pages, filter)?;
+    conditions.extend(
+        [
+            "Page.DuplicateOf IS NULL",
+            "Page.Indexed = 1",
+            "Page.WarcFile IS NULL",
+            "COALESCE(Page.StoredFile NOT LIKE '%.jsonl', 1)",
+        ]
+        .map(String::from),
+    );
+    let mut stmt = connection.prepare(&format!(
+        "SELECT Page.Url, PageMeta.Title, Page.Charset
+        FROM Page LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+        WHERE {}
+        ORDER BY Page.Id",
+        conditions.join(" AND ")
+    ))?;
+    let pages = stmt
+        .query_map(params_from_iter(args), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    drop(stmt);
+
+    let (mut exported, mut missing) = (0, 0);
+    let mut written = HashSet::new();
+    for (url, title, charset) in pages {
+        if !matches_domain(filter, Some(&url)) {
+            continue;
+        }
+        let body = match reader.read_url(&url).await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to read the saved file of {}: {}", url, e);
+                missing += 1;
+                continue;
+            }
+        };
+        let parsed_url = Url::parse(&url)?;
+        let contents = match format {
+            BodyFormat::Html => body,
+            BodyFormat::Text => plain_text(&Html::parse_document(&decode_saved(
+                &body,
+                charset.as_deref(),
+            )))
+            .into_bytes(),
+            BodyFormat::Markdown => {
+                let document = Html::parse_document(&decode_saved(&body, charset.as_deref()));
+                let mut contents = format!("---\nurl: {}\n", url);
+                if let Some(title) = title {
+                    contents.push_str(&format!("title: {}\n", serde_json::to_string(&title)?));
+                }
+                contents.push_str("---\n\n");
+                contents.push_str(&markdown(&document, &parsed_url));
+                contents.into_bytes()
+            }
+        };
+        let mut path = mirror_path(output_dir, &parsed_url).with_extension(format.extension());
+        // Another URL may map to the same file, e.g. `/a` and `/a.html`
+        if !written.insert(path.clone()) {
+            path = unique_mirror_path(output_dir, &parsed_url).with_extension(format.extension());
+            written.insert(path.clone());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents)?;
+        exported += 1;
+    }
+    Ok((exported, missing))
+}
+
+/// Returns whether a record passes the domain filter, given the URL of its page.
+fn matches_domain(filter: &ExportFilter, url: Option<&str>) -> bool {
+    let Some(domain) = &filter.domain else {
+        return true;
+    };
+    let url = url.and_then(|url| Url::parse(url).ok());
+    url.as_ref().and_then(|url| url.host_str()) == Some(normalize_host(domain).as_str())
+}
+
+/// Builds the SQL conditions and their arguments that restrict records of a table to a filter. The domain of pages
+/// is matched separately, see [`matches_domain`].
+///
+/// # Returns
+/// The conditions, or an error if the table cannot be filtered that way.
+fn filter_conditions(
+    table: ExportTable,
+    filter: &ExportFilter,
+) -> Result<(Vec<String>, Vec<Value>), CrawlerError> {
+    let mut conditions = Vec::new();
+    let mut args = Vec::new();
+    match (table, &filter.since) {
+        (_, None) => {}
+        (ExportTable::Domains, Some(Since::Run(id))) => {
+            conditions
+                .push("Created >= (SELECT MIN(Started) FROM CrawlRun WHERE Id > ?)".to_string());
+            args.push(Value::Integer(*id));
+        }
+        (ExportTable::Domains, Some(Since::Timestamp(ts))) => {
+            conditions.push("Created > ?".to_string());
+            args.push(Value::Text(ts.clone()));
+        }
+        (_, Some(Since::Run(id))) => {
+            conditions.push("Page.RunId > ?".to_string());
+            args.push(Value::Integer(*id));
+        }
+        (_, Some(Since::Timestamp(ts))) => {
+            conditions.push("COALESCE(Page.Updated, Page.Created) > ?".to_string());
+            args.push(Value::Text(ts.clone()));
+        }
+    }
+    if let Some(status) = filter.status {
+        if let ExportTable::Domains = table {
+            return Err(CrawlerError::Config(
+                "Domains cannot be filtered by status".into(),
+            ));
+        }
+        conditions.push(format!("{} = ?", PAGE_STATUS));
+        args.push(Value::Integer(status.into()));
+    }
+    if let Some(hash) = &filter.hash {
+        if let ExportTable::Domains = table {
+            return Err(CrawlerError::Config(
+                "Domains cannot be filtered by hash".into(),
+            ));
+        }
+        conditions.push("Page.Hash = ?".to_string());
+        args.push(Value::Text(hash.clone()));
+    }
+    if let Some(tag) = &filter.tag {
+        if let ExportTable::Domains = table {
+            return Err(CrawlerError::Config(
+                "Domains cannot be filtered by tag".into(),
+            ));
+        }
+        conditions.push(
+            "EXISTS (SELECT 1 FROM PageTag WHERE PageTag.PageId = Page.Id AND PageTag.Tag = ?)"
+                .to_string(),
+        );
+        args.push(Value::Text(tag.clone()));
+    }
+    if let (ExportTable::Domains, Some(domain)) = (table, &filter.domain) {
+        conditions.push("Name = ?".to_string());
+        args.push(Value::Text(domain.clone()));
+    }
+    Ok((conditions, args))
+}
+
+/// Converts a database value to JSON.
+fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => i.into(),
+        Value::Real(f) => Number::from_f64(f).map_or(serde_json::Value::Null, Into::into),
+        Value::Text(s) => s.into(),
+        Value::Blob(b) => hex::encode(b).into(),
+    }
+}
+
+/// Writes a single CSV row, quoting fields that contain separators, quotes or line breaks.
+///
+/// # Arguments
+/// * `writer` - The destination of the row.
+/// * `values` - The fields of the row. Nulls are written as empty fields.
+///
+/// # Returns
+/// A Result indicating success or failure.
+fn write_csv_row(
+    writer: &mut impl Write,
+    values: impl Iterator<Item = Value>,
+) -> Result<(), CrawlerError> {
+    let fields = values
+        .map(|value| {
+            let field = match value {
+                Value::Null => String::new(),
+                Value::Integer(i) => i.to_string(),
+                Value::Real(f) => f.to_string(),
+                Value::Text(s) => s,
+                Value::Blob(b) => hex::encode(b),
+            };
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>();
+    writeln!(writer, "{}", fields.join(","))?;
+    Ok(())
+}
@@ -0,0 +1,441 @@
+//! HTTP requests, with recording and offline replay of responses, and serving of local files.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use tracing::info;
+use url::Url;
+
+use crate::auth::Credentials;
+use crate::dns::is_private_address;
+use crate::error::CrawlerError;
+
+/// Where the responses to requests come from.
+pub enum FetchMode {
+    /// Requests are sent to servers.
+    Live,
+    /// Requests are sent to servers, and their responses saved to a fixtures directory.
+    Record(PathBuf),
+    /// Responses are read from a fixtures directory, without any network access.
+    Replay(PathBuf),
+    /// Responses are served from the files of a local directory, as a static web server would serve them, whatever
+    /// the host of the URL, without any network access.
+    Directory(PathBuf),
+}
+
+/// The host the pages of `file://` seeds are crawled under.
+pub const LOCAL_HOST: &str = "localhost";
+
+/// A response saved in a fixtures directory.
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// The body, if it is valid UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// The hex encoded body, if it is not valid UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_hex: Option<String>,
+}
+
+/// Sends single HTTP GET requests, or records and replays their responses.
+///
+/// Fixtures are JSON files of one response each, matched to requests by URL. Request headers are not taken into
+/// account, and recording a URL again replaces its fixture.
+pub struct Fetcher {
+    client: Client,
+    mode: FetchMode,
+    fixtures: HashMap<String, Fixture>,
+    block_private: bool,
+    credentials: Credentials,
+}
+
+impl Fetcher {
+    /// Creates a new `Fetcher`, loading the fixtures when replaying.
+    ///
+    /// # Arguments
+    /// * `client` - The client used to send requests.
+    /// * `mode` - Where responses come from.
+    ///
+    /// # Returns
+    /// The fetcher, or an error if the fixtures directory cannot be read or created.
+    pub fn new(client: Client, mode: FetchMode) -> Result<Self, CrawlerError> {
+        let mut fixtures = HashMap::new();
+        match &mode {
+            FetchMode::Live => {}
+            FetchMode::Record(dir) => fs::create_dir_all(dir)?,
+            FetchMode::Replay(dir) => {
+                for entry in fs::read_dir(dir)? {
+                    let path = entry?.path();
+                    if path
+                        .extension()
+                        .is_some_and(|extension| extension == "json")
+                    {
+                        let fixture: Fixture = serde_json::from_str(&fs::read_to_string(&path)?)
+                            .map_err(|e| {
+                                CrawlerError::Parse(format!(
+                                    "Invalid fixture {}: {}",
+                                    path.display(),
+                                    e
+                                ))
+                            })?;
+                        fixtures.insert(fixture.url.clone(), fixture);
+                    }
+                }
+                info!("Loaded {} fixtures from {}", fixtures.len(), dir.display());
+            }
+            FetchMode::Directory(dir) => {
+                if !dir.is_dir() {
+                    return Err(CrawlerError::Config(format!(
+                        "Fixture directory {} does not exist",
+                        dir.display()
+                    )));
+                }
+            }
+        }
+        Ok(Fetcher {
+            client,
+            mode,
+            fixtures,
+            block_private: false,
+            credentials: Credentials::default(),
+        })
+    }
+
+    /// Replaces the source of responses, keeping the client.
+    ///
+    /// # Arguments
+    /// * `mode` - Where responses come from.
+    ///
+    /// # Returns
+    /// The fetcher, or an error if the fixtures directory cannot be read or created.
+    pub fn with_mode(self, mode: FetchMode) -> Result<Self, CrawlerError> {
+        let block_private = self.block_private;
+        let credentials = self.credentials;
+        Ok(Fetcher::new(self.client, mode)?
+            .block_private_networks(block_private)
+            .credentials(credentials))
+    }
+
+    /// Sets whether requests to URLs whose host is a private network address are refused. Host names are checked by
+    /// the resolver of the client instead.
+    ///
+    /// # Arguments
+    /// * `block` - Whether the requests are refused.
+    pub fn block_private_networks(mut self, block: bool) -> Self {
+        self.block_private = block;
+        self
+    }
+
+    /// Sets the credentials sent to the hosts that require them.
+    ///
+    /// # Arguments
+    /// * `credentials` - The credentials, by origin.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Returns whether requests to the origin of a URL carry a credential.
+    pub fn has_credential(&self, url: &Url) -> bool {
+        self.credentials.covers(url)
+    }
+
+    /// Returns whether responses are read from fixtures instead of servers.
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.mode, FetchMode::Replay(_))
+    }
+
+    /// Returns whether responses come from fixtures or local files, so that no request reaches the network.
+    pub fn is_offline(&self) -> bool {
+        matches!(self.mode, FetchMode::Replay(_) | FetchMode::Directory(_))
+    }
+
+    /// Sends a GET request without following redirects.
+    ///
+    /// The credential of the origin of the URL, if it has one, is added to the headers.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to request.
+    /// * `headers` - Extra request headers.
+    ///
+    /// # Returns
+    /// The response, or an error if the request failed or no response was recorded for the URL.
+    pub async fn get(&self, url: &Url, mut headers: HeaderMap) -> Result<Response, CrawlerError> {
+        self.credentials.apply(url, &mut headers);
+        if self.block_private && !self.is_offline() {
+            let ip = match url.host() {
+                Some(url::Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+                Some(url::Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+                _ => None,
+            };
+            if ip.is_some_and(is_private_address) {
+                return Err(CrawlerError::Policy(format!(
+                    "{} is a private network address",
+                    url.host_str().unwrap_or_default()
+                )));
+            }
+        }
+        let dir = match &self.mode {
+            FetchMode::Live => {
+                return Ok(self
+                    .client
+                    .get(url.as_str())
+                    .headers(headers)
+                    .send()
+                    .await?)
+            }
+            FetchMode::Replay(_) => {
+                let fixture = self.fixtures.get(url.as_str()).ok_or_else(|| {
+                    CrawlerError::Fetch(format!("No recorded response for {}", url))
+                })?;
+                return fixture.to_response();
+            }
+            FetchMode::Directory(dir) => return serve_file(dir, url),
+            FetchMode::Record(dir) => dir,
+        };
+
+        let mut response = self
+            .client
+            .get(url.as_str())
+            .headers(headers)
+            .send()
+            .await?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        let fixture = Fixture::new(url, response.status(), response.headers(), body);
+        let path = dir.join(fixture_name(url));
+        fs::write(path, serde_json::to_string_pretty(&fixture)?)?;
+        fixture.to_response()
+    }
+}
+
+impl Fixture {
+    fn new(url: &Url, status: StatusCode, headers: &HeaderMap, body: Vec<u8>) -> Self {
+        let (body, body_hex) = match String::from_utf8(body) {
+            Ok(body) => (Some(body), None),
+            Err(e) => (None, Some(hex::encode(e.into_bytes()))),
+        };
+        Fixture {
+            url: url.to_string(),
+            status: status.as_u16(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into(),
+                    )
+                })
+                .collect(),
+            body,
+            body_hex,
+        }
+    }
+
+    /// Rebuilds the recorded response.
+    fn to_response(&self) -> Result<Response, CrawlerError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            CrawlerError::Parse(format!("Invalid fixture of {}: {}", self.url, e))
+        };
+        let body = match (&self.body, &self.body_hex) {
+            (_, Some(body_hex)) => hex::decode(body_hex).map_err(|e| invalid(&e))?,
+            (Some(body), None) => body.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        };
+        let mut response = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?,
+                HeaderValue::from_str(value).map_err(|e| invalid(&e))?,
+            );
+        }
+        Ok(Response::from(
+            response.body(body).map_err(|e| invalid(&e))?,
+        ))
+    }
+}
+
+/// Translates a `file://` seed into the directory its pages are served from and its URL under [`LOCAL_HOST`]. A seed
+/// ending in `/` is a directory, crawled from its index page, and any other seed is a file of the directory it is in.
+///
+/// # Returns
+/// The directory and the URL, or an error if the URL is not a local path.
+pub(crate) fn local_seed(url: &Url) -> Result<(PathBuf, Url), CrawlerError> {
+    let invalid = || CrawlerError::Config(format!("Invalid file URL {}", url));
+    let path = url.to_file_path().map_err(|_| invalid())?;
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default();
+    let dir = if name.is_empty() {
+        path
+    } else {
+        path.parent().ok_or_else(invalid)?.to_path_buf()
+    };
+    let seed = Url::parse(&format!("http://{}/{}", LOCAL_HOST, name)).map_err(|_| invalid())?;
+    Ok((dir, seed))
+}
+
+/// Answers a request from the files of a directory. The path of the URL is the path of the file within the directory,
+/// and its query is ignored. A directory is served from its `index.html`, and redirected to with a trailing slash
+/// when requested without one. Missing files are answered with 404 Not Found.
+fn serve_file(root: &Path, url: &Url) -> Result<Response, CrawlerError> {
+    let not_found = || local_response(StatusCode::NOT_FOUND, "text/html", b"Not Found".to_vec());
+    let mut file_url = Url::parse("file:///").expect("valid URL");
+    file_url.set_path(url.path());
+    let Ok(relative) = file_url.to_file_path() else {
+        return not_found();
+    };
+    let mut path = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::RootDir => {}
+            Component::Normal(name) => path.push(name),
+            _ => return not_found(),
+        }
+    }
+    if path.is_dir() {
+        if !url.path().ends_with('/') {
+            let mut location = url.clone();
+            location.set_path(&format!("{}/", url.path()));
+            let mut response =
+                local_response(StatusCode::MOVED_PERMANENTLY, "text/html", Vec::new())?;
+            response.headers_mut().insert(
+                reqwest::header::LOCATION,
+                HeaderValue::from_str(location.as_str())
+                    .map_err(|e| CrawlerError::Parse(e.to_string()))?,
+            );
+            return Ok(response);
+        }
+        path.push("index.html");
+    }
+    match fs::read(&path) {
+        Ok(body) => local_response(StatusCode::OK, content_type(&path), body),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => not_found(),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Builds the response to a request for a local file.
+fn local_response(
+    status: StatusCode,
+    content_type: &str,
+    body: Vec<u8>,
+) -> Result<Response, CrawlerError> {
+    let response = http::Response::builder()
+        .status(status)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .header(reqwest::header::CONTENT_LENGTH, body.len())
+        .body(body)
+        .map_err(|e| CrawlerError::Parse(e.to_string()))?;
+    Ok(Response::from(response))
+}
+
+/// Returns the MIME type of a local file, by its extension.
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html",
+        Some("txt") => "text/plain",
+        Some("xml") => "application/xml",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Names the fixture file of a URL after the URL, with a hash to tell apart URLs that only differ in punctuation.
+fn fixture_name(url: &Url) -> String {
+    let readable = url
+        .as_str()
+        .split_once("://")
+        .map_or(url.as_str(), |(_, rest)| rest)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(80)
+        .collect::<String>();
+    let hash = blake3::hash(url.as_str().as_bytes()).to_hex();
+    format!("{}-{}.json", readable, &hash[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fixture_round_trips_binary_body() {
+        let url = Url::parse("http://example.com/image.png").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("image/png"));
+        let body = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+
+        let fixture = Fixture::new(&url, StatusCode::OK, &headers, body.clone());
+        assert!(fixture.body.is_none());
+        let json = serde_json::to_string(&fixture).unwrap();
+        let response = serde_json::from_str::<Fixture>(&json)
+            .unwrap()
+            .to_response()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert_eq!(response.bytes().await.unwrap().as_ref(), body.as_slice());
+    }
+
+    #[test]
+    fn fixture_names_differ_for_similar_urls() {
+        let first = fixture_name(&Url::parse("http://example.com/a-b").unwrap());
+        let second = fixture_name(&Url::parse("http://example.com/a_b").unwrap());
+        assert!(first.starts_with("example_com_a_b-"));
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn serves_local_files() {
+        let root = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/site"));
+        let get = |url: &str| serve_file(root, &Url::parse(url).unwrap()).unwrap();
+
+        let index = get("http://example.com/?page=1");
+        assert_eq!(index.status(), StatusCode::OK);
+        assert_eq!(index.headers()["content-type"], "text/html");
+        assert!(index
+            .text()
+            .await
+            .unwrap()
+            .contains("<title>Fixture site</title>"));
+        let directory = get("http://example.com/docs");
+        assert_eq!(directory.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(directory.headers()["location"], "http://example.com/docs/");
+        assert_eq!(
+            get("http://example.com/robots.txt").headers()["content-type"],
+            "text/plain"
+        );
+        assert_eq!(
+            get("http://example.com/missing.html").status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get("http://example.com/docs/..%2F..%2F..%2F..%2FCargo.toml").status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,724 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::memory::MemoryUsage;
+use crate::scoring::Scheduling;
+use crate::store::CrawlStore;
+
+/// The number of spilled URLs moved back into memory at once.
+const REFILL_BATCH_SIZE: usize = 1000;
+
+/// A queued URL with its score.
+struct ScoredUrl {
+    score: f64,
+    /// The order the URL was queued in, so that URLs with equal scores are popped last in, first out.
+    sequence: u64,
+    url: String,
+}
+
+impl PartialEq for ScoredUrl {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredUrl {}
+
+impl PartialOrd for ScoredUrl {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredUrl {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+/// A priority queue of URLs without duplicates, which pops the URL with the highest score.
+#[derive(Default)]
+struct ScoredQueue {
+    heap: BinaryHeap<ScoredUrl>,
+    urls: HashSet<String>,
+}
+
+impl ScoredQueue {
+    /// Pushes a URL, returning `false` if it was already queued.
+    fn push(&mut self, entry: ScoredUrl) -> bool {
+        if !self.urls.insert(entry.url.clone()) {
+            return false;
+        }
+        self.heap.push(entry);
+        true
+    }
+
+    /// Returns the highest score.
+    fn top_score(&self) -> Option<f64> {
+        self.heap.peek().map(|entry| entry.score)
+    }
+
+    /// Removes every URL, returning them in no particular order.
+    fn take(&mut self) -> Vec<ScoredUrl> {
+        self.urls.clear();
+        std::mem::take(&mut self.heap).into_vec()
+    }
+
+    /// Pops the URL with the highest score.
+    fn pop(&mut self) -> Option<(String, f64)> {
+        let ScoredUrl { url, score, .. } = self.heap.pop()?;
+        self.urls.remove(&url);
+        Some((url, score))
+    }
+
+    /// Removes the URLs that do not satisfy a predicate, returning them.
+    fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) -> Vec<String> {
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.heap)
+            .into_vec()
+            .into_iter()
+            .partition(|entry| keep(&entry.url));
+        self.heap = BinaryHeap::from(kept);
+        removed
+            .into_iter()
+            .map(|entry| {
+                self.urls.remove(&entry.url);
+                entry.url
+            })
+            .collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+/// The URLs held in memory: one [`ScoredQueue`], or with [`Scheduling::RoundRobin`] one per host, which take turns.
+#[derive(Default)]
+struct MemoryQueue {
+    round_robin: bool,
+    /// The queues by host, or a single queue under the empty name.
+    queues: HashMap<String, ScoredQueue>,
+    /// The hosts with queued URLs, in the order of their turns.
+    turns: VecDeque<String>,
+    len: usize,
+    sequence: u64,
+}
+
+impl MemoryQueue {
+    /// Returns the name of the queue of a URL.
+    fn queue_name(&self, url: &str) -> String {
+        match self.round_robin {
+            true => Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default(),
+            false => String::new(),
+        }
+    }
+
+    /// Pushes a URL, returning `false` if it was already queued.
+    fn push(&mut self, url: String, score: f64) -> bool {
+        self.sequence += 1;
+        let entry = ScoredUrl {
+            score,
+            sequence: self.sequence,
+            url,
+        };
+        self.push_entry(entry)
+    }
+
+    /// Pushes a URL with its sequence, returning `false` if it was already queued.
+    fn push_entry(&mut self, entry: ScoredUrl) -> bool {
+        let name = self.queue_name(&entry.url);
+        let queue = self.queues.entry(name.clone()).or_default();
+        if !queue.push(entry) {
+            return false;
+        }
+        if queue.len() == 1 {
+            self.turns.push_back(name);
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Returns the highest score of any queue.
+    fn top_score(&self) -> Option<f64> {
+        self.queues
+            .values()
+            .filter_map(ScoredQueue::top_score)
+            .max_by(f64::total_cmp)
+    }
+
+    /// Removes every URL but the `keep` with the highest scores, returning them lowest score first, and among equal
+    /// scores in the order they were queued.
+    fn split_off_lowest(&mut self, keep: usize) -> Vec<ScoredUrl> {
+        let mut entries = self
+            .queues
+            .values_mut()
+            .flat_map(ScoredQueue::take)
+            .collect::<Vec<_>>();
+        entries.sort();
+        let kept = entries.split_off(entries.len().saturating_sub(keep));
+        let turns = std::mem::take(&mut self.turns);
+        self.queues.clear();
+        self.len = 0;
+        for entry in kept {
+            self.push_entry(entry);
+        }
+        // Hosts keep their place in the rotation
+        self.turns = turns
+            .into_iter()
+            .filter(|name| self.queues.contains_key(name))
+            .collect();
+        entries
+    }
+
+    /// Pops the URL with the highest score of the queue whose turn it is, and moves that queue to the back.
+    fn pop(&mut self) -> Option<(String, f64)> {
+        let name = self.turns.pop_front()?;
+        let queue = self.queues.get_mut(&name)?;
+        let popped = queue.pop();
+        match queue.is_empty() {
+            true => {
+                self.queues.remove(&name);
+            }
+            false => self.turns.push_back(name),
+        }
+        self.len -= popped.is_some() as usize;
+        popped
+    }
+
+    /// Removes the URLs that do not satisfy a predicate, returning them.
+    fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) -> Vec<String> {
+        let removed = self
+            .queues
+            .values_mut()
+            .flat_map(|queue| queue.retain(&mut keep))
+            .collect::<Vec<_>>();
+        self.queues.retain(|_, queue| !queue.is_empty());
+        self.turns.retain(|name| self.queues.contains_key(name));
+        self.len -= removed.len();
+        removed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// The queue of URLs waiting to be crawled, highest score first.
+///
+/// URLs are scored when they are queued, see [`crate::scoring::Scorer`]. URLs are held in memory until spilling is
+/// enabled, after which they are written to the `FrontierUrl` table of the [`CrawlStore`] and read back once the
+/// in-memory queue drains. The time each URL was queued is kept with it, in the `Created` column of spilled URLs, and
+/// their score in the `Score` column.
+///
+/// With a limit, at most that many URLs are held in memory: once it is exceeded, the lower scoring half is spilled,
+/// and URLs scoring no higher than a spilled URL are spilled directly. Spilled URLs are read back highest score first,
+/// before any URL in memory that scores lower, so URLs are crawled highest score first as without spilling, though URLs
+/// with equal scores may be crawled in another order.
+///
+/// With [`Scheduling::RoundRobin`], the URLs in memory are queued by host, and the hosts take turns, so that each
+/// host gets one URL crawled, its highest scoring, before any host gets a second. Spilled URLs are read back by score
+/// and then join the queue of their host.
+///
+/// A frontier shared by several worker processes keeps every URL in the store. Workers claim URLs one at a time,
+/// which marks them `claimed` with the id of the worker and a lease, and acknowledge them once crawled, which marks
+/// them `done` so they are not queued again. URLs whose lease expired, e.g. because their worker stopped, are claimed
+/// again by other workers.
+pub struct Frontier {
+    queue: MemoryQueue,
+    queued_at: HashMap<String, SystemTime>,
+    bytes: usize,
+    spilling: bool,
+    spilled: usize,
+    /// An upper bound of the scores of the spilled URLs.
+    spilled_max: f64,
+    limit: Option<usize>,
+    worker: Option<(String, Duration)>,
+}
+
+impl Default for Frontier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Frontier {
+    /// Creates a new, empty `Frontier`.
+    pub fn new() -> Self {
+        Frontier {
+            queue: MemoryQueue::default(),
+            queued_at: HashMap::new(),
+            bytes: 0,
+            spilling: false,
+            spilled: 0,
+            spilled_max: f64::NEG_INFINITY,
+            limit: None,
+            worker: None,
+        }
+    }
+
+    /// Creates a frontier shared with other worker processes through the `FrontierUrl` table of the store.
+    ///
+    /// # Arguments
+    /// * `worker_id` - The id of this worker, recorded with the URLs it claims.
+    /// * `lease` - How long a claimed URL is reserved for this worker before others may claim it.
+    pub fn shared(worker_id: String, lease: Duration) -> Self {
+        Frontier {
+            spilling: true,
+            worker: Some((worker_id, lease)),
+            ..Self::new()
+        }
+    }
+
+    /// Push a URL into the frontier.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier, used when spilling.
+    /// * `url` - The URL to queue.
+    /// * `score` - The score of the URL.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn push(
+        &mut self,
+        store: &dyn CrawlStore,
+        url: String,
+        score: f64,
+    ) -> Result<(), CrawlerError> {
+        self.push_at(store, url, SystemTime::now(), score)
+    }
+
+    /// Push a URL into the frontier that was queued earlier, e.g. one that was popped but could not be crawled yet.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier, used when spilling.
+    /// * `url` - The URL to queue.
+    /// * `queued` - When the URL was first queued.
+    /// * `score` - The score of the URL.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn push_at(
+        &mut self,
+        store: &dyn CrawlStore,
+        url: String,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<(), CrawlerError> {
+        // A URL popped earlier is claimed by this worker, so release it for any worker to take
+        if let Some((worker_id, _)) = &self.worker {
+            if store.frontier_release(&url, worker_id)? {
+                return Ok(());
+            }
+        }
+        if self.spilling || (self.spilled > 0 && score <= self.spilled_max) {
+            self.spill(store, &url, queued, score)?;
+        } else {
+            let size = MemoryUsage::url_size(&url);
+            let key = url.clone();
+            if self.queue.push(url, score) {
+                self.queued_at.insert(key, queued);
+                self.bytes += 3 * size;
+            }
+            if self.limit.is_some_and(|limit| self.queue.len() > limit) {
+                self.spill_lowest(store)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Spills the lower scoring half of the URLs held in memory.
+    fn spill_lowest(&mut self, store: &dyn CrawlStore) -> Result<(), CrawlerError> {
+        let keep = self.queue.len() / 2;
+        for entry in self.queue.split_off_lowest(keep) {
+            self.bytes -= 3 * MemoryUsage::url_size(&entry.url);
+            let queued = self
+                .queued_at
+                .remove(&entry.url)
+                .unwrap_or_else(SystemTime::now);
+            self.spill(store, &entry.url, queued, entry.score)?;
+        }
+        Ok(())
+    }
+
+    /// Pop the URL with the highest score from the frontier, refilling the in-memory queue from spilled URLs if it is
+    /// empty. A shared frontier claims the URL with the highest score, or one whose lease expired, instead.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier, used to refill spilled URLs.
+    ///
+    /// # Returns
+    /// `Some((url, queued, score))` with the time the URL was queued if the frontier is not empty, otherwise `None`.
+    pub fn pop(
+        &mut self,
+        store: &dyn CrawlStore,
+    ) -> Result<Option<(String, SystemTime, f64)>, CrawlerError> {
+        if let Some((worker_id, lease)) = &self.worker {
+            let claimed = store.frontier_claim(worker_id, *lease)?;
+            self.spilled = store.frontier_count(true)?;
+            return Ok(claimed);
+        }
+        if self.spilled > 0
+            && self
+                .queue
+                .top_score()
+                .is_none_or(|top| top < self.spilled_max)
+        {
+            self.refill(store)?;
+        }
+        let Some((url, score)) = self.queue.pop() else {
+            return Ok(None);
+        };
+        self.bytes -= 3 * MemoryUsage::url_size(&url);
+        let queued = self.queued_at.remove(&url).unwrap_or_else(SystemTime::now);
+        Ok(Some((url, queued, score)))
+    }
+
+    /// Marks a URL claimed by this worker as crawled, so that no worker queues it again. Does nothing unless the
+    /// frontier is shared.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier.
+    /// * `url` - The URL popped from the frontier.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn ack(&mut self, store: &dyn CrawlStore, url: &str) -> Result<(), CrawlerError> {
+        if let Some((worker_id, _)) = &self.worker {
+            store.frontier_ack(url, worker_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the frontier is shared with other worker processes.
+    pub fn is_shared(&self) -> bool {
+        self.worker.is_some()
+    }
+
+    /// Writes a URL to the store.
+    fn spill(
+        &mut self,
+        store: &dyn CrawlStore,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<(), CrawlerError> {
+        if store.frontier_push(url, queued, score)? {
+            self.spilled += 1;
+            self.spilled_max = self.spilled_max.max(score);
+        }
+        Ok(())
+    }
+
+    /// Moves a batch of the spilled URLs with the highest scores back into memory.
+    fn refill(&mut self, store: &dyn CrawlStore) -> Result<(), CrawlerError> {
+        let batch_size = self.limit.map_or(REFILL_BATCH_SIZE, |limit| {
+            (limit / 2).clamp(1, REFILL_BATCH_SIZE)
+        });
+        let urls = store.frontier_take(batch_size)?;
+        match urls.is_empty() {
+            true => self.spilled = 0,
+            false => self.spilled = self.spilled.saturating_sub(urls.len()),
+        }
+        // The URLs left in the store score no higher than the lowest URL taken
+        self.spilled_max = match urls.last() {
+            Some((_, _, score)) if self.spilled > 0 => *score,
+            _ => f64::NEG_INFINITY,
+        };
+
+        for (url, queued, score) in urls {
+            let size = MemoryUsage::url_size(&url);
+            let key = url.clone();
+            if self.queue.push(url, score) {
+                self.queued_at.insert(key, queued);
+                self.bytes += 3 * size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every queued URL of a host, in memory or spilled, that matches a predicate.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier, used to remove spilled URLs.
+    /// * `host` - The host name of the URLs, on any port. Only the spilled URLs of the host are read.
+    /// * `matches` - Returns whether to remove a URL of the host.
+    ///
+    /// # Returns
+    /// The removed URLs.
+    pub fn remove_matching(
+        &mut self,
+        store: &dyn CrawlStore,
+        host: &str,
+        matches: impl Fn(&str) -> bool,
+    ) -> Result<Vec<String>, CrawlerError> {
+        let mut removed = self.queue.retain(|url| !matches(url));
+        for url in &removed {
+            self.queued_at.remove(url);
+            self.bytes -= 3 * MemoryUsage::url_size(url);
+        }
+
+        if self.spilled > 0 {
+            let spilled = store.frontier_remove(host, &matches)?;
+            self.spilled = self.spilled.saturating_sub(spilled.len());
+            removed.extend(spilled);
+        }
+        Ok(removed)
+    }
+
+    /// Saves the URLs held in memory to the store, so that a later run can continue with them. A shared frontier
+    /// returns the URLs claimed by this worker to the other workers instead.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier.
+    ///
+    /// # Returns
+    /// The number of URLs left in the store, including those spilled before.
+    pub fn save(&mut self, store: &dyn CrawlStore) -> Result<usize, CrawlerError> {
+        if let Some((worker_id, _)) = &self.worker {
+            store.frontier_release_all(worker_id)?;
+            return store.frontier_count(true);
+        }
+        let mut urls = Vec::new();
+        while let Some((url, score)) = self.queue.pop() {
+            let queued = self.queued_at.remove(&url).unwrap_or_else(SystemTime::now);
+            urls.push((url, queued, score));
+        }
+        self.bytes = 0;
+        // Spilled URLs of equal score are popped in reverse within a refilled batch, so insert them in reverse to keep
+        // their order
+        for (url, queued, score) in urls.iter().rev() {
+            self.spill(store, url, *queued, *score)?;
+        }
+        Ok(self.spilled)
+    }
+
+    /// Continues with the URLs saved to the store by a previous run.
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier.
+    ///
+    /// # Returns
+    /// The number of saved URLs.
+    pub fn restore(&mut self, store: &dyn CrawlStore) -> Result<usize, CrawlerError> {
+        self.spilled = store.frontier_count(self.worker.is_some())?;
+        self.spilled_max = f64::INFINITY;
+        Ok(self.spilled)
+    }
+
+    /// Adds the links recorded on stored pages that were never crawled, see
+    /// [`CrawlStore::frontier_add_uncrawled_links`].
+    ///
+    /// # Arguments
+    /// * `store` - The store of the frontier.
+    /// * `ignore_robots` - Whether links marked nofollow, and the links of pages marked nofollow, are added too.
+    ///
+    /// # Returns
+    /// The number of URLs added.
+    pub fn add_uncrawled_links(
+        &mut self,
+        store: &dyn CrawlStore,
+        ignore_robots: bool,
+    ) -> Result<usize, CrawlerError> {
+        let added = store.frontier_add_uncrawled_links(ignore_robots)?;
+        self.spilled += added;
+        if added > 0 {
+            self.spilled_max = self.spilled_max.max(0.0);
+        }
+        Ok(added)
+    }
+
+    /// Sets the order URLs are popped in. Must be set before any URL is pushed.
+    ///
+    /// # Arguments
+    /// * `scheduling` - The order.
+    pub fn set_scheduling(&mut self, scheduling: Scheduling) {
+        self.queue.round_robin = scheduling == Scheduling::RoundRobin;
+    }
+
+    /// Limits how many URLs are held in memory, spilling the others to the database.
+    ///
+    /// # Arguments
+    /// * `limit` - The largest number of URLs held in memory, or None for no limit.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Sets whether new URLs are spilled to the database instead of being held in memory.
+    ///
+    /// # Arguments
+    /// * `spilling` - Whether to spill.
+    pub fn set_spilling(&mut self, spilling: bool) {
+        self.spilling = spilling || self.worker.is_some();
+    }
+
+    /// Returns whether new URLs are currently being spilled.
+    pub fn is_spilling(&self) -> bool {
+        self.spilling
+    }
+
+    /// Returns whether the frontier, including spilled URLs, is empty.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.spilled == 0
+    }
+
+    /// Returns the number of URLs in the frontier, including spilled URLs.
+    pub fn len(&self) -> usize {
+        self.queue.len() + self.spilled
+    }
+
+    /// Returns the approximate memory used by the in-memory queue, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_turns_between_hosts() {
+        let popped = |round_robin| {
+            let mut queue = MemoryQueue {
+                round_robin,
+                ..MemoryQueue::default()
+            };
+            for (url, score) in [
+                ("https://big.example/a", 3.0),
+                ("https://big.example/b", 2.0),
+                ("https://big.example/c", 1.0),
+                ("https://small.example/", 0.0),
+                ("https://other.example/", 0.5),
+            ] {
+                assert!(queue.push(url.to_string(), score));
+            }
+            assert!(!queue.push("https://big.example/a".to_string(), 3.0));
+            queue.retain(|url| !url.ends_with("/b"));
+            assert_eq!(queue.len(), 4);
+            std::iter::from_fn(|| queue.pop().map(|(url, _)| url)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            popped(false),
+            [
+                "https://big.example/a",
+                "https://big.example/c",
+                "https://other.example/",
+                "https://small.example/",
+            ]
+        );
+        assert_eq!(
+            popped(true),
+            [
+                "https://big.example/a",
+                "https://small.example/",
+                "https://other.example/",
+                "https://big.example/c",
+            ]
+        );
+    }
+
+    /// Creates a crawl database in memory, as the store of spilled URLs.
+    fn store() -> rusqlite::Connection {
+        let mut connection = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+    }
+
+    /// Pops every URL of a frontier.
+    fn drain(frontier: &mut Frontier, store: &dyn CrawlStore) -> Vec<String> {
+        std::iter::from_fn(|| frontier.pop(store).unwrap().map(|(url, _, _)| url)).collect()
+    }
+
+    #[test]
+    fn pops_highest_score_first() {
+        let store = store();
+        let mut frontier = Frontier::new();
+        for (url, score) in [("a", 1.0), ("b", 2.0), ("c", 1.0), ("d", -1.0), ("e", 2.0)] {
+            frontier.push(&store, url.to_string(), score).unwrap();
+        }
+        frontier.push(&store, "a".to_string(), 5.0).unwrap();
+        assert_eq!(frontier.len(), 5);
+        // URLs of equal score are popped last queued first
+        assert_eq!(drain(&mut frontier, &store), ["e", "b", "c", "a", "d"]);
+        assert!(frontier.is_empty());
+    }
+
+    /// URLs spilled over the limit, or while spilling, are read back before any URL in memory that scores lower.
+    #[test]
+    fn refills_spilled_urls_in_score_order() {
+        let store = store();
+        let mut frontier = Frontier::new();
+        frontier.set_limit(Some(2));
+        for (url, score) in [("a", 1.0), ("b", 5.0), ("c", 3.0), ("d", 4.0), ("e", 2.0)] {
+            frontier.push(&store, url.to_string(), score).unwrap();
+        }
+        assert!(frontier.queue.len() <= 2);
+        frontier.set_spilling(true);
+        frontier.push(&store, "f".to_string(), 6.0).unwrap();
+        frontier.set_spilling(false);
+        assert_eq!(frontier.len(), 6);
+        assert_eq!(drain(&mut frontier, &store), ["f", "b", "d", "c", "e", "a"]);
+
+        // URLs saved by a run are read back in the same order by the next
+        for (url, score) in [("g", 1.0), ("h", 3.0), ("i", 2.0)] {
+            frontier.push(&store, url.to_string(), score).unwrap();
+        }
+        assert_eq!(frontier.save(&store).unwrap(), 3);
+        let mut resumed = Frontier::new();
+        assert_eq!(resumed.restore(&store).unwrap(), 3);
+        assert_eq!(drain(&mut resumed, &store), ["h", "i", "g"]);
+    }
+
+    /// Workers of a shared frontier claim different URLs, and take over those whose lease expired.
+    #[test]
+    fn reclaims_urls_whose_lease_expired() {
+        let store = store();
+        let lease = Duration::from_secs(60);
+        let mut first = Frontier::shared("first".to_string(), lease);
+        let mut second = Frontier::shared("second".to_string(), lease);
+        for (url, score) in [("a", 2.0), ("b", 1.0)] {
+            first.push(&store, url.to_string(), score).unwrap();
+        }
+        let claim = |frontier: &mut Frontier| frontier.pop(&store).unwrap().map(|(url, _, _)| url);
+        assert_eq!(claim(&mut first).as_deref(), Some("a"));
+        assert_eq!(claim(&mut second).as_deref(), Some("b"));
+        // Claimed URLs are left to their workers while the leases last
+        assert_eq!(claim(&mut second), None);
+        assert!(!second.is_empty());
+
+        second.ack(&store, "b").unwrap();
+        store
+            .execute(
+                "UPDATE FrontierUrl SET LeaseExpires = datetime('now', '-1 seconds') WHERE Url = 'a'",
+                [],
+            )
+            .unwrap();
+        assert_eq!(claim(&mut second).as_deref(), Some("a"));
+        // The first worker lost the URL, so its acknowledgement is ignored
+        first.ack(&store, "a").unwrap();
+        assert_eq!(store.frontier_count(true).unwrap(), 1);
+        second.ack(&store, "a").unwrap();
+        assert_eq!(claim(&mut first), None);
+        assert!(first.is_empty());
+
+        // URLs already crawled are not queued again
+        first.push(&store, "a".to_string(), 3.0).unwrap();
+        assert!(first.is_empty());
+    }
+}
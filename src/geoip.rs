@@ -0,0 +1,51 @@
+//! Lookup of the countries servers are located in, from a MaxMind GeoIP2 or GeoLite2 database.
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::net::IpAddr;
+use std::path::Path;
+use tracing::warn;
+
+use crate::error::CrawlerError;
+
+/// A country database in the MaxMind DB format, such as `GeoLite2-Country.mmdb` or `GeoIP2-City.mmdb`.
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    /// Opens a database, reading it into memory.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the `.mmdb` file.
+    ///
+    /// # Returns
+    /// The database, or an error if the file cannot be read or is not a MaxMind database.
+    pub fn open(path: &Path) -> Result<Self, CrawlerError> {
+        let reader = Reader::open_readfile(path).map_err(|e| {
+            CrawlerError::Config(format!("Invalid GeoIP database {}: {}", path.display(), e))
+        })?;
+        Ok(GeoIp { reader })
+    }
+
+    /// Looks up the country of an address.
+    ///
+    /// # Arguments
+    /// * `ip` - The address.
+    ///
+    /// # Returns
+    /// The ISO 3166-1 alpha-2 code of the country, e.g. `DE`, or None if the address is not in the database.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        match self.reader.lookup::<geoip2::Country>(ip) {
+            Ok(record) => record
+                .country
+                .or(record.registered_country)
+                .and_then(|country| country.iso_code)
+                .map(str::to_string),
+            Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+            Err(e) => {
+                warn!("GeoIP lookup of {} failed: {}", ip, e);
+                None
+            }
+        }
+    }
+}
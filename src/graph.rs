@@ -0,0 +1,388 @@
+//! The link graph of a crawl, written as DOT or GraphML, and the counts of links between domains.
+
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::Write;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::links::LinkElement;
+use crate::normalize::normalize_host;
+
+/// The file format a link graph is written in.
+#[derive(Clone, Copy)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// GraphML, as read by Gephi.
+    GraphMl,
+}
+
+/// A directed graph of the links between crawled pages or domains.
+pub struct LinkGraph {
+    /// The URLs or domain names of the nodes, and whether they were crawled.
+    nodes: Vec<(String, bool)>,
+    /// The number of links between each pair of nodes, by node index.
+    edges: BTreeMap<(usize, usize), usize>,
+}
+
+impl LinkGraph {
+    /// Builds the graph of the stored pages and the links found on them.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `root` - The URL depth is measured from. Defaults to the first stored page.
+    /// * `max_depth` - Only include nodes at most this many links away from the root. Includes every node if None.
+    ///
+    /// # Returns
+    /// The graph, with a node per URL.
+    pub fn load(
+        connection: &Connection,
+        root: Option<&str>,
+        max_depth: Option<usize>,
+    ) -> Result<Self, CrawlerError> {
+        let mut stmt = connection.prepare(&format!(
+            "SELECT Page.Url, PageLink.Url FROM PageLink JOIN Page ON Page.Id = PageLink.PageId
+            WHERE {}",
+            LinkElement::navigable_sql("PageLink.Element")
+        ))?;
+        let links = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut stmt = connection.prepare("SELECT Url FROM Page ORDER BY Id")?;
+        let pages = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let crawled: HashSet<&str> = pages.iter().map(String::as_str).collect();
+        let mut graph = LinkGraph {
+            nodes: Vec::new(),
+            edges: BTreeMap::new(),
+        };
+        let mut indices = HashMap::new();
+        for url in pages.iter().chain(links.iter().map(|(_, target)| target)) {
+            indices.entry(url.as_str()).or_insert_with(|| {
+                graph
+                    .nodes
+                    .push((url.clone(), crawled.contains(url.as_str())));
+                graph.nodes.len() - 1
+            });
+        }
+        for (source, target) in &links {
+            *graph
+                .edges
+                .entry((indices[source.as_str()], indices[target.as_str()]))
+                .or_insert(0) += 1;
+        }
+
+        match max_depth {
+            Some(max_depth) => {
+                let root = match root.or(pages.first().map(String::as_str)) {
+                    Some(root) => *indices.get(root).ok_or_else(|| {
+                        CrawlerError::Config(format!("{} is not in the link graph", root))
+                    })?,
+                    None => return Ok(graph),
+                };
+                Ok(graph.limit_depth(root, max_depth))
+            }
+            None => Ok(graph),
+        }
+    }
+
+    /// Removes the nodes that are more than a number of links away from a root node.
+    ///
+    /// # Arguments
+    /// * `root` - The index of the root node.
+    /// * `max_depth` - The maximum distance of kept nodes.
+    ///
+    /// # Returns
+    /// The reduced graph.
+    fn limit_depth(self, root: usize, max_depth: usize) -> Self {
+        let mut outgoing: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &(source, target) in self.edges.keys() {
+            outgoing.entry(source).or_default().push(target);
+        }
+
+        let mut depths = HashMap::from([(root, 0)]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(node) = queue.pop_front() {
+            let depth = depths[&node];
+            if depth == max_depth {
+                continue;
+            }
+            for &target in outgoing.get(&node).into_iter().flatten() {
+                if let Entry::Vacant(entry) = depths.entry(target) {
+                    entry.insert(depth + 1);
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        self.map_nodes(|index, node| depths.contains_key(&index).then(|| node.clone()))
+    }
+
+    /// Merges the nodes of each domain into one. Links within a domain are dropped.
+    ///
+    /// # Returns
+    /// The graph, with a node per domain and edges weighted by the number of links between domains.
+    pub fn collapse_domains(self) -> Self {
+        let graph = self.map_nodes(|_, (url, crawled)| {
+            let domain = Url::parse(url).ok()?.host_str()?.to_string();
+            Some((domain, *crawled))
+        });
+        LinkGraph {
+            edges: graph
+                .edges
+                .into_iter()
+                .filter(|((source, target), _)| source != target)
+                .collect(),
+            ..graph
+        }
+    }
+
+    /// Replaces the nodes of the graph, merging nodes with the same name and summing the weights of their edges.
+    ///
+    /// # Arguments
+    /// * `map` - Returns the new node of each node, or None to remove the node and its edges.
+    fn map_nodes(self, map: impl Fn(usize, &(String, bool)) -> Option<(String, bool)>) -> Self {
+        let mut nodes: Vec<(String, bool)> = Vec::new();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mapping = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let (name, crawled) = map(index, node)?;
+                let new_index = *indices.entry(name.clone()).or_insert_with(|| {
+                    nodes.push((name, false));
+                    nodes.len() - 1
+                });
+                nodes[new_index].1 |= crawled;
+                Some(new_index)
+            })
+            .collect::<Vec<_>>();
+
+        let mut edges = BTreeMap::new();
+        for ((source, target), weight) in self.edges {
+            if let (Some(source), Some(target)) = (mapping[source], mapping[target]) {
+                *edges.entry((source, target)).or_insert(0) += weight;
+            }
+        }
+        LinkGraph { nodes, edges }
+    }
+
+    /// Writes the graph in a file format.
+    ///
+    /// # Arguments
+    /// * `format` - The file format.
+    /// * `writer` - The destination of the graph.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn write(&self, format: GraphFormat, writer: &mut impl Write) -> Result<(), CrawlerError> {
+        match format {
+            GraphFormat::Dot => self.write_dot(writer),
+            GraphFormat::GraphMl => self.write_graphml(writer),
+        }
+    }
+
+    /// Writes the graph as Graphviz DOT. Nodes that were not crawled are dashed.
+    fn write_dot(&self, writer: &mut impl Write) -> Result<(), CrawlerError> {
+        writeln!(writer, "digraph crawl {{")?;
+        for (index, (name, crawled)) in self.nodes.iter().enumerate() {
+            let style = if *crawled { "" } else { ", style=dashed" };
+            writeln!(
+                writer,
+                "  n{} [label=\"{}\"{}];",
+                index,
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                style
+            )?;
+        }
+        for ((source, target), weight) in &self.edges {
+            writeln!(writer, "  n{} -> n{} [weight={}];", source, target, weight)?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    /// Writes the graph as GraphML, with the label and crawl state of nodes, and the weight of edges.
+    fn write_graphml(&self, writer: &mut impl Write) -> Result<(), CrawlerError> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="crawled" for="node" attr.name="crawled" attr.type="boolean"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>"#
+        )?;
+        writeln!(writer, r#"  <graph id="crawl" edgedefault="directed">"#)?;
+        for (index, (name, crawled)) in self.nodes.iter().enumerate() {
+            writeln!(
+                writer,
+                r#"    <node id="n{}"><data key="label">{}</data><data key="crawled">{}</data></node>"#,
+                index,
+                escape_xml(name),
+                crawled
+            )?;
+        }
+        for ((source, target), weight) in &self.edges {
+            writeln!(
+                writer,
+                r#"    <edge source="n{}" target="n{}"><data key="weight">{}</data></edge>"#,
+                source, target, weight
+            )?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        Ok(())
+    }
+}
+
+/// The number of links from the pages of one domain to another domain.
+#[derive(Serialize)]
+pub struct DomainLinkCount {
+    /// The domain of the pages the links were found on.
+    pub source: String,
+    /// The host the links point at.
+    pub target: String,
+    /// The number of links, counting a URL once per page it is linked from.
+    pub links: i64,
+}
+
+/// Counts the external links to pages, from `a`, `area` and `iframe` elements, by the domain of the page they were
+/// found on and the host they point at, and reads the domains linked to most.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `domain` - Only count the links from the pages of this domain. Counts the links of every domain if None.
+/// * `limit` - The largest number of counts returned.
+///
+/// # Returns
+/// The counts, most links first.
+pub fn top_linked_domains(
+    connection: &Connection,
+    domain: Option<&str>,
+    limit: usize,
+) -> Result<Vec<DomainLinkCount>, CrawlerError> {
+    let mut stmt = connection.prepare(&format!(
+        "SELECT Page.Url, PageLink.Url FROM PageLink JOIN Page ON Page.Id = PageLink.PageId
+        WHERE PageLink.External = 1 AND {}",
+        LinkElement::navigable_sql("PageLink.Element")
+    ))?;
+    let domain = domain.map(normalize_host);
+    let mut counts: HashMap<(String, String), i64> = HashMap::new();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let host = |column| -> Result<Option<String>, rusqlite::Error> {
+            let url = row.get_ref(column)?.as_str()?;
+            Ok(Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string)))
+        };
+        let (Some(source), Some(target)) = (host(0)?, host(1)?) else {
+            continue;
+        };
+        if domain.as_ref().is_none_or(|domain| *domain == source) {
+            *counts.entry((source, target)).or_insert(0) += 1;
+        }
+    }
+    let mut counts = counts
+        .into_iter()
+        .map(|((source, target), links)| DomainLinkCount {
+            source,
+            target,
+            links,
+        })
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| {
+        b.links
+            .cmp(&a.links)
+            .then_with(|| a.source.cmp(&b.source))
+            .then_with(|| a.target.cmp(&b.target))
+    });
+    counts.truncate(limit);
+    Ok(counts)
+}
+
+/// Escapes the characters of a string that are special in XML.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_links_between_domains() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO Page (Id, Url, RequestedUrl, Hash) VALUES
+                    (1, 'https://example.com/', 'https://example.com/', 'a'),
+                    (2, 'https://www.example.com/about', 'https://www.example.com/about', 'b'),
+                    (3, 'https://blog.example.org/', 'https://blog.example.org/', 'c');
+                INSERT INTO PageLink (PageId, Url, Element, External) VALUES
+                    (1, 'https://www.example.com/about', 'a', 0),
+                    (1, 'https://docs.rs/', 'a', 1),
+                    (1, 'https://github.com/a', 'a', 1),
+                    (1, 'https://github.com/b', 'area', 1),
+                    (1, 'https://cdn.example.net/logo.png', 'img', 1),
+                    (2, 'https://github.com/a', 'a', 1),
+                    (3, 'https://github.com/c', 'iframe', 1),
+                    (3, 'https://example.com/', 'a', 1);",
+            )
+            .unwrap();
+        let counts = |domain, limit| {
+            top_linked_domains(&connection, domain, limit)
+                .unwrap()
+                .into_iter()
+                .map(|count| (count.source, count.target, count.links))
+                .collect::<Vec<_>>()
+        };
+        let count =
+            |source: &str, target: &str, links| (source.to_string(), target.to_string(), links);
+        assert_eq!(
+            counts(None, 10),
+            [
+                count("example.com", "github.com", 2),
+                count("blog.example.org", "example.com", 1),
+                count("blog.example.org", "github.com", 1),
+                count("example.com", "docs.rs", 1),
+                count("www.example.com", "github.com", 1),
+            ]
+        );
+        assert_eq!(
+            counts(Some("EXAMPLE.com"), 1),
+            [count("example.com", "github.com", 2)]
+        );
+        assert!(counts(Some("example.net"), 10).is_empty());
+
+        // Links taken away with their page are no longer counted
+        connection
+            .execute("DELETE FROM PageLink WHERE PageId = 1", [])
+            .unwrap();
+        assert_eq!(
+            counts(Some("example.com"), 10),
+            Vec::<(String, String, i64)>::new()
+        );
+    }
+}
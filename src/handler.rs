@@ -0,0 +1,71 @@
+//! Extension points that let library users act on pages as they are crawled.
+
+pub use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use url::Url;
+
+/// A response the crawler received for a page.
+pub struct PageFetch<'a> {
+    /// The requested URL.
+    pub url: &'a Url,
+    /// The URL the response came from, after redirects.
+    pub final_url: &'a Url,
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: &'a HeaderMap,
+    /// The body of the response, or None if the status has no body worth reading, such as errors and 304.
+    pub body: Option<&'a [u8]>,
+    /// How many links away from the start URL the page was found.
+    pub depth: u32,
+}
+
+/// Receives the pages, links and errors of a crawl, in the order they are processed.
+///
+/// Every method does nothing by default, so handlers only implement those they need. Handlers are awaited one after
+/// the other, in the order they were registered with [`CrawlerBuilder::page_handler`](crate::CrawlerBuilder), while
+/// the crawler waits, so slow work should be handed off to another task.
+///
+/// ```no_run
+/// use rust_web_crawler::handler::{async_trait, PageFetch, PageHandler};
+/// use rust_web_crawler::Crawler;
+///
+/// struct PrintStatuses;
+///
+/// #[async_trait]
+/// impl PageHandler for PrintStatuses {
+///     async fn on_fetch(&self, fetch: &PageFetch<'_>) {
+///         println!("{} {}", fetch.status, fetch.final_url);
+///     }
+/// }
+///
+/// # fn build() -> Result<(), Box<dyn std::error::Error>> {
+/// let crawler = Crawler::builder("https://example.com/")
+///     .page_handler(PrintStatuses)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[async_trait]
+pub trait PageHandler: Send + Sync {
+    /// Called for every response that is processed, including error statuses.
+    ///
+    /// # Arguments
+    /// * `fetch` - The response.
+    async fn on_fetch(&self, _fetch: &PageFetch<'_>) {}
+
+    /// Called with the links found on a page, once the crawlable ones are queued.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    /// * `links` - The resolved URLs of the links, in document order.
+    async fn on_links_extracted(&self, _url: &Url, _links: &[Url]) {}
+
+    /// Called when a URL could not be crawled, because the request failed or the response was rejected.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `error` - A description of the error.
+    async fn on_error(&self, _url: &Url, _error: &str) {}
+}
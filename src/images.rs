@@ -0,0 +1,135 @@
+//! Downloading of the images shown on pages, for building image datasets.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::disk_writer::DiskWriter;
+
+/// The MIME types of the images downloaded when none are given.
+pub const DEFAULT_IMAGE_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/webp"];
+/// The size in KiB of the largest image downloaded when no limit is given.
+pub const DEFAULT_MAX_IMAGE_SIZE: u64 = 5 * 1024;
+
+/// The images that are saved.
+#[derive(Clone)]
+pub struct ImageLimits {
+    /// The MIME types of the images, e.g. `image/png`.
+    pub mime_types: Vec<String>,
+    /// The size in bytes of the largest image.
+    pub max_size: u64,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        ImageLimits {
+            mime_types: DEFAULT_IMAGE_TYPES.map(String::from).to_vec(),
+            max_size: DEFAULT_MAX_IMAGE_SIZE * 1024,
+        }
+    }
+}
+
+/// Why a downloaded image is not saved.
+pub enum ImageRejection {
+    /// Its MIME type is not one of those saved.
+    MimeType,
+    /// It is larger than the size limit.
+    TooLarge,
+}
+
+impl ImageRejection {
+    /// Returns the name of the reason, as stored in the `RejectedImage` table.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageRejection::MimeType => "mime_type",
+            ImageRejection::TooLarge => "too_large",
+        }
+    }
+}
+
+/// Saves the images within the limits to the images directory, named by the hash of their contents.
+pub struct ImageStore {
+    dir: PathBuf,
+    limits: ImageLimits,
+    writer: DiskWriter,
+}
+
+impl ImageStore {
+    /// Creates a new `ImageStore`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory images are saved in.
+    /// * `limits` - The images that are saved.
+    /// * `writer` - The writer files are written with.
+    pub fn new(dir: impl Into<PathBuf>, limits: ImageLimits, writer: DiskWriter) -> Self {
+        ImageStore {
+            dir: dir.into(),
+            limits,
+            writer,
+        }
+    }
+
+    /// Returns the size in bytes of the largest image saved, which limits the size of the image bodies read.
+    pub fn max_size(&self) -> u64 {
+        self.limits.max_size
+    }
+
+    /// Checks an image against the limits.
+    ///
+    /// # Arguments
+    /// * `mime_type` - The MIME type of the image, without parameters.
+    /// * `size` - The size of the image in bytes.
+    ///
+    /// # Returns
+    /// Why the image is not saved, or None if it is within the limits.
+    pub fn check(&self, mime_type: &str, size: usize) -> Option<ImageRejection> {
+        if !self
+            .limits
+            .mime_types
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(mime_type))
+        {
+            Some(ImageRejection::MimeType)
+        } else if size as u64 > self.limits.max_size {
+            Some(ImageRejection::TooLarge)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the path an image is saved at, with the usual extension of its MIME type.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of the contents.
+    /// * `mime_type` - The MIME type of the image.
+    pub fn path(&self, hash: &str, mime_type: &str) -> PathBuf {
+        let extension = match mime_type {
+            "image/jpeg" => "jpg",
+            "image/svg+xml" => "svg",
+            "image/x-icon" | "image/vnd.microsoft.icon" => "ico",
+            _ => mime_type
+                .strip_prefix("image/")
+                .filter(|subtype| subtype.chars().all(|c| c.is_ascii_alphanumeric()))
+                .unwrap_or("img"),
+        };
+        self.dir.join(format!("{}.{}", hash, extension))
+    }
+
+    /// Saves an image.
+    ///
+    /// # Arguments
+    /// * `path` - The path returned by [`ImageStore::path`].
+    /// * `body` - The contents.
+    pub async fn save(&self, path: PathBuf, body: &[u8]) -> io::Result<()> {
+        self.writer.write(path, body.to_vec()).await
+    }
+}
+
+/// Reads the width and height of an image from its header.
+///
+/// # Returns
+/// The dimensions in pixels, or None for formats without a fixed size, such as SVG, and unreadable images.
+pub fn dimensions(body: &[u8]) -> Option<(usize, usize)> {
+    imagesize::blob_size(body)
+        .ok()
+        .map(|size| (size.width, size.height))
+}
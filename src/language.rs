@@ -0,0 +1,175 @@
+//! Detection of the language pages are written in, for building monolingual corpora.
+
+use whatlang::Lang;
+
+/// The ISO 639-1 codes of the languages that can be detected, by their ISO 639-3 code.
+const ISO_639_1: [(&str, &str); 69] = [
+    ("afr", "af"),
+    ("aka", "ak"),
+    ("amh", "am"),
+    ("ara", "ar"),
+    ("aze", "az"),
+    ("bel", "be"),
+    ("ben", "bn"),
+    ("bul", "bg"),
+    ("cat", "ca"),
+    ("ces", "cs"),
+    ("cmn", "zh"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("epo", "eo"),
+    ("est", "et"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("guj", "gu"),
+    ("heb", "he"),
+    ("hin", "hi"),
+    ("hrv", "hr"),
+    ("hun", "hu"),
+    ("hye", "hy"),
+    ("ind", "id"),
+    ("ita", "it"),
+    ("jav", "jv"),
+    ("jpn", "ja"),
+    ("kan", "kn"),
+    ("kat", "ka"),
+    ("khm", "km"),
+    ("kor", "ko"),
+    ("lat", "la"),
+    ("lav", "lv"),
+    ("lit", "lt"),
+    ("mal", "ml"),
+    ("mar", "mr"),
+    ("mkd", "mk"),
+    ("mya", "my"),
+    ("nep", "ne"),
+    ("nld", "nl"),
+    ("nob", "nb"),
+    ("ori", "or"),
+    ("pan", "pa"),
+    ("pes", "fa"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("sin", "si"),
+    ("slk", "sk"),
+    ("slv", "sl"),
+    ("sna", "sn"),
+    ("spa", "es"),
+    ("srp", "sr"),
+    ("swe", "sv"),
+    ("tam", "ta"),
+    ("tel", "te"),
+    ("tgl", "tl"),
+    ("tha", "th"),
+    ("tuk", "tk"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("urd", "ur"),
+    ("uzb", "uz"),
+    ("vie", "vi"),
+    ("yid", "yi"),
+    ("zul", "zu"),
+];
+
+/// Detects the language of the text of a page.
+///
+/// # Arguments
+/// * `text` - The plain text of the page.
+/// * `declared` - The language the page declares, e.g. with `<html lang>`, used when the text is too short or mixed
+///   for a reliable detection.
+///
+/// # Returns
+/// The ISO 639-1 code of the language, e.g. `en`, or None if it cannot be told.
+pub fn detect(text: &str, declared: Option<&str>) -> Option<String> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .and_then(|info| iso_639_1(info.lang()))
+        .map(str::to_string)
+        .or_else(|| declared.and_then(primary_subtag))
+}
+
+/// Returns the primary language subtag of a language tag, e.g. `pt` for `pt-BR`.
+///
+/// # Returns
+/// The lowercase subtag, or None if the tag does not start with one.
+pub fn primary_subtag(tag: &str) -> Option<String> {
+    let subtag = tag.trim().split(['-', '_']).next()?;
+    (matches!(subtag.len(), 2 | 3) && subtag.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| subtag.to_ascii_lowercase())
+}
+
+/// Returns the ISO 639-1 code of a detected language.
+fn iso_639_1(lang: Lang) -> Option<&'static str> {
+    ISO_639_1
+        .iter()
+        .find(|(code, _)| *code == lang.code())
+        .map(|(_, code)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_languages() {
+        let cases = [
+            (
+                "The quick brown fox jumps over the lazy dog, while the farmer watches from the porch of his house.",
+                None,
+                Some("en"),
+            ),
+            (
+                "Der schnelle braune Fuchs springt über den faulen Hund, während der Bauer von der Veranda zusieht.",
+                Some("en"),
+                Some("de"),
+            ),
+            (
+                "Le renard brun rapide saute par-dessus le chien paresseux pendant que le fermier regarde.",
+                None,
+                Some("fr"),
+            ),
+            (
+                "Быстрая коричневая лиса прыгает через ленивую собаку, пока фермер смотрит с крыльца.",
+                None,
+                Some("ru"),
+            ),
+            // Text too short for a reliable detection falls back to the declared language
+            ("OK", Some("pt-BR"), Some("pt")),
+            ("OK", Some("en_GB"), Some("en")),
+            ("OK", None, None),
+            ("", Some("x-klingon"), None),
+        ];
+        for (text, declared, expected) in cases {
+            assert_eq!(detect(text, declared).as_deref(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_primary_subtags() {
+        let cases = [
+            ("en", Some("en")),
+            (" DE-at ", Some("de")),
+            ("zh_Hant_TW", Some("zh")),
+            ("fil", Some("fil")),
+            ("x-private", None),
+            ("e", None),
+            ("english", None),
+            ("12", None),
+            ("", None),
+        ];
+        for (tag, expected) in cases {
+            assert_eq!(primary_subtag(tag).as_deref(), expected, "{:?}", tag);
+        }
+    }
+
+    #[test]
+    fn knows_the_code_of_every_detected_language() {
+        for lang in Lang::all() {
+            assert!(iso_639_1(*lang).is_some(), "{}", lang.code());
+        }
+    }
+}
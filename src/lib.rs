@@ -0,0 +1,92 @@
+//! A web crawler that follows links between pages, respecting robots.txt rules, and records the pages, links and
+//! domains it finds in a SQLite database.
+//!
+//! The crawler is configured with a builder, and driven one batch of URLs at a time:
+//! ```no_run
+//! use rust_web_crawler::{CrawlPaths, Crawler, CrawlerError};
+//!
+//! # async fn run() -> Result<(), CrawlerError> {
+//! let mut crawler = Crawler::builder("https://example.com/")
+//!     .paths(CrawlPaths::in_dir("crawl"))
+//!     .user_agent("my_crawler")
+//!     .max_depth(Some(3))
+//!     .build()?;
+//! while crawler.crawl().await? {}
+//! crawler.finish().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The database is created when the crawler first opens it, and its schema upgraded by the migrations of [`schema`].
+#![warn(missing_docs)]
+
+pub mod api;
+pub mod assets;
+pub mod auth;
+mod body;
+pub mod builder;
+pub mod calendar;
+pub mod charset;
+pub mod config;
+pub mod content;
+pub mod coverage;
+pub mod crawler;
+pub mod diff;
+pub mod digest;
+pub mod disk_writer;
+pub mod dns;
+pub mod error;
+mod events;
+pub mod export;
+pub mod fetcher;
+mod frontier;
+pub mod geoip;
+pub mod graph;
+pub mod handler;
+pub mod images;
+pub mod language;
+pub mod links;
+mod memory;
+pub mod meta;
+pub mod normalize;
+pub mod notify;
+pub mod object_store;
+pub mod policy;
+pub mod preview;
+pub mod progress;
+pub mod query;
+mod reachability;
+pub mod reader;
+mod redirect_map;
+pub mod reload;
+pub mod render;
+pub mod retry;
+pub mod robots;
+pub mod routing;
+pub mod schedule;
+pub mod schema;
+pub mod scoring;
+pub mod search;
+pub mod shutdown;
+pub mod sitemap;
+pub mod stats;
+pub mod storage;
+pub mod store;
+pub mod tagging;
+pub mod terms;
+pub mod throttle;
+pub mod tls;
+pub mod unique_queue;
+pub mod validate;
+pub mod verify;
+pub mod visited;
+mod warc;
+pub mod webhook;
+
+pub use crate::builder::CrawlerBuilder;
+pub use crate::crawler::{BanPolicy, CrawlPaths, Crawler};
+pub use crate::error::CrawlerError;
+pub use crate::fetcher::{FetchMode, Fetcher, LOCAL_HOST};
+pub use crate::robots::{is_disallowed, parse_robots_txt, RobotsRules};
+pub use crate::storage::{PageCompression, PageStore, StorageFormat};
+pub use crate::unique_queue::UniqueQueue;
@@ -0,0 +1,499 @@
+//! Extraction of the links of pages, from anchors and optionally from other elements that refer to URLs.
+
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+/// Selects every element that can hold a link, whichever kinds of links are extracted.
+static LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse(
+        &LinkElement::ALL
+            .map(|element| element.source().0)
+            .join(", "),
+    )
+    .unwrap()
+});
+
+/// An element links are extracted from, recorded with each link in the `Element` column of `PageLink`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkElement {
+    /// `<a href>`.
+    A,
+    /// `<area href>` of image maps.
+    Area,
+    /// `<iframe src>`.
+    Iframe,
+    /// `<link href>`, e.g. stylesheets, icons and alternate versions.
+    Link,
+    /// `<img src>`.
+    Img,
+    /// `<script src>`.
+    Script,
+    /// Every candidate of the `srcset` of `<img>` and `<source>`.
+    Srcset,
+}
+
+impl LinkElement {
+    /// Every element.
+    pub const ALL: [LinkElement; 7] = [
+        LinkElement::A,
+        LinkElement::Area,
+        LinkElement::Iframe,
+        LinkElement::Link,
+        LinkElement::Img,
+        LinkElement::Script,
+        LinkElement::Srcset,
+    ];
+
+    /// Returns the name of the element, as recorded in `PageLink`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkElement::A => "a",
+            LinkElement::Area => "area",
+            LinkElement::Iframe => "iframe",
+            LinkElement::Link => "link",
+            LinkElement::Img => "img",
+            LinkElement::Script => "script",
+            LinkElement::Srcset => "srcset",
+        }
+    }
+
+    /// Finds an element by its name.
+    ///
+    /// # Arguments
+    /// * `name` - The name, e.g. `img`.
+    ///
+    /// # Returns
+    /// The element, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|element| element.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns whether links from the element lead to pages, which are queued, rather than to assets of the page,
+    /// which are only recorded.
+    pub fn is_navigable(self) -> bool {
+        matches!(
+            self,
+            LinkElement::A | LinkElement::Area | LinkElement::Iframe
+        )
+    }
+
+    /// Returns the SQL condition that a column of element names, such as `PageLink.Element`, holds a navigable
+    /// element, e.g. `PageLink.Element IN ('a', 'area', 'iframe')`.
+    ///
+    /// # Arguments
+    /// * `column` - The column.
+    pub fn navigable_sql(column: &str) -> String {
+        let names = Self::ALL
+            .into_iter()
+            .filter(|element| element.is_navigable())
+            .map(|element| format!("'{}'", element.name()))
+            .collect::<Vec<_>>();
+        format!("{} IN ({})", column, names.join(", "))
+    }
+
+    /// Returns the CSS selector of the elements holding links, and the attribute holding the URL.
+    fn source(self) -> (&'static str, &'static str) {
+        match self {
+            LinkElement::A => ("a[href]", "href"),
+            LinkElement::Area => ("area[href]", "href"),
+            LinkElement::Iframe => ("iframe[src]", "src"),
+            LinkElement::Link => ("link[href]", "href"),
+            LinkElement::Img => ("img[src]", "src"),
+            LinkElement::Script => ("script[src]", "src"),
+            LinkElement::Srcset => ("img[srcset], source[srcset]", "srcset"),
+        }
+    }
+
+    /// Returns whether an element of the document holds a link of this kind.
+    fn holds(self, element: &ElementRef) -> bool {
+        let name = element.value().name();
+        match self {
+            LinkElement::Srcset => name == "img" || name == "source",
+            _ => name == self.name(),
+        }
+    }
+}
+
+/// The part of a page a link was found in, recorded in the `Section` column of `PageLink`.
+///
+/// The section is that of the nearest enclosing element that tells, by its name, its ARIA role or a word of its `class`
+/// or `id`. As in HTML, a `<header>` or `<footer>` within an `<article>`, `<aside>`, `<main>`, `<nav>` or `<section>`
+/// belongs to that element rather than to the page, so only its role tells. Sites marking up their layout differently
+/// may have navigation links counted as `body` links.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinkSection {
+    /// The `<head>` of the page, e.g. stylesheets and alternate versions.
+    Head,
+    /// Navigation: `<nav>`, `role="navigation"`, or a class or id such as `nav`, `navbar` or `menu`.
+    Nav,
+    /// The page header: `<header>`, `role="banner"` or a class or id `header`.
+    Header,
+    /// The page footer: `<footer>`, `role="contentinfo"` or a class or id `footer`.
+    Footer,
+    /// Sidebars: `<aside>`, `role="complementary"` or a class or id `sidebar`.
+    Aside,
+    /// The main content: `<main>`, `<article>` or `role="main"`.
+    Main,
+    /// Anywhere else in the body.
+    Body,
+}
+
+impl LinkSection {
+    /// Returns the name of the section, as recorded in `PageLink`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkSection::Head => "head",
+            LinkSection::Nav => "nav",
+            LinkSection::Header => "header",
+            LinkSection::Footer => "footer",
+            LinkSection::Aside => "aside",
+            LinkSection::Main => "main",
+            LinkSection::Body => "body",
+        }
+    }
+
+    /// Finds the section of an element from its nearest enclosing element that tells.
+    fn of(element: &ElementRef) -> Self {
+        element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .find_map(|ancestor| Self::of_container(&ancestor))
+            .unwrap_or(LinkSection::Body)
+    }
+
+    /// Returns the section an element marks, if any.
+    fn of_container(element: &ElementRef) -> Option<Self> {
+        let value = element.value();
+        let by_role = || match value.attr("role")?.trim().to_ascii_lowercase().as_str() {
+            "navigation" => Some(LinkSection::Nav),
+            "banner" => Some(LinkSection::Header),
+            "contentinfo" => Some(LinkSection::Footer),
+            "complementary" => Some(LinkSection::Aside),
+            "main" => Some(LinkSection::Main),
+            _ => None,
+        };
+        if matches!(value.name(), "header" | "footer")
+            && element
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| {
+                    matches!(
+                        ancestor.value().name(),
+                        "article" | "aside" | "main" | "nav" | "section"
+                    )
+                })
+        {
+            return by_role();
+        }
+        let by_name = match value.name() {
+            "head" => Some(LinkSection::Head),
+            "nav" => Some(LinkSection::Nav),
+            "header" => Some(LinkSection::Header),
+            "footer" => Some(LinkSection::Footer),
+            "aside" => Some(LinkSection::Aside),
+            "main" | "article" => Some(LinkSection::Main),
+            _ => None,
+        };
+        let by_word = || {
+            let names = value.attr("class").into_iter().chain(value.attr("id"));
+            names
+                .flat_map(|name| name.split(|c: char| !c.is_ascii_alphanumeric()))
+                .find_map(|word| match word.to_ascii_lowercase().as_str() {
+                    "nav" | "navbar" | "navigation" | "menu" => Some(LinkSection::Nav),
+                    "header" => Some(LinkSection::Header),
+                    "footer" => Some(LinkSection::Footer),
+                    "sidebar" => Some(LinkSection::Aside),
+                    _ => None,
+                })
+        };
+        by_name.or_else(by_role).or_else(by_word)
+    }
+}
+
+/// A link found on a page, before it is resolved.
+pub struct FoundLink<'a> {
+    /// The URL as written in the page.
+    pub href: &'a str,
+    /// The element the link was found in.
+    pub element: LinkElement,
+    /// The text of the anchor, or the `alt` text of image map areas and images, with whitespace collapsed.
+    pub anchor_text: String,
+    /// Whether the link is marked `rel="nofollow"`.
+    pub nofollow: bool,
+    /// The values of the `rel` attribute, in lower case and separated by single spaces, e.g. `nofollow noopener`.
+    pub rel: String,
+    /// The part of the page the link was found in.
+    pub section: LinkSection,
+}
+
+/// Finds the links of a page in the given elements, in document order.
+///
+/// # Arguments
+/// * `document` - The parsed page.
+/// * `elements` - The elements links are extracted from.
+pub fn extract_links<'a>(document: &'a Html, elements: &[LinkElement]) -> Vec<FoundLink<'a>> {
+    let mut links = Vec::new();
+    for node in document.select(&LINK_SELECTOR) {
+        for &element in elements.iter().filter(|element| element.holds(&node)) {
+            let Some(value) = node.value().attr(element.source().1) else {
+                continue;
+            };
+            let anchor_text = match element {
+                LinkElement::A => node
+                    .text()
+                    .flat_map(str::split_whitespace)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                LinkElement::Area | LinkElement::Img => node
+                    .value()
+                    .attr("alt")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => String::new(),
+            };
+            let rel = node
+                .value()
+                .attr("rel")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>();
+            let nofollow = rel.iter().any(|value| value == "nofollow");
+            let rel = rel.join(" ");
+            let section = LinkSection::of(&node);
+            let hrefs = match element {
+                LinkElement::Srcset => srcset_urls(value),
+                _ => vec![value],
+            };
+            for href in hrefs {
+                links.push(FoundLink {
+                    href,
+                    element,
+                    anchor_text: anchor_text.clone(),
+                    nofollow,
+                    rel: rel.clone(),
+                    section,
+                });
+            }
+        }
+    }
+    links
+}
+
+/// Returns the URLs of the candidates of a `srcset` attribute, e.g. `small.jpg 480w, large.jpg 1080w`.
+fn srcset_urls(srcset: &str) -> Vec<&str> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fields of a found link.
+    type Found<'a> = (&'a str, LinkElement, String, bool, String, LinkSection);
+
+    fn found(links: Vec<FoundLink>) -> Vec<Found> {
+        links
+            .into_iter()
+            .map(|link| {
+                (
+                    link.href,
+                    link.element,
+                    link.anchor_text,
+                    link.nofollow,
+                    link.rel,
+                    link.section,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extracts_links_in_document_order() {
+        let document = Html::parse_document(
+            r#"<html><head><link rel="stylesheet" href="/style.css"></head><body>
+            <nav><a href="/" rel="Home NoFollow">  Home
+                page </a></nav>
+            <main><img src="/logo.png" alt="The  logo" srcset="/logo-2x.png 2x, /logo-3x.png 3x">
+                <map><area href="/map" alt="Map"></map><iframe src="/embed"></iframe></main>
+            <div class="site-footer"><a href="/contact">Contact</a><a>No link</a></div>
+            <script src="/app.js"></script></body></html>"#,
+        );
+        let anchors = found(extract_links(&document, &[LinkElement::A]));
+        assert_eq!(
+            anchors,
+            [
+                (
+                    "/",
+                    LinkElement::A,
+                    "Home page".to_string(),
+                    true,
+                    "home nofollow".to_string(),
+                    LinkSection::Nav
+                ),
+                (
+                    "/contact",
+                    LinkElement::A,
+                    "Contact".to_string(),
+                    false,
+                    String::new(),
+                    LinkSection::Footer
+                ),
+            ]
+        );
+
+        let every = found(extract_links(&document, &LinkElement::ALL));
+        let hrefs = every
+            .iter()
+            .map(|(href, element, ..)| (*href, *element))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hrefs,
+            [
+                ("/style.css", LinkElement::Link),
+                ("/", LinkElement::A),
+                ("/logo.png", LinkElement::Img),
+                ("/logo-2x.png", LinkElement::Srcset),
+                ("/logo-3x.png", LinkElement::Srcset),
+                ("/map", LinkElement::Area),
+                ("/embed", LinkElement::Iframe),
+                ("/contact", LinkElement::A),
+                ("/app.js", LinkElement::Script),
+            ]
+        );
+        assert_eq!(every[0].5, LinkSection::Head);
+        assert_eq!(every[2].2, "The logo");
+        assert_eq!(every[5].2, "Map");
+        assert_eq!(every[5].5, LinkSection::Main);
+    }
+
+    #[test]
+    fn finds_link_sections() {
+        let cases = [
+            (r#"<nav><a href="/">x</a></nav>"#, LinkSection::Nav),
+            (r#"<header><a href="/">x</a></header>"#, LinkSection::Header),
+            (
+                r#"<footer><div><a href="/">x</a></div></footer>"#,
+                LinkSection::Footer,
+            ),
+            (r#"<aside><a href="/">x</a></aside>"#, LinkSection::Aside),
+            (r#"<main><a href="/">x</a></main>"#, LinkSection::Main),
+            (r#"<div><p><a href="/">x</a></p></div>"#, LinkSection::Body),
+            (
+                r#"<div role="Navigation"><a href="/">x</a></div>"#,
+                LinkSection::Nav,
+            ),
+            (
+                r#"<div role="banner"><a href="/">x</a></div>"#,
+                LinkSection::Header,
+            ),
+            (
+                r#"<div role="contentinfo"><a href="/">x</a></div>"#,
+                LinkSection::Footer,
+            ),
+            (
+                r#"<div role="complementary"><a href="/">x</a></div>"#,
+                LinkSection::Aside,
+            ),
+            (
+                r#"<div role="main"><a href="/">x</a></div>"#,
+                LinkSection::Main,
+            ),
+            (
+                r#"<ul class="main-menu"><a href="/">x</a></ul>"#,
+                LinkSection::Nav,
+            ),
+            (
+                r#"<div id="NavBar"><a href="/">x</a></div>"#,
+                LinkSection::Nav,
+            ),
+            (
+                r#"<div class="page header"><a href="/">x</a></div>"#,
+                LinkSection::Header,
+            ),
+            (
+                r#"<div id="sidebar"><a href="/">x</a></div>"#,
+                LinkSection::Aside,
+            ),
+            // Words are matched whole, not as parts of other words
+            (
+                r#"<div class="menuitem headers"><a href="/">x</a></div>"#,
+                LinkSection::Body,
+            ),
+            // The nearest enclosing element that tells wins
+            (
+                r#"<footer><nav><a href="/">x</a></nav></footer>"#,
+                LinkSection::Nav,
+            ),
+            (
+                r#"<nav><div class="footer"><a href="/">x</a></div></nav>"#,
+                LinkSection::Footer,
+            ),
+            // The header and footer of an article belong to the article
+            (
+                r#"<article><header><a href="/">x</a></header></article>"#,
+                LinkSection::Main,
+            ),
+            (
+                r#"<main><section><footer><a href="/">x</a></footer></section></main>"#,
+                LinkSection::Main,
+            ),
+            (
+                r#"<section><header><a href="/">x</a></header></section>"#,
+                LinkSection::Body,
+            ),
+            (
+                r#"<aside><footer><a href="/">x</a></footer></aside>"#,
+                LinkSection::Aside,
+            ),
+            (
+                r#"<article><header role="banner"><a href="/">x</a></header></article>"#,
+                LinkSection::Header,
+            ),
+        ];
+        for (body, section) in cases {
+            let document = Html::parse_document(&format!("<html><body>{}</body></html>", body));
+            let links = extract_links(&document, &[LinkElement::A]);
+            assert_eq!(links[0].section, section, "{}", body);
+        }
+        let document = Html::parse_document(r#"<html><head><link href="/a.css"></head></html>"#);
+        assert_eq!(
+            extract_links(&document, &[LinkElement::Link])[0].section,
+            LinkSection::Head
+        );
+    }
+
+    #[test]
+    fn parses_srcset_candidates() {
+        let cases: [(&str, &[&str]); 5] = [
+            (
+                "small.jpg 480w, large.jpg 1080w",
+                &["small.jpg", "large.jpg"],
+            ),
+            ("image.png", &["image.png"]),
+            ("  a.png 1x ,b.png   2x  ", &["a.png", "b.png"]),
+            ("a.png 1x,, ,b.png", &["a.png", "b.png"]),
+            ("", &[]),
+        ];
+        for (srcset, urls) in cases {
+            assert_eq!(srcset_urls(srcset), urls, "{}", srcset);
+        }
+    }
+
+    #[test]
+    fn builds_navigable_condition() {
+        assert_eq!(
+            LinkElement::navigable_sql("PageLink.Element"),
+            "PageLink.Element IN ('a', 'area', 'iframe')"
+        );
+    }
+}
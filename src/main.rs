@@ -1,35 +1,693 @@
-use clap::{Arg, ArgAction, Command};
-use env_logger;
-use log::{error, info};
-use rusqlite::Connection;
+use chrono::Utc;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::BTreeMap;
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+use tracing_subscriber::fmt::format::debug_fn;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
 use url::Url;
 
-mod crawler;
-mod unique_queue;
-use crate::crawler::Crawler;
+use rust_web_crawler::auth::Credentials;
+use rust_web_crawler::builder::DEFAULT_CONNECT_TIMEOUT;
+use rust_web_crawler::calendar::DateUrlLimits;
+use rust_web_crawler::config::{CrawlConfig, DnsConfig, StorageConfig};
+use rust_web_crawler::coverage::{self, UrlReport, UrlStatusLookup};
+use rust_web_crawler::diff::PageDiff;
+use rust_web_crawler::digest::{self, HashAlgorithm};
+use rust_web_crawler::dns::Resolver;
+use rust_web_crawler::export::{self, BodyFormat, ExportFilter, ExportFormat, ExportTable, Since};
+use rust_web_crawler::geoip::GeoIp;
+use rust_web_crawler::graph::{self, GraphFormat, LinkGraph};
+use rust_web_crawler::images::{ImageLimits, DEFAULT_IMAGE_TYPES};
+use rust_web_crawler::links::LinkElement;
+use rust_web_crawler::normalize::{display_host, QueryParamRules, TrailingSlashPolicy};
+use rust_web_crawler::notify::{Notifier, RunReport};
+use rust_web_crawler::object_store::{BodyStore, S3BodyStore};
+use rust_web_crawler::policy::{self, DomainPolicy};
+use rust_web_crawler::preview::PagePreview;
+use rust_web_crawler::progress::ProgressDisplay;
+use rust_web_crawler::query::{QueryResult, Report};
+use rust_web_crawler::reader::BodyReader;
+use rust_web_crawler::reload::LiveSettings;
+use rust_web_crawler::render::RenderPolicy;
+use rust_web_crawler::retry::{self, FailureClass, RetryPolicy};
+use rust_web_crawler::routing::RulesFile;
+use rust_web_crawler::schedule::{CrawlSchedule, FreshnessPolicy};
+use rust_web_crawler::scoring::Scheduling;
+use rust_web_crawler::shutdown::Shutdown;
+use rust_web_crawler::stats::CrawlStats;
+use rust_web_crawler::store::{CrawlStore, PostgresStore};
+use rust_web_crawler::tls::TlsConfig;
+use rust_web_crawler::verify::{self, BodyProblem, SavedBody};
+use rust_web_crawler::visited::BloomSettings;
+use rust_web_crawler::{api, robots, schema, search, sitemap, stats, terms, validate};
+use rust_web_crawler::{
+    BanPolicy, CrawlPaths, Crawler, CrawlerError, FetchMode, Fetcher, PageCompression,
+    StorageFormat,
+};
 
-const SAVE_DIR: &str = "pages";
-const DB_NAME: &str = "web_crawler.db";
-const CREATE_SCRIPT: &str = "scripts/create.sql";
+const USER_AGENT: &str = "web_crawler_homework";
+
+/// What a crawl run starts from.
+#[derive(Clone, PartialEq)]
+enum RunKind {
+    /// The seed URLs.
+    New,
+    /// The frontier left by the previous run.
+    Resume,
+    /// The pages stored by previous runs.
+    Recrawl,
+    /// The pages stored by previous runs, parsed again without fetching them.
+    Reprocess,
+    /// The frontier left by the previous pass of a scheduled crawl, and the stale pages.
+    Refresh(FreshnessPolicy),
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    std::env::set_var("RUST_LOG", "info");
-    env_logger::init();
-
-    let arguments = Command::new("web_crawler_homework")
+    let arguments = Command::new(USER_AGENT)
         .version("0.1.0")
         .author("Erik")
         .about("Web crawler homework")
+        .subcommand_required(true)
         .arg(
-            Arg::new("clean")
-                .short('c')
-                .long("clean")
-                .help("Cleans the database")
-                .action(ArgAction::SetTrue),
+            Arg::new("config")
+                .long("config")
+                .help("TOML file with crawl settings, overridden by command line arguments")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .help("Format of log lines on standard error")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            Arg::new("dns-server")
+                .long("dns-server")
+                .help("DNS server to look up hosts with, as ip or ip:port. Can be given several times")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            Arg::new("doh")
+                .long("doh")
+                .help("DNS-over-HTTPS server to look up hosts with, e.g. https://1.1.1.1/dns-query")
+                .global(true),
+        )
+        .arg(
+            Arg::new("resolve")
+                .long("resolve")
+                .help("Fixed addresses of a host, as host:port:addr[,addr] like curl, used instead of looking it up. Can be given several times")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            Arg::new("ca-cert")
+                .long("ca-cert")
+                .value_name("PEM")
+                .help("Trust the CA certificates of a PEM file besides the system roots")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            Arg::new("insecure")
+                .long("insecure")
+                .help("DANGEROUS: accept invalid TLS certificates, e.g. expired or self-signed ones")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .subcommand(with_crawl_arguments(
+            Command::new("crawl")
+                .about("Crawl from seed URLs, creating the database if necessary")
+                .arg(
+                    Arg::new("url")
+                        .short('u')
+                        .long("url")
+                        .help("URL to start crawling. Can be given several times")
+                        .action(ArgAction::Append)
+                        .required_unless_present_any(["config", "seed-file"]),
+                )
+                .arg(
+                    Arg::new("seed-file")
+                        .long("seed-file")
+                        .help("File listing URLs to start crawling, one per line, or - to read standard input")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        ))
+        .subcommand(with_crawl_arguments(
+            Command::new("resume")
+                .about("Continue with the URLs left in the frontier when the previous crawl stopped")
+                .arg(
+                    Arg::new("from-links")
+                        .long("from-links")
+                        .help("Also queue the recorded links that were never crawled")
+                        .action(ArgAction::SetTrue),
+                ),
+        ))
+        .subcommand(with_crawl_arguments(Command::new("recrawl").about(
+            "Fetch the stored pages again and keep the earlier versions of pages that changed",
+        )))
+        .subcommand(with_crawl_arguments(Command::new("reprocess").about(
+            "Parse the stored pages again and update their links, metadata and text, without network access",
+        )))
+        .subcommand(
+            Command::new("clean")
+                .about("Delete the saved pages, database and events file")
+                .arg(
+                    Arg::new("yes")
+                        .short('y')
+                        .long("yes")
+                        .help("Do not ask for confirmation")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export stored pages, links or domains as JSON lines or CSV")
+                .arg(
+                    Arg::new("what")
+                        .short('w')
+                        .long("what")
+                        .help("Records to export")
+                        .value_parser(["pages", "links", "domains"])
+                        .default_value("pages"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .help("Format to export in")
+                        .value_parser(["jsonl", "csv"])
+                        .default_value("jsonl"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("File to write to instead of standard output"),
+                )
+                .arg(
+                    Arg::new("since").short('s').long("since").help(
+                        "Only export records added or changed after this run id or timestamp",
+                    ),
+                )
+                .arg(
+                    Arg::new("domain")
+                        .long("domain")
+                        .help("Only export records of this domain"),
+                )
+                .arg(
+                    Arg::new("status")
+                        .long("status")
+                        .help("Only export records of pages whose last fetch had this status")
+                        .value_parser(clap::value_parser!(u16)),
+                )
+                .arg(
+                    Arg::new("hash")
+                        .long("hash")
+                        .help("Only export records of the pages with this hash or short id"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .long("tag")
+                        .help("Only export records of pages with this tag"),
+                )
+                .arg(
+                    Arg::new("bodies")
+                        .long("bodies")
+                        .help("Write the saved bodies of pages to a file per page in the output directory")
+                        .requires("output")
+                        .conflicts_with_all(["what", "format"])
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("as")
+                        .long("as")
+                        .help("Format to write page bodies in")
+                        .value_parser(["markdown", "text", "html"])
+                        .default_value("markdown")
+                        .requires("bodies"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-graph")
+                .about("Export the link graph as DOT or GraphML")
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .help("Format to export in")
+                        .value_parser(["dot", "graphml"])
+                        .default_value("dot"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("File to write to instead of standard output"),
+                )
+                .arg(
+                    Arg::new("domains")
+                        .long("domains")
+                        .help("Collapse pages into one node per domain")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("depth")
+                        .short('d')
+                        .long("depth")
+                        .help("Only include pages at most this many links away from the root")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("root")
+                        .long("root")
+                        .help("URL the depth is measured from. Default is the first stored page")
+                        .requires("depth"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-sitemap")
+                .about("Export the stored pages of a domain as a sitemap.xml")
+                .arg(
+                    Arg::new("domain")
+                        .long("domain")
+                        .help("Host whose pages are listed")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("File to write to instead of standard output. Needed when the sitemap is split")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("base-url")
+                        .long("base-url")
+                        .help("URL the split sitemaps are published under, for the sitemap index. Default is the root of the domain")
+                        .value_parser(Url::parse),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Print totals and a per-domain breakdown of the crawl database")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the statistics as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("linked-domains")
+                .about("List the external domains linked to most from the crawled pages")
+                .arg(
+                    Arg::new("domain")
+                        .long("domain")
+                        .help("Only count the links from the pages of this domain"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('l')
+                        .long("limit")
+                        .help("Maximum number of domains")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the counts as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Run a built-in report, or a read-only SQL query, on the crawl database")
+                .arg(
+                    Arg::new("report")
+                        .help("The report to run")
+                        .value_parser(Report::ALL.map(Report::name))
+                        .required_unless_present("sql"),
+                )
+                .arg(
+                    Arg::new("sql")
+                        .long("sql")
+                        .help("Run a SQL statement on the database, opened read-only, instead of a report")
+                        .conflicts_with("report"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('l')
+                        .long("limit")
+                        .help("Maximum number of rows of a report")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the rows as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve the crawl database as a read-only JSON API")
+                .arg(
+                    Arg::new("listen")
+                        .short('l')
+                        .long("listen")
+                        .help("Address to listen on")
+                        .value_parser(clap::value_parser!(SocketAddr))
+                        .default_value("127.0.0.1:8080"),
+                ),
         )
+        .subcommand(
+            Command::new("status")
+                .about("Report what became of each URL in a list: crawled, skipped, failed or never seen")
+                .arg(
+                    Arg::new("urls-file")
+                        .long("urls-file")
+                        .help("File with one URL per line, or - to read standard input")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the statuses as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search the crawl database")
+                .arg(Arg::new("query").help("Find pages by their text"))
+                .arg(
+                    Arg::new("anchors")
+                        .short('a')
+                        .long("anchors")
+                        .help("Find pages by the text of links pointing at them"),
+                )
+                .arg(
+                    Arg::new("term")
+                        .long("term")
+                        .help("Find the pages mentioning a word most often, among the most frequent words of each page"),
+                )
+                .group(
+                    ArgGroup::new("search")
+                        .args(["query", "anchors", "term"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .short('t')
+                        .long("tag")
+                        .help("Only find pages with this tag")
+                        .conflicts_with_all(["anchors", "term"]),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('l')
+                        .long("limit")
+                        .help("Maximum number of results")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                ),
+        )
+        .subcommand(
+            Command::new("keywords")
+                .about("List the most frequent words of a stored page")
+                .arg(
+                    Arg::new("url")
+                        .help("The final or requested URL of the page")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('l')
+                        .long("limit")
+                        .help("Maximum number of words")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Show stored records in the terminal")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("page")
+                        .about("Show the metadata of a stored page")
+                        .arg(
+                            Arg::new("url")
+                                .help("The final or requested URL of the page")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("preview")
+                                .short('p')
+                                .long("preview")
+                                .help("Also show the start of the extracted text")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("lines")
+                                .short('n')
+                                .long("lines")
+                                .help("Number of lines of text shown with --preview")
+                                .value_parser(clap::value_parser!(usize))
+                                .default_value("20"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare the two latest stored versions of a page")
+                .arg(
+                    Arg::new("url")
+                        .help("The final or requested URL of the page")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("html")
+                        .long("html")
+                        .help("Also compare the raw HTML")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("context")
+                        .short('U')
+                        .long("context")
+                        .help("Number of unchanged lines shown around each change")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("3"),
+                ),
+        )
+        .subcommand(
+            Command::new("reindex")
+                .about("Rebuild the page text and search index from the saved HTML files"),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the seed URLs, storage and database before crawling")
+                .arg(
+                    Arg::new("url")
+                        .short('u')
+                        .long("url")
+                        .help("URL to start crawling. Can be given several times")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("seed-file")
+                        .long("seed-file")
+                        .help("File listing URLs to start crawling, one per line, or - to read standard input")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check the saved pages against the database, re-hashing every saved body")
+                .arg(
+                    Arg::new("repair")
+                        .long("repair")
+                        .help("Delete corrupt and orphan bodies, and queue the pages whose bodies are missing or corrupt for resume --revalidate")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("retry")
+                .about("Queue the failed URLs worth fetching again for resume, e.g. after timeouts but not after 404s")
+                .arg(
+                    Arg::new("class")
+                        .long("class")
+                        .help("Class of failures to retry. Can be given several times. Default is every class but client_error and other")
+                        .value_parser(FailureClass::ALL.map(|class| class.name()))
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("max-attempts")
+                        .long("max-attempts")
+                        .help("Number of failed attempts after which a URL is given up on")
+                        .value_parser(clap::value_parser!(u32))
+                        .default_value("5"),
+                )
+                .arg(
+                    Arg::new("backoff")
+                        .long("backoff")
+                        .help("Seconds to wait after the first failed attempt, doubled after every further attempt")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("300"),
+                )
+                .arg(
+                    Arg::new("db-url")
+                        .long("db-url")
+                        .help("Queue the URLs in the frontier shared through this Postgres database"),
+                ),
+        )
+        .subcommand(
+            Command::new("robots")
+                .about("Manage the robots.txt rules of domains")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("fetch")
+                        .about("Fetch and store the robots.txt rules of domains before crawling")
+                        .arg(
+                            Arg::new("domains-file")
+                                .long("domains-file")
+                                .help("File with one domain or URL per line")
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("policy")
+                .about("Export or import the crawl policies of domains as JSON")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Write the policy of every known domain")
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .help("File to write to instead of standard output"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Replace the policies of the domains in a file")
+                        .arg(Arg::new("file").help("File to read from").required(true)),
+                ),
+        )
+        .get_matches();
+
+    let progress = match arguments.subcommand() {
+        Some(("crawl" | "resume" | "recrawl" | "reprocess", sub_arguments))
+            if sub_arguments.get_flag("progress") =>
+        {
+            Some(ProgressDisplay::new())
+        }
+        _ => None,
+    };
+    init_logging(
+        arguments.get_one::<String>("log-format").unwrap(),
+        progress.as_ref(),
+    )?;
+
+    // `validate` reports an invalid configuration file as a failed check rather than stopping at it.
+    let config = match arguments.get_one::<PathBuf>("config") {
+        Some(path) if arguments.subcommand_name() == Some("validate") => {
+            CrawlConfig::load(path).unwrap_or_default()
+        }
+        Some(path) => CrawlConfig::load(path)?,
+        None => CrawlConfig::default(),
+    };
+    let paths = config.paths();
+
+    match arguments.subcommand() {
+        Some(("export", sub_arguments)) => export(sub_arguments, &config.storage, &paths).await,
+        Some(("export-graph", sub_arguments)) => export_graph(sub_arguments, &paths),
+        Some(("export-sitemap", sub_arguments)) => export_sitemap(sub_arguments, &paths),
+        Some(("policy", sub_arguments)) => policy(sub_arguments, &paths),
+        Some(("robots", sub_arguments)) => robots(sub_arguments, &config, &paths).await,
+        Some(("search", sub_arguments)) => search(sub_arguments, &paths),
+        Some(("keywords", sub_arguments)) => keywords(sub_arguments, &paths),
+        Some(("linked-domains", sub_arguments)) => linked_domains(sub_arguments, &paths),
+        Some(("query", sub_arguments)) => query(sub_arguments, &paths),
+        Some(("show", sub_arguments)) => show(sub_arguments, &paths),
+        Some(("diff", sub_arguments)) => diff_page(sub_arguments, &config.storage, &paths).await,
+        Some(("status", sub_arguments)) => url_status(sub_arguments, &paths),
+        Some(("serve", sub_arguments)) => {
+            let address = *sub_arguments.get_one::<SocketAddr>("listen").unwrap();
+            Ok(api::serve(paths.database.clone(), address).await?)
+        }
+        Some(("reindex", _)) => {
+            let reader = body_reader(&config.storage, &paths)?;
+            let (reindexed, missing) =
+                search::reindex(&mut open_database(&paths)?, &reader).await?;
+            info!(
+                "Reindexed {} pages ({} saved files could not be read)",
+                reindexed, missing
+            );
+            Ok(())
+        }
+        Some(("validate", sub_arguments)) => validate(sub_arguments, &config, &paths).await,
+        Some(("verify", sub_arguments)) => verify_pages(sub_arguments, &paths),
+        Some(("retry", sub_arguments)) => retry_failed(sub_arguments, &config, &paths),
+        Some(("stats", sub_arguments)) => {
+            let connection = open_database(&paths)?;
+            if sub_arguments.get_flag("json") {
+                let stats = CrawlStats::load(&connection)?;
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                Ok(())
+            } else {
+                Ok(stats::write_stats(&connection, &mut io::stdout().lock())?)
+            }
+        }
+        Some(("clean", sub_arguments)) => clean(sub_arguments, &paths),
+        Some(("crawl", sub_arguments)) => {
+            crawl(sub_arguments, &config, &paths, RunKind::New, progress).await
+        }
+        Some(("resume", sub_arguments)) => {
+            crawl(sub_arguments, &config, &paths, RunKind::Resume, progress).await
+        }
+        Some(("recrawl", sub_arguments)) => {
+            crawl(sub_arguments, &config, &paths, RunKind::Recrawl, progress).await
+        }
+        Some(("reprocess", sub_arguments)) => {
+            let shutdown = Shutdown::new();
+            shutdown.listen_for_signals(Duration::from_secs(
+                setting(sub_arguments, "shutdown-grace", config.shutdown_grace).unwrap(),
+            ));
+            let kind = RunKind::Reprocess;
+            crawl_pass(sub_arguments, &config, &paths, kind, progress, &shutdown).await
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Adds the arguments shared by the `crawl`, `resume` and `recrawl` subcommands.
+fn with_crawl_arguments(command: Command) -> Command {
+    command
         .arg(
             Arg::new("depth")
                 .short('d')
@@ -39,11 +697,122 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("16"),
         )
         .arg(
-            Arg::new("url")
-                .short('u')
-                .long("url")
-                .help("URL to start crawling")
-                .required(true),
+            Arg::new("hash")
+                .long("hash")
+                .help("Algorithm page contents are hashed with")
+                .value_parser(HashAlgorithm::ALL.map(HashAlgorithm::name))
+                .default_value("blake3"),
+        )
+        .arg(
+            Arg::new("trailing-slash")
+                .long("trailing-slash")
+                .help("Crawl /dir, /dir/ and /dir/index.html as one page, stored with (add) or without (remove) the slash")
+                .value_parser(TrailingSlashPolicy::ALL.map(TrailingSlashPolicy::name))
+                .default_value("keep"),
+        )
+        .arg(
+            Arg::new("scheduling")
+                .long("scheduling")
+                .help("Order queued URLs are crawled in: highest score first, or one URL of each host in turn")
+                .value_parser(Scheduling::ALL.map(Scheduling::name))
+                .default_value("score"),
+        )
+        .arg(
+            Arg::new("extract")
+                .long("extract")
+                .help("Elements links are extracted from, separated by commas. Links from a, area and iframe are queued, and the others only recorded")
+                .value_parser(LinkElement::ALL.map(LinkElement::name))
+                .value_delimiter(',')
+                .default_value("a"),
+        )
+        .arg(
+            Arg::new("languages")
+                .long("languages")
+                .help("Only store and follow the links of pages in these languages, as ISO 639-1 codes separated by commas, e.g. en,de")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("download-assets")
+                .long("download-assets")
+                .help("Save linked documents with these extensions, separated by commas, to the assets directory instead of parsing them as pages, e.g. pdf,docx,xls")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("images")
+                .long("images")
+                .help("Download the images shown on pages to the images directory, deduplicated by their contents, and record their dimensions and alt text")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("image-types")
+                .long("image-types")
+                .help("MIME types of the images downloaded with --images, separated by commas")
+                .value_delimiter(',')
+                .default_values(DEFAULT_IMAGE_TYPES),
+        )
+        .arg(
+            Arg::new("max-image-size")
+                .long("max-image-size")
+                .help("Size in KiB of the largest image downloaded with --images")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("5120"),
+        )
+        .arg(
+            Arg::new("max-link-depth")
+                .long("max-link-depth")
+                .help("Only follow links up to this many hops away from the start URL")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("reload-config")
+                .long("reload-config")
+                .help("Apply changes to the rate limits, URL patterns and budgets of the configuration file while crawling")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-pages")
+                .long("max-pages")
+                .help("Stop the crawl once this many pages are stored")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("max-pages-per-domain")
+                .long("max-pages-per-domain")
+                .help("Stop queueing the URLs of a domain once this many of its pages are stored")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .help("Number of pages fetched at the same time")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("domain-concurrency")
+                .long("domain-concurrency")
+                .help("Number of pages of a single domain fetched at the same time")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .help("Number of seconds after which a request is abandoned")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .help("Only crawl URLs matching this regular expression. May be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Do not crawl URLs matching this regular expression. May be repeated")
+                .action(ArgAction::Append),
         )
         .arg(
             Arg::new("ignore-robots")
@@ -52,66 +821,1518 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Ignore robots.txt rules when crawling")
                 .action(ArgAction::SetTrue),
         )
-        .get_matches();
+        .arg(
+            Arg::new("allow-private-networks")
+                .long("allow-private-networks")
+                .help("Crawl hosts on private, loopback and link-local addresses")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("revalidate")
+                .short('r')
+                .long("revalidate")
+                .help("Re-fetch pages stored by previous runs, skipping unchanged ones")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("parent-run")
+                .long("parent-run")
+                .help("Id of the run this run is a recrawl of. Default is the latest run when resuming or revalidating")
+                .value_parser(clap::value_parser!(i64)),
+        )
+        .arg(
+            Arg::new("follow-canonical")
+                .long("follow-canonical")
+                .help("Queue the canonical URLs declared by pages")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("skip-canonical-variants")
+                .long("skip-canonical-variants")
+                .help("Do not store pages whose canonical URL is another page, nor re-crawl them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ban-after")
+                .long("ban-after")
+                .help("Ban domains after this many consecutive 403/429 responses")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            Arg::new("ban-hours")
+                .long("ban-hours")
+                .help("Number of hours a banned domain is skipped for")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("24"),
+        )
+        .arg(
+            Arg::new("error-budget")
+                .long("error-budget")
+                .help("Suspend domains for the rest of the run after this many errors")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            Arg::new("memory-cap")
+                .long("memory-cap")
+                .help(
+                    "Approximate memory in MiB above which queued URLs are spilled to the database and fewer pages are fetched at a time",
+                )
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("visited-filter")
+                .long("visited-filter")
+                .help("Keep visited URLs in a Bloom filter sized for this many URLs")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("visited-fp-rate")
+                .long("visited-fp-rate")
+                .help("False positive rate of the Bloom filter of visited URLs")
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0.001"),
+        )
+        .arg(
+            Arg::new("frontier-limit")
+                .long("frontier-limit")
+                .help("Number of queued URLs held in memory, the others are spilled to the database")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("shutdown-grace")
+                .long("shutdown-grace")
+                .help("Seconds in-flight requests are given to finish after Ctrl+C or SIGTERM")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Format to save pages in")
+                .value_parser(["html", "warc"])
+                .default_value("html"),
+        )
+        .arg(
+            Arg::new("warc-max-size")
+                .long("warc-max-size")
+                .help("Size in MiB after which a new WARC file is started")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1024"),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .help("Compression of saved HTML files or BLOBs")
+                .value_parser(["none", "gzip", "zstd"])
+                .default_value("none"),
+        )
+        .arg(
+            Arg::new("store")
+                .long("store")
+                .help("Where page bodies are saved: files in the pages directory, BLOBs in the database, or objects in the bucket of [storage.s3]")
+                .value_parser(["files", "blob", "s3"])
+                .default_value("files"),
+        )
+        .arg(
+            Arg::new("max-date-urls")
+                .long("max-date-urls")
+                .help("Maximum number of calendar and date archive URLs crawled per directory")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("rules")
+                .long("rules")
+                .help("JSON rules file routing pages to storage targets by URL pattern, and tagging pages")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("render")
+                .long("render")
+                .help("Render HTML pages in a headless Chromium browser and store the DOM after their scripts ran. Requires the headless feature")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("chrome")
+                .long("chrome")
+                .help("Chromium executable pages are rendered with, instead of the one found on the PATH")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("geoip")
+                .long("geoip")
+                .help("MaxMind country database to record the country of the server of each page with")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("no-store")
+                .long("no-store")
+                .help("Record page metadata and links without saving page bodies")
+                .conflicts_with_all(["format", "compress", "store"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Record page metadata and links without saving bodies, assets or images, and report what a crawl would store")
+                .conflicts_with_all(["no-store", "format", "compress", "store"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("notify-webhook")
+                .long("notify-webhook")
+                .help("POST a JSON report to this URL when the crawl finishes or aborts")
+                .value_parser(Url::parse),
+        )
+        .arg(
+            Arg::new("event-webhook")
+                .long("event-webhook")
+                .help("POST a JSON event to this URL when a page is stored, a domain is first seen, and the crawl completes")
+                .value_parser(Url::parse),
+        )
+        .arg(
+            Arg::new("notify-email")
+                .long("notify-email")
+                .help("Email a report to this address when the crawl finishes or aborts, through --smtp-url"),
+        )
+        .arg(
+            Arg::new("notify-from")
+                .long("notify-from")
+                .help("Sender address of report emails. Defaults to the recipient")
+                .requires("notify-email"),
+        )
+        .arg(
+            Arg::new("smtp-url")
+                .long("smtp-url")
+                .help("SMTP server report emails are sent through, e.g. smtps://user@smtp.example.com. The password is read from SMTP_PASSWORD")
+                .requires("notify-email"),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .help("Save every response to a fixtures directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .help("Read responses from a fixtures directory instead of the network")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with("record"),
+        )
+        .arg(
+            Arg::new("fixture-dir")
+                .long("fixture-dir")
+                .help("Serve the files of a local directory as the site being crawled, whatever the host of its URLs")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(["record", "replay"]),
+        )
+        .arg(
+            Arg::new("worker-id")
+                .long("worker-id")
+                .help("Share the frontier in the database with other crawler processes, as the worker with this id"),
+        )
+        .arg(
+            Arg::new("frontier-lease")
+                .long("frontier-lease")
+                .help("Seconds a URL claimed from a shared frontier is reserved for its worker")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("300")
+                .requires("worker-id"),
+        )
+        .arg(
+            Arg::new("db-url")
+                .long("db-url")
+                .help("Share robots.txt rules, pages, links and the frontier through this Postgres database, e.g. postgres://crawler@db/crawl"),
+        )
+        .arg(
+            Arg::new("schedule")
+                .long("schedule")
+                .help("Keep running, starting a pass at these times, e.g. \"every 6h\" or \"0 */6 * * *\" (UTC)"),
+        )
+        .arg(
+            Arg::new("refresh-hours")
+                .long("refresh-hours")
+                .help("Hours after which scheduled passes fetch stored pages again. Default is at every pass")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("progress")
+                .long("progress")
+                .conflicts_with("schedule")
+                .help("Show pages per second, queue size, errors and active domains instead of the log")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+/// Logs to standard error at the info level, or only warnings when a progress display replaces the log.
+///
+/// Text lines show only the message of each event. JSON lines also carry the fields of events, such as the URL,
+/// domain, status, duration and depth of fetches.
+fn init_logging(format: &str, progress: Option<&ProgressDisplay>) -> Result<(), Box<dyn Error>> {
+    let level = if progress.is_some() { "warn" } else { "info" };
+    let writer = match progress {
+        Some(progress) => {
+            let writer = progress.log_writer();
+            BoxMakeWriter::new(move || writer.clone())
+        }
+        None => BoxMakeWriter::new(io::stderr),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(level))
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(writer);
+    let result = match format {
+        "json" => subscriber.json().flatten_event(true).try_init(),
+        _ => subscriber
+            .fmt_fields(debug_fn(|writer, field, value| match field.name() {
+                "message" => write!(writer, "{:?}", value),
+                _ => Ok(()),
+            }))
+            .try_init(),
+    };
+    result.map_err(|e| e as Box<dyn Error>)
+}
 
+/// Runs a crawl, or with a schedule, keeps running passes of it. Later passes continue the frontier of the previous
+/// pass, fetch robots.txt rules again and revalidate stale pages, appending to the same database.
+async fn crawl(
+    arguments: &ArgMatches,
+    config: &CrawlConfig,
+    paths: &CrawlPaths,
+    kind: RunKind,
+    progress: Option<ProgressDisplay>,
+) -> Result<(), Box<dyn Error>> {
+    let shutdown = Shutdown::new();
+    shutdown.listen_for_signals(Duration::from_secs(
+        setting(arguments, "shutdown-grace", config.shutdown_grace).unwrap(),
+    ));
+    let schedule = match arguments
+        .get_one::<String>("schedule")
+        .or(config.schedule.as_ref())
+    {
+        Some(spec) => CrawlSchedule::parse(spec)?,
+        None => return crawl_pass(arguments, config, paths, kind, progress, &shutdown).await,
+    };
+    let mut freshness = FreshnessPolicy {
+        max_age: setting(arguments, "refresh-hours", config.refresh_hours)
+            .map(|hours| Duration::from_secs_f64(hours * 3600.0)),
+        ..FreshnessPolicy::default()
+    };
+    for (domain, settings) in &config.domains {
+        if let Some(hours) = settings.refresh_hours {
+            freshness
+                .domains
+                .insert(domain.clone(), Duration::from_secs_f64(hours * 3600.0));
+        }
+    }
+
+    let mut started = Utc::now();
+    crawl_pass(arguments, config, paths, kind, progress, &shutdown).await?;
+    loop {
+        let Some(next) = schedule.next_after(started) else {
+            info!("The schedule has no further passes");
+            return Ok(());
+        };
+        if shutdown.is_requested() {
+            return Ok(());
+        }
+        info!("Next crawl pass at {}", next.to_rfc3339());
+        tokio::select! {
+            _ = tokio::time::sleep((next - Utc::now()).to_std().unwrap_or_default()) => {}
+            _ = shutdown.requested() => return Ok(()),
+        }
+        started = Utc::now();
+        let pass = RunKind::Refresh(freshness.clone());
+        if let Err(e) = crawl_pass(arguments, config, paths, pass, None, &shutdown).await {
+            error!("Crawl pass failed: {}", e);
+        }
+    }
+}
+
+/// Runs a single crawl run, which stops early once the shutdown is requested.
+async fn crawl_pass(
+    arguments: &ArgMatches,
+    config: &CrawlConfig,
+    paths: &CrawlPaths,
+    kind: RunKind,
+    progress: Option<ProgressDisplay>,
+    shutdown: &Shutdown,
+) -> Result<(), Box<dyn Error>> {
     // Initialize database if necessary
-    if arguments.get_flag("clean") || !fs::metadata(DB_NAME).is_ok() {
-        initialize_data_store()
+    if kind != RunKind::New {
+        open_database(paths)?;
+    } else if fs::metadata(&paths.database).is_err() {
+        initialize_data_store(paths)
             .inspect_err(|e| error!("Failed to create database {}", e))
             .unwrap();
     }
 
-    // Parse start URL
-    let start_url = arguments.get_one::<String>("url").unwrap();
-    if !Url::parse(&start_url).is_ok() {
-        error!("\"{}\" is not a valid URL", start_url);
-        return Ok(());
+    // Start crawling
+    let connection = Connection::open(&paths.database).unwrap();
+    let iterations = setting(arguments, "depth", config.depth).unwrap();
+    let builder = match &kind {
+        RunKind::Resume => {
+            Crawler::resume().queue_uncrawled_links(arguments.get_flag("from-links"))
+        }
+        RunKind::Recrawl => Crawler::recrawl(),
+        RunKind::Reprocess => Crawler::reprocess(),
+        RunKind::Refresh(freshness) => Crawler::resume().refresh(freshness.clone()),
+        RunKind::New => {
+            let seeds = seed_urls(arguments, config)?;
+            let (start_url, other_seeds) = seeds.split_first().expect("at least one seed");
+            other_seeds
+                .iter()
+                .fold(Crawler::builder(start_url), |builder, seed| {
+                    builder.seed(seed)
+                })
+        }
+    };
+    let politeness = &config.politeness;
+    let mut settings = live_settings(arguments, config);
+    let hash_algorithm = match arguments.value_source("hash") {
+        Some(ValueSource::CommandLine) => None,
+        _ => config.storage.hash,
     }
+    .unwrap_or_else(|| {
+        HashAlgorithm::from_name(arguments.get_one::<String>("hash").unwrap()).unwrap()
+    });
+    let trailing_slash = match arguments.value_source("trailing-slash") {
+        Some(ValueSource::CommandLine) => None,
+        _ => config.trailing_slash,
+    }
+    .unwrap_or_else(|| {
+        TrailingSlashPolicy::from_name(arguments.get_one::<String>("trailing-slash").unwrap())
+            .unwrap()
+    });
+    let scheduling = match arguments.value_source("scheduling") {
+        Some(ValueSource::CommandLine) => None,
+        _ => config.scheduling,
+    }
+    .unwrap_or_else(|| {
+        Scheduling::from_name(arguments.get_one::<String>("scheduling").unwrap()).unwrap()
+    });
+    let link_elements = match arguments.value_source("extract") {
+        Some(ValueSource::CommandLine) => None,
+        _ => config.extract.clone(),
+    }
+    .unwrap_or_else(|| {
+        arguments
+            .get_many::<String>("extract")
+            .unwrap()
+            .filter_map(|name| LinkElement::from_name(name))
+            .collect()
+    });
+    let mut builder = builder
+        .paths(paths.clone())
+        .user_agent(config.user_agent.as_deref().unwrap_or(USER_AGENT))
+        .ignore_robots(setting(arguments, "ignore-robots", politeness.ignore_robots).unwrap())
+        .allow_private_networks(
+            setting(
+                arguments,
+                "allow-private-networks",
+                config.allow_private_networks,
+            )
+            .unwrap(),
+        )
+        .revalidate(arguments.get_flag("revalidate"))
+        .worker(
+            arguments.get_one::<String>("worker-id").cloned(),
+            Duration::from_secs(*arguments.get_one::<u64>("frontier-lease").unwrap()),
+        )
+        .database_url(setting(arguments, "db-url", config.db_url.clone()))
+        .parent_run(arguments.get_one::<i64>("parent-run").copied())
+        .live_settings(&settings)
+        .event_webhook(setting(
+            arguments,
+            "event-webhook",
+            config
+                .event_webhook
+                .as_deref()
+                .map(Url::parse)
+                .transpose()?,
+        ))
+        .storage_format(storage_format(arguments, &config.storage)?)
+        .dry_run(arguments.get_flag("dry-run"))
+        .hash_algorithm(hash_algorithm)
+        .trailing_slash(trailing_slash)
+        .scheduling(scheduling)
+        .link_elements(link_elements)
+        .languages(match arguments.get_many::<String>("languages") {
+            Some(languages) => languages.cloned().collect(),
+            None => config.languages.clone(),
+        })
+        .download_assets(match arguments.get_many::<String>("download-assets") {
+            Some(extensions) => extensions.cloned().collect(),
+            None => config.download_assets.clone(),
+        })
+        .memory_cap(
+            setting(arguments, "memory-cap", config.memory_cap)
+                .map(|mebibytes| (mebibytes as usize) * 1024 * 1024),
+        )
+        .frontier_limit(setting(arguments, "frontier-limit", config.frontier_limit))
+        .shutdown(
+            shutdown.clone(),
+            Duration::from_secs(
+                setting(arguments, "shutdown-grace", config.shutdown_grace).unwrap(),
+            ),
+        )
+        .visited_filter(
+            setting(arguments, "visited-filter", config.visited_filter).map(|capacity| {
+                BloomSettings {
+                    capacity,
+                    false_positive_rate: setting(
+                        arguments,
+                        "visited-fp-rate",
+                        config.visited_fp_rate,
+                    )
+                    .unwrap(),
+                }
+            }),
+        )
+        .canonical_policy(
+            arguments.get_flag("follow-canonical"),
+            arguments.get_flag("skip-canonical-variants"),
+        )
+        .timeouts(
+            Duration::from_secs(setting(arguments, "timeout", config.timeout).unwrap()),
+            DEFAULT_CONNECT_TIMEOUT,
+        )
+        .max_depth(setting(arguments, "max-link-depth", config.max_link_depth))
+        .concurrency(setting(arguments, "concurrency", config.concurrency).unwrap())
+        .domain_concurrency(
+            setting(
+                arguments,
+                "domain-concurrency",
+                politeness.domain_concurrency,
+            )
+            .unwrap(),
+        );
+    let mut query_params =
+        QueryParamRules::default().global(&config.strip_params, &config.keep_params);
+    let mut credentials = Credentials::default();
+    for (domain, settings) in &config.domains {
+        if let Some(concurrency) = settings.concurrency {
+            builder = builder.domain_concurrency_override(domain, concurrency);
+        }
+        if !settings.strip_params.is_empty() || !settings.keep_params.is_empty() {
+            query_params =
+                query_params.domain(domain, &settings.strip_params, &settings.keep_params);
+        }
+        if let Some(credential) = &settings.auth {
+            credentials = match &settings.auth_origin {
+                Some(origin) => credentials.add_origin(&Url::parse(origin)?, credential)?,
+                None => credentials.add(domain, credential)?,
+            };
+        }
+    }
+    builder = builder.query_params(query_params).credentials(credentials);
+    builder = builder.tls(tls_config(arguments, config));
+    if let Some(settings) = &config.storage.s3 {
+        builder = builder.body_store(S3BodyStore::new(settings.clone())?);
+    }
+    let mut date_limits = DateUrlLimits::default();
+    if let Some(path) = arguments
+        .get_one::<PathBuf>("rules")
+        .or(config.rules.as_ref())
+    {
+        let rules = RulesFile::load(path)?;
+        builder = builder
+            .storage_routes(rules.storage_routes()?)
+            .page_tagger(rules.page_tagger()?);
+        date_limits = rules.date_urls;
+    }
+    if setting(arguments, "images", config.images).unwrap() {
+        let mime_types = match arguments.value_source("image-types") {
+            Some(ValueSource::CommandLine) => None,
+            _ => Some(config.image_types.clone()).filter(|mime_types| !mime_types.is_empty()),
+        };
+        let mime_types = mime_types.unwrap_or_else(|| {
+            arguments
+                .get_many::<String>("image-types")
+                .unwrap()
+                .cloned()
+                .collect()
+        });
+        builder = builder.download_images(ImageLimits {
+            mime_types,
+            max_size: setting(arguments, "max-image-size", config.max_image_size).unwrap() * 1024,
+        });
+    }
+    if let Some(path) = arguments
+        .get_one::<PathBuf>("geoip")
+        .or(config.geoip.as_ref())
+    {
+        builder = builder.geoip(GeoIp::open(path)?);
+    }
+    let mut render_policy = RenderPolicy {
+        all: setting(arguments, "render", config.render).unwrap(),
+        executable: setting(arguments, "chrome", config.chrome.clone()),
+        timeout: Duration::from_secs(setting(arguments, "timeout", config.timeout).unwrap()),
+        ..RenderPolicy::default()
+    };
+    for (domain, settings) in &config.domains {
+        if let Some(render) = settings.render {
+            render_policy.domains.insert(domain.clone(), render);
+        }
+    }
+    builder = builder.render_policy(render_policy);
+    if let Some(limit) = setting(arguments, "max-date-urls", config.max_date_urls) {
+        date_limits.max_per_directory = Some(limit);
+    }
+    for (domain, settings) in &config.domains {
+        if let Some(limit) = settings.max_date_urls {
+            date_limits.domains.insert(domain.clone(), limit);
+        }
+    }
+    builder = builder.date_limits(date_limits);
+    if let Some(dir) = arguments.get_one::<PathBuf>("record") {
+        builder = builder.fetch_mode(FetchMode::Record(dir.clone()));
+    } else if let Some(dir) = arguments.get_one::<PathBuf>("replay") {
+        builder = builder.fetch_mode(FetchMode::Replay(dir.clone()));
+    } else if let Some(dir) = arguments.get_one::<PathBuf>("fixture-dir") {
+        builder = builder.fetch_mode(FetchMode::Directory(dir.clone()));
+    }
+    if let Some(progress) = progress {
+        builder = builder.progress(progress);
+    }
+    builder = builder.resolver(resolver(arguments, &config.dns).await?);
+    let mut crawler = builder.build()?;
+    // Reprocessing makes no network requests, so it sends no notifications either
+    let notifier = match kind {
+        RunKind::Reprocess => Notifier::default(),
+        _ => notifier(arguments, config)?,
+    };
+    let run_id = crawler.run_id();
+    let started = Instant::now();
+    info!("Starting crawl run {}", run_id);
 
-    // Start crawling
-    let connection = Connection::open(DB_NAME).unwrap();
-    let iterations = arguments.get_one::<u32>("depth").unwrap();
-    let mut crawler = Crawler::new(start_url, "web_crawler_homework", Some(arguments.get_flag("ignore-robots")));
-
-    for _ in 0..*iterations {
-        let result = crawler.crawl().await;
-        match result {
-            Ok(true) => {
-                info!("Crawling completed successfully.");
+    let mut config_watch = match arguments.get_one::<PathBuf>("config") {
+        Some(path) if arguments.get_flag("reload-config") => {
+            Some((path, fs::metadata(path)?.modified()?))
+        }
+        None if arguments.get_flag("reload-config") => {
+            return Err("--reload-config needs a configuration file given with --config".into());
+        }
+        _ => None,
+    };
+    let result = async {
+        if kind == RunKind::Reprocess {
+            let (reprocessed, missing) = crawler.reprocess_pages().await?;
+            info!(
+                "Reprocessed {} pages ({} stored bodies could not be read)",
+                reprocessed, missing
+            );
+            return crawler.finish().await;
+        }
+        let mut failure = None;
+        for _ in 0..iterations {
+            if let Some((path, modified)) = &mut config_watch {
+                reload_config(&mut crawler, arguments, path, modified, &mut settings);
             }
-            Ok(false) => {
-                info!("No more URLs to crawl.");
-                break;
+            let result = crawler.crawl().await;
+            match result {
+                Ok(_) if shutdown.is_requested() => {
+                    info!("Stopped on request.");
+                    break;
+                }
+                Ok(true) => {
+                    info!("Crawling completed successfully.");
+                }
+                Ok(false) if crawler.is_page_budget_spent() => {
+                    info!("The page budget is spent.");
+                    break;
+                }
+                Ok(false) => {
+                    info!("No more URLs to crawl.");
+                    break;
+                }
+                Err(e) => {
+                    error!("Error during crawling: {}", e);
+                    failure = Some(e);
+                    break;
+                }
             }
-            Err(e) => {
-                error!("Error during crawling: {}", e);
+        }
+
+        match crawler.dry_run_totals() {
+            Some(totals) => info!(
+                pages = totals.pages,
+                page_bytes = totals.page_bytes,
+                files = totals.files,
+                file_bytes = totals.file_bytes,
+                "Dry run: a crawl would store {} pages ({:.1} MiB) and {} assets and images ({:.1} MiB)",
+                totals.pages,
+                totals.page_bytes as f64 / (1024.0 * 1024.0),
+                totals.files,
+                totals.file_bytes as f64 / (1024.0 * 1024.0)
+            ),
+            None => info!("Stored {} pages", crawler.stored_pages()),
+        }
+        if kind == RunKind::Recrawl {
+            info!("{} pages changed", crawler.changed_pages());
+        }
+        info!(
+            "Peak memory usage: {:.1} MiB",
+            crawler.peak_memory() as f64 / (1024.0 * 1024.0)
+        );
+        let finished = crawler.finish().await;
+        // The error that stopped the crawl is the one reported
+        match failure {
+            Some(e) => {
+                if let Err(finish_error) = finished {
+                    error!("Failed to finish the crawl: {}", finish_error);
+                }
+                Err(e)
             }
+            None => finished,
         }
     }
+    .await;
 
+    if !notifier.is_empty() {
+        let error = result.as_ref().err().map(|e| e.to_string());
+        match RunReport::load(&connection, run_id, started.elapsed(), error) {
+            Ok(report) => notifier.notify(&report).await,
+            Err(e) => error!("Failed to build the report of run {}: {}", run_id, e),
+        }
+    }
+    result?;
     connection.close().unwrap();
 
     Ok(())
 }
 
-fn initialize_data_store() -> Result<(), Box<dyn Error>> {
+/// Creates the resolver hosts are looked up with, from the DNS servers given on the command line, or else in the
+/// configuration file. Without servers, the resolver of the operating system is used. Fixed addresses given on the
+/// command line replace those of the configuration file.
+async fn resolver(arguments: &ArgMatches, config: &DnsConfig) -> Result<Resolver, Box<dyn Error>> {
+    let servers = match arguments.get_many::<String>("dns-server") {
+        Some(servers) => servers.cloned().collect(),
+        None => config.servers.clone(),
+    };
+    let servers = match servers.is_empty() {
+        true => Resolver::system(),
+        false => Resolver::servers(&servers)?,
+    };
+    let resolver = match arguments.get_one::<String>("doh").or(config.doh.as_ref()) {
+        Some(url) => Resolver::doh(url, &servers).await?,
+        None => servers,
+    };
+    let overrides: Vec<String> = match arguments.get_many::<String>("resolve") {
+        Some(overrides) => overrides.cloned().collect(),
+        None => config.resolve.clone(),
+    };
+    Ok(resolver.overrides(&overrides)?)
+}
+
+/// Returns the TLS settings of every client: the CA files of the configuration file and of the command line, and
+/// whether invalid certificates are accepted.
+fn tls_config(arguments: &ArgMatches, config: &CrawlConfig) -> TlsConfig {
+    TlsConfig {
+        ca_certs: config
+            .ca_certs
+            .iter()
+            .chain(
+                arguments
+                    .get_many::<PathBuf>("ca-cert")
+                    .into_iter()
+                    .flatten(),
+            )
+            .cloned()
+            .collect(),
+        accept_invalid_certs: setting(arguments, "insecure", config.insecure).unwrap(),
+    }
+}
+
+/// Collects the settings that can be reloaded while crawling, from the command line and the configuration file.
+fn live_settings(arguments: &ArgMatches, config: &CrawlConfig) -> LiveSettings {
+    let politeness = &config.politeness;
+    let mut exclude: Vec<String> = match arguments.get_many::<String>("exclude") {
+        Some(patterns) => patterns.cloned().collect(),
+        None => config.exclude.clone(),
+    };
+    let mut domain_crawl_delays = BTreeMap::new();
+    for (domain, settings) in &config.domains {
+        if let Some(delay) = settings.crawl_delay {
+            domain_crawl_delays.insert(domain.clone(), Duration::from_secs_f64(delay));
+        }
+        if settings.skip {
+            exclude.push(format!(r"^[a-z]+://{}(:\d+)?/", regex::escape(domain)));
+        }
+    }
+    LiveSettings {
+        crawl_delay: politeness.crawl_delay.map(Duration::from_secs_f64),
+        domain_crawl_delays,
+        ban_policy: setting(arguments, "ban-after", politeness.ban_after).map(|threshold| {
+            BanPolicy {
+                threshold,
+                quarantine_hours: setting(arguments, "ban-hours", politeness.ban_hours).unwrap(),
+            }
+        }),
+        error_budget: setting(arguments, "error-budget", politeness.error_budget),
+        include: match arguments.get_many::<String>("include") {
+            Some(patterns) => patterns.cloned().collect(),
+            None => config.include.clone(),
+        },
+        exclude,
+        max_pages: setting(arguments, "max-pages", config.max_pages),
+        max_pages_per_domain: setting(
+            arguments,
+            "max-pages-per-domain",
+            config.max_pages_per_domain,
+        ),
+    }
+}
+
+/// Applies the changed settings of the configuration file to a running crawl, if the file was modified since it was
+/// last read. Command line arguments still take precedence, and an invalid file is reported and ignored.
+fn reload_config(
+    crawler: &mut Crawler,
+    arguments: &ArgMatches,
+    path: &Path,
+    modified: &mut SystemTime,
+    settings: &mut LiveSettings,
+) {
+    let Ok(last_modified) = fs::metadata(path).and_then(|metadata| metadata.modified()) else {
+        return;
+    };
+    if last_modified == *modified {
+        return;
+    }
+    *modified = last_modified;
+    let config = match CrawlConfig::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Ignoring the changes to {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let new_settings = live_settings(arguments, &config);
+    let changes = settings.changes(&new_settings);
+    if changes.is_empty() {
+        info!(
+            "Reloaded {}, no setting that can change while crawling changed",
+            path.display()
+        );
+        return;
+    }
+    if let Err(e) = crawler.apply_settings(&new_settings) {
+        warn!("Ignoring the changes to {}: {}", path.display(), e);
+        return;
+    }
+    for change in &changes {
+        info!(config = %path.display(), "Changed setting {}", change);
+    }
+    *settings = new_settings;
+}
+
+/// Returns the value of an argument given on the command line, or else the value from the configuration file, or else
+/// the default value of the argument.
+fn setting<T: Clone + Send + Sync + 'static>(
+    arguments: &ArgMatches,
+    id: &str,
+    configured: Option<T>,
+) -> Option<T> {
+    if arguments.value_source(id) == Some(ValueSource::CommandLine) {
+        return arguments.get_one::<T>(id).cloned();
+    }
+    configured.or_else(|| arguments.get_one::<T>(id).cloned())
+}
+
+fn storage_format(
+    arguments: &ArgMatches,
+    storage: &StorageConfig,
+) -> Result<StorageFormat, Box<dyn Error>> {
+    if arguments.get_flag("no-store") || arguments.get_flag("dry-run") {
+        return Ok(StorageFormat::Discard);
+    }
+    let compression = match arguments.get_one::<String>("compress").unwrap().as_str() {
+        "gzip" => PageCompression::Gzip,
+        "zstd" => PageCompression::Zstd,
+        _ => PageCompression::None,
+    };
+    let compression = match arguments.value_source("compress") {
+        Some(ValueSource::CommandLine) => compression,
+        _ => storage.compress.unwrap_or(compression),
+    };
+    let format = setting(arguments, "format", storage.format.clone()).unwrap();
+    let store = setting(arguments, "store", storage.store.clone()).unwrap();
+    Ok(match (format.as_str(), store.as_str()) {
+        ("warc", "blob" | "s3") => {
+            return Err(format!(
+                "WARC records are saved as files, so --format warc cannot be used with --store {}",
+                store
+            )
+            .into())
+        }
+        (_, "s3") if storage.s3.is_none() => {
+            return Err("--store s3 needs a [storage.s3] table in the configuration file".into())
+        }
+        ("warc", _) => StorageFormat::Warc {
+            max_file_size: setting(arguments, "warc-max-size", storage.warc_max_size).unwrap()
+                * 1024
+                * 1024,
+        },
+        (_, "blob") => StorageFormat::Blob { compression },
+        (_, "s3") => StorageFormat::Object { compression },
+        _ => StorageFormat::Html { compression },
+    })
+}
+
+fn notifier(arguments: &ArgMatches, config: &CrawlConfig) -> Result<Notifier, Box<dyn Error>> {
+    let mut notifier = Notifier::default();
+    if let Some(url) = arguments.get_one::<Url>("notify-webhook") {
+        let client = tls_config(arguments, config)
+            .configure(reqwest::Client::builder())?
+            .build()?;
+        notifier = notifier.with_webhook(url.clone(), client);
+    }
+    if let Some(to) = arguments.get_one::<String>("notify-email") {
+        let from = arguments.get_one::<String>("notify-from").unwrap_or(to);
+        let smtp_url = setting(arguments, "smtp-url", config.smtp_url.clone())
+            .ok_or("--notify-email requires --smtp-url or smtp_url in the configuration file")?;
+        // The command line of a process can be read by every user of the machine
+        let on_command_line = arguments.value_source("smtp-url") == Some(ValueSource::CommandLine);
+        if on_command_line && Url::parse(&smtp_url).is_ok_and(|url| url.password().is_some()) {
+            return Err("Give the SMTP password in the SMTP_PASSWORD environment variable or smtp_password in the \
+                configuration file, not in --smtp-url"
+                .into());
+        }
+        let password = env::var("SMTP_PASSWORD")
+            .ok()
+            .filter(|password| !password.is_empty())
+            .or_else(|| config.smtp_password.clone());
+        notifier = notifier.with_email(&smtp_url, password.as_deref(), from, to)?;
+    }
+    Ok(notifier)
+}
+
+fn open_database(paths: &CrawlPaths) -> Result<Connection, Box<dyn Error>> {
+    if fs::metadata(&paths.database).is_err() {
+        return Err(format!("Database {} does not exist", paths.database.display()).into());
+    }
+    let mut connection = Connection::open(&paths.database)?;
+    schema::migrate(&mut connection)?;
+    Ok(connection)
+}
+
+/// Opens the stored page bodies of a crawl for reading, with the object store of the configuration, if any.
+fn body_reader(storage: &StorageConfig, paths: &CrawlPaths) -> Result<BodyReader, Box<dyn Error>> {
+    let body_store = match &storage.s3 {
+        Some(settings) => Some(Arc::new(S3BodyStore::new(settings.clone())?) as Arc<dyn BodyStore>),
+        None => None,
+    };
+    Ok(BodyReader::open(paths)?.with_body_store(body_store))
+}
+
+async fn export(
+    arguments: &ArgMatches,
+    storage: &StorageConfig,
+    paths: &CrawlPaths,
+) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let what = arguments.get_one::<String>("what").unwrap();
+    let table = match what.as_str() {
+        "links" => ExportTable::Links,
+        "domains" => ExportTable::Domains,
+        _ => ExportTable::Pages,
+    };
+    let format = match arguments.get_one::<String>("format").unwrap().as_str() {
+        "csv" => ExportFormat::Csv,
+        _ => ExportFormat::Jsonl,
+    };
+    let filter = ExportFilter {
+        since: match arguments.get_one::<String>("since") {
+            Some(value) => Some(Since::parse(&connection, value)?),
+            None => None,
+        },
+        domain: arguments.get_one::<String>("domain").cloned(),
+        status: arguments.get_one::<u16>("status").copied(),
+        hash: match arguments.get_one::<String>("hash") {
+            Some(prefix) => Some(digest::resolve_short_id(&connection, prefix)?),
+            None => None,
+        },
+        tag: arguments.get_one::<String>("tag").cloned(),
+        ..ExportFilter::default()
+    };
+    if arguments.get_flag("bodies") {
+        let output = arguments.get_one::<String>("output").unwrap();
+        let format = match arguments.get_one::<String>("as").unwrap().as_str() {
+            "text" => BodyFormat::Text,
+            "html" => BodyFormat::Html,
+            _ => BodyFormat::Markdown,
+        };
+        let (exported, missing) = export::export_bodies(
+            &connection,
+            &body_reader(storage, paths)?,
+            format,
+            &filter,
+            Path::new(output),
+        )
+        .await?;
+        info!(
+            "Exported the bodies of {} pages to {} ({} saved files could not be read)",
+            exported, output, missing
+        );
+        return Ok(());
+    }
+    let count = match arguments.get_one::<String>("output") {
+        Some(path) => {
+            let mut file = io::BufWriter::new(fs::File::create(path)?);
+            export::export(&connection, table, format, &filter, &mut file)?
+        }
+        None => export::export(
+            &connection,
+            table,
+            format,
+            &filter,
+            &mut io::stdout().lock(),
+        )?,
+    };
+    info!("Exported {} {}", count, what);
+    Ok(())
+}
+
+fn export_graph(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let format = match arguments.get_one::<String>("format").unwrap().as_str() {
+        "graphml" => GraphFormat::GraphMl,
+        _ => GraphFormat::Dot,
+    };
+    let mut graph = LinkGraph::load(
+        &connection,
+        arguments.get_one::<String>("root").map(String::as_str),
+        arguments.get_one::<usize>("depth").copied(),
+    )?;
+    if arguments.get_flag("domains") {
+        graph = graph.collapse_domains();
+    }
+    match arguments.get_one::<String>("output") {
+        Some(path) => graph.write(format, &mut io::BufWriter::new(fs::File::create(path)?))?,
+        None => graph.write(format, &mut io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn export_sitemap(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let domain = arguments.get_one::<String>("domain").unwrap();
+    let entries = sitemap::sitemap_entries(&connection, domain)?;
+    let sitemaps = sitemap::split_sitemaps(&entries);
+    let output = arguments.get_one::<PathBuf>("output");
+    if let [entries] = sitemaps.as_slice() {
+        match output {
+            Some(path) => {
+                sitemap::write_sitemap(entries, &mut io::BufWriter::new(fs::File::create(path)?))?
+            }
+            None => sitemap::write_sitemap(entries, &mut io::stdout().lock())?,
+        }
+        info!(
+            "Exported a sitemap of {} pages of {}",
+            entries.len(),
+            domain
+        );
+        return Ok(());
+    }
+
+    let Some(output) = output else {
+        return Err(format!(
+            "The {} pages of {} need {} sitemaps, give the file of their index with --output",
+            entries.len(),
+            domain,
+            sitemaps.len()
+        )
+        .into());
+    };
+    let base_url = match arguments.get_one::<Url>("base-url") {
+        Some(url) => url.clone(),
+        None => {
+            let scheme = Url::parse(&entries[0].url)?.scheme().to_string();
+            Url::parse(&format!("{}://{}/", scheme, domain))?
+        }
+    };
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "sitemap".to_string());
+    let mut locations = Vec::new();
+    for (index, entries) in sitemaps.iter().enumerate() {
+        let name = format!("{}-{}.xml", stem, index + 1);
+        sitemap::write_sitemap(
+            entries,
+            &mut io::BufWriter::new(fs::File::create(output.with_file_name(&name))?),
+        )?;
+        locations.push(base_url.join(&name)?);
+    }
+    sitemap::write_sitemap_index(
+        &locations,
+        &mut io::BufWriter::new(fs::File::create(output)?),
+    )?;
+    info!(
+        "Exported {} pages of {} in {} sitemaps, listed in {}",
+        entries.len(),
+        domain,
+        sitemaps.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn policy(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let mut connection = open_database(paths)?;
+    match arguments.subcommand() {
+        Some(("export", sub_arguments)) => {
+            let policies = policy::export_policies(&connection)?;
+            let json = serde_json::to_string_pretty(&policies)?;
+            match sub_arguments.get_one::<String>("output") {
+                Some(path) => fs::write(path, json)?,
+                None => println!("{}", json),
+            }
+            info!("Exported the policies of {} domains", policies.len());
+        }
+        Some(("import", sub_arguments)) => {
+            let path = sub_arguments.get_one::<String>("file").unwrap();
+            let policies: Vec<DomainPolicy> = serde_json::from_str(&fs::read_to_string(path)?)?;
+            policy::import_policies(&mut connection, &policies)?;
+            info!("Imported the policies of {} domains", policies.len());
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+async fn robots(
+    arguments: &ArgMatches,
+    config: &CrawlConfig,
+    paths: &CrawlPaths,
+) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    match arguments.subcommand() {
+        Some(("fetch", sub_arguments)) => {
+            let path = sub_arguments.get_one::<PathBuf>("domains-file").unwrap();
+            let client = resolver(arguments, &config.dns)
+                .await?
+                .configure(reqwest::Client::builder());
+            let fetcher = Fetcher::new(
+                tls_config(arguments, config)
+                    .configure(client)?
+                    .user_agent(USER_AGENT)
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()?,
+                FetchMode::Live,
+            )?;
+            let (fetched, fresh, failed) = robots::warm_up(
+                &connection,
+                &fetcher,
+                USER_AGENT,
+                &fs::read_to_string(path)?,
+            )
+            .await?;
+            info!(
+                "Fetched robots.txt for {} domains ({} already fresh, {} failed)",
+                fetched, fresh, failed
+            );
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn search(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let limit = *arguments.get_one::<usize>("limit").unwrap();
+    if let Some(query) = arguments.get_one::<String>("query") {
+        for (position, result) in search::search_pages(
+            &connection,
+            query,
+            arguments.get_one::<String>("tag").map(String::as_str),
+            limit,
+        )?
+        .iter()
+        .enumerate()
+        {
+            println!(
+                "{}. [{}] {} (score {:.3})",
+                position + 1,
+                digest::short_id(&connection, &result.hash)?,
+                result.url,
+                -result.rank
+            );
+            println!("  {}", result.snippet);
+        }
+        return Ok(());
+    }
+    if let Some(term) = arguments.get_one::<String>("term") {
+        for result in terms::pages_with_term(&connection, term, limit)? {
+            println!("{:>6}  {}", result.count, result.url);
+        }
+        return Ok(());
+    }
+
+    let query = arguments.get_one::<String>("anchors").unwrap();
+    for result in search::search_anchors(&connection, query, limit)? {
+        let crawled = if result.crawled { "" } else { " (not crawled)" };
+        println!("{}{}", result.url, crawled);
+        println!(
+            "  {} links: {}",
+            result.links,
+            result.anchor_texts.join(" | ")
+        );
+    }
+    Ok(())
+}
+
+fn keywords(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let url = arguments.get_one::<String>("url").unwrap();
+    let limit = *arguments.get_one::<usize>("limit").unwrap();
+    let Some(terms) = terms::page_keywords(&connection, url, limit)? else {
+        return Err(format!("No stored page has the URL {}", url).into());
+    };
+    for term in terms {
+        println!("{:>6}  {}", term.count, term.term);
+    }
+    Ok(())
+}
+
+/// Prints the external domains linked to most, with the number of links to each.
+fn linked_domains(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let domain = arguments.get_one::<String>("domain").map(String::as_str);
+    let limit = *arguments.get_one::<usize>("limit").unwrap();
+    let counts = graph::top_linked_domains(&connection, domain, limit)?;
+    if arguments.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&counts)?);
+        return Ok(());
+    }
+    for count in counts {
+        if domain.is_some() {
+            println!("{:>6}  {}", count.links, display_host(&count.target));
+        } else {
+            println!(
+                "{:>6}  {} -> {}",
+                count.links,
+                display_host(&count.source),
+                display_host(&count.target)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints the rows of a report or SQL query, on a read-only connection.
+fn query(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    // Upgrade the schema first, as the reports expect the latest one
+    open_database(paths)?;
+    let connection =
+        Connection::open_with_flags(&paths.database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let result = match arguments.get_one::<String>("sql") {
+        Some(sql) => QueryResult::sql(&connection, sql)?,
+        None => {
+            let name = arguments.get_one::<String>("report").unwrap();
+            let report = Report::from_name(name).unwrap();
+            QueryResult::report(
+                &connection,
+                report,
+                *arguments.get_one::<usize>("limit").unwrap(),
+            )?
+        }
+    };
+    if arguments.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&result.to_json())?);
+    } else {
+        result.write_table(&mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+fn show(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    match arguments.subcommand() {
+        Some(("page", sub_arguments)) => {
+            let url = sub_arguments.get_one::<String>("url").unwrap();
+            let lines = *sub_arguments.get_one::<usize>("lines").unwrap();
+            let Some(preview) = PagePreview::load(&connection, url, lines)? else {
+                return Err(format!("No stored page has the URL {}", url).into());
+            };
+            preview.write(&mut io::stdout().lock(), sub_arguments.get_flag("preview"))?;
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Prints the changes between the two latest stored versions of a page.
+async fn diff_page(
+    arguments: &ArgMatches,
+    storage: &StorageConfig,
+    paths: &CrawlPaths,
+) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let reader = body_reader(storage, paths)?;
+    let diff = PageDiff::load(
+        &connection,
+        &reader,
+        arguments.get_one::<String>("url").unwrap(),
+        arguments.get_flag("html"),
+        *arguments.get_one::<usize>("context").unwrap(),
+    )
+    .await?;
+    diff.write(&mut io::stdout().lock())?;
+    Ok(())
+}
+
+/// Prints what became of the URLs listed in a file.
+fn url_status(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let path = arguments.get_one::<PathBuf>("urls-file").unwrap();
+    let urls = if path.as_os_str() == "-" {
+        coverage::read_url_list(io::stdin().lock())?
+    } else {
+        coverage::read_url_list(io::BufReader::new(fs::File::open(path)?))?
+    };
+    let connection = open_database(paths)?;
+    let lookup = UrlStatusLookup::new(&connection, &paths.events_file)?;
+    let reports = urls
+        .into_iter()
+        .map(|url| {
+            let status = lookup.status(&url)?;
+            Ok(UrlReport { url, status })
+        })
+        .collect::<Result<Vec<_>, CrawlerError>>()?;
+    if arguments.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        coverage::write_reports(&reports, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Gathers the seed URLs of a crawl: those given with `--url` and listed in `--seed-file`, or else the `seeds` of the
+/// configuration file.
+///
+/// # Arguments
+/// * `arguments` - The arguments of the subcommand.
+/// * `config` - The settings of the configuration file.
+///
+/// # Returns
+/// The seed URLs, or an error if the seed file cannot be read or there are none.
+fn seed_urls(arguments: &ArgMatches, config: &CrawlConfig) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut seeds = arguments
+        .get_many::<String>("url")
+        .map(|urls| urls.cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    if let Some(path) = arguments.get_one::<PathBuf>("seed-file") {
+        let listed = if path.as_os_str() == "-" {
+            coverage::read_url_list(io::stdin().lock())?
+        } else {
+            coverage::read_url_list(io::BufReader::new(fs::File::open(path)?))?
+        };
+        info!("Read {} seed URLs from {}", listed.len(), path.display());
+        seeds.extend(listed);
+    }
+    if seeds.is_empty() {
+        seeds = config.seeds.clone();
+    }
+    if seeds.is_empty() {
+        return Err(
+            "No seed URLs, give --url, --seed-file or seeds in the configuration file".into(),
+        );
+    }
+    Ok(seeds)
+}
+
+async fn validate(
+    arguments: &ArgMatches,
+    config: &CrawlConfig,
+    paths: &CrawlPaths,
+) -> Result<(), Box<dyn Error>> {
+    let resolver = resolver(arguments, &config.dns).await?;
+    let client = tls_config(arguments, config)
+        .configure(resolver.configure(reqwest::Client::builder()))?
+        .user_agent(USER_AGENT)
+        .build()?;
+
+    let mut checks = Vec::new();
+    if let Some(path) = arguments.get_one::<PathBuf>("config") {
+        checks.push(validate::check_config(path));
+    }
+    match seed_urls(arguments, config) {
+        Ok(seeds) => {
+            for seed in &seeds {
+                checks.extend(validate::check_seed(&client, &resolver, seed).await);
+            }
+        }
+        Err(e) => checks.push(validate::Check {
+            name: "Seed URLs".to_string(),
+            problem: Some(e.to_string()),
+        }),
+    }
+    checks.push(validate::check_save_dir(&paths.save_dir.to_string_lossy()));
+    checks.push(validate::check_database(
+        &paths.database.to_string_lossy(),
+        schema::SCHEMA_VERSION,
+    ));
+
+    let mut problems = 0;
+    for check in checks {
+        match check.problem {
+            Some(problem) => {
+                problems += 1;
+                println!("[FAIL] {}: {}", check.name, problem);
+            }
+            None => println!("[ OK ] {}", check.name),
+        }
+    }
+    if problems > 0 {
+        return Err(format!("{} problems found", problems).into());
+    }
+    Ok(())
+}
+
+fn verify_pages(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    let mut connection = open_database(paths)?;
+    let report = verify::verify(&connection, &paths.save_dir)?;
+    for page in &report.broken {
+        let location = match &page.location {
+            Some(SavedBody::File(path)) => path.display().to_string(),
+            Some(SavedBody::Blob) => "PageBody".to_string(),
+            None => format!("{} in {}", page.hash, paths.save_dir.display()),
+        };
+        match &page.problem {
+            BodyProblem::Missing => {
+                println!("[MISSING] {}: no body {}", page.url, location)
+            }
+            BodyProblem::Mismatch { actual } => println!(
+                "[CORRUPT] {}: {} hashes to {} instead of {}",
+                page.url, location, actual, page.hash
+            ),
+            BodyProblem::Unreadable(e) => {
+                println!("[UNREADABLE] {}: {}: {}", page.url, location, e)
+            }
+        }
+    }
+    for path in &report.orphan_files {
+        println!("[ORPHAN] {}", path.display());
+    }
+    for hash in &report.orphan_blobs {
+        println!("[ORPHAN] PageBody {}", hash);
+    }
+    let problems = report.broken.len() + report.orphan_files.len() + report.orphan_blobs.len();
+    info!(
+        "Checked {} saved pages: {} broken, {} orphan files and {} orphan BLOBs",
+        report.checked,
+        report.broken.len(),
+        report.orphan_files.len(),
+        report.orphan_blobs.len()
+    );
+    if problems == 0 {
+        return Ok(());
+    }
+    if !arguments.get_flag("repair") {
+        return Err(format!(
+            "{} problems found, run verify --repair to fix them",
+            problems
+        )
+        .into());
+    }
+    let (queued, deleted) = verify::repair(&mut connection, &report)?;
+    info!(
+        "Deleted {} files and BLOBs and queued {} pages, which resume --revalidate fetches again",
+        deleted, queued
+    );
+    Ok(())
+}
+
+fn retry_failed(
+    arguments: &ArgMatches,
+    config: &CrawlConfig,
+    paths: &CrawlPaths,
+) -> Result<(), Box<dyn Error>> {
+    let connection = open_database(paths)?;
+    let shared = setting(arguments, "db-url", config.db_url.clone())
+        .map(|url| PostgresStore::connect(&url))
+        .transpose()?;
+    let mut policy = RetryPolicy {
+        max_attempts: *arguments.get_one::<u32>("max-attempts").unwrap(),
+        backoff: Duration::from_secs(*arguments.get_one::<u64>("backoff").unwrap()),
+        ..RetryPolicy::default()
+    };
+    if let Some(classes) = arguments.get_many::<String>("class") {
+        policy.classes = classes
+            .filter_map(|name| FailureClass::from_name(name))
+            .collect();
+    }
+    let store: &dyn CrawlStore = match &shared {
+        Some(shared) => shared,
+        None => &connection,
+    };
+    let report = retry::queue_failed_urls(&connection, store, &policy)?;
+    for url in &report.queued {
+        println!("{}", url);
+    }
+    info!(
+        "Queued {} failed URLs, which resume fetches again. {} are waiting for their backoff, {} were given up on after {} attempts and {} are not retried",
+        report.queued.len(),
+        report.waiting,
+        report.exhausted,
+        policy.max_attempts,
+        report.permanent
+    );
+    Ok(())
+}
+
+fn clean(arguments: &ArgMatches, paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
+    if !arguments.get_flag("yes") {
+        print!(
+            "Delete {}, {}, {}, {} and {}? [y/N] ",
+            paths.save_dir.display(),
+            paths.assets_dir.display(),
+            paths.images_dir.display(),
+            paths.database.display(),
+            paths.events_file.display()
+        );
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+            info!("Nothing was deleted");
+            return Ok(());
+        }
+    }
+    initialize_data_store(paths)
+}
+
+fn initialize_data_store(paths: &CrawlPaths) -> Result<(), Box<dyn Error>> {
     info!("Initializing database...");
 
-    // Remove existing pages
-    if fs::metadata(SAVE_DIR).is_ok() {
-        fs::remove_dir_all(SAVE_DIR)?;
+    // Remove existing pages, assets and images
+    if fs::metadata(&paths.save_dir).is_ok() {
+        fs::remove_dir_all(&paths.save_dir)?;
+    }
+    fs::create_dir_all(&paths.save_dir)?;
+    if fs::metadata(&paths.assets_dir).is_ok() {
+        fs::remove_dir_all(&paths.assets_dir)?;
+    }
+    if fs::metadata(&paths.images_dir).is_ok() {
+        fs::remove_dir_all(&paths.images_dir)?;
     }
-    fs::create_dir(SAVE_DIR)?;
 
-    // Remove existing database
-    if fs::metadata(DB_NAME).is_ok() {
-        fs::remove_file(DB_NAME)?;
+    // Remove existing database, with the write-ahead log a crawl that did not finish left next to it, and events
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = paths.database.clone().into_os_string();
+        file.push(suffix);
+        if fs::metadata(&file).is_ok() {
+            fs::remove_file(&file)?;
+        }
+    }
+    if fs::metadata(&paths.events_file).is_ok() {
+        fs::remove_file(&paths.events_file)?;
     }
 
     // Create database
-    let create_script = fs::read_to_string(CREATE_SCRIPT)?;
-    let connection = Connection::open(DB_NAME)?;
-    connection.execute_batch(&create_script)?;
+    let mut connection = Connection::open(&paths.database)?;
+    schema::migrate(&mut connection)?;
     connection.close().unwrap();
 
     Ok(())
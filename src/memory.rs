@@ -0,0 +1,42 @@
+use std::mem::size_of;
+
+/// The approximate bookkeeping cost of a stored URL, on top of its characters.
+const URL_OVERHEAD: usize = size_of::<String>() + size_of::<u64>();
+/// How many times larger than the raw HTML a parsed document is estimated to be.
+const PARSE_BUFFER_FACTOR: usize = 4;
+
+/// Approximate memory used by a crawl, in bytes.
+#[derive(Clone, Copy, Default)]
+pub struct MemoryUsage {
+    /// URLs waiting in the in-memory frontier.
+    pub frontier: usize,
+    /// URLs visited during the run.
+    pub visited: usize,
+    /// Response bodies currently being processed.
+    pub bodies: usize,
+    /// Parsed documents currently being processed.
+    pub parse_buffers: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the total of all tracked memory.
+    pub fn total(&self) -> usize {
+        self.frontier + self.visited + self.bodies + self.parse_buffers
+    }
+
+    /// Estimates the memory used to hold a URL in a queue or set.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    pub fn url_size(url: &str) -> usize {
+        url.len() + URL_OVERHEAD
+    }
+
+    /// Estimates the memory used to parse a document.
+    ///
+    /// # Arguments
+    /// * `body` - The raw contents of the document.
+    pub fn parse_size(body: &str) -> usize {
+        body.len() * PARSE_BUFFER_FACTOR
+    }
+}
@@ -0,0 +1,306 @@
+//! Metadata declared in the head of pages.
+
+use scraper::{Html, Selector};
+use std::collections::BTreeMap;
+use url::Url;
+
+use crate::language::primary_subtag;
+
+/// Robots directives that take a value after a colon, which is not to be mistaken for a user agent.
+const VALUED_DIRECTIVES: [&str; 4] = [
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+/// Metadata declared in the head of a page.
+pub struct PageMeta {
+    /// The contents of the `<title>` element.
+    pub title: Option<String>,
+    /// The contents of `<meta name="description">`.
+    pub description: Option<String>,
+    /// The absolute URL of `<link rel="canonical">`.
+    pub canonical: Option<Url>,
+    /// The `og:*` properties, keyed by name without the prefix.
+    pub open_graph: BTreeMap<String, String>,
+    /// The directives of `<meta name="robots">`.
+    pub robots: RobotsDirectives,
+    /// The ISO 639-1 code of the language of the page, from `<html lang>` until the crawler detects it from the text.
+    pub language: Option<String>,
+}
+
+/// The indexing directives of a page.
+#[derive(Clone, Copy, Default)]
+pub struct RobotsDirectives {
+    /// The page should not be stored.
+    pub noindex: bool,
+    /// The links of the page should not be followed.
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Parses the values of the `X-Robots-Tag` response headers.
+    ///
+    /// Directives may be scoped to a user agent with a prefix, e.g. `otherbot: noindex, nofollow`. The scope lasts
+    /// until the next prefix or the end of the header value, and only unscoped directives and those of our user agent
+    /// apply.
+    ///
+    /// # Arguments
+    /// * `values` - The values of every `X-Robots-Tag` header.
+    /// * `user_agent` - The name of the crawler's user agent.
+    pub fn from_header<'a>(values: impl IntoIterator<Item = &'a str>, user_agent: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for value in values {
+            let mut applies = true;
+            for token in value.split(',') {
+                let mut directive = token.trim();
+                if let Some((prefix, rest)) = directive.split_once(':') {
+                    let prefix = prefix.trim();
+                    if !VALUED_DIRECTIVES
+                        .iter()
+                        .any(|valued| prefix.eq_ignore_ascii_case(valued))
+                    {
+                        applies = prefix.eq_ignore_ascii_case(user_agent);
+                        directive = rest.trim();
+                    }
+                }
+                if applies {
+                    directives.apply(directive);
+                }
+            }
+        }
+        directives
+    }
+
+    /// Sets the flags of a single directive, ignoring unsupported ones. `none` means both `noindex` and `nofollow`.
+    ///
+    /// # Arguments
+    /// * `directive` - The directive, e.g. `noindex`.
+    fn apply(&mut self, directive: &str) {
+        match directive.trim().to_ascii_lowercase().as_str() {
+            "noindex" => self.noindex = true,
+            "nofollow" => self.nofollow = true,
+            "none" => {
+                self.noindex = true;
+                self.nofollow = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Combines two sets of directives, keeping every restriction of either.
+    ///
+    /// # Arguments
+    /// * `other` - The other directives.
+    pub fn union(self, other: RobotsDirectives) -> Self {
+        RobotsDirectives {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
+
+/// Returns the URL relative links of a page are resolved against: the first `<base href>` of the page, resolved
+/// against the page URL, or else the page URL itself.
+///
+/// # Arguments
+/// * `document` - The parsed page.
+/// * `url` - The URL of the page.
+pub fn base_url(document: &Html, url: &Url) -> Url {
+    Selector::parse("base[href]")
+        .ok()
+        .and_then(|selector| document.select(&selector).next())
+        .and_then(|element| url.join(element.value().attr("href")?.trim()).ok())
+        .filter(|base| !base.cannot_be_a_base())
+        .unwrap_or_else(|| url.clone())
+}
+
+impl PageMeta {
+    /// Extracts the metadata of a parsed page. Only the first of repeated elements is used.
+    ///
+    /// # Arguments
+    /// * `document` - The parsed page.
+    /// * `url` - The URL of the page, used to resolve a relative canonical URL with the `<base href>` of the page.
+    pub fn from_document(document: &Html, url: &Url) -> Self {
+        let base = base_url(document, url);
+        let title = Selector::parse("title").ok().and_then(|selector| {
+            document
+                .select(&selector)
+                .next()
+                .map(|element| normalize(&element.text().collect::<String>()))
+        });
+        let description = Selector::parse("meta[name]").ok().and_then(|selector| {
+            document
+                .select(&selector)
+                .find(|element| {
+                    element
+                        .value()
+                        .attr("name")
+                        .is_some_and(|name| name.eq_ignore_ascii_case("description"))
+                })
+                .and_then(|element| element.value().attr("content"))
+                .map(normalize)
+        });
+        let canonical = Selector::parse("link[rel][href]")
+            .ok()
+            .and_then(|selector| {
+                document
+                    .select(&selector)
+                    .find(|element| {
+                        element.value().attr("rel").is_some_and(|rel| {
+                            rel.split_whitespace()
+                                .any(|value| value.eq_ignore_ascii_case("canonical"))
+                        })
+                    })
+                    .and_then(|element| base.join(element.value().attr("href")?.trim()).ok())
+            });
+
+        let mut robots = RobotsDirectives::default();
+        if let Ok(selector) = Selector::parse("meta[name][content]") {
+            document
+                .select(&selector)
+                .filter(|element| {
+                    element
+                        .value()
+                        .attr("name")
+                        .is_some_and(|name| name.eq_ignore_ascii_case("robots"))
+                })
+                .filter_map(|element| element.value().attr("content"))
+                .flat_map(|content| content.split(','))
+                .for_each(|directive| robots.apply(directive));
+        }
+
+        let mut open_graph = BTreeMap::new();
+        if let Ok(selector) = Selector::parse("meta[property][content]") {
+            for element in document.select(&selector) {
+                let property = element.value().attr("property").unwrap_or_default();
+                let content = element.value().attr("content").unwrap_or_default();
+                if let Some(name) = property.strip_prefix("og:") {
+                    open_graph
+                        .entry(name.to_string())
+                        .or_insert_with(|| normalize(content));
+                }
+            }
+        }
+
+        PageMeta {
+            title: title.filter(|title| !title.is_empty()),
+            description: description.filter(|description| !description.is_empty()),
+            canonical,
+            open_graph,
+            robots,
+            language: document
+                .root_element()
+                .value()
+                .attr("lang")
+                .and_then(primary_subtag),
+        }
+    }
+}
+
+/// Collapses whitespace to single spaces.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_x_robots_tag_headers() {
+        let cases: [(&[&str], bool, bool); 11] = [
+            (&[], false, false),
+            (&["noindex"], true, false),
+            (&["NoFollow"], false, true),
+            (&["none"], true, true),
+            (&["noindex", "nofollow"], true, true),
+            (&["noarchive, nosnippet, max-snippet: 20"], false, false),
+            // Directives scoped to other crawlers do not apply, until the scope ends with the header value
+            (&["otherbot: noindex, nofollow"], false, false),
+            (&["otherbot: noindex", "nofollow"], false, true),
+            (&["TestBot: noindex"], true, false),
+            (&["otherbot: none, testbot: nofollow"], false, true),
+            // Valued directives are not taken for user agents
+            (
+                &["unavailable_after: 25 Jun 2010 15:00:00 PST, noindex"],
+                true,
+                false,
+            ),
+        ];
+        for (values, noindex, nofollow) in cases {
+            let directives = RobotsDirectives::from_header(values.iter().copied(), "testbot");
+            assert_eq!(
+                (directives.noindex, directives.nofollow),
+                (noindex, nofollow),
+                "{:?}",
+                values
+            );
+        }
+    }
+
+    #[test]
+    fn reads_robots_meta_tags() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let document = Html::parse_document(
+            r#"<html><head><meta name="ROBOTS" content="NoIndex, follow">
+            <meta name="description" content="nofollow"></head></html>"#,
+        );
+        let robots = PageMeta::from_document(&document, &url).robots;
+        assert!(robots.noindex && !robots.nofollow);
+        let header = RobotsDirectives::from_header(["nofollow"], "testbot");
+        let combined = robots.union(header);
+        assert!(combined.noindex && combined.nofollow);
+    }
+
+    #[test]
+    fn resolves_the_base_url() {
+        let url = Url::parse("https://example.com/docs/guide/page.html").unwrap();
+        let cases = [
+            ("", "https://example.com/docs/guide/page.html"),
+            (
+                r#"<base href="https://cdn.example.com/assets/">"#,
+                "https://cdn.example.com/assets/",
+            ),
+            (r#"<base href="/root/">"#, "https://example.com/root/"),
+            (r#"<base href=" ../ ">"#, "https://example.com/docs/"),
+            (
+                r#"<base href="?lang=en">"#,
+                "https://example.com/docs/guide/page.html?lang=en",
+            ),
+            // Only the first base with an href counts
+            (
+                r#"<base target="_blank"><base href="/a/"><base href="/b/">"#,
+                "https://example.com/a/",
+            ),
+            // Bases that cannot resolve links are ignored
+            (
+                r#"<base href="mailto:someone@example.com">"#,
+                "https://example.com/docs/guide/page.html",
+            ),
+            (
+                r#"<base href="http://[::1">"#,
+                "https://example.com/docs/guide/page.html",
+            ),
+        ];
+        for (head, expected) in cases {
+            let document = Html::parse_document(&format!("<html><head>{}</head></html>", head));
+            assert_eq!(base_url(&document, &url).as_str(), expected, "{}", head);
+        }
+    }
+
+    #[test]
+    fn resolves_canonical_urls_against_the_base() {
+        let url = Url::parse("https://example.com/docs/page.html").unwrap();
+        let document = Html::parse_document(
+            r#"<html><head><base href="https://example.org/en/">
+            <link rel="alternate stylesheet" href="/style.css"><link rel="Canonical" href="page"></head></html>"#,
+        );
+        let meta = PageMeta::from_document(&document, &url);
+        assert_eq!(
+            meta.canonical.map(String::from).as_deref(),
+            Some("https://example.org/en/page")
+        );
+    }
+}
@@ -0,0 +1,593 @@
+//! Normalization of URLs, so that variants of one page are crawled and stored once.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::{Host, Url};
+
+/// The file names servers commonly serve for a directory URL.
+const INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
+/// The schemes of links that do not lead to a page, e.g. `mailto:` addresses and `javascript:` actions.
+const NON_NAVIGABLE_SCHEMES: [&str; 4] = ["mailto", "javascript", "tel", "data"];
+/// The query parameters that only tell where a visitor came from, stripped from URLs by default.
+pub const DEFAULT_TRACKING_PARAMS: [&str; 9] = [
+    "utm_*", "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "mc_cid", "mc_eid",
+];
+
+/// How the URLs of directories, such as `/docs`, `/docs/` and `/docs/index.html`, are treated.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlashPolicy {
+    /// Every variant is a page of its own.
+    #[default]
+    Keep,
+    /// Variants are stored as `/docs/`.
+    Add,
+    /// Variants are stored as `/docs`.
+    Remove,
+}
+
+impl TrailingSlashPolicy {
+    /// Every policy.
+    pub const ALL: [TrailingSlashPolicy; 3] = [
+        TrailingSlashPolicy::Keep,
+        TrailingSlashPolicy::Add,
+        TrailingSlashPolicy::Remove,
+    ];
+
+    /// Returns the name of the policy.
+    pub fn name(self) -> &'static str {
+        match self {
+            TrailingSlashPolicy::Keep => "keep",
+            TrailingSlashPolicy::Add => "add",
+            TrailingSlashPolicy::Remove => "remove",
+        }
+    }
+
+    /// Finds a policy by its name.
+    ///
+    /// # Arguments
+    /// * `name` - The name, e.g. `add`.
+    ///
+    /// # Returns
+    /// The policy, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Resolves the href of a link against the URL of its page, or the `<base href>` of the page.
+///
+/// Absolute, scheme-relative, root-relative and relative hrefs, including `../` segments and query-only hrefs such as
+/// `?page=2`, are resolved like browsers do. The fragment is dropped, as it names a part of the same page.
+///
+/// # Arguments
+/// * `href` - The href attribute value.
+/// * `base` - The URL to resolve against.
+///
+/// # Returns
+/// The absolute URL, or None if the href is invalid or uses a scheme that does not lead to a page, such as `mailto:`,
+/// `javascript:`, `tel:` or `data:`.
+pub fn resolve_href(href: &str, base: &Url) -> Option<Url> {
+    let mut url = base.join(href.trim()).ok()?;
+    if NON_NAVIGABLE_SCHEMES.contains(&url.scheme()) {
+        return None;
+    }
+    url.set_fragment(None);
+    Some(url)
+}
+
+/// Returns the form of a host that domains are stored and matched by, which is the form of the host in URLs:
+/// domains in lowercase, internationalized labels in punycode, e.g. `xn--bcher-kva.de` for `bücher.de`, IPv4
+/// addresses in dotted decimal and IPv6 addresses in brackets, e.g. `[2001:db8::1]`.
+///
+/// # Arguments
+/// * `host` - The host as written by a user, e.g. in the configuration file. IPv6 addresses may leave out the
+///   brackets.
+///
+/// # Returns
+/// The normalized host, or the host in lowercase if it is not a valid host.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let parsed = if host.contains(':') && !host.starts_with('[') {
+        Host::parse(&format!("[{}]", host))
+    } else {
+        Host::parse(host)
+    };
+    parsed.map_or_else(|_| host.to_lowercase(), |host| host.to_string())
+}
+
+/// Returns whether two URLs are on the same site: their hosts are equal once a leading `www.` is dropped, so that
+/// `www.example.com` and `example.com` are one site, while other subdomains are sites of their own.
+pub fn is_same_site(a: &Url, b: &Url) -> bool {
+    let site = |url: &Url| {
+        url.host_str()
+            .map(|host| host.strip_prefix("www.").unwrap_or(host).to_string())
+    };
+    site(a) == site(b)
+}
+
+/// Returns a host as read by people, with punycode labels shown in Unicode, e.g. `bücher.de` for
+/// `xn--bcher-kva.de`. Other hosts, including IP addresses, are returned as they are.
+pub fn display_host(host: &str) -> String {
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return host.to_string();
+    }
+    match idna::domain_to_unicode(host) {
+        (unicode, Ok(())) => unicode,
+        (_, Err(_)) => host.to_string(),
+    }
+}
+
+/// Returns a URL as read by people, with its host shown as by [`display_host`].
+pub fn display_url(url: &Url) -> String {
+    match url.host_str() {
+        Some(host) if host.contains("xn--") => url.as_str().replacen(host, &display_host(host), 1),
+        _ => url.to_string(),
+    }
+}
+
+/// Which query parameters are stripped from URLs, given as names, or as prefixes ending in `*`. A lone `*` matches
+/// every parameter. Names are matched case-insensitively.
+///
+/// The rules of a domain take precedence over the global rules: a parameter the domain keeps is kept, and one it
+/// strips is stripped. Otherwise a parameter is stripped if it is a tracking parameter or in the global strip list,
+/// unless it is in the global keep list.
+#[derive(Clone, Debug)]
+pub struct QueryParamRules {
+    strip: Vec<String>,
+    keep: Vec<String>,
+    domains: HashMap<String, (Vec<String>, Vec<String>)>,
+}
+
+impl Default for QueryParamRules {
+    /// Strips the [`DEFAULT_TRACKING_PARAMS`].
+    fn default() -> Self {
+        QueryParamRules {
+            strip: DEFAULT_TRACKING_PARAMS.map(String::from).to_vec(),
+            keep: Vec::new(),
+            domains: HashMap::new(),
+        }
+    }
+}
+
+impl QueryParamRules {
+    /// Adds global rules to the default ones.
+    ///
+    /// # Arguments
+    /// * `strip` - The parameters stripped besides the tracking parameters.
+    /// * `keep` - The parameters kept, even tracking parameters.
+    pub fn global(mut self, strip: &[String], keep: &[String]) -> Self {
+        self.strip.extend_from_slice(strip);
+        self.keep.extend_from_slice(keep);
+        self
+    }
+
+    /// Sets the rules of the URLs of a host.
+    ///
+    /// # Arguments
+    /// * `host` - The host of the URLs.
+    /// * `strip` - The parameters stripped.
+    /// * `keep` - The parameters kept.
+    pub fn domain(mut self, host: &str, strip: &[String], keep: &[String]) -> Self {
+        self.domains
+            .insert(normalize_host(host), (strip.to_vec(), keep.to_vec()));
+        self
+    }
+
+    /// Returns whether a query parameter of a URL on a host is stripped.
+    fn strips(&self, host: Option<&str>, name: &str) -> bool {
+        if let Some((strip, keep)) = host.and_then(|host| self.domains.get(host)) {
+            if matches_param(keep, name) {
+                return false;
+            }
+            if matches_param(strip, name) {
+                return true;
+            }
+        }
+        matches_param(&self.strip, name) && !matches_param(&self.keep, name)
+    }
+
+    /// Removes the stripped parameters from the query of a URL, keeping the others as they were written. A query
+    /// left empty is removed.
+    fn apply(&self, url: &mut Url) {
+        let Some(query) = url.query() else {
+            return;
+        };
+        let host = url.host_str();
+        let kept: Vec<&str> = query
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                let name = percent_decode(name);
+                pair.is_empty() || !self.strips(host, &name)
+            })
+            .collect();
+        if kept.len() == query.split('&').count() {
+            return;
+        }
+        let kept = kept.join("&");
+        let kept = kept.trim_matches('&');
+        url.set_query(
+            (!kept.is_empty())
+                .then_some(kept)
+                .map(str::to_string)
+                .as_deref(),
+        );
+    }
+}
+
+/// Returns whether a parameter name matches one of a list of names and prefixes.
+fn matches_param(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => {
+                name.len() >= prefix.len() && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+            }
+            None => pattern.eq_ignore_ascii_case(name),
+        })
+}
+
+/// Returns whether the last segment of a path ends in a file extension: up to 5 letters and digits, at least one of
+/// them a letter, after a dot that does not start the segment.
+pub(crate) fn has_file_extension(segment: &str) -> bool {
+    match segment.rsplit_once('.') {
+        Some((name, extension)) => {
+            !name.is_empty()
+                && (1..=5).contains(&extension.len())
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+                && extension.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+/// Decodes the name of a query parameter, as written in a URL.
+fn percent_decode(name: &str) -> String {
+    url::form_urlencoded::parse(name.as_bytes())
+        .next()
+        .map(|(name, _)| name.into_owned())
+        .unwrap_or_default()
+}
+
+/// Rewrites URLs into the form they are queued, crawled and stored in.
+#[derive(Clone, Default)]
+pub struct UrlNormalizer {
+    trailing_slash: TrailingSlashPolicy,
+    query_params: Arc<QueryParamRules>,
+}
+
+impl UrlNormalizer {
+    /// Creates a new `UrlNormalizer`, which strips the default tracking parameters.
+    ///
+    /// # Arguments
+    /// * `trailing_slash` - How directory URLs are treated.
+    pub fn new(trailing_slash: TrailingSlashPolicy) -> Self {
+        UrlNormalizer {
+            trailing_slash,
+            query_params: Arc::default(),
+        }
+    }
+
+    /// Sets which query parameters are stripped.
+    ///
+    /// # Arguments
+    /// * `rules` - The rules.
+    pub fn query_params(mut self, rules: QueryParamRules) -> Self {
+        self.query_params = Arc::new(rules);
+        self
+    }
+
+    /// Returns the normalized form of a URL.
+    ///
+    /// The query parameters stripped by the [`QueryParamRules`] are removed, tracking parameters such as `utm_source`
+    /// by default. Unless directory URLs are kept as they are, a trailing `index.html` or `index.htm` is dropped, and
+    /// the path gets or loses its trailing slash. Paths whose last segment has a file extension, such as
+    /// `/guide.pdf`, are not directories and keep their form, and so does the root path `/`. Dots followed by digits
+    /// only, as in `/v1.2` or `/jquery-3.7`, are taken for versions, not file extensions.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    pub fn normalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        self.query_params.apply(&mut url);
+        if self.trailing_slash == TrailingSlashPolicy::Keep || url.cannot_be_a_base() {
+            return url;
+        }
+        let url = &url;
+        let mut path = url.path();
+        for index in INDEX_FILES {
+            if let Some(directory) = path.strip_suffix(index).filter(|dir| dir.ends_with('/')) {
+                path = directory;
+                break;
+            }
+        }
+        let last_segment = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        if last_segment.is_empty() || has_file_extension(last_segment) {
+            let mut normalized = url.clone();
+            normalized.set_path(path);
+            return normalized;
+        }
+        let directory = path.trim_end_matches('/');
+        let mut normalized = url.clone();
+        match self.trailing_slash {
+            TrailingSlashPolicy::Add => normalized.set_path(&format!("{}/", directory)),
+            _ => normalized.set_path(directory),
+        }
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_sites() {
+        let same_site =
+            |a: &str, b: &str| is_same_site(&Url::parse(a).unwrap(), &Url::parse(b).unwrap());
+        assert!(same_site("https://example.com/a", "https://example.com/b"));
+        assert!(same_site("https://www.example.com/", "http://example.com/"));
+        assert!(same_site(
+            "https://example.com/",
+            "https://WWW.example.com/"
+        ));
+        assert!(!same_site(
+            "https://docs.example.com/",
+            "https://example.com/"
+        ));
+        assert!(!same_site(
+            "https://www.example.com/",
+            "https://www.example.org/"
+        ));
+    }
+
+    #[test]
+    fn strips_query_params() {
+        let rules = QueryParamRules::default()
+            .global(&["sessionid".to_string()], &["utm_campaign".to_string()])
+            .domain("shop.example.com", &["*".to_string()], &["id".to_string()]);
+        let normalizer = UrlNormalizer::new(TrailingSlashPolicy::Keep).query_params(rules);
+        let cases = [
+            (
+                "https://example.com/a?id=1&utm_source=feed&UTM_Medium=x",
+                "https://example.com/a?id=1",
+            ),
+            (
+                "https://example.com/a?fbclid=abc&gclid=def",
+                "https://example.com/a",
+            ),
+            (
+                "https://example.com/a?utm_campaign=spring&sessionid=9",
+                "https://example.com/a?utm_campaign=spring",
+            ),
+            (
+                "https://example.com/a?q=a%26b&x=",
+                "https://example.com/a?q=a%26b&x=",
+            ),
+            (
+                "https://shop.example.com/p?id=4&color=red&utm_source=x",
+                "https://shop.example.com/p?id=4",
+            ),
+        ];
+        for (url, expected) in cases {
+            let normalized = normalizer.normalize(&Url::parse(url).unwrap());
+            assert_eq!(normalized.as_str(), expected, "{}", url);
+        }
+    }
+
+    const BASE: &str = "https://example.com/docs/guide/index.html?lang=en";
+
+    #[test]
+    fn resolves_hrefs() {
+        let cases: [(&str, Option<&str>); 22] = [
+            // Absolute
+            ("https://other.com/a?b=1", Some("https://other.com/a?b=1")),
+            ("http://example.com/plain", Some("http://example.com/plain")),
+            ("HTTPS://Example.COM/Case", Some("https://example.com/Case")),
+            // Scheme-relative
+            (
+                "//cdn.example.com/app.js",
+                Some("https://cdn.example.com/app.js"),
+            ),
+            // Root-relative
+            ("/about", Some("https://example.com/about")),
+            ("/search?q=rust", Some("https://example.com/search?q=rust")),
+            // Relative
+            (
+                "about.html",
+                Some("https://example.com/docs/guide/about.html"),
+            ),
+            ("./setup/", Some("https://example.com/docs/guide/setup/")),
+            ("../pricing", Some("https://example.com/docs/pricing")),
+            ("../../../../top", Some("https://example.com/top")),
+            (
+                "?page=2",
+                Some("https://example.com/docs/guide/index.html?page=2"),
+            ),
+            (
+                "  spaced.html\n",
+                Some("https://example.com/docs/guide/spaced.html"),
+            ),
+            // Fragments name a part of the page
+            (
+                "#install",
+                Some("https://example.com/docs/guide/index.html?lang=en"),
+            ),
+            (
+                "faq.html#billing",
+                Some("https://example.com/docs/guide/faq.html"),
+            ),
+            (
+                "",
+                Some("https://example.com/docs/guide/index.html?lang=en"),
+            ),
+            // Links that do not lead to a page
+            ("mailto:team@example.com", None),
+            ("javascript:void(0)", None),
+            ("JavaScript:alert(1)", None),
+            ("tel:+441234567890", None),
+            ("data:text/html,<p>hi</p>", None),
+            // Invalid
+            ("http://[::1", None),
+            ("https://exa mple.com/", None),
+        ];
+        let base = Url::parse(BASE).unwrap();
+        for (href, expected) in cases {
+            let resolved = resolve_href(href, &base);
+            assert_eq!(
+                resolved.as_ref().map(Url::as_str),
+                expected,
+                "href {:?}",
+                href
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_hrefs_against_directory_base() {
+        let base = Url::parse("https://cdn.example.com/app/").unwrap();
+        let cases = [
+            ("main.html", "https://cdn.example.com/app/main.html"),
+            ("../shared/x", "https://cdn.example.com/shared/x"),
+            ("/rooted", "https://cdn.example.com/rooted"),
+        ];
+        for (href, expected) in cases {
+            assert_eq!(resolve_href(href, &base).unwrap().as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn normalizes_directory_urls() {
+        use TrailingSlashPolicy::{Add, Keep, Remove};
+        let cases = [
+            (Keep, "https://example.com/docs", "https://example.com/docs"),
+            (
+                Keep,
+                "https://example.com/docs/index.html",
+                "https://example.com/docs/index.html",
+            ),
+            (Add, "https://example.com/docs", "https://example.com/docs/"),
+            (
+                Add,
+                "https://example.com/docs/",
+                "https://example.com/docs/",
+            ),
+            (
+                Add,
+                "https://example.com/docs/index.html",
+                "https://example.com/docs/",
+            ),
+            (
+                Add,
+                "https://example.com/docs/index.htm?a=1",
+                "https://example.com/docs/?a=1",
+            ),
+            (
+                Add,
+                "https://example.com/index.html",
+                "https://example.com/",
+            ),
+            (Add, "https://example.com", "https://example.com/"),
+            (
+                Add,
+                "https://example.com/guide.pdf",
+                "https://example.com/guide.pdf",
+            ),
+            (
+                Add,
+                "https://example.com/api/v1.2",
+                "https://example.com/api/v1.2/",
+            ),
+            (
+                Add,
+                "https://example.com/jquery-3.7",
+                "https://example.com/jquery-3.7/",
+            ),
+            (
+                Add,
+                "https://example.com/.well-known",
+                "https://example.com/.well-known/",
+            ),
+            (
+                Add,
+                "https://example.com/myindex.html",
+                "https://example.com/myindex.html",
+            ),
+            (
+                Remove,
+                "https://example.com/docs/",
+                "https://example.com/docs",
+            ),
+            (
+                Remove,
+                "https://example.com/docs//",
+                "https://example.com/docs",
+            ),
+            (
+                Remove,
+                "https://example.com/docs/index.html",
+                "https://example.com/docs",
+            ),
+            (Remove, "https://example.com/", "https://example.com/"),
+            (
+                Remove,
+                "https://example.com/api/v1.2/",
+                "https://example.com/api/v1.2",
+            ),
+            (
+                Remove,
+                "https://example.com/archive.tar.gz",
+                "https://example.com/archive.tar.gz",
+            ),
+            (
+                Remove,
+                "https://example.com/page.php5",
+                "https://example.com/page.php5",
+            ),
+            (
+                Remove,
+                "https://example.com/a/?utm_source=x#top",
+                "https://example.com/a#top",
+            ),
+            (
+                Remove,
+                "mailto:someone@example.com",
+                "mailto:someone@example.com",
+            ),
+        ];
+        for (policy, url, expected) in cases {
+            let normalizer = UrlNormalizer::new(policy);
+            assert_eq!(
+                normalizer.normalize(&Url::parse(url).unwrap()).as_str(),
+                expected,
+                "{} with {:?}",
+                url,
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_hosts() {
+        let cases = [
+            ("Example.COM", "example.com"),
+            ("bücher.de", "xn--bcher-kva.de"),
+            ("xn--bcher-kva.de", "xn--bcher-kva.de"),
+            ("192.168.0.1", "192.168.0.1"),
+            ("2001:DB8::1", "[2001:db8::1]"),
+            ("[::1]", "[::1]"),
+        ];
+        for (host, expected) in cases {
+            assert_eq!(normalize_host(host), expected, "host {:?}", host);
+        }
+        assert_eq!(display_host("xn--bcher-kva.de"), "bücher.de");
+        assert_eq!(display_host("[::1]"), "[::1]");
+        let url = Url::parse("https://bücher.de/xn--a?q=1").unwrap();
+        assert_eq!(url.host_str(), Some("xn--bcher-kva.de"));
+        assert_eq!(display_url(&url), "https://bücher.de/xn--a?q=1");
+    }
+}
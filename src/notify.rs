@@ -0,0 +1,291 @@
+//! Webhook and email notifications sent when a crawl run finishes or aborts.
+
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{error, info};
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::stats::write_stats;
+
+/// How a crawl run ended.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// The run crawled every iteration, or ran out of URLs.
+    Finished,
+    /// The run was stopped by an error.
+    Aborted,
+}
+
+/// The outcome of a crawl run and a summary of the database after it.
+#[derive(Serialize)]
+pub struct RunReport {
+    /// The id of the run.
+    pub run_id: i64,
+    /// How the run ended.
+    pub outcome: RunOutcome,
+    /// The error that stopped the run, if it was aborted.
+    pub error: Option<String>,
+    /// How long the run took, in seconds.
+    pub duration_secs: u64,
+    /// The number of pages stored by the run.
+    pub pages: i64,
+    /// The number of requests sent by the run.
+    pub fetches: i64,
+    /// The number of URLs that could not be fetched during the run.
+    pub failed: i64,
+    /// The number of links not followed during the run because their page is marked nofollow.
+    pub suppressed_links: i64,
+    /// The domains suspended during the run after spending their error budget.
+    pub suspended_domains: Vec<String>,
+    /// The summary of the crawl database, as printed by the `stats` subcommand.
+    pub summary: String,
+}
+
+impl RunReport {
+    /// Builds the report of a run from the database.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `run_id` - The id of the run.
+    /// * `duration` - How long the run took.
+    /// * `error` - The error that stopped the run, or None if it finished.
+    ///
+    /// # Returns
+    /// The report, or an error if the database could not be read.
+    pub fn load(
+        connection: &Connection,
+        run_id: i64,
+        duration: Duration,
+        error: Option<String>,
+    ) -> Result<Self, CrawlerError> {
+        let count = |table: &str| {
+            connection.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE RunId = ?", table),
+                [run_id],
+                |row| row.get::<_, i64>(0),
+            )
+        };
+        let mut summary = Vec::new();
+        write_stats(connection, &mut summary)?;
+        let suspended_domains = connection
+            .prepare("SELECT Name FROM Domain WHERE SuspendedRunId = ? ORDER BY Name")?
+            .query_map([run_id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(RunReport {
+            run_id,
+            outcome: if error.is_some() {
+                RunOutcome::Aborted
+            } else {
+                RunOutcome::Finished
+            },
+            error,
+            duration_secs: duration.as_secs(),
+            pages: count("Page")?,
+            fetches: count("PageFetch")?,
+            failed: count("FailedUrl")?,
+            suppressed_links: connection.query_row(
+                "SELECT COALESCE(SUM(SuppressedLinks), 0) FROM Page WHERE RunId = ?",
+                [run_id],
+                |row| row.get(0),
+            )?,
+            suspended_domains,
+            summary: String::from_utf8_lossy(&summary).into_owned(),
+        })
+    }
+
+    /// Returns a one-line description of the run, used as the subject of emails.
+    pub fn subject(&self) -> String {
+        let outcome = match self.outcome {
+            RunOutcome::Finished => "finished",
+            RunOutcome::Aborted => "aborted",
+        };
+        format!(
+            "Crawl run #{} {}: {} pages, {} failed URLs",
+            self.run_id, outcome, self.pages, self.failed
+        )
+    }
+
+    /// Returns the plain text body of emails.
+    fn text(&self) -> String {
+        let mut text = format!(
+            "Duration: {}s\nRequests: {}\nNofollow: {} links not followed\n",
+            self.duration_secs, self.fetches, self.suppressed_links
+        );
+        if !self.suspended_domains.is_empty() {
+            text.push_str(&format!(
+                "Suspended: {}\n",
+                self.suspended_domains.join(", ")
+            ));
+        }
+        if let Some(error) = &self.error {
+            text.push_str(&format!("Error:    {}\n", error));
+        }
+        text.push('\n');
+        text.push_str(&self.summary);
+        text
+    }
+}
+
+/// An email notification and the SMTP server it is sent through.
+struct EmailTarget {
+    smtp_url: String,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+/// Sends the report of a crawl run to the configured webhook and email address.
+#[derive(Default)]
+pub struct Notifier {
+    webhook: Option<(Url, Client)>,
+    email: Option<EmailTarget>,
+}
+
+impl Notifier {
+    /// Posts reports as JSON to a webhook.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the webhook.
+    /// * `client` - The client reports are posted with.
+    ///
+    /// # Returns
+    /// The notifier.
+    pub fn with_webhook(mut self, url: Url, client: Client) -> Self {
+        self.webhook = Some((url, client));
+        self
+    }
+
+    /// Emails reports through an SMTP server.
+    ///
+    /// # Arguments
+    /// * `smtp_url` - The server, e.g. `smtps://user@smtp.example.com` or `smtp://localhost:25`.
+    /// * `password` - The password of the user of the server, replacing any in the URL.
+    /// * `from` - The sender address.
+    /// * `to` - The recipient address.
+    ///
+    /// # Returns
+    /// The notifier, or an error if the URL or an address is invalid.
+    pub fn with_email(
+        mut self,
+        smtp_url: &str,
+        password: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> Result<Self, CrawlerError> {
+        let mut smtp_url = Url::parse(smtp_url)
+            .map_err(|e| CrawlerError::Config(format!("Invalid SMTP URL: {}", e)))?;
+        if password.is_some() && smtp_url.set_password(password).is_err() {
+            return Err(CrawlerError::Config(
+                "The SMTP URL cannot have a password".to_string(),
+            ));
+        }
+        self.email = Some(EmailTarget {
+            smtp_url: smtp_url.to_string(),
+            from: from.parse().map_err(|e| {
+                CrawlerError::Config(format!("Invalid sender address {}: {}", from, e))
+            })?,
+            to: to.parse().map_err(|e| {
+                CrawlerError::Config(format!("Invalid recipient address {}: {}", to, e))
+            })?,
+        });
+        Ok(self)
+    }
+
+    /// Returns whether no notifications are configured.
+    pub fn is_empty(&self) -> bool {
+        self.webhook.is_none() && self.email.is_none()
+    }
+
+    /// Sends a report to every configured target. Failures are logged, and do not stop the other notifications.
+    ///
+    /// # Arguments
+    /// * `report` - The report of the run.
+    pub async fn notify(&self, report: &RunReport) {
+        if let Some((url, client)) = &self.webhook {
+            match Self::post_webhook(client, url, report).await {
+                Ok(()) => info!("Sent the report of run {} to {}", report.run_id, url),
+                Err(e) => error!("Failed to send the report to {}: {}", url, e),
+            }
+        }
+        if let Some(email) = &self.email {
+            match Self::send_email(email, report).await {
+                Ok(()) => info!(
+                    "Emailed the report of run {} to {}",
+                    report.run_id, email.to
+                ),
+                Err(e) => error!("Failed to email the report to {}: {}", email.to, e),
+            }
+        }
+    }
+
+    async fn post_webhook(
+        client: &Client,
+        url: &Url,
+        report: &RunReport,
+    ) -> Result<(), CrawlerError> {
+        client
+            .post(url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(report)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send_email(email: &EmailTarget, report: &RunReport) -> Result<(), CrawlerError> {
+        let message = Message::builder()
+            .from(email.from.clone())
+            .to(email.to.clone())
+            .subject(report.subject())
+            .body(report.text())
+            .map_err(|e| CrawlerError::Delivery(e.to_string()))?;
+        AsyncSmtpTransport::<Tokio1Executor>::from_url(&email.smtp_url)
+            .map_err(|e| CrawlerError::Config(format!("Invalid SMTP URL: {}", e)))?
+            .build()
+            .send(message)
+            .await
+            .map_err(|e| CrawlerError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_smtp_password_to_url() {
+        let notifier = Notifier::default()
+            .with_email(
+                "smtps://reports%40example.com@smtp.example.com",
+                Some("p@ss:word"),
+                "reports@example.com",
+                "ops@example.com",
+            )
+            .unwrap();
+        let smtp_url = Url::parse(&notifier.email.unwrap().smtp_url).unwrap();
+        assert_eq!(smtp_url.username(), "reports%40example.com");
+        assert_eq!(smtp_url.password(), Some("p%40ss%3Aword"));
+
+        let notifier = Notifier::default()
+            .with_email(
+                "smtp://localhost:25",
+                None,
+                "a@example.com",
+                "b@example.com",
+            )
+            .unwrap();
+        assert_eq!(notifier.email.unwrap().smtp_url, "smtp://localhost:25");
+        assert!(matches!(
+            Notifier::default().with_email("localhost", None, "a@example.com", "b@example.com"),
+            Err(CrawlerError::Config(_))
+        ));
+    }
+}
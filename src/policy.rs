@@ -0,0 +1,91 @@
+//! Export and import of the crawl policies of domains.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CrawlerError;
+use crate::robots::{disallowed_patterns, replace_disallowed_patterns};
+
+/// The effective crawl policy of a domain.
+#[derive(Serialize, Deserialize)]
+pub struct DomainPolicy {
+    /// The name of the domain.
+    pub name: String,
+    /// Path prefixes that must not be crawled.
+    #[serde(default)]
+    pub disallowed: Vec<String>,
+    /// The minimum number of seconds between requests.
+    #[serde(default)]
+    pub crawl_delay: Option<f64>,
+    /// The number of consecutive 403/429 responses received.
+    #[serde(default)]
+    pub refused_count: u32,
+    /// The UTC time until which the domain is banned.
+    #[serde(default)]
+    pub banned_until: Option<String>,
+}
+
+/// Reads the policies of every known domain.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+///
+/// # Returns
+/// The policies, ordered by domain name.
+pub fn export_policies(connection: &Connection) -> Result<Vec<DomainPolicy>, CrawlerError> {
+    let mut stmt = connection.prepare(
+        "SELECT Id, Name, CrawlDelay, RefusedCount, BannedUntil FROM Domain ORDER BY Name",
+    )?;
+
+    let mut policies = Vec::new();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let domain_id: i64 = row.get(0)?;
+        let disallowed = disallowed_patterns(connection, domain_id)?;
+        policies.push(DomainPolicy {
+            name: row.get(1)?,
+            disallowed,
+            crawl_delay: row.get(2)?,
+            refused_count: row.get(3)?,
+            banned_until: row.get(4)?,
+        });
+    }
+    Ok(policies)
+}
+
+/// Replaces the policies of the given domains.
+///
+/// Imported domains are pinned, so their robots.txt rules are not refreshed by later crawls.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `policies` - The policies to import.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub fn import_policies(
+    connection: &mut Connection,
+    policies: &[DomainPolicy],
+) -> Result<(), CrawlerError> {
+    let transaction = connection.transaction()?;
+    for policy in policies {
+        transaction.execute(
+            "INSERT OR IGNORE INTO Domain (Name) VALUES (?)",
+            [&policy.name],
+        )?;
+        let domain_id: i64 = transaction.query_row(
+            "UPDATE Domain SET CrawlDelay = ?, RefusedCount = ?, BannedUntil = ?, RobotsFetched = CURRENT_TIMESTAMP, Pinned = 1
+            WHERE Name = ? RETURNING Id",
+            params![
+                policy.crawl_delay,
+                policy.refused_count,
+                policy.banned_until,
+                policy.name
+            ],
+            |row| row.get(0),
+        )?;
+        replace_disallowed_patterns(&transaction, domain_id, &policy.disallowed)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! A summary of a single stored page, for spot checks in the terminal.
+
+use rusqlite::{Connection, OptionalExtension, ToSql};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::error::CrawlerError;
+
+/// The metadata of a stored page, and the start of its text.
+#[derive(Serialize)]
+pub struct PagePreview {
+    /// The id of the page.
+    pub id: i64,
+    /// The final URL of the page.
+    pub url: String,
+    /// The URL that was requested, before redirects.
+    pub requested_url: String,
+    /// The status of the last fetch of the page.
+    pub status: Option<u16>,
+    /// The content hash of the page.
+    pub hash: String,
+    /// The URL of the page this page duplicates, if any.
+    pub duplicate_of: Option<String>,
+    /// The title of the page.
+    pub title: Option<String>,
+    /// The meta description of the page.
+    pub description: Option<String>,
+    /// The canonical URL the page declares.
+    pub canonical: Option<String>,
+    /// The number of words of the page.
+    pub word_count: Option<i64>,
+    /// The estimated reading time of the page, in seconds.
+    pub reading_time: Option<i64>,
+    /// The tags of the page.
+    pub tags: Vec<String>,
+    /// When the contents of the page last changed.
+    pub updated: String,
+    /// The first lines of the plain text of the page, or None if its contents were not saved.
+    pub text: Option<Vec<String>>,
+    /// The number of lines of the plain text of the page.
+    pub text_lines: usize,
+}
+
+impl PagePreview {
+    /// Loads the preview of a page.
+    ///
+    /// The text of a duplicate page is taken from the page it duplicates.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `url` - The final or requested URL of the page.
+    /// * `lines` - The number of lines of text to include.
+    ///
+    /// # Returns
+    /// The preview, or None if no page has the URL.
+    pub fn load(
+        connection: &Connection,
+        url: &str,
+        lines: usize,
+    ) -> Result<Option<Self>, CrawlerError> {
+        Self::load_where(
+            connection,
+            "Page.Url = ?1 OR Page.RequestedUrl = ?1 ORDER BY Page.Url = ?1 DESC",
+            &url,
+            lines,
+        )
+    }
+
+    /// Loads the preview of a page by its id.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `id` - The id of the page.
+    /// * `lines` - The number of lines of text to include.
+    ///
+    /// # Returns
+    /// The preview, or None if no page has the id.
+    pub fn load_id(
+        connection: &Connection,
+        id: i64,
+        lines: usize,
+    ) -> Result<Option<Self>, CrawlerError> {
+        Self::load_where(connection, "Page.Id = ?1", &id, lines)
+    }
+
+    /// Loads the preview of the first page matching a condition on the `Page` table.
+    fn load_where(
+        connection: &Connection,
+        condition: &str,
+        param: &dyn ToSql,
+        lines: usize,
+    ) -> Result<Option<Self>, CrawlerError> {
+        let page = connection
+            .query_row(
+                &format!(
+                    "SELECT Page.Id, Page.Url, Page.RequestedUrl, Page.Hash, Original.Url, PageMeta.Title,
+                    PageMeta.Description, PageMeta.Canonical, Page.WordCount, Page.ReadingTime, Page.Updated,
+                    (SELECT Status FROM PageFetch WHERE PageId = Page.Id ORDER BY Id DESC LIMIT 1),
+                    (SELECT Text FROM PageText WHERE PageId = COALESCE(Page.DuplicateOf, Page.Id))
+                FROM Page
+                    LEFT JOIN Page AS Original ON Original.Id = Page.DuplicateOf
+                    LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+                WHERE {}
+                LIMIT 1",
+                    condition
+                ),
+                [param],
+                |row| {
+                    let text: Option<String> = row.get(12)?;
+                    let preview = PagePreview {
+                        id: row.get(0)?,
+                        url: row.get(1)?,
+                        requested_url: row.get(2)?,
+                        status: row.get(11)?,
+                        hash: row.get(3)?,
+                        duplicate_of: row.get(4)?,
+                        title: row.get(5)?,
+                        description: row.get(6)?,
+                        canonical: row.get(7)?,
+                        word_count: row.get(8)?,
+                        reading_time: row.get(9)?,
+                        tags: Vec::new(),
+                        updated: row.get(10)?,
+                        text_lines: text.as_deref().map_or(0, |text| text.lines().count()),
+                        text: text.map(|text| text.lines().take(lines).map(String::from).collect()),
+                    };
+                    Ok(preview)
+                },
+            )
+            .optional()?;
+        let Some(mut preview) = page else {
+            return Ok(None);
+        };
+        let mut stmt =
+            connection.prepare("SELECT Tag FROM PageTag WHERE PageId = ? ORDER BY Tag")?;
+        preview.tags = stmt
+            .query_map([preview.id], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(preview))
+    }
+
+    /// Writes the metadata of the page, one field per line, followed by its text if given.
+    ///
+    /// # Arguments
+    /// * `writer` - Where the preview is written.
+    /// * `with_text` - Whether the start of the text is written.
+    pub fn write(&self, writer: &mut impl Write, with_text: bool) -> Result<(), CrawlerError> {
+        writeln!(writer, "URL:         {}", self.url)?;
+        if self.requested_url != self.url {
+            writeln!(writer, "Requested:   {}", self.requested_url)?;
+        }
+        if let Some(status) = self.status {
+            writeln!(writer, "Status:      {}", status)?;
+        }
+        writeln!(
+            writer,
+            "Title:       {}",
+            self.title.as_deref().unwrap_or("-")
+        )?;
+        if let Some(description) = &self.description {
+            writeln!(writer, "Description: {}", description)?;
+        }
+        if let Some(canonical) = &self.canonical {
+            writeln!(writer, "Canonical:   {}", canonical)?;
+        }
+        writeln!(writer, "Hash:        {}", self.hash)?;
+        if let Some(original) = &self.duplicate_of {
+            writeln!(writer, "Duplicates:  {}", original)?;
+        }
+        if let Some(words) = self.word_count {
+            writeln!(
+                writer,
+                "Words:       {} ({} s to read)",
+                words,
+                self.reading_time.unwrap_or(0)
+            )?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(writer, "Tags:        {}", self.tags.join(", "))?;
+        }
+        writeln!(writer, "Updated:     {}", self.updated)?;
+
+        if !with_text {
+            return Ok(());
+        }
+        writeln!(writer)?;
+        match &self.text {
+            Some(lines) => {
+                for line in lines {
+                    writeln!(writer, "  {}", line)?;
+                }
+                if self.text_lines > lines.len() {
+                    writeln!(writer, "  ... {} more lines", self.text_lines - lines.len())?;
+                }
+            }
+            None => writeln!(writer, "  (the text of this page was not saved)")?,
+        }
+        Ok(())
+    }
+}
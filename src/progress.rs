@@ -0,0 +1,163 @@
+//! A live display of crawl progress in the terminal, updated from crawl events.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+
+use crate::events::CrawlEvent;
+use crate::normalize::display_host;
+
+/// The number of domains shown in the per-domain activity line.
+const SHOWN_DOMAINS: usize = 5;
+
+/// Counts shown by the display, besides the number of fetches kept by the main bar.
+#[derive(Default)]
+struct ProgressCounts {
+    queued: usize,
+    skipped: u64,
+    errors: u64,
+    /// The number of fetches per domain, and the fetch each domain was last seen at.
+    domains: HashMap<String, (u64, u64)>,
+}
+
+/// Shows the number of pages fetched per second, the frontier size, errors and the most active domains, below the log
+/// output.
+///
+/// Log lines must be printed through [`ProgressDisplay::log_writer`], so they do not break the display.
+pub struct ProgressDisplay {
+    multi: MultiProgress,
+    fetches: ProgressBar,
+    domains: ProgressBar,
+    current: ProgressBar,
+    counts: Mutex<ProgressCounts>,
+}
+
+impl Default for ProgressDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressDisplay {
+    /// Creates a new `ProgressDisplay`, drawn on standard error.
+    pub fn new() -> Self {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let fetches = multi.add(
+            ProgressBar::new_spinner().with_style(
+                ProgressStyle::with_template(
+                    "{spinner} [{elapsed_precise}] {pos} fetched ({per_sec}), {msg}",
+                )
+                .unwrap(),
+            ),
+        );
+        fetches.set_message("0 queued, 0 skipped, 0 errors");
+        fetches.enable_steady_tick(Duration::from_millis(200));
+        let line = ProgressStyle::with_template("  {wide_msg}").unwrap();
+        let domains = multi.add(ProgressBar::new_spinner().with_style(line.clone()));
+        let current = multi.add(ProgressBar::new_spinner().with_style(line));
+        ProgressDisplay {
+            multi,
+            fetches,
+            domains,
+            current,
+            counts: Mutex::new(ProgressCounts::default()),
+        }
+    }
+
+    /// Returns a writer that prints above the display.
+    pub fn log_writer(&self) -> ProgressWriter {
+        ProgressWriter {
+            multi: self.multi.clone(),
+        }
+    }
+
+    /// Updates the display with an event.
+    ///
+    /// # Arguments
+    /// * `event` - The event.
+    pub(crate) fn record(&self, event: &CrawlEvent) {
+        let mut counts = self.counts.lock().unwrap();
+        match event {
+            CrawlEvent::Fetch { url, error, .. } => {
+                self.fetches.inc(1);
+                if error.is_some() {
+                    counts.errors += 1;
+                }
+                if let Some(host) = Url::parse(url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(str::to_owned))
+                {
+                    let seen = self.fetches.position();
+                    let entry = counts.domains.entry(host).or_default();
+                    entry.0 += 1;
+                    entry.1 = seen;
+                }
+                self.current.set_message(format!("last: {}", url));
+                self.domains.set_message(domain_activity(&counts.domains));
+            }
+            CrawlEvent::Skip { .. } => counts.skipped += 1,
+            CrawlEvent::Error { .. } => counts.errors += 1,
+        }
+        self.update_message(&counts);
+    }
+
+    /// Sets the number of URLs waiting in the frontier.
+    ///
+    /// # Arguments
+    /// * `queued` - The number of URLs, including spilled ones.
+    pub(crate) fn set_queued(&self, queued: usize) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.queued = queued;
+        self.update_message(&counts);
+    }
+
+    /// Stops the display, leaving its last state on screen.
+    pub(crate) fn finish(&self) {
+        self.fetches.finish();
+        self.domains.finish();
+        self.current.finish_and_clear();
+    }
+
+    fn update_message(&self, counts: &ProgressCounts) {
+        self.fetches.set_message(format!(
+            "{} queued, {} skipped, {} errors",
+            counts.queued, counts.skipped, counts.errors
+        ));
+    }
+}
+
+/// Writes to standard error above a [`ProgressDisplay`], hiding the display while writing.
+#[derive(Clone)]
+pub struct ProgressWriter {
+    multi: MultiProgress,
+}
+
+impl Write for ProgressWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.multi.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Lists the domains fetched from most recently, with their number of fetches.
+fn domain_activity(domains: &HashMap<String, (u64, u64)>) -> String {
+    let mut recent: Vec<_> = domains.iter().collect();
+    recent.sort_by_key(|(_, (_, seen))| Reverse(*seen));
+    let shown: Vec<String> = recent
+        .iter()
+        .take(SHOWN_DOMAINS)
+        .map(|(host, (fetches, _))| format!("{} {}", display_host(host), fetches))
+        .collect();
+    let mut line = format!("{} domains: {}", domains.len(), shown.join(", "));
+    if domains.len() > SHOWN_DOMAINS {
+        line.push_str(", ...");
+    }
+    line
+}
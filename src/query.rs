@@ -0,0 +1,444 @@
+//! Canned reports on the crawl database, and read-only SQL queries, rendered as tables or JSON.
+
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::types::ValueRef;
+use rusqlite::{params_from_iter, Batch, Connection, ToSql};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+use crate::error::CrawlerError;
+use crate::links::LinkElement;
+
+/// A built-in report of the `query` subcommand.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Report {
+    /// The distinct pages with the largest bodies.
+    LargestPages,
+    /// The URLs linked to from the most pages.
+    MostLinked,
+    /// The URLs that could not be crawled, by domain and class of failure.
+    ErrorsByDomain,
+    /// The stored pages that no other stored page links to.
+    OrphanPages,
+    /// The pages stored with the same contents as other pages.
+    DuplicateContent,
+}
+
+impl Report {
+    /// Every report.
+    pub const ALL: [Report; 5] = [
+        Report::LargestPages,
+        Report::MostLinked,
+        Report::ErrorsByDomain,
+        Report::OrphanPages,
+        Report::DuplicateContent,
+    ];
+
+    /// Returns the name of the report, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Report::LargestPages => "largest-pages",
+            Report::MostLinked => "most-linked",
+            Report::ErrorsByDomain => "errors-by-domain",
+            Report::OrphanPages => "orphan-pages",
+            Report::DuplicateContent => "duplicate-content",
+        }
+    }
+
+    /// Finds a report by its name.
+    ///
+    /// # Returns
+    /// The report, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|report| report.name() == name)
+    }
+
+    /// Returns the query of the report, which takes the largest number of rows as its parameter.
+    fn sql(self) -> String {
+        let sql = match self {
+            Report::LargestPages => {
+                "SELECT Page.Url AS url, PageFetch.ContentLength AS bytes, PageMeta.Title AS title
+                FROM Page
+                JOIN PageFetch ON PageFetch.Id = (
+                    SELECT MAX(Id) FROM PageFetch WHERE PageId = Page.Id AND ContentLength IS NOT NULL
+                )
+                LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+                WHERE Page.DuplicateOf IS NULL
+                ORDER BY bytes DESC, url LIMIT ?1"
+            }
+            Report::MostLinked => {
+                return format!(
+                    "SELECT PageLink.Url AS url, COUNT(*) AS linking_pages,
+                        EXISTS (SELECT 1 FROM Page WHERE Page.Url = PageLink.Url) AS crawled
+                    FROM PageLink JOIN Page AS Source ON Source.Id = PageLink.PageId
+                    WHERE {} AND PageLink.Url != Source.Url
+                    GROUP BY PageLink.Url
+                    ORDER BY linking_pages DESC, url LIMIT ?1",
+                    LinkElement::navigable_sql("PageLink.Element")
+                );
+            }
+            Report::ErrorsByDomain => {
+                "SELECT COALESCE(Domain.Name, '') AS domain, FailedUrl.Class AS class, COUNT(*) AS urls,
+                    SUM(FailedUrl.Attempts) AS attempts
+                FROM FailedUrl LEFT JOIN Domain ON Domain.Id = FailedUrl.DomainId
+                GROUP BY domain, class
+                ORDER BY urls DESC, domain, class LIMIT ?1"
+            }
+            Report::OrphanPages => {
+                "SELECT Page.Url AS url, PageMeta.Title AS title
+                FROM Page LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+                WHERE Page.DuplicateOf IS NULL AND NOT EXISTS (
+                    SELECT 1 FROM PageLink
+                    WHERE PageLink.PageId != Page.Id AND PageLink.Url IN (
+                        SELECT Page.Url UNION SELECT Page.RequestedUrl
+                        UNION SELECT SourceUrl FROM Redirect WHERE TargetUrl = Page.Url
+                    )
+                )
+                ORDER BY url LIMIT ?1"
+            }
+            Report::DuplicateContent => {
+                "SELECT Original.Url AS url, COUNT(*) AS duplicates,
+                    group_concat(Duplicate.Url, ' ') AS duplicate_urls
+                FROM Page AS Duplicate JOIN Page AS Original ON Original.Id = Duplicate.DuplicateOf
+                GROUP BY Original.Id
+                ORDER BY duplicates DESC, url LIMIT ?1"
+            }
+        };
+        sql.to_string()
+    }
+}
+
+/// The rows returned by a query, with the names of its columns.
+pub struct QueryResult {
+    /// The names of the columns.
+    pub columns: Vec<String>,
+    /// The values of each row, in the order of the columns. BLOBs are hex encoded.
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    /// Runs a built-in report.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `report` - The report.
+    /// * `limit` - The largest number of rows.
+    ///
+    /// # Returns
+    /// The rows of the report.
+    pub fn report(
+        connection: &Connection,
+        report: Report,
+        limit: usize,
+    ) -> Result<Self, CrawlerError> {
+        Self::run(connection, &report.sql(), &[&(limit as i64)])
+    }
+
+    /// Runs a SQL statement that does not write to the database.
+    ///
+    /// The connection should be opened read-only as well, as statements such as `PRAGMA` can change settings.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `sql` - A single SQL statement.
+    ///
+    /// # Returns
+    /// The rows returned, or an error if the statement is invalid or writes to the database.
+    pub fn sql(connection: &Connection, sql: &str) -> Result<Self, CrawlerError> {
+        Self::run(connection, sql, &[])
+    }
+
+    /// Runs a read-only statement and collects its rows.
+    fn run(
+        connection: &Connection,
+        sql: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Self, CrawlerError> {
+        let mut batch = Batch::new(connection, sql);
+        let Some(mut stmt) = batch.next()? else {
+            return Err(CrawlerError::Config("No SQL statement was given".into()));
+        };
+        if batch.next()?.is_some() {
+            return Err(CrawlerError::Config(
+                "Only one SQL statement can be run at a time".into(),
+            ));
+        }
+        if !stmt.readonly() {
+            return Err(CrawlerError::Config(
+                "Only statements that do not write to the database can be run".into(),
+            ));
+        }
+        let columns = stmt
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let mut rows = Vec::new();
+        let mut results = stmt.query(params_from_iter(params))?;
+        while let Some(row) = results.next()? {
+            let values = (0..columns.len())
+                .map(|index| Ok(json_value(row.get_ref(index)?)))
+                .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+            rows.push(values);
+        }
+        Ok(QueryResult { columns, rows })
+    }
+
+    /// Returns the rows as JSON objects keyed by column name.
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.rows
+                .iter()
+                .map(|row| {
+                    Value::Object(
+                        self.columns
+                            .iter()
+                            .cloned()
+                            .zip(row.iter().cloned())
+                            .collect::<Map<_, _>>(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Writes the rows as a table with a header, one line per row, NULL values left empty.
+    ///
+    /// # Arguments
+    /// * `writer` - Where to write the table.
+    ///
+    /// # Returns
+    /// A Result indicating success or failure.
+    pub fn write_table(&self, writer: &mut impl Write) -> Result<(), CrawlerError> {
+        let cells = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(cell_text).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let widths = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                cells
+                    .iter()
+                    .map(|row| row[index].chars().count())
+                    .chain([column.chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let line = |values: &[String]| {
+            values
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:<width$}", value, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        writeln!(writer, "{}", line(&self.columns))?;
+        let rule = widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", line(&rule))?;
+        for row in &cells {
+            writeln!(writer, "{}", line(row))?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a value read from the database into JSON.
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(integer) => Value::from(integer),
+        ValueRef::Real(real) => Value::from(real),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => Value::from(hex::encode(blob)),
+    }
+}
+
+/// Returns the text of a value in a table.
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Creates a crawl database in memory with a few pages, links, fetches and failures.
+    fn database() -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO CrawlRun DEFAULT VALUES;
+                INSERT INTO Domain (Id, Name) VALUES (1, 'example.com');
+                INSERT INTO Page (Id, Url, RequestedUrl, Hash, DuplicateOf) VALUES
+                    (1, 'https://example.com/', 'https://example.com/', 'a', NULL),
+                    (2, 'https://example.com/a', 'https://example.com/a', 'b', NULL),
+                    (3, 'https://example.com/b', 'https://example.com/b', 'c', NULL),
+                    (4, 'https://example.com/b-copy', 'https://example.com/b-copy', 'c', 3),
+                    (5, 'https://example.com/new', 'https://example.com/new', 'd', NULL),
+                    (6, 'https://example.com/lonely', 'https://example.com/lonely', 'e', NULL),
+                    (7, 'https://example.com/b-print', 'https://example.com/b-print', 'c', 3);
+                INSERT INTO PageMeta (PageId, Title, OpenGraph) VALUES (1, 'Home', '{}'), (2, 'A', '{}');
+                INSERT INTO PageLink (PageId, Url, Element) VALUES
+                    (1, 'https://example.com/', 'a'),
+                    (1, 'https://example.com/a', 'a'),
+                    (1, 'https://example.com/b', 'a'),
+                    (1, 'https://example.com/old', 'a'),
+                    (1, 'https://example.com/style.css', 'link'),
+                    (2, 'https://example.com/', 'a'),
+                    (2, 'https://example.com/b', 'a'),
+                    (3, 'https://other.example/', 'a'),
+                    (6, 'https://example.com/lonely', 'a');
+                INSERT INTO Redirect (SourceUrl, TargetUrl) VALUES ('https://example.com/old', 'https://example.com/new');
+                INSERT INTO PageFetch (RunId, PageId, Url, FinalUrl, Status, ContentLength, DurationMs, Headers) VALUES
+                    (1, 1, 'https://example.com/', 'https://example.com/', 200, 50, 1, '{}'),
+                    (1, 1, 'https://example.com/', 'https://example.com/', 200, 100, 1, '{}'),
+                    (1, 2, 'https://example.com/a', 'https://example.com/a', 200, 300, 1, '{}'),
+                    (1, 3, 'https://example.com/b', 'https://example.com/b', 200, NULL, 1, '{}'),
+                    (1, 4, 'https://example.com/b-copy', 'https://example.com/b-copy', 200, 900, 1, '{}');
+                INSERT INTO FailedUrl (RunId, DomainId, Url, Class, Error, Attempts) VALUES
+                    (1, 1, 'https://example.com/slow', 'timeout', 'timed out', 2),
+                    (1, 1, 'https://example.com/slower', 'timeout', 'timed out', 1),
+                    (1, 1, 'https://example.com/broken', 'server_error', '500', 1),
+                    (1, NULL, 'https://gone.example/', 'dns', 'no such host', 3);",
+            )
+            .unwrap();
+        connection
+    }
+
+    fn report(connection: &Connection, report: Report, limit: usize) -> Value {
+        QueryResult::report(connection, report, limit)
+            .unwrap()
+            .to_json()
+    }
+
+    #[test]
+    fn lists_largest_pages() {
+        // The latest fetch with a length counts, and duplicates are left out
+        assert_eq!(
+            report(&database(), Report::LargestPages, 10),
+            json!([
+                {"url": "https://example.com/a", "bytes": 300, "title": "A"},
+                {"url": "https://example.com/", "bytes": 100, "title": "Home"},
+            ])
+        );
+    }
+
+    #[test]
+    fn lists_most_linked_urls() {
+        // Links from pages to themselves and to assets are not counted
+        assert_eq!(
+            report(&database(), Report::MostLinked, 3),
+            json!([
+                {"url": "https://example.com/b", "linking_pages": 2, "crawled": 1},
+                {"url": "https://example.com/", "linking_pages": 1, "crawled": 1},
+                {"url": "https://example.com/a", "linking_pages": 1, "crawled": 1},
+            ])
+        );
+        let urls = report(&database(), Report::MostLinked, 10);
+        assert_eq!(urls.as_array().unwrap().len(), 5);
+        assert_eq!(
+            urls[4],
+            json!({"url": "https://other.example/", "linking_pages": 1, "crawled": 0})
+        );
+    }
+
+    #[test]
+    fn counts_errors_by_domain() {
+        assert_eq!(
+            report(&database(), Report::ErrorsByDomain, 10),
+            json!([
+                {"domain": "example.com", "class": "timeout", "urls": 2, "attempts": 3},
+                {"domain": "", "class": "dns", "urls": 1, "attempts": 3},
+                {"domain": "example.com", "class": "server_error", "urls": 1, "attempts": 1},
+            ])
+        );
+    }
+
+    #[test]
+    fn lists_orphan_pages() {
+        // A page linked to through a redirect is not an orphan, and neither is one that links to itself only
+        assert_eq!(
+            report(&database(), Report::OrphanPages, 10),
+            json!([{"url": "https://example.com/lonely", "title": null}])
+        );
+    }
+
+    #[test]
+    fn lists_duplicate_content() {
+        let rows = report(&database(), Report::DuplicateContent, 10);
+        assert_eq!(rows.as_array().unwrap().len(), 1);
+        assert_eq!(rows[0]["url"], "https://example.com/b");
+        assert_eq!(rows[0]["duplicates"], 2);
+        let mut duplicates = rows[0]["duplicate_urls"]
+            .as_str()
+            .unwrap()
+            .split(' ')
+            .collect::<Vec<_>>();
+        duplicates.sort();
+        assert_eq!(
+            duplicates,
+            ["https://example.com/b-copy", "https://example.com/b-print"]
+        );
+    }
+
+    #[test]
+    fn runs_read_only_statements() {
+        let connection = database();
+        let result = QueryResult::sql(
+            &connection,
+            "SELECT Name, Id, NULL AS Missing, x'0aff' AS Bytes FROM Domain",
+        )
+        .unwrap();
+        assert_eq!(result.columns, ["Name", "Id", "Missing", "Bytes"]);
+        assert_eq!(
+            result.to_json(),
+            json!([{"Name": "example.com", "Id": 1, "Missing": null, "Bytes": "0aff"}])
+        );
+        let mut table = Vec::new();
+        result.write_table(&mut table).unwrap();
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "Name         Id  Missing  Bytes\n-----------  --  -------  -----\nexample.com  1            0aff\n"
+        );
+    }
+
+    #[test]
+    fn refuses_statements_that_write() {
+        let connection = database();
+        for sql in [
+            "DELETE FROM Page",
+            "UPDATE Page SET Hash = 'x'",
+            "DROP TABLE Page",
+            "SELECT 1; DELETE FROM Page",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    QueryResult::sql(&connection, sql),
+                    Err(CrawlerError::Config(_))
+                ),
+                "{}",
+                sql
+            );
+        }
+        let pages: i64 = connection
+            .query_row("SELECT COUNT(*) FROM Page", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(pages, 7);
+    }
+}
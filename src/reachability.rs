@@ -0,0 +1,45 @@
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use url::Url;
+
+use crate::dns::Resolver;
+
+/// How long resolving and connecting to a host may take before it is considered dead.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks cheaply whether the host of a URL can be reached, by resolving it and opening a TCP connection.
+///
+/// # Arguments
+/// * `url` - A URL of the host.
+/// * `limit` - The time allowed for each of the lookup and the connection.
+/// * `resolver` - The resolver the host is looked up with.
+///
+/// # Returns
+/// None if the host accepted a connection, otherwise a description of the failure.
+pub async fn check_host(url: &Url, limit: Duration, resolver: &Resolver) -> Option<String> {
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return Some("URL has no host".to_string()),
+    };
+    // Hosts of schemes without a known port are not checked
+    let port = url.port_or_known_default()?;
+    let addresses = match timeout(limit, resolver.lookup(host, port)).await {
+        Ok(Ok(addresses)) => addresses,
+        Ok(Err(e)) => return Some(format!("DNS lookup failed: {}", e)),
+        Err(_) => return Some("DNS lookup timed out".to_string()),
+    };
+    if addresses.is_empty() {
+        return Some("DNS lookup returned no addresses".to_string());
+    }
+
+    let mut error = None;
+    for address in addresses {
+        match timeout(limit, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => return None,
+            Ok(Err(e)) => error = Some(format!("Connection to {} failed: {}", address, e)),
+            Err(_) => error = Some(format!("Connection to {} timed out", address)),
+        }
+    }
+    error
+}
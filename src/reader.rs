@@ -0,0 +1,315 @@
+//! Reading stored page bodies back, whichever way they were saved.
+
+use flate2::read::GzDecoder;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::crawler::CrawlPaths;
+use crate::digest;
+use crate::error::CrawlerError;
+use crate::object_store::BodyStore;
+use crate::storage::{decode_object, read_page, read_page_blob, read_page_file};
+
+/// A stored page body, read as a stream.
+pub type PageBody = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Where the body of a page was saved.
+enum BodyLocation {
+    /// A `<hash>.html` file in the pages directory, possibly compressed.
+    Hashed(String),
+    /// A file chosen by a storage route.
+    File(PathBuf),
+    /// An object in the object store, at its recorded location.
+    Object(String),
+    /// A line of a JSONL file chosen by a storage route.
+    Jsonl { file: PathBuf, hash: String },
+    /// A response record in a WARC file in the pages directory.
+    Warc { file: String, offset: u64 },
+    /// A row of the `PageBody` table, already read and decompressed.
+    Blob(Vec<u8>),
+}
+
+/// Opens the stored bodies of pages by their URL or hash, without callers knowing the storage format, routes or
+/// compression the pages were saved with.
+///
+/// Bodies of duplicate pages are read from the page they duplicate. Pages whose contents were not saved, such as
+/// pages marked `noindex` or crawled with `--no-store`, give an `Io` error of kind `NotFound`.
+pub struct BodyReader {
+    connection: Mutex<Connection>,
+    save_dir: PathBuf,
+    body_store: Option<Arc<dyn BodyStore>>,
+}
+
+impl BodyReader {
+    /// Opens the database of a crawl for reading.
+    ///
+    /// # Arguments
+    /// * `paths` - The paths of the crawl.
+    pub fn open(paths: &CrawlPaths) -> Result<Self, CrawlerError> {
+        let connection =
+            Connection::open_with_flags(&paths.database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(BodyReader::new(connection, &paths.save_dir))
+    }
+
+    /// Creates a new `BodyReader` over an open database.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    /// * `save_dir` - The directory pages are saved in.
+    pub fn new(connection: Connection, save_dir: impl Into<PathBuf>) -> Self {
+        BodyReader {
+            connection: Mutex::new(connection),
+            save_dir: save_dir.into(),
+            body_store: None,
+        }
+    }
+
+    /// Sets the object store that bodies saved as objects are read from.
+    ///
+    /// # Arguments
+    /// * `body_store` - The object store, or None if no bodies were saved as objects.
+    pub fn with_body_store(mut self, body_store: Option<Arc<dyn BodyStore>>) -> Self {
+        self.body_store = body_store;
+        self
+    }
+
+    /// Opens the stored body of the page at a URL.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    ///
+    /// # Returns
+    /// The body, or an error if the page is unknown or its body was not saved.
+    pub async fn open_url(&self, url: &str) -> Result<PageBody, CrawlerError> {
+        let location = self.locate("Page.Url = ?", url)?;
+        self.open_location(location).await
+    }
+
+    /// Reads the stored body of the page at a URL into memory.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    ///
+    /// # Returns
+    /// The body, or an error if the page is unknown or its body was not saved or cannot be read.
+    pub async fn read_url(&self, url: &str) -> Result<Vec<u8>, CrawlerError> {
+        let mut body = Vec::new();
+        self.open_url(url).await?.read_to_end(&mut body).await?;
+        Ok(body)
+    }
+
+    /// Opens a stored body by its hash.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of the body, in full or as a short id.
+    ///
+    /// # Returns
+    /// The body, or an error if no page has the hash or its body was not saved.
+    pub async fn open_hash(&self, hash: &str) -> Result<PageBody, CrawlerError> {
+        let hash = digest::resolve_short_id(&self.connection.lock().unwrap(), hash)?;
+        let location = self.locate("Page.Hash = ? AND Page.DuplicateOf IS NULL", &hash)?;
+        self.open_location(location).await
+    }
+
+    /// Opens the body of a version of a page recorded in `PageRevision`.
+    ///
+    /// # Arguments
+    /// * `revision_id` - The id of the revision.
+    ///
+    /// # Returns
+    /// The body, or an error if the revision is unknown or its body was not saved.
+    pub async fn open_revision(&self, revision_id: i64) -> Result<PageBody, CrawlerError> {
+        let location = {
+            let connection = self.connection.lock().unwrap();
+            let revision = connection
+                .query_row(
+                    "SELECT Hash, StoredFile, WarcFile, WarcOffset FROM PageRevision WHERE Id = ?",
+                    [revision_id],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                            row.get::<_, Option<u64>>(3)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((hash, stored_file, warc_file, warc_offset)) = revision else {
+                return Err(not_found(format!("No revision has the id {}", revision_id)));
+            };
+            body_location(&connection, hash, stored_file, warc_file, warc_offset)?
+        };
+        self.open_location(location).await
+    }
+
+    /// Finds where the body of the first page matching a condition was saved.
+    fn locate(&self, condition: &str, value: &str) -> Result<BodyLocation, CrawlerError> {
+        let connection = self.connection.lock().unwrap();
+        let page = connection
+            .query_row(
+                &format!(
+                    "SELECT COALESCE(Original.Hash, Page.Hash), COALESCE(Original.Indexed, Page.Indexed),
+                        COALESCE(Original.StoredFile, Page.StoredFile),
+                        CASE WHEN Original.Id IS NULL THEN Page.WarcFile ELSE Original.WarcFile END,
+                        CASE WHEN Original.Id IS NULL THEN Page.WarcOffset ELSE Original.WarcOffset END
+                    FROM Page LEFT JOIN Page AS Original ON Original.Id = Page.DuplicateOf
+                    WHERE {}
+                    ORDER BY Page.Id LIMIT 1",
+                    condition
+                ),
+                [value],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<u64>>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((hash, indexed, stored_file, warc_file, warc_offset)) = page else {
+            return Err(not_found(format!("No stored page matches {}", value)));
+        };
+        if !indexed {
+            return Err(not_found(format!(
+                "The contents of page {} were not saved",
+                value
+            )));
+        }
+        body_location(&connection, hash, stored_file, warc_file, warc_offset)
+    }
+
+    /// Opens a body at its location. Uncompressed files are streamed, and other bodies are decoded first.
+    async fn open_location(&self, location: BodyLocation) -> Result<PageBody, CrawlerError> {
+        if let BodyLocation::Object(location) = location {
+            let Some(body_store) = &self.body_store else {
+                return Err(CrawlerError::Config(format!(
+                    "The body {} is in object storage, which is not configured",
+                    location
+                )));
+            };
+            let contents = body_store.get(&location).await?;
+            let body = tokio::task::spawn_blocking(move || decode_object(&location, contents))
+                .await
+                .map_err(io::Error::other)??;
+            return Ok(Box::new(Cursor::new(body)));
+        }
+        if let BodyLocation::File(path) = &location {
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if !matches!(extension, Some("gz" | "zst")) {
+                return Ok(Box::new(tokio::fs::File::open(path).await?));
+            }
+        }
+        let save_dir = self.save_dir.clone();
+        let body = tokio::task::spawn_blocking(move || match location {
+            BodyLocation::Hashed(hash) => read_page(&save_dir, &hash),
+            BodyLocation::File(path) => read_page_file(&path),
+            BodyLocation::Jsonl { file, hash } => read_jsonl_body(&file, &hash),
+            BodyLocation::Warc { file, offset } => read_warc_body(&save_dir.join(file), offset),
+            BodyLocation::Blob(body) => Ok(body),
+            // Objects are read from the object store above
+            BodyLocation::Object(_) => unreachable!(),
+        })
+        .await
+        .map_err(io::Error::other)??;
+        Ok(Box::new(Cursor::new(body)))
+    }
+}
+
+/// Tells where a body was saved from the columns recorded with its page or revision.
+fn body_location(
+    connection: &Connection,
+    hash: String,
+    stored_file: Option<String>,
+    warc_file: Option<String>,
+    warc_offset: Option<u64>,
+) -> Result<BodyLocation, CrawlerError> {
+    Ok(match (stored_file, warc_file, warc_offset) {
+        (Some(file), _, _) if file.ends_with(".jsonl") => BodyLocation::Jsonl {
+            file: file.into(),
+            hash,
+        },
+        (Some(file), _, _) if file.contains("://") => BodyLocation::Object(file),
+        (Some(file), _, _) => BodyLocation::File(file.into()),
+        (None, Some(file), Some(offset)) => BodyLocation::Warc { file, offset },
+        _ => match read_page_blob(connection, &hash)? {
+            Some(body) => BodyLocation::Blob(body),
+            None => BodyLocation::Hashed(hash),
+        },
+    })
+}
+
+/// Returns a `NotFound` error.
+fn not_found(message: String) -> CrawlerError {
+    io::Error::new(io::ErrorKind::NotFound, message).into()
+}
+
+/// Reads the body of the last record with a hash from a JSONL file written by a storage route.
+fn read_jsonl_body(path: &Path, hash: &str) -> io::Result<Vec<u8>> {
+    let mut body = None;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let record: serde_json::Value = serde_json::from_str(&line?)?;
+        if record["hash"] == hash {
+            body = record["body"].as_str().map(|body| body.as_bytes().to_vec());
+        }
+    }
+    body.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No record of page {} in {}", hash, path.display()),
+        )
+    })
+}
+
+/// Reads the payload of the HTTP response held by the WARC record at an offset of a file.
+fn read_warc_body(path: &Path, offset: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    // Every record is a gzip member of its own
+    let mut record = BufReader::new(GzDecoder::new(file));
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if record.read_line(&mut line)? == 0 {
+            return Err(invalid_warc(path, offset));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<u64>().ok();
+            }
+        }
+    }
+    let mut block = Vec::new();
+    record
+        .take(content_length.ok_or_else(|| invalid_warc(path, offset))?)
+        .read_to_end(&mut block)?;
+    let head_end = block
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid_warc(path, offset))?;
+    Ok(block.split_off(head_end + 4))
+}
+
+/// Returns the error of a WARC record that cannot be read.
+fn invalid_warc(path: &Path, offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "No response record at offset {} of {}",
+            offset,
+            path.display()
+        ),
+    )
+}
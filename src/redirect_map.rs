@@ -0,0 +1,53 @@
+use std::collections::{HashMap, HashSet};
+
+/// A map of URLs to the URLs they redirect to.
+pub struct RedirectMap {
+    targets: HashMap<String, String>,
+}
+
+impl Default for RedirectMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RedirectMap {
+    /// Creates a new, empty `RedirectMap`.
+    pub fn new() -> Self {
+        RedirectMap {
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Records that a URL redirects to another.
+    ///
+    /// # Arguments
+    /// * `source` - The requested URL.
+    /// * `target` - The URL the request was redirected to.
+    pub fn insert(&mut self, source: String, target: String) {
+        if source != target {
+            self.targets.insert(source, target);
+        }
+    }
+
+    /// Follows the recorded redirects of a URL to its final target.
+    ///
+    /// Redirect loops are cut at the first repeated URL.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to resolve.
+    ///
+    /// # Returns
+    /// The final target, or `url` itself if it is not known to redirect.
+    pub fn resolve<'a>(&'a self, url: &'a str) -> &'a str {
+        let mut seen = HashSet::new();
+        let mut current = url;
+        while let Some(target) = self.targets.get(current) {
+            if !seen.insert(current) {
+                break;
+            }
+            current = target;
+        }
+        current
+    }
+}
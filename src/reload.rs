@@ -0,0 +1,115 @@
+//! The settings of a running crawl that can be changed without restarting it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::crawler::BanPolicy;
+
+/// Rate limits, URL patterns and budgets, which a crawl can switch to between iterations with
+/// [`Crawler::apply_settings`](crate::Crawler::apply_settings).
+#[derive(Clone, Default, PartialEq)]
+pub struct LiveSettings {
+    /// The minimum time between requests to a domain.
+    pub crawl_delay: Option<Duration>,
+    /// The minimum time between requests to single domains, by domain name.
+    pub domain_crawl_delays: BTreeMap<String, Duration>,
+    /// When domains that keep refusing requests are banned.
+    pub ban_policy: Option<BanPolicy>,
+    /// The number of errors after which a domain is suspended for the rest of the run.
+    pub error_budget: Option<u32>,
+    /// Regular expressions that URLs must match to be crawled.
+    pub include: Vec<String>,
+    /// Regular expressions of URLs that are not crawled.
+    pub exclude: Vec<String>,
+    /// The number of stored pages after which the crawl stops.
+    pub max_pages: Option<usize>,
+    /// The maximum number of pages stored per domain.
+    pub max_pages_per_domain: Option<usize>,
+}
+
+impl LiveSettings {
+    /// Describes the settings that differ from another set of settings.
+    ///
+    /// # Arguments
+    /// * `new` - The settings changed to.
+    ///
+    /// # Returns
+    /// One line per changed setting, e.g. `crawl_delay: 1s -> 2s` or `error_budget: 50 -> unset`.
+    pub fn changes(&self, new: &LiveSettings) -> Vec<String> {
+        let mut changes = Vec::new();
+        describe(
+            &mut changes,
+            "crawl_delay",
+            &self.crawl_delay,
+            &new.crawl_delay,
+        );
+        let domains = self
+            .domain_crawl_delays
+            .keys()
+            .chain(new.domain_crawl_delays.keys())
+            .collect::<BTreeSet<_>>();
+        for domain in domains {
+            describe(
+                &mut changes,
+                &format!("domains.\"{}\".crawl_delay", domain),
+                &self.domain_crawl_delays.get(domain),
+                &new.domain_crawl_delays.get(domain),
+            );
+        }
+        describe(
+            &mut changes,
+            "ban_after",
+            &self.ban_policy.map(|policy| policy.threshold),
+            &new.ban_policy.map(|policy| policy.threshold),
+        );
+        describe(
+            &mut changes,
+            "ban_hours",
+            &self.ban_policy.map(|policy| policy.quarantine_hours),
+            &new.ban_policy.map(|policy| policy.quarantine_hours),
+        );
+        describe(
+            &mut changes,
+            "error_budget",
+            &self.error_budget,
+            &new.error_budget,
+        );
+        describe(
+            &mut changes,
+            "include",
+            &Some(&self.include),
+            &Some(&new.include),
+        );
+        describe(
+            &mut changes,
+            "exclude",
+            &Some(&self.exclude),
+            &Some(&new.exclude),
+        );
+        describe(&mut changes, "max_pages", &self.max_pages, &new.max_pages);
+        describe(
+            &mut changes,
+            "max_pages_per_domain",
+            &self.max_pages_per_domain,
+            &new.max_pages_per_domain,
+        );
+        changes
+    }
+}
+
+/// Adds the description of a setting to a list of changes if its value changed.
+fn describe<T: Debug + PartialEq>(
+    changes: &mut Vec<String>,
+    name: &str,
+    old: &Option<T>,
+    new: &Option<T>,
+) {
+    let value = |value: &Option<T>| match value {
+        Some(value) => format!("{:?}", value),
+        None => "unset".to_string(),
+    };
+    if old != new {
+        changes.push(format!("{}: {} -> {}", name, value(old), value(new)));
+    }
+}
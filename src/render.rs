@@ -0,0 +1,200 @@
+//! Rendering of JavaScript-heavy pages in a headless browser, so the DOM after scripts ran is stored.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::tls::TlsConfig;
+
+/// The time after which rendering a page is abandoned, unless configured otherwise.
+pub const DEFAULT_RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which pages are rendered in a headless browser.
+#[derive(Clone)]
+pub struct RenderPolicy {
+    /// Whether the pages of every domain without its own setting are rendered.
+    pub all: bool,
+    /// The settings of single domains, which take precedence.
+    pub domains: HashMap<String, bool>,
+    /// The Chromium executable, or None to look for one on the `PATH`.
+    pub executable: Option<PathBuf>,
+    /// The time after which rendering a page is abandoned.
+    pub timeout: Duration,
+}
+
+impl Default for RenderPolicy {
+    fn default() -> Self {
+        RenderPolicy {
+            all: false,
+            domains: HashMap::new(),
+            executable: None,
+            timeout: DEFAULT_RENDER_TIMEOUT,
+        }
+    }
+}
+
+impl RenderPolicy {
+    /// Returns whether the pages of a host are rendered.
+    ///
+    /// # Arguments
+    /// * `host` - The host name of a URL.
+    pub fn renders(&self, host: &str) -> bool {
+        self.domains.get(host).copied().unwrap_or(self.all)
+    }
+
+    /// Returns whether any pages are rendered.
+    pub fn is_enabled(&self) -> bool {
+        self.all || self.domains.values().any(|renders| *renders)
+    }
+}
+
+/// Renders pages in a headless Chromium browser, launched when the first page is rendered and shared by the crawl.
+///
+/// The browser loads pages without the credentials of the crawl, so the crawler does not render pages of origins
+/// that have one.
+pub struct Renderer {
+    policy: RenderPolicy,
+    #[cfg(feature = "headless")]
+    accept_invalid_certs: bool,
+    #[cfg(feature = "headless")]
+    browser: tokio::sync::OnceCell<chromiumoxide::Browser>,
+}
+
+impl Renderer {
+    /// Creates a new `Renderer`.
+    ///
+    /// # Arguments
+    /// * `policy` - Which pages are rendered, and how.
+    /// * `tls` - The TLS settings of the crawler. The browser accepts invalid certificates if the crawler does, but
+    ///   only trusts the CA certificates of the system, as it cannot be given CA files.
+    ///
+    /// # Returns
+    /// The renderer, or an error if the crawler was built without the `headless` feature.
+    pub fn new(policy: RenderPolicy, tls: &TlsConfig) -> Result<Self, CrawlerError> {
+        if cfg!(not(feature = "headless")) {
+            return Err(CrawlerError::Config(
+                "Rendering pages requires building the crawler with the headless feature"
+                    .to_string(),
+            ));
+        }
+        if !tls.ca_certs.is_empty() && !tls.accept_invalid_certs {
+            tracing::warn!(
+                "The browser does not trust the CA files given, so pages of sites with their certificates are not rendered"
+            );
+        }
+        Ok(Renderer {
+            policy,
+            #[cfg(feature = "headless")]
+            accept_invalid_certs: tls.accept_invalid_certs,
+            #[cfg(feature = "headless")]
+            browser: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    /// Returns whether a page is rendered.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    pub fn renders(&self, url: &Url) -> bool {
+        url.host_str().is_some_and(|host| self.policy.renders(host))
+    }
+
+    /// Loads a page in the browser and waits for it to finish loading.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    ///
+    /// # Returns
+    /// The HTML serialization of the DOM after the scripts of the page ran, or an error if the browser could not be
+    /// launched or the page could not be loaded in time.
+    #[cfg(feature = "headless")]
+    pub async fn render(&self, url: &Url) -> Result<String, CrawlerError> {
+        let failed = |e: chromiumoxide::error::CdpError| {
+            CrawlerError::Fetch(format!("Rendering {} failed: {}", url, e))
+        };
+        let browser = self.browser.get_or_try_init(|| self.launch()).await?;
+        let page = browser.new_page("about:blank").await.map_err(failed)?;
+        let content = tokio::time::timeout(self.policy.timeout, async {
+            page.goto(url.as_str()).await?;
+            page.wait_for_navigation().await?;
+            page.content().await
+        })
+        .await;
+        // The page is closed whether or not it loaded, so timed out pages do not keep running
+        let _ = page.close().await;
+        match content {
+            Ok(content) => content.map_err(failed),
+            Err(_) => Err(CrawlerError::Fetch(format!(
+                "Rendering {} timed out after {} seconds",
+                url,
+                self.policy.timeout.as_secs()
+            ))),
+        }
+    }
+
+    /// Loads a page in the browser. Without the `headless` feature a renderer cannot be created, so this is never
+    /// called.
+    #[cfg(not(feature = "headless"))]
+    pub async fn render(&self, url: &Url) -> Result<String, CrawlerError> {
+        Err(CrawlerError::Config(format!(
+            "Cannot render {} without the headless feature",
+            url
+        )))
+    }
+
+    /// Launches the browser, and drives its connection on a background task.
+    #[cfg(feature = "headless")]
+    async fn launch(&self) -> Result<chromiumoxide::Browser, CrawlerError> {
+        use futures::StreamExt;
+
+        let mut config = chromiumoxide::BrowserConfig::builder()
+            .request_timeout(self.policy.timeout)
+            .arg("--disable-gpu");
+        if self.accept_invalid_certs {
+            config = config.arg("--ignore-certificate-errors");
+        }
+        if let Some(executable) = &self.policy.executable {
+            config = config.chrome_executable(executable);
+        }
+        let config = config.build().map_err(CrawlerError::Config)?;
+        let (browser, mut handler) = chromiumoxide::Browser::launch(config)
+            .await
+            .map_err(|e| CrawlerError::Config(format!("Cannot launch the browser: {}", e)))?;
+        tokio::spawn(async move { while handler.next().await.is_some() {} });
+        tracing::info!("Launched a headless browser to render pages");
+        Ok(browser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_chosen_domains() {
+        let policy = RenderPolicy {
+            all: true,
+            domains: HashMap::from([("static.example.com".to_string(), false)]),
+            ..RenderPolicy::default()
+        };
+        let only_app = RenderPolicy {
+            domains: HashMap::from([("app.example.com".to_string(), true)]),
+            ..RenderPolicy::default()
+        };
+        let cases = [
+            (&policy, "example.com", true),
+            (&policy, "static.example.com", false),
+            (&only_app, "app.example.com", true),
+            (&only_app, "example.com", false),
+            (&RenderPolicy::default(), "example.com", false),
+        ];
+        for (policy, host, renders) in cases {
+            assert_eq!(policy.renders(host), renders, "{}", host);
+        }
+        assert!(policy.is_enabled());
+        assert!(only_app.is_enabled());
+        assert!(!RenderPolicy::default().is_enabled());
+    }
+}
@@ -0,0 +1,299 @@
+//! Classification of failed URLs, and queueing of those worth fetching again.
+
+use reqwest::StatusCode;
+use rusqlite::Connection;
+use std::time::{Duration, SystemTime};
+
+use crate::error::CrawlerError;
+use crate::store::CrawlStore;
+
+/// Why a URL could not be crawled, as recorded in the `Class` column of `FailedUrl`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FailureClass {
+    /// The request, or reading the response, timed out, or the server answered 408 Request Timeout.
+    Timeout,
+    /// The host could not be resolved or connected to.
+    Connect,
+    /// The host did not accept a TCP connection when it was first seen, so none of its URLs were requested.
+    Unreachable,
+    /// The server kept answering 429 Too Many Requests or 503 Service Unavailable.
+    Throttled,
+    /// The server answered with another 5xx status.
+    ServerError,
+    /// The response was rejected, e.g. because its body was cut short or too large.
+    Body,
+    /// The server answered with a 4xx status, e.g. 404 Not Found.
+    ClientError,
+    /// Any other failure, e.g. too many redirects.
+    Other,
+}
+
+impl FailureClass {
+    /// Every class, in the order they are listed.
+    pub const ALL: [FailureClass; 8] = [
+        FailureClass::Timeout,
+        FailureClass::Connect,
+        FailureClass::Unreachable,
+        FailureClass::Throttled,
+        FailureClass::ServerError,
+        FailureClass::Body,
+        FailureClass::ClientError,
+        FailureClass::Other,
+    ];
+
+    /// Classifies an error that stopped the crawl of a URL.
+    ///
+    /// # Returns
+    /// The class, or None for errors that are not caused by the URL, e.g. of the database.
+    pub fn of_error(error: &CrawlerError) -> Option<FailureClass> {
+        match error {
+            CrawlerError::Request(e) if e.is_timeout() => Some(FailureClass::Timeout),
+            CrawlerError::Request(e) if e.is_connect() => Some(FailureClass::Connect),
+            CrawlerError::Request(_) | CrawlerError::Fetch(_) => Some(FailureClass::Other),
+            _ => None,
+        }
+    }
+
+    /// Classifies an error status.
+    pub fn of_status(status: StatusCode) -> FailureClass {
+        match status {
+            StatusCode::REQUEST_TIMEOUT => FailureClass::Timeout,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                FailureClass::Throttled
+            }
+            _ if status.is_server_error() => FailureClass::ServerError,
+            _ if status.is_client_error() => FailureClass::ClientError,
+            _ => FailureClass::Other,
+        }
+    }
+
+    /// Returns the name of the class, as stored in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FailureClass::Timeout => "timeout",
+            FailureClass::Connect => "connect",
+            FailureClass::Unreachable => "unreachable",
+            FailureClass::Throttled => "throttled",
+            FailureClass::ServerError => "server_error",
+            FailureClass::Body => "body",
+            FailureClass::ClientError => "client_error",
+            FailureClass::Other => "other",
+        }
+    }
+
+    /// Returns the class with the given name, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<FailureClass> {
+        FailureClass::ALL
+            .into_iter()
+            .find(|class| class.name() == name)
+    }
+
+    /// Returns whether URLs failing this way may succeed when fetched again. Client errors and other failures are
+    /// deemed permanent.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, FailureClass::ClientError | FailureClass::Other)
+    }
+}
+
+/// Which failed URLs are queued again, and when.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// The classes of failures that are retried.
+    pub classes: Vec<FailureClass>,
+    /// The number of attempts after which a URL is given up on.
+    pub max_attempts: u32,
+    /// The time to wait after the first failed attempt, doubled after every further attempt.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait after a number of failed attempts: the backoff after one attempt, twice that after
+    /// two, and so on.
+    pub fn backoff_after(&self, attempts: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempts.max(1) - 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            classes: FailureClass::ALL
+                .into_iter()
+                .filter(FailureClass::is_retryable)
+                .collect(),
+            max_attempts: 5,
+            backoff: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// The outcome of queueing failed URLs again.
+#[derive(Default)]
+pub struct RetryReport {
+    /// The URLs queued in the frontier.
+    pub queued: Vec<String>,
+    /// The number of URLs whose backoff has not elapsed yet.
+    pub waiting: usize,
+    /// The number of URLs given up on after the maximum number of attempts.
+    pub exhausted: usize,
+    /// The number of URLs whose class of failure is not retried.
+    pub permanent: usize,
+}
+
+/// Queues the failed URLs that are worth fetching again in the frontier, for `resume` to crawl.
+///
+/// A URL is queued when its class of failure is retried, it has failed fewer than the maximum number of times, and
+/// its backoff has elapsed since its last attempt: the backoff of the policy after one attempt, twice that after two,
+/// and so on. URLs that fail again have their attempts counted up, and URLs that succeed are removed from `FailedUrl`.
+/// URLs are queued again also if a shared frontier holds them as crawled.
+///
+/// # Arguments
+/// * `connection` - The database connection, which records the failed URLs.
+/// * `store` - Where the frontier is kept, the crawl database itself unless it is shared through Postgres.
+/// * `policy` - Which failures are retried, and when.
+///
+/// # Returns
+/// The queued URLs, and the numbers of URLs that were not queued, by reason.
+pub fn queue_failed_urls(
+    connection: &Connection,
+    store: &dyn CrawlStore,
+    policy: &RetryPolicy,
+) -> Result<RetryReport, CrawlerError> {
+    let failures = connection
+        .prepare(
+            "SELECT Url, Class, Attempts,
+                (julianday('now') - julianday(LastAttempt)) * 86400
+            FROM FailedUrl ORDER BY LastAttempt, Id",
+        )?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, f64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut report = RetryReport::default();
+    // Without a shared frontier, the URLs are queued in this transaction
+    let transaction = connection.unchecked_transaction()?;
+    for (url, class, attempts, elapsed) in failures {
+        let retried =
+            FailureClass::from_name(&class).is_some_and(|class| policy.classes.contains(&class));
+        if !retried {
+            report.permanent += 1;
+            continue;
+        }
+        if attempts >= policy.max_attempts {
+            report.exhausted += 1;
+            continue;
+        }
+        if elapsed < policy.backoff_after(attempts).as_secs_f64() {
+            report.waiting += 1;
+            continue;
+        }
+        if store.frontier_requeue(&url, SystemTime::now(), 0.0)? {
+            report.queued.push(url);
+        }
+    }
+    transaction.commit()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    #[test]
+    fn classifies_statuses() {
+        let cases = [
+            (408, FailureClass::Timeout),
+            (429, FailureClass::Throttled),
+            (503, FailureClass::Throttled),
+            (500, FailureClass::ServerError),
+            (502, FailureClass::ServerError),
+            (404, FailureClass::ClientError),
+            (410, FailureClass::ClientError),
+            (302, FailureClass::Other),
+        ];
+        for (status, class) in cases {
+            assert_eq!(
+                FailureClass::of_status(StatusCode::from_u16(status).unwrap()),
+                class,
+                "{}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn doubles_the_backoff() {
+        let policy = RetryPolicy {
+            backoff: Duration::from_secs(300),
+            ..RetryPolicy::default()
+        };
+        let backoffs = [0, 1, 2, 3].map(|attempts| policy.backoff_after(attempts).as_secs());
+        assert_eq!(backoffs, [300, 300, 600, 1200]);
+        assert!(policy.backoff_after(u32::MAX) > Duration::from_secs(365 * 24 * 3600));
+    }
+
+    #[test]
+    fn queues_failed_urls_worth_retrying() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute("INSERT INTO CrawlRun DEFAULT VALUES", [])
+            .unwrap();
+        // Minutes since the last attempt
+        let failures = [
+            ("https://example.com/timeout", "timeout", 1, 10),
+            ("https://example.com/waiting", "server_error", 2, 8),
+            ("https://example.com/retried", "server_error", 2, 12),
+            ("https://example.com/exhausted", "timeout", 5, 1000),
+            ("https://example.com/missing", "client_error", 1, 1000),
+            ("https://example.com/queued", "connect", 1, 1000),
+        ];
+        for (url, class, attempts, minutes) in failures {
+            connection
+                .execute(
+                    "INSERT INTO FailedUrl (RunId, Url, Class, Error, Attempts, LastAttempt)
+                    VALUES (1, ?, ?, '', ?, datetime('now', ?))",
+                    params![url, class, attempts, format!("-{} minutes", minutes)],
+                )
+                .unwrap();
+        }
+        // A URL a worker crawled stays in a shared frontier, and one waiting there is not queued twice
+        connection
+            .execute(
+                "INSERT INTO FrontierUrl (Url, Status) VALUES
+                    ('https://example.com/retried', 'done'), ('https://example.com/queued', 'queued')",
+                [],
+            )
+            .unwrap();
+        let policy = RetryPolicy {
+            backoff: Duration::from_secs(5 * 60),
+            ..RetryPolicy::default()
+        };
+
+        let report = queue_failed_urls(&connection, &connection, &policy).unwrap();
+        assert_eq!(
+            report.queued,
+            ["https://example.com/retried", "https://example.com/timeout"]
+        );
+        assert_eq!(
+            (report.waiting, report.exhausted, report.permanent),
+            (1, 1, 1)
+        );
+        let queued: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM FrontierUrl WHERE Status = 'queued'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(queued, 3);
+    }
+}
@@ -0,0 +1,515 @@
+//! Parsing, caching and matching of robots.txt rules. The rules of each domain are recorded in the
+//! `DisallowedPattern` table, which is only read and written through this module.
+
+use itertools::Itertools;
+use regex::Regex;
+use reqwest::header::{HeaderMap, LOCATION};
+use rusqlite::{params, Connection};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::fetcher::Fetcher;
+use crate::normalize::{display_host, normalize_host};
+use crate::store::CrawlStore;
+
+const USER_AGENT_ROBOTS_REGEX: &str = r"(?i)User-agent:\s*(\S+*)";
+const DISALLOWED_ROBOTS_REGEX: &str = r"(?i)Disallow:\s*(\S+*)";
+const CRAWL_DELAY_ROBOTS_REGEX: &str = r"(?i)Crawl-delay:\s*([0-9]+(?:\.[0-9]+)?)";
+const SITEMAP_ROBOTS_REGEX: &str = r"(?im)^\s*Sitemap:\s*(\S+)";
+/// The most redirects followed for a robots.txt file, as RFC 9309 asks for at least five.
+const MAX_ROBOTS_REDIRECTS: usize = 5;
+
+/// The rules of a robots.txt file that apply to a user agent.
+#[derive(Default)]
+pub struct RobotsRules {
+    /// Path prefixes that must not be crawled.
+    pub disallowed: Vec<String>,
+    /// The minimum number of seconds between requests, if requested.
+    pub crawl_delay: Option<f64>,
+    /// The URLs of the sitemaps the file lists, which belong to no section. Rules copied from the shared store have
+    /// none, as the sitemaps are read by the worker that fetched the file.
+    pub sitemaps: Vec<String>,
+}
+
+/// Parses a robots.txt file, collecting the rules of every section for `*` or the given user agent.
+///
+/// If several matching sections declare a crawl delay, the longest is used. The sitemaps are collected from the whole
+/// file.
+///
+/// # Arguments
+/// * `robots_txt` - The contents of the robots.txt file.
+/// * `user_agent` - The name of the crawler's user agent.
+///
+/// # Returns
+/// The applicable rules.
+pub fn parse_robots_txt(robots_txt: &str, user_agent: &str) -> Result<RobotsRules, CrawlerError> {
+    // Split the file into "user-agent" sections
+    let user_agent_regex = Regex::new(USER_AGENT_ROBOTS_REGEX)?;
+    let disallowed_regex = Regex::new(DISALLOWED_ROBOTS_REGEX)?;
+    let crawl_delay_regex = Regex::new(CRAWL_DELAY_ROBOTS_REGEX)?;
+    let sitemap_regex = Regex::new(SITEMAP_ROBOTS_REGEX)?;
+    let mut user_agent_matches = user_agent_regex
+        .find_iter(robots_txt)
+        .map(|m| m.start())
+        .collect::<Vec<_>>();
+    user_agent_matches.push(robots_txt.len());
+
+    // Iterate over the user-agent sections and collect the rules if the user-agent matches
+    let mut rules = RobotsRules {
+        sitemaps: sitemap_regex
+            .captures_iter(robots_txt)
+            .map(|cap| cap[1].to_string())
+            .collect(),
+        ..RobotsRules::default()
+    };
+    for (first_match, last_match) in user_agent_matches.iter().tuple_windows() {
+        let section = &robots_txt[*first_match..*last_match];
+        let section_user_agent = user_agent_regex
+            .captures(section)
+            .and_then(|cap| cap.get(1))
+            .map(|m| m.as_str())
+            .unwrap_or("");
+
+        if section_user_agent != "*" && section_user_agent != user_agent {
+            continue;
+        }
+
+        for disallowed in disallowed_regex.captures_iter(section) {
+            if let Some(disallowed_pattern) = disallowed.get(1) {
+                rules
+                    .disallowed
+                    .push(disallowed_pattern.as_str().to_string());
+            }
+        }
+        let crawl_delay = crawl_delay_regex
+            .captures(section)
+            .and_then(|cap| cap.get(1))
+            .and_then(|m| m.as_str().parse::<f64>().ok());
+        if let Some(crawl_delay) = crawl_delay {
+            rules.crawl_delay = Some(
+                rules
+                    .crawl_delay
+                    .map_or(crawl_delay, |d| d.max(crawl_delay)),
+            );
+        }
+    }
+    Ok(rules)
+}
+
+/// Returns whether a URL path matches one of the disallowed patterns of a robots.txt file.
+///
+/// # Arguments
+/// * `patterns` - The disallowed patterns, as recorded.
+/// * `path` - The path of the URL.
+pub fn is_disallowed(patterns: &[String], path: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| path.starts_with(pattern.as_str()) || pattern == "*")
+}
+
+/// Reads the recorded disallowed patterns of a domain.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `domain_id` - The id of the domain entity.
+///
+/// # Returns
+/// The patterns, in the order they were recorded.
+pub(crate) fn disallowed_patterns(
+    connection: &Connection,
+    domain_id: i64,
+) -> Result<Vec<String>, CrawlerError> {
+    let mut stmt = connection
+        .prepare_cached("SELECT Pattern FROM DisallowedPattern WHERE DomainId = ? ORDER BY Id")?;
+    let patterns = stmt
+        .query_map([domain_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(patterns)
+}
+
+/// Replaces the recorded disallowed patterns of a domain.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `domain_id` - The id of the domain entity.
+/// * `patterns` - The new patterns.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub(crate) fn replace_disallowed_patterns(
+    connection: &Connection,
+    domain_id: i64,
+    patterns: &[String],
+) -> Result<(), CrawlerError> {
+    connection.execute(
+        "DELETE FROM DisallowedPattern WHERE DomainId = ?",
+        [domain_id],
+    )?;
+    for pattern in patterns {
+        connection.execute(
+            "INSERT OR IGNORE INTO DisallowedPattern (DomainId, Pattern) VALUES (?, ?)",
+            params![domain_id, pattern],
+        )?;
+    }
+    Ok(())
+}
+
+/// Fetches the robots.txt file of a domain and records its rules.
+///
+/// The file is only fetched if the domain's rules are older than a day and have not been imported. Imported rules are
+/// pinned, and never fetched again. Up to five redirects are followed, e.g. from http to https, and any previous rules
+/// are removed if the file is not found, or answers another client error, or redirects further. A server error does not
+/// tell whether the site may be crawled, so the previous rules are kept if there are any, and otherwise the whole site
+/// is treated as disallowed by failing with an error, as RFC 9309 asks. Rules another worker recorded in the shared
+/// store less than a day ago are copied instead of fetching the file again, and fetched rules are passed to the store.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `store` - The store shared with other workers, which may be the crawl database itself.
+/// * `fetcher` - Sends the request for the file.
+/// * `user_agent` - The name of the crawler's user agent.
+/// * `url` - A URL of the domain.
+/// * `domain_id` - The id of the domain entity.
+///
+/// # Returns
+/// The rules if they were fetched or copied, or None if the recorded rules are still fresh or were kept, or an error
+/// if the file could not be fetched and the domain has no rules yet.
+pub async fn record_robots_txt(
+    connection: &Connection,
+    store: &dyn CrawlStore,
+    fetcher: &Fetcher,
+    user_agent: &str,
+    url: &Url,
+    domain_id: i64,
+) -> Result<Option<RobotsRules>, CrawlerError> {
+    let is_fresh: bool = connection.query_row(
+        "SELECT Pinned OR COALESCE(RobotsFetched > datetime('now', '-1 day'), 0) FROM Domain WHERE Id = ?",
+        [domain_id],
+        |row| row.get(0),
+    )?;
+    if is_fresh {
+        return Ok(None);
+    }
+    let domain_name = url
+        .host_str()
+        .ok_or_else(|| CrawlerError::Parse(format!("{} has no host", url)))?;
+    if let Some(rules) = store.fresh_robots_rules(domain_name)? {
+        record_rules(connection, domain_id, &rules)?;
+        return Ok(Some(rules));
+    }
+
+    // Fetch the robots.txt file, which has no rules if it is not found
+    let mut robots_url = url.join("/robots.txt")?;
+    let mut response = fetcher.get(&robots_url, HeaderMap::new()).await?;
+    for _ in 0..MAX_ROBOTS_REDIRECTS {
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok());
+        let Some(location) = location.filter(|_| response.status().is_redirection()) else {
+            break;
+        };
+        robots_url = robots_url.join(location)?;
+        response = fetcher.get(&robots_url, HeaderMap::new()).await?;
+    }
+    let status = response.status();
+    let rules = if status.is_success() {
+        let robots_txt = response.text().await?;
+        parse_robots_txt(&robots_txt, user_agent)?
+    } else if status.is_client_error() || status.is_redirection() {
+        info!("No robots.txt found for {}", display_host(domain_name));
+        RobotsRules::default()
+    } else {
+        let known: bool = connection.query_row(
+            "SELECT RobotsFetched IS NOT NULL FROM Domain WHERE Id = ?",
+            [domain_id],
+            |row| row.get(0),
+        )?;
+        if known {
+            warn!(
+                "The robots.txt of {} is unavailable ({}), keeping its previous rules",
+                display_host(domain_name),
+                status
+            );
+            return Ok(None);
+        }
+        return Err(CrawlerError::Fetch(format!(
+            "The robots.txt of {} is unavailable ({}), so none of its URLs are crawled",
+            display_host(domain_name),
+            status
+        )));
+    };
+    record_rules(connection, domain_id, &rules)?;
+    store.save_robots_rules(domain_name, &rules)?;
+    Ok(Some(rules))
+}
+
+/// Replaces the recorded robots.txt rules of a domain, and marks them as fetched now.
+fn record_rules(
+    connection: &Connection,
+    domain_id: i64,
+    rules: &RobotsRules,
+) -> Result<(), CrawlerError> {
+    replace_disallowed_patterns(connection, domain_id, &rules.disallowed)?;
+    connection.execute(
+        "UPDATE Domain SET RobotsFetched = CURRENT_TIMESTAMP, CrawlDelay = ? WHERE Id = ?",
+        params![rules.crawl_delay, domain_id],
+    )?;
+    Ok(())
+}
+
+/// Fetches and records the robots.txt rules of a list of domains ahead of a crawl.
+///
+/// Domains are given one per line, either as a bare name, which is fetched over https, or as a URL. Blank lines and
+/// lines starting with `#` are skipped. Domains whose rules are still fresh are not fetched again, and failures are
+/// logged without stopping the warm-up.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `fetcher` - Sends the requests for the files.
+/// * `user_agent` - The name of the crawler's user agent.
+/// * `domains` - The contents of the domains file.
+///
+/// # Returns
+/// The number of domains fetched, skipped as fresh, and failed.
+pub async fn warm_up(
+    connection: &Connection,
+    fetcher: &Fetcher,
+    user_agent: &str,
+    domains: &str,
+) -> Result<(usize, usize, usize), CrawlerError> {
+    let (mut fetched, mut fresh, mut failed) = (0, 0, 0);
+    let lines = domains
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for line in lines {
+        let url = if line.contains("://") {
+            Url::parse(line)
+        } else {
+            Url::parse(&format!("https://{}/", normalize_host(line)))
+        };
+        let Some((url, domain_name)) = url
+            .ok()
+            .and_then(|url| Some((url.clone(), url.host_str()?.to_string())))
+        else {
+            warn!("Skipping invalid domain {}", line);
+            failed += 1;
+            continue;
+        };
+
+        connection.execute(
+            "INSERT OR IGNORE INTO Domain (Name) VALUES (?)",
+            [&domain_name],
+        )?;
+        let domain_id: i64 = connection.query_row(
+            "SELECT Id FROM Domain WHERE Name = ?",
+            [&domain_name],
+            |row| row.get(0),
+        )?;
+        match record_robots_txt(connection, connection, fetcher, user_agent, &url, domain_id).await
+        {
+            Ok(Some(_)) => fetched += 1,
+            Ok(None) => fresh += 1,
+            Err(e) => {
+                warn!("Failed to fetch robots.txt of {}: {}", domain_name, e);
+                failed += 1;
+            }
+        }
+    }
+    Ok((fetched, fresh, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{export_policies, import_policies, DomainPolicy};
+
+    const ROBOTS_TXT: &str = "User-agent: *\nDisallow: /private\nCrawl-delay: 2\n\n\
+        User-agent: other_bot\nDisallow: /\n\n\
+        User-agent: test_bot\nDisallow: /drafts/\nCrawl-delay: 5\n\n\
+        sitemap: https://example.com/sitemap.xml\nSitemap: https://example.com/news.xml.gz\n";
+
+    #[test]
+    fn parses_matching_sections() {
+        let rules = parse_robots_txt(ROBOTS_TXT, "test_bot").unwrap();
+        assert_eq!(rules.disallowed, ["/private", "/drafts/"]);
+        assert_eq!(rules.crawl_delay, Some(5.0));
+
+        let rules = parse_robots_txt(ROBOTS_TXT, "another_bot").unwrap();
+        assert_eq!(rules.disallowed, ["/private"]);
+        assert_eq!(rules.crawl_delay, Some(2.0));
+        assert_eq!(
+            rules.sitemaps,
+            [
+                "https://example.com/sitemap.xml",
+                "https://example.com/news.xml.gz"
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_disallowed_paths() {
+        let patterns = ["/private".to_string(), "/drafts/".to_string()];
+        assert!(is_disallowed(&patterns, "/private"));
+        assert!(is_disallowed(&patterns, "/private/notes.html"));
+        assert!(is_disallowed(&patterns, "/drafts/post"));
+        assert!(!is_disallowed(&patterns, "/drafts"));
+        assert!(!is_disallowed(&patterns, "/public"));
+        assert!(is_disallowed(&["*".to_string()], "/anything"));
+        assert!(!is_disallowed(&[], "/"));
+    }
+
+    /// Writes a fixture of a response for a replaying fetcher.
+    fn write_fixture(dir: &std::path::Path, name: &str, fixture: serde_json::Value) {
+        std::fs::write(dir.join(name), fixture.to_string()).unwrap();
+    }
+
+    /// Returns a database with one domain, and a directory for fixtures.
+    fn robots_test_setup(name: &str) -> (Connection, std::path::PathBuf) {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute("INSERT INTO Domain (Name) VALUES ('example.com')", [])
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("web_crawler_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        (connection, dir)
+    }
+
+    #[tokio::test]
+    async fn follows_robots_redirects() {
+        let (connection, dir) = robots_test_setup("robots_redirect");
+        write_fixture(
+            &dir,
+            "http.json",
+            serde_json::json!({
+                "url": "http://example.com/robots.txt",
+                "status": 301,
+                "headers": [["location", "https://example.com/robots.txt"]],
+            }),
+        );
+        write_fixture(
+            &dir,
+            "https.json",
+            serde_json::json!({
+                "url": "https://example.com/robots.txt",
+                "status": 200,
+                "headers": [["content-type", "text/plain"]],
+                "body": "User-agent: *\nDisallow: /private\n",
+            }),
+        );
+        let fetcher = Fetcher::new(
+            reqwest::Client::new(),
+            crate::fetcher::FetchMode::Replay(dir.clone()),
+        )
+        .unwrap();
+        let url = Url::parse("http://example.com/page").unwrap();
+        assert!(
+            record_robots_txt(&connection, &connection, &fetcher, "test", &url, 1)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(disallowed_patterns(&connection, 1).unwrap(), ["/private"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Only client errors remove the rules of a domain, while server errors keep them, or fail if there are none.
+    #[tokio::test]
+    async fn keeps_rules_on_server_errors() {
+        let (connection, dir) = robots_test_setup("robots_unavailable");
+        let url = Url::parse("http://example.com/page").unwrap();
+        let fetch = |status: u16| {
+            write_fixture(
+                &dir,
+                "robots.json",
+                serde_json::json!({
+                    "url": "http://example.com/robots.txt",
+                    "status": status,
+                    "headers": [],
+                }),
+            );
+            Fetcher::new(
+                reqwest::Client::new(),
+                crate::fetcher::FetchMode::Replay(dir.clone()),
+            )
+            .unwrap()
+        };
+
+        let unavailable = fetch(503);
+        assert!(
+            record_robots_txt(&connection, &connection, &unavailable, "test", &url, 1)
+                .await
+                .is_err()
+        );
+        let rules = parse_robots_txt("User-agent: *\nDisallow: /private\n", "test").unwrap();
+        record_rules(&connection, 1, &rules).unwrap();
+        connection
+            .execute(
+                "UPDATE Domain SET RobotsFetched = datetime('now', '-2 days')",
+                [],
+            )
+            .unwrap();
+        assert!(
+            record_robots_txt(&connection, &connection, &unavailable, "test", &url, 1)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(disallowed_patterns(&connection, 1).unwrap(), ["/private"]);
+
+        let missing = fetch(404);
+        assert!(
+            record_robots_txt(&connection, &connection, &missing, "test", &url, 1)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(disallowed_patterns(&connection, 1).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Rules recorded from a robots.txt file and rules imported as a policy end up in the same table, and are read
+    /// back the same way by the crawler, the store and the policy export.
+    #[test]
+    fn records_rules_in_one_table() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute("INSERT INTO Domain (Name) VALUES ('example.com')", [])
+            .unwrap();
+
+        let rules = parse_robots_txt(ROBOTS_TXT, "test_bot").unwrap();
+        record_rules(&connection, 1, &rules).unwrap();
+        assert_eq!(
+            disallowed_patterns(&connection, 1).unwrap(),
+            ["/private", "/drafts/"]
+        );
+        let fresh = connection
+            .fresh_robots_rules("example.com")
+            .unwrap()
+            .unwrap();
+        assert_eq!(fresh.disallowed, rules.disallowed);
+        assert_eq!(fresh.crawl_delay, Some(5.0));
+
+        import_policies(
+            &mut connection,
+            &[DomainPolicy {
+                name: "example.com".to_string(),
+                disallowed: vec!["/admin".to_string()],
+                crawl_delay: None,
+                refused_count: 0,
+                banned_until: None,
+            }],
+        )
+        .unwrap();
+        assert_eq!(disallowed_patterns(&connection, 1).unwrap(), ["/admin"]);
+        assert_eq!(
+            export_policies(&connection).unwrap()[0].disallowed,
+            ["/admin"]
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! The rules file, and routing of pages to storage targets by URL pattern.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::calendar::DateUrlLimits;
+use crate::error::CrawlerError;
+use crate::normalize::has_file_extension;
+use crate::storage::PageCompression;
+use crate::tagging::{PageTagger, TagRule};
+
+/// The rules file given with `--rules`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RulesFile {
+    /// Where pages matching URL patterns are stored, in order of precedence.
+    #[serde(default)]
+    pub storage: Vec<StorageRule>,
+    /// How many calendar and date archive URLs are crawled per directory.
+    #[serde(default)]
+    pub date_urls: DateUrlLimits,
+    /// The tags assigned to pages matching conditions.
+    #[serde(default)]
+    pub tags: Vec<TagRule>,
+}
+
+/// Routes the pages matching a URL pattern to a storage target.
+#[derive(Deserialize)]
+pub struct StorageRule {
+    /// A glob matched against the URL path, e.g. `/docs/**`, or against the host and path if it does not start
+    /// with `/`, e.g. `docs.example.com/**`. `*` matches within a path segment and `**` across segments.
+    pub pattern: String,
+    /// Where the matching pages are stored.
+    #[serde(flatten)]
+    pub target: StorageTarget,
+}
+
+/// Where and how a routed page is stored.
+#[derive(Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StorageTarget {
+    /// One `<hash>.html` file per distinct body in a directory.
+    Html {
+        /// The directory of the files.
+        dir: PathBuf,
+        /// The compression of the files.
+        #[serde(default)]
+        compression: PageCompression,
+    },
+    /// A file per URL in a directory tree that mirrors the hosts and paths of the site.
+    Mirror {
+        /// The root directory of the tree.
+        dir: PathBuf,
+    },
+    /// One JSON object per page, with its URL, status, hash and body, appended to a file.
+    Jsonl {
+        /// The file to append to.
+        file: PathBuf,
+    },
+    /// The body is not saved.
+    Discard,
+}
+
+/// A compiled URL glob, matched against the path of URLs, or against their host and path if the glob does not
+/// start with `/`.
+pub(crate) struct UrlPattern {
+    pattern: Regex,
+    matches_host: bool,
+}
+
+/// A storage rule with its pattern compiled.
+pub struct StorageRoute {
+    pattern: UrlPattern,
+    /// Where the matching pages are stored.
+    pub target: StorageTarget,
+}
+
+impl RulesFile {
+    /// Reads a rules file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the JSON file.
+    ///
+    /// # Returns
+    /// The rules, or an error if the file cannot be read or is invalid.
+    pub fn load(path: &Path) -> Result<Self, CrawlerError> {
+        let rules = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| {
+            CrawlerError::Config(format!("Invalid rules file {}: {}", path.display(), e))
+        })?;
+        Ok(rules)
+    }
+
+    /// Compiles the storage rules.
+    ///
+    /// # Returns
+    /// The routes, in order of precedence, or an error if a pattern is invalid.
+    pub fn storage_routes(&self) -> Result<Vec<StorageRoute>, CrawlerError> {
+        self.storage
+            .iter()
+            .map(|rule| {
+                Ok(StorageRoute {
+                    pattern: UrlPattern::new(&rule.pattern)?,
+                    target: rule.target.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Compiles the tag rules.
+    ///
+    /// # Returns
+    /// The tagger, or an error if a pattern or selector is invalid.
+    pub fn page_tagger(&self) -> Result<PageTagger, CrawlerError> {
+        PageTagger::new(&self.tags)
+    }
+}
+
+impl StorageRoute {
+    /// Checks whether a URL matches the pattern of the route.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    pub fn matches(&self, url: &Url) -> bool {
+        self.pattern.matches(url)
+    }
+}
+
+impl UrlPattern {
+    /// Compiles a glob.
+    ///
+    /// # Arguments
+    /// * `glob` - The glob, e.g. `/docs/**` or `docs.example.com/**`.
+    pub(crate) fn new(glob: &str) -> Result<Self, regex::Error> {
+        Ok(UrlPattern {
+            pattern: glob_to_regex(glob)?,
+            matches_host: !glob.starts_with('/'),
+        })
+    }
+
+    /// Checks whether a URL matches the glob.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    pub(crate) fn matches(&self, url: &Url) -> bool {
+        if self.matches_host {
+            let subject = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+            self.pattern.is_match(&subject)
+        } else {
+            self.pattern.is_match(url.path())
+        }
+    }
+}
+
+/// Translates a glob into an anchored regular expression.
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    let mut rest = glob;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("**") {
+            pattern.push_str(".*");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('*') {
+            pattern.push_str("[^/]*");
+            rest = after;
+        } else {
+            let literal = rest.find('*').unwrap_or(rest.len());
+            pattern.push_str(&regex::escape(&rest[..literal]));
+            rest = &rest[literal..];
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern)
+}
+
+/// Returns the path of the file a URL is saved as in a mirror tree.
+///
+/// Paths ending in `/` are saved as `index.html`, and paths without a file extension, such as `/v1.2`, get `.html`.
+/// A hash of the query is added to the file name of URLs with one.
+///
+/// # Arguments
+/// * `dir` - The root directory of the mirror.
+/// * `url` - The URL of the page.
+pub fn mirror_path(dir: &Path, url: &Url) -> PathBuf {
+    let mut path = dir.join(url.host_str().unwrap_or("unknown"));
+    if let Some(port) = url.port() {
+        path = dir.join(format!("{}_{}", url.host_str().unwrap_or("unknown"), port));
+    }
+    let mut segments = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if url.path().ends_with('/') {
+        segments.push("index.html");
+    }
+    let file_name = segments.pop().unwrap_or("index.html");
+    for segment in segments {
+        path.push(segment);
+    }
+
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if has_file_extension(file_name) => (stem, extension),
+        _ => (file_name, "html"),
+    };
+    match url.query() {
+        Some(query) => {
+            let hash = blake3::hash(query.as_bytes()).to_hex();
+            path.push(format!("{}-{}.{}", stem, &hash[..8], extension));
+        }
+        None => path.push(format!("{}.{}", stem, extension)),
+    }
+    path
+}
+
+/// Returns the path of the file a URL is saved as in a mirror tree when another URL took its [`mirror_path`], e.g.
+/// `/a` and `/a.html`, or `/a/` and `/a/index.html`. A hash of the whole URL is added to the file name.
+///
+/// # Arguments
+/// * `dir` - The root directory of the mirror.
+/// * `url` - The URL of the page.
+pub fn unique_mirror_path(dir: &Path, url: &Url) -> PathBuf {
+    let path = mirror_path(dir, url);
+    let hash = blake3::hash(url.as_str().as_bytes()).to_hex();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{}.{}", stem, &hash[..8], extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_url_globs() {
+        let cases = [
+            ("/docs/**", "https://example.com/docs/a/b.html", true),
+            ("/docs/**", "https://example.com/docs/", true),
+            ("/docs/**", "https://example.com/docs", false),
+            ("/docs/*", "https://example.com/docs/a.html", true),
+            ("/docs/*", "https://example.com/docs/a/b.html", false),
+            ("/*.pdf", "https://example.com/guide.pdf", true),
+            ("/*.pdf", "https://example.com/files/guide.pdf", false),
+            ("/**.pdf", "https://example.com/files/guide.pdf", true),
+            // Dots and other characters of patterns are matched literally
+            ("/a.html", "https://example.com/aXhtml", false),
+            ("/a+b(1)", "https://example.com/a+b(1)", true),
+            // The query is not matched
+            ("/search", "https://example.com/search?q=rust", true),
+            ("docs.example.com/**", "https://docs.example.com/a", true),
+            (
+                "docs.example.com/**",
+                "https://example.com/docs.example.com/a",
+                false,
+            ),
+            ("*.example.com/*", "https://blog.example.com/post", true),
+            ("*.example.com/*", "https://example.com/post", false),
+        ];
+        for (glob, url, matches) in cases {
+            let pattern = UrlPattern::new(glob).unwrap();
+            assert_eq!(
+                pattern.matches(&Url::parse(url).unwrap()),
+                matches,
+                "{} against {}",
+                glob,
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn maps_urls_to_mirror_files() {
+        let dir = Path::new("mirror");
+        let cases = [
+            ("https://example.com/", "example.com/index.html"),
+            ("https://example.com/a", "example.com/a.html"),
+            ("https://example.com/a/", "example.com/a/index.html"),
+            ("https://example.com/a/b.css", "example.com/a/b.css"),
+            ("https://example.com/v1.2", "example.com/v1.2.html"),
+            (
+                "https://example.com/.well-known",
+                "example.com/.well-known.html",
+            ),
+            ("https://example.com:8080/a", "example.com_8080/a.html"),
+            (
+                "https://example.com/../a/./b/",
+                "example.com/a/b/index.html",
+            ),
+        ];
+        for (url, path) in cases {
+            assert_eq!(
+                mirror_path(dir, &Url::parse(url).unwrap()),
+                dir.join(path),
+                "{}",
+                url
+            );
+        }
+
+        // Query variants get files of their own
+        let file = |url: &str| mirror_path(dir, &Url::parse(url).unwrap());
+        let variants = [
+            file("https://example.com/a"),
+            file("https://example.com/a?page=1"),
+            file("https://example.com/a?page=2"),
+            file("https://example.com/a/"),
+        ];
+        for (index, path) in variants.iter().enumerate() {
+            assert!(!variants[..index].contains(path), "{}", path.display());
+        }
+        let query = file("https://example.com/a?page=1");
+        assert_eq!(query.parent().unwrap(), dir.join("example.com"));
+        assert!(query.to_string_lossy().ends_with(".html"));
+
+        // URLs sharing a file are told apart by a hash of the URL
+        let bare = Url::parse("https://example.com/a").unwrap();
+        let html = Url::parse("https://example.com/a.html").unwrap();
+        assert_eq!(mirror_path(dir, &bare), mirror_path(dir, &html));
+        let unique = unique_mirror_path(dir, &bare);
+        assert_ne!(unique, unique_mirror_path(dir, &html));
+        assert_eq!(unique.parent(), mirror_path(dir, &bare).parent());
+        assert!(unique
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("a-"));
+        assert_eq!(unique.extension().unwrap(), "html");
+    }
+
+    #[test]
+    fn routes_pages_by_the_first_matching_rule() {
+        let rules: RulesFile = serde_json::from_str(
+            r#"{"storage": [
+                {"pattern": "/docs/private/**", "format": "discard"},
+                {"pattern": "/docs/**", "format": "mirror", "dir": "mirror"},
+                {"pattern": "/**", "format": "html", "dir": "pages", "compression": "gzip"}
+            ]}"#,
+        )
+        .unwrap();
+        let routes = rules.storage_routes().unwrap();
+        let target = |url| {
+            let url = Url::parse(url).unwrap();
+            routes
+                .iter()
+                .find(|route| route.matches(&url))
+                .map(|route| route.target.clone())
+        };
+        assert!(matches!(
+            target("https://example.com/docs/private/a"),
+            Some(StorageTarget::Discard)
+        ));
+        assert!(matches!(
+            target("https://example.com/docs/a"),
+            Some(StorageTarget::Mirror { dir }) if dir == Path::new("mirror")
+        ));
+        assert!(matches!(
+            target("https://example.com/"),
+            Some(StorageTarget::Html { dir, compression: PageCompression::Gzip }) if dir == Path::new("pages")
+        ));
+    }
+
+    #[test]
+    fn refuses_invalid_rules() {
+        for rules in [
+            r#"{"storage": [{"pattern": "/**", "format": "zip", "dir": "a"}]}"#,
+            r#"{"storage": [{"pattern": "/**", "format": "jsonl"}]}"#,
+            r#"{"routes": []}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<RulesFile>(rules).is_err(),
+                "{}",
+                rules
+            );
+        }
+        let rules: RulesFile = serde_json::from_str("{}").unwrap();
+        assert!(rules.storage.is_empty() && rules.tags.is_empty());
+    }
+}
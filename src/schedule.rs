@@ -0,0 +1,186 @@
+//! When scheduled crawls run again, and when stored pages are due to be fetched again.
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::CrawlerError;
+
+/// When the passes of a long-running crawl start.
+pub enum CrawlSchedule {
+    /// A fixed time after the previous pass started, e.g. `every 6h`.
+    Every(Duration),
+    /// The times of a cron expression, in UTC, e.g. `0 */6 * * *`.
+    Cron(Box<Schedule>),
+}
+
+impl CrawlSchedule {
+    /// Parses a schedule.
+    ///
+    /// # Arguments
+    /// * `spec` - Either `every <n><unit>`, with the unit `s`, `m`, `h` or `d`, e.g. `every 6h` or `every 30 minutes`,
+    ///   or a cron expression with five fields, or six or seven fields starting with the seconds.
+    ///
+    /// # Returns
+    /// The schedule, or an error if it cannot be parsed.
+    pub fn parse(spec: &str) -> Result<Self, CrawlerError> {
+        let spec = spec.trim();
+        if let Some(interval) = spec.strip_prefix("every ") {
+            return parse_interval(interval.trim())
+                .map(CrawlSchedule::Every)
+                .ok_or_else(|| CrawlerError::Config(format!("Invalid schedule \"{}\"", spec)));
+        }
+        // The cron crate expects the seconds first, which the usual five fields do not have
+        let expression = match spec.split_whitespace().count() {
+            5 => format!("0 {}", spec),
+            _ => spec.to_string(),
+        };
+        Schedule::from_str(&expression)
+            .map(|schedule| CrawlSchedule::Cron(Box::new(schedule)))
+            .map_err(|e| CrawlerError::Config(format!("Invalid schedule \"{}\": {}", spec, e)))
+    }
+
+    /// Returns when the pass after one that started at a given time is due.
+    ///
+    /// # Arguments
+    /// * `started` - When the previous pass started.
+    ///
+    /// # Returns
+    /// The time of the next pass, or None if the cron expression has no later times.
+    pub fn next_after(&self, started: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            CrawlSchedule::Every(interval) => {
+                Some(started + chrono::Duration::from_std(*interval).ok()?)
+            }
+            CrawlSchedule::Cron(schedule) => schedule.after(&started).next(),
+        }
+    }
+}
+
+/// Parses an interval such as `6h`, `6 h` or `6 hours`.
+fn parse_interval(interval: &str) -> Option<Duration> {
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (count, unit) = interval.split_at(split);
+    let count: u64 = count.parse().ok().filter(|count| *count > 0)?;
+    let seconds = match unit.trim() {
+        "s" | "sec" | "second" | "seconds" => 1,
+        "m" | "min" | "minute" | "minutes" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(seconds)?))
+}
+
+/// How long stored pages stay fresh before a scheduled pass fetches them again.
+#[derive(Clone, Default, PartialEq)]
+pub struct FreshnessPolicy {
+    /// The age after which pages are stale, or None if pages are stale at every pass.
+    pub max_age: Option<Duration>,
+    /// The age after which the pages of single domains are stale, by domain name.
+    pub domains: HashMap<String, Duration>,
+}
+
+impl FreshnessPolicy {
+    /// Returns whether a page is stale.
+    ///
+    /// # Arguments
+    /// * `domain` - The domain of the page.
+    /// * `age` - The time since the page was last fetched.
+    pub fn is_stale(&self, domain: &str, age: Duration) -> bool {
+        match self.domains.get(domain).or(self.max_age.as_ref()) {
+            Some(max_age) => age >= *max_age,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the time of an RFC 3339 date.
+    fn time(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date).unwrap().to_utc()
+    }
+
+    #[test]
+    fn parses_intervals() {
+        let cases = [
+            ("every 30s", Some(30)),
+            ("every 6h", Some(6 * 60 * 60)),
+            ("every 6 h", Some(6 * 60 * 60)),
+            ("  every 30 minutes ", Some(30 * 60)),
+            ("every 2 days", Some(2 * 24 * 60 * 60)),
+            ("every 1 sec", Some(1)),
+            ("every 0h", None),
+            ("every h", None),
+            ("every 6", None),
+            ("every 6 weeks", None),
+            ("every -1h", None),
+            ("every 99999999999999999999d", None),
+        ];
+        for (spec, seconds) in cases {
+            match (CrawlSchedule::parse(spec), seconds) {
+                (Ok(CrawlSchedule::Every(interval)), Some(seconds)) => {
+                    assert_eq!(interval, Duration::from_secs(seconds), "{}", spec)
+                }
+                (Err(CrawlerError::Config(_)), None) => {}
+                (_, seconds) => panic!("{} should parse to {:?}", spec, seconds),
+            }
+        }
+    }
+
+    #[test]
+    fn finds_the_next_pass() {
+        let started = time("2024-03-10T10:17:00Z");
+        let cases = [
+            ("every 6h", "2024-03-10T16:17:00Z"),
+            ("every 1 day", "2024-03-11T10:17:00Z"),
+            // Five fields are minutes to weekdays, six or seven start with the seconds
+            ("0 */6 * * *", "2024-03-10T12:00:00Z"),
+            ("30 2 * * *", "2024-03-11T02:30:00Z"),
+            ("15 0 0 * * *", "2024-03-11T00:00:15Z"),
+            ("0 0 12 1 * * 2024", "2024-04-01T12:00:00Z"),
+        ];
+        for (spec, next) in cases {
+            let schedule = CrawlSchedule::parse(spec).unwrap();
+            assert_eq!(schedule.next_after(started), Some(time(next)), "{}", spec);
+        }
+        let ended = CrawlSchedule::parse("0 0 12 1 * * 2023").unwrap();
+        assert_eq!(ended.next_after(started), None);
+        assert!(CrawlSchedule::parse("every day at noon").is_err());
+        assert!(CrawlSchedule::parse("61 * * * *").is_err());
+    }
+
+    #[test]
+    fn tells_stale_pages() {
+        let hour = Duration::from_secs(60 * 60);
+        let always = FreshnessPolicy::default();
+        assert!(always.is_stale("example.com", Duration::ZERO));
+
+        let policy = FreshnessPolicy {
+            max_age: Some(24 * hour),
+            domains: HashMap::from([("news.example.com".to_string(), hour)]),
+        };
+        let cases = [
+            ("example.com", 23 * hour, false),
+            ("example.com", 24 * hour, true),
+            ("news.example.com", hour / 2, false),
+            ("news.example.com", 2 * hour, true),
+        ];
+        for (domain, age, stale) in cases {
+            assert_eq!(
+                policy.is_stale(domain, age),
+                stale,
+                "{} at {:?}",
+                domain,
+                age
+            );
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! The schema of the SQLite database, built and upgraded by migrations embedded in the binary.
+//!
+//! Each migration is a SQL script in `scripts/migrations`, applied once in order. The versions applied to a database
+//! are recorded in its `SchemaVersion` table, so opening a database created by an older version of the crawler
+//! upgrades it in place. Databases created with the `scripts/create.sql` of earlier releases have no record of their
+//! schema, which differs from release to release, and are first brought to the schema of the first migration.
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+use crate::error::CrawlerError;
+
+/// The migrations, in order. Migration `n` brings the schema to version `n`, counting from 1.
+const MIGRATIONS: [&str; 7] = [
+    include_str!("../scripts/migrations/0001_initial.sql"),
+    include_str!("../scripts/migrations/0002_page_link_url_index.sql"),
+    include_str!("../scripts/migrations/0003_page_dry_run.sql"),
+    include_str!("../scripts/migrations/0004_rejected_image.sql"),
+    include_str!("../scripts/migrations/0005_sitemap_url.sql"),
+    include_str!("../scripts/migrations/0006_drop_domain_link.sql"),
+    include_str!("../scripts/migrations/0007_page_body_hash_text.sql"),
+];
+
+/// The schema version of the databases this crawler creates, the version of its latest migration.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Values of the `NOT NULL` columns without a default that tables of earlier releases lack, as SQL expressions over
+/// the columns they have, by table and column. Other such columns are filled with the zero value of their type.
+const LEGACY_FILLS: [(&str, &str, &str); 1] = [("Page", "RequestedUrl", "Url")];
+
+/// Returns the schema version of a database.
+///
+/// Databases created with the `scripts/create.sql` of earlier releases have no `SchemaVersion` table, and are at the
+/// version of their `user_version` pragma, which does not tell their schema apart.
+///
+/// # Returns
+/// The version, or 0 for an empty database.
+pub fn schema_version(connection: &Connection) -> Result<i64, CrawlerError> {
+    match recorded_version(connection)? {
+        Some(version) => Ok(version),
+        None => Ok(connection.query_row("PRAGMA user_version", [], |row| row.get(0))?),
+    }
+}
+
+/// Returns the latest version recorded in the `SchemaVersion` table, or None if the database has no such table.
+fn recorded_version(connection: &Connection) -> Result<Option<i64>, CrawlerError> {
+    let tables: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'SchemaVersion'",
+        [],
+        |row| row.get(0),
+    )?;
+    if tables == 0 {
+        return Ok(None);
+    }
+    Ok(Some(connection.query_row(
+        "SELECT COALESCE(MAX(Version), 0) FROM SchemaVersion",
+        [],
+        |row| row.get(0),
+    )?))
+}
+
+/// Brings the schema of a database up to [`SCHEMA_VERSION`], creating it in an empty database.
+///
+/// The migrations are applied in one transaction, so a failed migration leaves the database as it was. A database
+/// that is up to date is not written to.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+///
+/// # Returns
+/// The number of migrations applied, or an error if a migration failed or the database was created by a newer
+/// version of the crawler.
+pub fn migrate(connection: &mut Connection) -> Result<usize, CrawlerError> {
+    let recorded = recorded_version(connection)?;
+    if recorded == Some(SCHEMA_VERSION) {
+        return Ok(0);
+    }
+    let version = schema_version(connection)?;
+    if version > SCHEMA_VERSION {
+        return Err(CrawlerError::Config(format!(
+            "The database has schema version {}, newer than version {} of this crawler",
+            version, SCHEMA_VERSION
+        )));
+    }
+    let legacy = recorded.is_none() && has_tables(connection)?;
+    if !legacy {
+        return apply_migrations(connection, recorded.unwrap_or(0), false);
+    }
+    // Tables are rebuilt by dropping them, which must not delete the rows referencing them
+    let foreign_keys: bool = connection.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    connection.pragma_update(None, "foreign_keys", false)?;
+    let applied = apply_migrations(connection, 0, true);
+    connection.pragma_update(None, "foreign_keys", foreign_keys)?;
+    applied
+}
+
+/// Applies the migrations after a version in one transaction, recording them.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `version` - The version of the database.
+/// * `legacy` - Whether the database was created by `scripts/create.sql` and is adopted as version 1 first.
+///
+/// # Returns
+/// The number of migrations applied, not counting the adoption.
+fn apply_migrations(
+    connection: &mut Connection,
+    mut version: i64,
+    legacy: bool,
+) -> Result<usize, CrawlerError> {
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS SchemaVersion (
+            Version INTEGER PRIMARY KEY,
+            Applied DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )?;
+    if legacy {
+        adopt_legacy(&transaction)?;
+        transaction.execute("INSERT INTO SchemaVersion (Version) VALUES (1)", [])?;
+        info!("Upgraded the database of an earlier release to schema version 1");
+        version = 1;
+    }
+    let pending = &MIGRATIONS[version as usize..];
+    for (index, migration) in pending.iter().enumerate() {
+        let target = version + index as i64 + 1;
+        transaction.execute_batch(migration)?;
+        transaction.execute(
+            "INSERT INTO SchemaVersion (Version) VALUES (?)",
+            params![target],
+        )?;
+        if version > 0 {
+            info!("Upgraded the database to schema version {}", target);
+        }
+    }
+    transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    transaction.commit()?;
+    Ok(pending.len())
+}
+
+/// Returns whether a database has any tables.
+fn has_tables(connection: &Connection) -> Result<bool, CrawlerError> {
+    Ok(connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Brings a database created with the `scripts/create.sql` of an earlier release to the schema of the first migration.
+///
+/// Missing tables are created. Tables whose definition differs, in their columns, keys or types, are rebuilt with the
+/// definition of the migration, keeping the columns they share with it. Rows that break the new keys, such as a
+/// second link of a page in the `PageLink` table of the first release, are dropped. Indexes are created last.
+///
+/// # Arguments
+/// * `transaction` - The transaction of the migration, with foreign keys off.
+///
+/// # Returns
+/// A Result indicating success or failure.
+fn adopt_legacy(transaction: &Transaction) -> Result<(), CrawlerError> {
+    let target = Connection::open_in_memory()?;
+    target.execute_batch(MIGRATIONS[0])?;
+    let objects = target
+        .prepare("SELECT type, name, sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY rowid")?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    // The full-text tables create their own shadow tables, e.g. PageSearch_data
+    let virtual_tables = objects
+        .iter()
+        .filter(|(_, _, sql)| sql.starts_with("CREATE VIRTUAL TABLE"))
+        .map(|(_, name, _)| format!("{}_", name))
+        .collect::<Vec<_>>();
+    let tables = objects.iter().filter(|(kind, name, _)| {
+        kind == "table" && !virtual_tables.iter().any(|prefix| name.starts_with(prefix))
+    });
+    for (_, name, sql) in tables {
+        let existing = transaction
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?",
+                [name],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        match existing {
+            None => transaction.execute_batch(sql)?,
+            Some(existing) if normalize_sql(&existing) == normalize_sql(sql) => {}
+            Some(_) => rebuild_table(transaction, &target, name, sql)?,
+        }
+    }
+    for (_, _, sql) in objects.iter().filter(|(kind, _, _)| kind == "index") {
+        transaction.execute_batch(&sql.replacen("INDEX", "INDEX IF NOT EXISTS", 1))?;
+    }
+    Ok(())
+}
+
+/// Rebuilds a table of an earlier release with its definition in the first migration, copying the rows over.
+///
+/// # Arguments
+/// * `transaction` - The transaction of the migration.
+/// * `target` - A database with the schema of the first migration.
+/// * `name` - The name of the table.
+/// * `sql` - The definition of the table in the first migration.
+///
+/// # Returns
+/// A Result indicating success or failure.
+fn rebuild_table(
+    transaction: &Transaction,
+    target: &Connection,
+    name: &str,
+    sql: &str,
+) -> Result<(), CrawlerError> {
+    let existing = table_columns(transaction, name)?
+        .into_iter()
+        .map(|column| column.name)
+        .collect::<HashSet<_>>();
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for column in table_columns(target, name)? {
+        let value = if existing.contains(&column.name) {
+            format!("\"{}\"", column.name)
+        } else if column.not_null && !column.has_default {
+            let fill = LEGACY_FILLS
+                .iter()
+                .find(|(table, filled, _)| *table == name && *filled == column.name)
+                .map(|(_, _, fill)| fill.to_string());
+            fill.unwrap_or_else(|| match column.column_type.to_ascii_uppercase().as_str() {
+                "TEXT" => "''".to_string(),
+                "BLOB" => "X''".to_string(),
+                _ => "0".to_string(),
+            })
+        } else {
+            continue;
+        };
+        columns.push(format!("\"{}\"", column.name));
+        values.push(value);
+    }
+    let rebuilt = format!("{}_rebuilt", name);
+    transaction.execute_batch(&sql.replacen(
+        &format!("TABLE {} ", name),
+        &format!("TABLE {} ", rebuilt),
+        1,
+    ))?;
+    let count = |table: &str| {
+        transaction.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+            row.get::<_, i64>(0)
+        })
+    };
+    // Full-text tables have no keys to break, and do not support conflict clauses
+    let insert = if sql.starts_with("CREATE VIRTUAL TABLE") {
+        "INSERT"
+    } else {
+        "INSERT OR IGNORE"
+    };
+    transaction.execute(
+        &format!(
+            "{} INTO \"{}\" ({}) SELECT {} FROM \"{}\"",
+            insert,
+            rebuilt,
+            columns.join(", "),
+            values.join(", "),
+            name
+        ),
+        [],
+    )?;
+    let dropped = count(name)? - count(&rebuilt)?;
+    if dropped > 0 {
+        warn!(
+            "Dropped {} rows of table {} that do not fit its current keys",
+            dropped, name
+        );
+    }
+    transaction.execute_batch(&format!(
+        "DROP TABLE \"{}\"; ALTER TABLE \"{}\" RENAME TO \"{}\"",
+        name, rebuilt, name
+    ))?;
+    Ok(())
+}
+
+/// A column of a table, as described by `PRAGMA table_info`.
+struct Column {
+    /// The name of the column.
+    name: String,
+    /// The declared type of the column.
+    column_type: String,
+    /// Whether the column is `NOT NULL`.
+    not_null: bool,
+    /// Whether the column has a default value.
+    has_default: bool,
+}
+
+/// Returns the columns of a table, in order.
+fn table_columns(connection: &Connection, table: &str) -> Result<Vec<Column>, CrawlerError> {
+    let columns = connection
+        .prepare(&format!("PRAGMA table_info(\"{}\")", table))?
+        .query_map([], |row| {
+            Ok(Column {
+                name: row.get(1)?,
+                column_type: row.get(2)?,
+                not_null: row.get(3)?,
+                has_default: row.get::<_, Option<String>>(4)?.is_some(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+/// Returns the definition of a table with its whitespace collapsed, for comparison.
+fn normalize_sql(sql: &str) -> String {
+    sql.replace('"', "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_and_adopts_databases() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), 0);
+        assert_eq!(migrate(&mut connection).unwrap(), MIGRATIONS.len());
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+        assert_eq!(migrate(&mut connection).unwrap(), 0);
+
+        // A database created with scripts/create.sql before migrations were recorded
+        let mut legacy = Connection::open_in_memory().unwrap();
+        legacy.execute_batch(MIGRATIONS[0]).unwrap();
+        legacy.pragma_update(None, "user_version", 1).unwrap();
+        assert_eq!(migrate(&mut legacy).unwrap(), MIGRATIONS.len() - 1);
+        let recorded: i64 = legacy
+            .query_row("SELECT COUNT(*) FROM SchemaVersion", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(recorded, SCHEMA_VERSION);
+
+        legacy
+            .execute(
+                "INSERT INTO SchemaVersion (Version) VALUES (?)",
+                [SCHEMA_VERSION + 1],
+            )
+            .unwrap();
+        assert!(matches!(migrate(&mut legacy), Err(CrawlerError::Config(_))));
+    }
+
+    /// Returns the normalized SQL of the tables and indexes of a database, by name.
+    fn schema_objects(connection: &Connection) -> Vec<(String, String)> {
+        connection
+            .prepare("SELECT name, sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY name")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, normalize_sql(&row.get::<_, String>(1)?)))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn upgrades_databases_of_earlier_releases() {
+        let mut current = Connection::open_in_memory().unwrap();
+        migrate(&mut current).unwrap();
+        let expected = schema_objects(&current);
+
+        let releases = [
+            (
+                "baseline",
+                include_str!("../tests/fixtures/create_baseline.sql"),
+                0,
+            ),
+            (
+                "version 1",
+                include_str!("../tests/fixtures/create_version_1.sql"),
+                1,
+            ),
+        ];
+        for (name, script, user_version) in releases {
+            let mut legacy = Connection::open_in_memory().unwrap();
+            legacy.execute_batch(script).unwrap();
+            legacy.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+            assert_eq!(schema_version(&legacy).unwrap(), user_version, "{}", name);
+            if user_version == 0 {
+                legacy
+                    .execute_batch(
+                        "INSERT INTO Page (Url, Hash) VALUES ('https://example.com/', 'hash');
+                        INSERT INTO Domain (Name) VALUES ('example.com');
+                        INSERT INTO DisallowedPattern (DomainId, Pattern) VALUES (1, '/private');
+                        INSERT INTO PageLink (PageId, Url) VALUES (1, 'https://example.com/about');",
+                    )
+                    .unwrap();
+            } else {
+                legacy
+                    .execute_batch(
+                        "INSERT INTO Page (Url, RequestedUrl, Hash)
+                            VALUES ('https://example.com/', 'https://example.com', 'hash');
+                        INSERT INTO Domain (Name) VALUES ('example.com');
+                        INSERT INTO PageLink (PageId, Url) VALUES (1, 'https://example.com/about');",
+                    )
+                    .unwrap();
+            }
+
+            assert_eq!(
+                migrate(&mut legacy).unwrap(),
+                MIGRATIONS.len() - 1,
+                "{}",
+                name
+            );
+            assert_eq!(schema_version(&legacy).unwrap(), SCHEMA_VERSION, "{}", name);
+            let mut objects = schema_objects(&legacy);
+            objects.retain(|(object, _)| expected.iter().any(|(other, _)| other == object));
+            assert_eq!(objects, expected, "{}", name);
+
+            let page: (String, String, i64) = legacy
+                .query_row(
+                    "SELECT Url, RequestedUrl, Indexed FROM Page WHERE Id = 1",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap();
+            let requested = if user_version == 0 {
+                "https://example.com/"
+            } else {
+                "https://example.com"
+            };
+            assert_eq!(
+                page,
+                ("https://example.com/".to_string(), requested.to_string(), 1),
+                "{}",
+                name
+            );
+            let links: i64 = legacy
+                .query_row(
+                    "SELECT COUNT(*) FROM PageLink WHERE PageId = 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(links, 1, "{}", name);
+            let foreign_keys: bool = legacy
+                .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+                .unwrap();
+            assert!(foreign_keys, "{}", name);
+            assert_eq!(migrate(&mut legacy).unwrap(), 0, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Scoring of queued URLs, so that the frontier hands out the most important ones first.
+
+use serde::Deserialize;
+use url::Url;
+
+/// What is known about a URL when it is queued.
+pub struct ScoreContext<'a> {
+    /// How many links away from a seed the URL was found.
+    pub depth: u32,
+    /// The page the URL was found on, or None for seeds and pages queued again by a recrawl.
+    pub source: Option<&'a Url>,
+    /// The priority of the URL in a sitemap, between 0 and 1, if it was listed in one.
+    pub sitemap_priority: Option<f64>,
+}
+
+/// Scores URLs when they are queued. URLs with higher scores are crawled first, and URLs with equal scores in the
+/// reverse order they were queued.
+pub trait Scorer: Send + Sync {
+    /// Scores a URL.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `context` - What is known about the URL.
+    ///
+    /// # Returns
+    /// The score. Any finite number can be used.
+    fn score(&self, url: &Url, context: &ScoreContext) -> f64;
+}
+
+/// The order the frontier hands out queued URLs in.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheduling {
+    /// The URL with the highest score first.
+    #[default]
+    Score,
+    /// One URL of each host in turn, the one with the highest score of its host, so that a large site queued first
+    /// does not take the whole budget of the crawl.
+    RoundRobin,
+}
+
+impl Scheduling {
+    /// Every scheduling.
+    pub const ALL: [Scheduling; 2] = [Scheduling::Score, Scheduling::RoundRobin];
+
+    /// Returns the name of the scheduling.
+    pub fn name(self) -> &'static str {
+        match self {
+            Scheduling::Score => "score",
+            Scheduling::RoundRobin => "round-robin",
+        }
+    }
+
+    /// Finds a scheduling by its name.
+    ///
+    /// # Arguments
+    /// * `name` - The name, e.g. `round-robin`.
+    ///
+    /// # Returns
+    /// The scheduling, or None if the name is unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|scheduling| scheduling.name() == name)
+    }
+}
+
+/// Scores URLs by a weighted sum of their distance from the seeds, whether they stay on the domain of the page they
+/// were found on, their sitemap priority and the number of segments of their path.
+#[derive(Clone)]
+pub struct WeightedScorer {
+    /// Subtracted for every link between a seed and the URL.
+    pub depth_weight: f64,
+    /// Added for URLs on the same host as the page they were found on, and for seeds.
+    pub same_domain_bonus: f64,
+    /// Multiplied with the sitemap priority, which is 0.5 for URLs not listed in a sitemap.
+    pub sitemap_weight: f64,
+    /// Subtracted for every segment of the path, so that `/docs/` comes before `/docs/2019/05/notes`.
+    pub path_depth_weight: f64,
+}
+
+impl Default for WeightedScorer {
+    fn default() -> Self {
+        WeightedScorer {
+            depth_weight: 1.0,
+            same_domain_bonus: 0.5,
+            sitemap_weight: 1.0,
+            path_depth_weight: 0.1,
+        }
+    }
+}
+
+impl Scorer for WeightedScorer {
+    fn score(&self, url: &Url, context: &ScoreContext) -> f64 {
+        let same_domain = context
+            .source
+            .is_none_or(|source| source.host_str() == url.host_str());
+        let path_depth = url
+            .path_segments()
+            .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count());
+        let mut score = -self.depth_weight * f64::from(context.depth)
+            + self.sitemap_weight * context.sitemap_priority.unwrap_or(0.5)
+            - self.path_depth_weight * path_depth as f64;
+        if same_domain {
+            score += self.same_domain_bonus;
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighs_depth_domain_sitemap_and_path() {
+        let scorer = WeightedScorer::default();
+        let source = Url::parse("https://example.com/docs/").unwrap();
+        let score = |url: &str, depth, source, sitemap_priority| {
+            let context = ScoreContext {
+                depth,
+                source,
+                sitemap_priority,
+            };
+            scorer.score(&Url::parse(url).unwrap(), &context)
+        };
+        let cases = [
+            // A seed gets the same domain bonus and the default sitemap priority
+            (score("https://example.com/", 0, None, None), 1.0),
+            (score("https://example.com/docs/", 0, None, None), 0.9),
+            (
+                score("https://example.com/docs/a", 1, Some(&source), None),
+                -0.2,
+            ),
+            (
+                score("https://example.com/docs/a", 1, Some(&source), Some(1.0)),
+                0.3,
+            ),
+            (
+                score("https://example.com/docs/a", 2, Some(&source), Some(0.0)),
+                -1.7,
+            ),
+            (
+                score("https://other.example/a", 1, Some(&source), None),
+                -0.6,
+            ),
+        ];
+        for (index, (score, expected)) in cases.into_iter().enumerate() {
+            assert!((score - expected).abs() < 1e-9, "case {}: {}", index, score);
+        }
+
+        let flat = WeightedScorer {
+            depth_weight: 0.0,
+            same_domain_bonus: 0.0,
+            sitemap_weight: 0.0,
+            path_depth_weight: 0.0,
+        };
+        let context = ScoreContext {
+            depth: 3,
+            source: Some(&source),
+            sitemap_priority: Some(1.0),
+        };
+        assert_eq!(flat.score(&source, &context), 0.0);
+    }
+}
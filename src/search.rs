@@ -0,0 +1,172 @@
+//! Full-text search of crawled pages and link anchors.
+
+use rusqlite::{params, Connection};
+use scraper::Html;
+use tracing::warn;
+
+use crate::charset::decode_saved;
+use crate::content::plain_text;
+use crate::error::CrawlerError;
+use crate::reader::BodyReader;
+use crate::terms;
+
+/// A page found by the text of the links pointing at it.
+pub struct AnchorMatch {
+    /// The URL of the page.
+    pub url: String,
+    /// The number of matching links pointing at the page.
+    pub links: i64,
+    /// The distinct matching anchor texts.
+    pub anchor_texts: Vec<String>,
+    /// Whether the page has been crawled.
+    pub crawled: bool,
+}
+
+/// A page found by its text.
+pub struct PageMatch {
+    /// The URL of the page.
+    pub url: String,
+    /// The content hash of the page.
+    pub hash: String,
+    /// An excerpt of the text around the matches, with matches in square brackets.
+    pub snippet: String,
+    /// The BM25 relevance of the page. Lower is more relevant.
+    pub rank: f64,
+}
+
+/// Searches for pages by their visible text.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `query` - An FTS5 query, e.g. `rust async` or `"getting started"`.
+/// * `tag` - Only pages with this tag, if given.
+/// * `limit` - The maximum number of pages to return.
+///
+/// # Returns
+/// The matching pages, best matches first.
+pub fn search_pages(
+    connection: &Connection,
+    query: &str,
+    tag: Option<&str>,
+    limit: usize,
+) -> Result<Vec<PageMatch>, CrawlerError> {
+    let mut stmt = connection.prepare(
+        "SELECT PageSearch.Url, snippet(PageSearch, 2, '[', ']', '...', 16), rank, Page.Hash
+        FROM PageSearch JOIN Page ON Page.Id = PageSearch.PageId
+        WHERE PageSearch MATCH ?1
+            AND (?2 IS NULL OR EXISTS (SELECT 1 FROM PageTag WHERE PageTag.PageId = Page.Id AND Tag = ?2))
+        ORDER BY rank
+        LIMIT ?3",
+    )?;
+    let matches = stmt
+        .query_map(params![query, tag, limit], |row| {
+            Ok(PageMatch {
+                url: row.get(0)?,
+                hash: row.get(3)?,
+                snippet: row.get(1)?,
+                rank: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(matches)
+}
+
+/// Rebuilds the plain text, search index and term counts of every distinct page from its saved HTML file.
+///
+/// Files are decompressed automatically, routed pages are read from the files they were routed to, and pages saved
+/// as objects from the object store of the reader. Pages saved in WARC or JSONL files, or whose contents were not
+/// saved, are left as they are.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `reader` - Reads the saved bodies of the pages.
+///
+/// # Returns
+/// The number of pages reindexed, and the number whose file could not be read.
+pub async fn reindex(
+    connection: &mut Connection,
+    reader: &BodyReader,
+) -> Result<(usize, usize), CrawlerError> {
+    let transaction = connection.transaction()?;
+    let pages = transaction
+        .prepare(
+            "SELECT Page.Id, Page.Url, Page.Charset, PageMeta.Language
+            FROM Page LEFT JOIN PageMeta ON PageMeta.PageId = Page.Id
+            WHERE DuplicateOf IS NULL AND Indexed = 1 AND WarcFile IS NULL AND Hash IS NOT NULL
+                AND COALESCE(StoredFile NOT LIKE '%.jsonl', 1)",
+        )?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (mut reindexed, mut missing) = (0, 0);
+    for (page_id, url, charset, language) in pages {
+        let body = match reader.read_url(&url).await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to read the saved file of {}: {}", url, e);
+                missing += 1;
+                continue;
+            }
+        };
+        let text = plain_text(&Html::parse_document(&decode_saved(
+            &body,
+            charset.as_deref(),
+        )));
+        transaction.execute(
+            "INSERT OR REPLACE INTO PageText (PageId, Text) VALUES (?, ?)",
+            params![page_id, text],
+        )?;
+        transaction.execute("DELETE FROM PageSearch WHERE PageId = ?", [page_id])?;
+        transaction.execute(
+            "INSERT INTO PageSearch (PageId, Url, Text) VALUES (?, ?, ?)",
+            params![page_id, url, text],
+        )?;
+        terms::record_terms(&transaction, page_id, &text, language.as_deref())?;
+        reindexed += 1;
+    }
+    transaction.commit()?;
+    Ok((reindexed, missing))
+}
+
+/// Searches for pages by the anchor texts of links pointing at them.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `query` - An FTS5 query, e.g. `pricing` or `"getting started"`.
+/// * `limit` - The maximum number of pages to return.
+///
+/// # Returns
+/// The matching pages, best matches first.
+pub fn search_anchors(
+    connection: &Connection,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<AnchorMatch>, CrawlerError> {
+    let mut stmt = connection.prepare(
+        "SELECT Url, COUNT(*), json_group_array(DISTINCT Text), MIN(rank),
+            EXISTS (SELECT 1 FROM Page WHERE Page.Url = LinkAnchor.Url)
+        FROM LinkAnchor
+        WHERE LinkAnchor MATCH ?
+        GROUP BY Url
+        ORDER BY MIN(rank), COUNT(*) DESC
+        LIMIT ?",
+    )?;
+    let matches = stmt
+        .query_map(params![query, limit], |row| {
+            Ok(AnchorMatch {
+                url: row.get(0)?,
+                links: row.get(1)?,
+                anchor_texts: serde_json::from_str(&row.get::<_, String>(2)?).unwrap_or_default(),
+                crawled: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(matches)
+}
@@ -0,0 +1,86 @@
+//! Graceful shutdown of a crawl on Ctrl+C or SIGTERM.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// The time in-flight requests are given to finish once a shutdown is requested, unless set otherwise.
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// Asks a crawler to stop: it takes no more URLs from the frontier, and waits for the requests in flight until the
+/// grace period runs out, after which they are put back in the frontier. Clones share the request.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    /// Creates a `Shutdown` that has not been requested.
+    pub fn new() -> Self {
+        Shutdown::default()
+    }
+
+    /// Requests the shutdown.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Returns whether the shutdown was requested.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the shutdown is requested.
+    pub async fn requested(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Requests the shutdown on the first Ctrl+C or SIGTERM, and exits the process at once on the second.
+    ///
+    /// # Arguments
+    /// * `grace` - The grace period of in-flight requests, for the message shown on the first signal.
+    pub fn listen_for_signals(&self, grace: Duration) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            if signal().await.is_err() {
+                return;
+            }
+            warn!(
+                "Stopping after the requests in flight, for at most {} seconds. Press Ctrl+C again to quit at once",
+                grace.as_secs()
+            );
+            shutdown.request();
+            if signal().await.is_ok() {
+                warn!("Quitting without saving the frontier");
+                std::process::exit(130);
+            }
+        });
+    }
+}
+
+/// Waits for Ctrl+C, or on Unix for SIGTERM too.
+async fn signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
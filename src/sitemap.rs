@@ -0,0 +1,451 @@
+//! Sitemaps in the sitemaps.org XML format: those of the stored pages of a domain, and those sites list in their
+//! robots.txt, which give the priority of their URLs.
+
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use rusqlite::{params_from_iter, Connection};
+use std::io::{Read, Write};
+use std::sync::LazyLock;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::normalize::normalize_host;
+
+/// The most URLs a sitemap may list.
+pub const MAX_SITEMAP_URLS: usize = 50_000;
+/// The largest size of an uncompressed sitemap, in bytes.
+pub const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+
+/// The namespace of sitemaps and sitemap indexes.
+const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+/// The size of the XML declaration, the opening and the closing tag of a sitemap, rounded up.
+const SITEMAP_OVERHEAD: usize = 200;
+/// The priority of a URL listed without one, as the protocol defines.
+pub const DEFAULT_PRIORITY: f64 = 0.5;
+
+/// A `<url>` element of a sitemap or a `<sitemap>` element of a sitemap index, with its contents.
+static SITEMAP_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<(url|sitemap)(?:\s[^>]*)?>(.*?)</(?:url|sitemap)\s*>").unwrap()
+});
+/// The `<loc>` of an element, which may be a CDATA section.
+static LOC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<loc(?:\s[^>]*)?>\s*(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?\s*</loc\s*>").unwrap()
+});
+/// The `<priority>` of a `<url>` element.
+static PRIORITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<priority(?:\s[^>]*)?>\s*([^<]*?)\s*</priority\s*>").unwrap());
+
+/// What a sitemap read from a site lists.
+#[derive(Default)]
+pub struct ParsedSitemap {
+    /// The URLs of a sitemap, with their priority between 0 and 1.
+    pub urls: Vec<(String, f64)>,
+    /// The sitemaps of a sitemap index.
+    pub sitemaps: Vec<String>,
+}
+
+/// Reads the URLs of a sitemap, or the sitemaps of a sitemap index.
+///
+/// URLs listed without a valid priority get [`DEFAULT_PRIORITY`], and priorities are clamped between 0 and 1.
+///
+/// # Arguments
+/// * `xml` - The contents of the sitemap.
+///
+/// # Returns
+/// The URLs and sitemaps listed, in document order.
+pub fn parse_sitemap(xml: &str) -> ParsedSitemap {
+    let mut parsed = ParsedSitemap::default();
+    for element in SITEMAP_ELEMENT.captures_iter(xml) {
+        let Some(loc) = LOC.captures(&element[2]).map(|loc| unescape_xml(&loc[1])) else {
+            continue;
+        };
+        if &element[1] == "sitemap" {
+            parsed.sitemaps.push(loc);
+            continue;
+        }
+        let priority = PRIORITY
+            .captures(&element[2])
+            .and_then(|priority| priority[1].parse::<f64>().ok())
+            .filter(|priority| priority.is_finite())
+            .map_or(DEFAULT_PRIORITY, |priority| priority.clamp(0.0, 1.0));
+        parsed.urls.push((loc, priority));
+    }
+    parsed
+}
+
+/// Decompresses a gzipped sitemap, e.g. `sitemap.xml.gz`, and returns any other sitemap as it is.
+///
+/// # Returns
+/// The sitemap, or an error if it does not decompress or is larger than [`MAX_SITEMAP_BYTES`].
+pub fn decompress_sitemap(body: Vec<u8>) -> Result<Vec<u8>, CrawlerError> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Ok(body);
+    }
+    let mut decoded = Vec::new();
+    MultiGzDecoder::new(body.as_slice())
+        .take(MAX_SITEMAP_BYTES as u64 + 1)
+        .read_to_end(&mut decoded)?;
+    if decoded.len() > MAX_SITEMAP_BYTES {
+        return Err(CrawlerError::Parse(format!(
+            "Sitemap is larger than {} bytes",
+            MAX_SITEMAP_BYTES
+        )));
+    }
+    Ok(decoded)
+}
+
+/// A URL listed in a sitemap.
+pub struct SitemapEntry {
+    /// The URL of the page.
+    pub url: String,
+    /// When the page was last fetched, as a W3C datetime in UTC.
+    pub lastmod: Option<String>,
+}
+
+impl SitemapEntry {
+    /// Returns the `<url>` element of the entry.
+    fn element(&self) -> String {
+        match &self.lastmod {
+            Some(lastmod) => format!(
+                "  <url><loc>{}</loc><lastmod>{}</lastmod></url>\n",
+                escape_xml(&self.url),
+                lastmod
+            ),
+            None => format!("  <url><loc>{}</loc></url>\n", escape_xml(&self.url)),
+        }
+    }
+}
+
+/// Reads the pages of a domain that belong in a sitemap.
+///
+/// These are the stored pages whose host is the domain, leaving out duplicates, pages whose contents were not
+/// indexed, e.g. because they are marked `noindex`, and pages declaring another page as canonical.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `domain` - The host of the pages.
+///
+/// # Returns
+/// The pages ordered by URL, with the time of their latest fetch as `lastmod`.
+pub fn sitemap_entries(
+    connection: &Connection,
+    domain: &str,
+) -> Result<Vec<SitemapEntry>, CrawlerError> {
+    let domain = normalize_host(domain);
+    // The URLs of the host sort between these bounds, after the host comes either the path or the port
+    let bounds: Vec<String> = ["http", "https"]
+        .into_iter()
+        .flat_map(|scheme| [(scheme, '/', '0'), (scheme, ':', ';')])
+        .flat_map(|(scheme, after, next)| {
+            [
+                format!("{}://{}{}", scheme, domain, after),
+                format!("{}://{}{}", scheme, domain, next),
+            ]
+        })
+        .collect();
+    let pages = connection
+        .prepare(
+            "WITH DomainPage AS (
+                SELECT Id, Url, Updated FROM Page
+                WHERE ((Url >= ?1 AND Url < ?2) OR (Url >= ?3 AND Url < ?4)
+                    OR (Url >= ?5 AND Url < ?6) OR (Url >= ?7 AND Url < ?8))
+                    AND DuplicateOf IS NULL AND Indexed = 1 AND NoIndex = 0
+            )
+            SELECT DomainPage.Url, PageMeta.Canonical, COALESCE(LastFetch.Fetched, DomainPage.Updated)
+            FROM DomainPage
+            LEFT JOIN PageMeta ON PageMeta.PageId = DomainPage.Id
+            LEFT JOIN (
+                SELECT PageId, MAX(Fetched) AS Fetched FROM PageFetch
+                WHERE PageId IN (SELECT Id FROM DomainPage) GROUP BY PageId
+            ) AS LastFetch ON LastFetch.PageId = DomainPage.Id
+            ORDER BY DomainPage.Url",
+        )?
+        .query_map(params_from_iter(&bounds), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(pages
+        .into_iter()
+        .filter_map(|(url, canonical, fetched)| {
+            let parsed = Url::parse(&url).ok()?;
+            if parsed.host_str() != Some(domain.as_str()) {
+                return None;
+            }
+            let canonical = canonical.and_then(|canonical| Url::parse(&canonical).ok());
+            if canonical.is_some_and(|canonical| canonical != parsed) {
+                return None;
+            }
+            Some(SitemapEntry {
+                url,
+                lastmod: fetched.map(|fetched| format!("{}+00:00", fetched.replace(' ', "T"))),
+            })
+        })
+        .collect())
+}
+
+/// Splits entries into sitemaps within the limits of [`MAX_SITEMAP_URLS`] and [`MAX_SITEMAP_BYTES`].
+///
+/// # Returns
+/// The entries of each sitemap, one sitemap if they all fit in it.
+pub fn split_sitemaps(entries: &[SitemapEntry]) -> Vec<&[SitemapEntry]> {
+    let mut sitemaps = Vec::new();
+    let (mut start, mut bytes) = (0, SITEMAP_OVERHEAD);
+    for (index, entry) in entries.iter().enumerate() {
+        let size = entry.element().len();
+        if index > start && (index - start == MAX_SITEMAP_URLS || bytes + size > MAX_SITEMAP_BYTES)
+        {
+            sitemaps.push(&entries[start..index]);
+            (start, bytes) = (index, SITEMAP_OVERHEAD);
+        }
+        bytes += size;
+    }
+    if start < entries.len() || sitemaps.is_empty() {
+        sitemaps.push(&entries[start..]);
+    }
+    sitemaps
+}
+
+/// Writes a sitemap.
+///
+/// # Arguments
+/// * `entries` - The URLs, at most [`MAX_SITEMAP_URLS`].
+/// * `writer` - Where to write the sitemap.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub fn write_sitemap(
+    entries: &[SitemapEntry],
+    writer: &mut impl Write,
+) -> Result<(), CrawlerError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<urlset xmlns="{}">"#, SITEMAP_NAMESPACE)?;
+    for entry in entries {
+        writer.write_all(entry.element().as_bytes())?;
+    }
+    writeln!(writer, "</urlset>")?;
+    Ok(())
+}
+
+/// Writes a sitemap index.
+///
+/// # Arguments
+/// * `sitemaps` - The URLs of the sitemaps.
+/// * `writer` - Where to write the index.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub fn write_sitemap_index(sitemaps: &[Url], writer: &mut impl Write) -> Result<(), CrawlerError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<sitemapindex xmlns="{}">"#, SITEMAP_NAMESPACE)?;
+    for sitemap in sitemaps {
+        writeln!(
+            writer,
+            "  <sitemap><loc>{}</loc></sitemap>",
+            escape_xml(sitemap.as_str())
+        )?;
+    }
+    writeln!(writer, "</sitemapindex>")?;
+    Ok(())
+}
+
+/// Escapes the characters of a string that are special in XML.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Replaces the entities of the characters that are special in XML, the reverse of [`escape_xml`].
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: String) -> SitemapEntry {
+        SitemapEntry { url, lastmod: None }
+    }
+
+    #[test]
+    fn lists_the_indexed_pages_of_a_host() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute("INSERT INTO CrawlRun DEFAULT VALUES", [])
+            .unwrap();
+        let pages = [
+            ("https://example.com/", 0, 1, None),
+            ("https://example.com/b?x=1&y=2", 0, 1, None),
+            ("http://example.com:8080/port", 0, 1, None),
+            ("https://example.com/noindex", 1, 1, None),
+            ("https://example.com/skipped", 0, 0, None),
+            (
+                "https://example.com/copy",
+                0,
+                1,
+                Some("https://example.com/"),
+            ),
+            ("https://example.community/", 0, 1, None),
+            ("https://www.example.com/", 0, 1, None),
+            ("https://example.org/", 0, 1, None),
+        ];
+        for (url, no_index, indexed, canonical) in pages {
+            connection
+                .execute(
+                    "INSERT INTO Page (Url, RequestedUrl, Hash, NoIndex, Indexed, Updated)
+                    VALUES (?1, ?1, '', ?2, ?3, '2024-01-01 00:00:00')",
+                    rusqlite::params![url, no_index, indexed],
+                )
+                .unwrap();
+            connection
+                .execute(
+                    "INSERT INTO PageMeta (PageId, Canonical, OpenGraph)
+                    VALUES (last_insert_rowid(), ?, '{}')",
+                    [canonical],
+                )
+                .unwrap();
+        }
+        for fetched in ["2024-02-01 10:00:00", "2024-03-01 12:30:00"] {
+            connection
+                .execute(
+                    "INSERT INTO PageFetch (RunId, PageId, Url, FinalUrl, Status, DurationMs, Headers, Fetched)
+                    VALUES (1, 1, 'https://example.com/', 'https://example.com/', 200, 1, '{}', ?)",
+                    [fetched],
+                )
+                .unwrap();
+        }
+
+        let entries = sitemap_entries(&connection, "EXAMPLE.com").unwrap();
+        let listed: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.url.as_str(), entry.lastmod.as_deref()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (
+                    "http://example.com:8080/port",
+                    Some("2024-01-01T00:00:00+00:00")
+                ),
+                ("https://example.com/", Some("2024-03-01T12:30:00+00:00")),
+                (
+                    "https://example.com/b?x=1&y=2",
+                    Some("2024-01-01T00:00:00+00:00")
+                ),
+            ]
+        );
+        assert_eq!(
+            entries[2].element(),
+            "  <url><loc>https://example.com/b?x=1&amp;y=2</loc>\
+                <lastmod>2024-01-01T00:00:00+00:00</lastmod></url>\n"
+        );
+    }
+
+    #[test]
+    fn splits_sitemaps_at_the_limits() {
+        assert_eq!(split_sitemaps(&[]).len(), 1);
+
+        let many: Vec<_> = (0..MAX_SITEMAP_URLS + 1)
+            .map(|index| entry(format!("https://example.com/{}", index)))
+            .collect();
+        let sitemaps = split_sitemaps(&many);
+        assert_eq!(
+            sitemaps
+                .iter()
+                .map(|sitemap| sitemap.len())
+                .collect::<Vec<_>>(),
+            [MAX_SITEMAP_URLS, 1]
+        );
+
+        // Each URL takes a little over a fiftieth of the size of a sitemap
+        let long: Vec<_> = (0..60)
+            .map(|index| {
+                entry(format!(
+                    "https://example.com/{}/{}",
+                    index,
+                    "a".repeat(1 << 20)
+                ))
+            })
+            .collect();
+        let sitemaps = split_sitemaps(&long);
+        assert_eq!(
+            sitemaps
+                .iter()
+                .map(|sitemap| sitemap.len())
+                .collect::<Vec<_>>(),
+            [49, 11]
+        );
+        for sitemap in sitemaps {
+            let mut xml = Vec::new();
+            write_sitemap(sitemap, &mut xml).unwrap();
+            assert!(xml.len() <= MAX_SITEMAP_BYTES);
+        }
+    }
+
+    #[test]
+    fn escapes_xml() {
+        let text = r#"/search?q="a" & b<c>'d'"#;
+        let escaped = escape_xml(text);
+        assert_eq!(
+            escaped,
+            "/search?q=&quot;a&quot; &amp; b&lt;c&gt;&apos;d&apos;"
+        );
+        assert_eq!(unescape_xml(&escaped), text);
+        assert_eq!(unescape_xml("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn parses_sitemaps_and_indexes() {
+        let sitemap = parse_sitemap(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/</loc><priority>1.0</priority></url>
+              <url>
+                <loc> https://example.com/search?q=a&amp;page=2 </loc>
+                <lastmod>2024-01-01</lastmod>
+                <priority>0.2</priority>
+              </url>
+              <url><loc><![CDATA[https://example.com/about]]></loc></url>
+              <url><loc>https://example.com/top</loc><priority>7</priority></url>
+              <url><priority>0.9</priority></url>
+            </urlset>"#,
+        );
+        assert_eq!(
+            sitemap.urls,
+            [
+                ("https://example.com/".to_string(), 1.0),
+                ("https://example.com/search?q=a&page=2".to_string(), 0.2),
+                ("https://example.com/about".to_string(), DEFAULT_PRIORITY),
+                ("https://example.com/top".to_string(), 1.0),
+            ]
+        );
+        assert!(sitemap.sitemaps.is_empty());
+
+        let index = parse_sitemap(
+            r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap>
+              <sitemap><loc>https://example.com/sitemap-2.xml.gz</loc><lastmod>2024-01-01</lastmod></sitemap>
+            </sitemapindex>"#,
+        );
+        assert!(index.urls.is_empty());
+        assert_eq!(
+            index.sitemaps,
+            [
+                "https://example.com/sitemap-1.xml",
+                "https://example.com/sitemap-2.xml.gz"
+            ]
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! A summary of the crawl database.
+
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use url::Url;
+
+use crate::content::THIN_CONTENT_WORDS;
+use crate::error::CrawlerError;
+
+/// Totals of the crawl database, and a breakdown per domain.
+#[derive(Serialize)]
+pub struct CrawlStats {
+    /// The number of stored pages.
+    pub pages: i64,
+    /// The number of stored pages with the same contents as another page.
+    pub duplicates: i64,
+    /// The number of distinct pages with fewer than [`THIN_CONTENT_WORDS`] words.
+    pub thin_pages: i64,
+    /// The number of requests sent.
+    pub fetches: i64,
+    /// The average time requests took, in milliseconds.
+    pub average_fetch_ms: Option<f64>,
+    /// The number of known domains.
+    pub domains: i64,
+    /// The number of distinct URLs linked to from stored pages.
+    pub links: i64,
+    /// The number of links not followed because their page is marked nofollow.
+    pub suppressed_links: i64,
+    /// The number of pages marked nofollow that had links.
+    pub nofollow_pages: i64,
+    /// The number of URLs that could not be fetched.
+    pub failures: i64,
+    /// The number of distinct URLs not crawled because robots.txt disallows them.
+    pub robots_blocked: i64,
+    /// The statistics of each domain, by name.
+    pub per_domain: BTreeMap<String, DomainStats>,
+    /// How long crawled URLs waited in the frontier, and the URLs still waiting.
+    pub frontier: FrontierStats,
+    /// The most recent crawl runs, latest first.
+    pub recent_runs: Vec<RunSummary>,
+    /// The domains that are currently banned.
+    pub banned: Vec<BannedDomain>,
+}
+
+/// The statistics of a single domain.
+#[derive(Default, Serialize)]
+pub struct DomainStats {
+    /// The number of stored pages of the domain.
+    pub pages: i64,
+    /// The number of requests sent to the domain.
+    pub fetches: i64,
+    /// The average time requests to the domain took, in milliseconds.
+    pub average_fetch_ms: Option<f64>,
+    /// The number of URLs of the domain that could not be fetched.
+    pub failures: i64,
+    /// The number of distinct URLs of the domain not crawled because robots.txt disallows them.
+    pub robots_blocked: i64,
+    #[serde(skip)]
+    total_fetch_ms: i64,
+}
+
+/// How long crawled URLs waited in the frontier, to tune the fairness of broad crawls.
+#[derive(Serialize)]
+pub struct FrontierStats {
+    /// The waits of every crawled URL, or None if no URL was crawled.
+    pub wait: Option<WaitPercentiles>,
+    /// The waits of the URLs of each domain, by name.
+    pub wait_per_domain: BTreeMap<String, WaitPercentiles>,
+    /// The waits of the URLs found at each depth, the number of links away from the seeds.
+    pub wait_per_depth: BTreeMap<u32, WaitPercentiles>,
+    /// The URLs left in the frontier by the last run of each domain, by name.
+    pub waiting: BTreeMap<String, WaitingUrls>,
+}
+
+/// Percentiles of the time URLs waited in the frontier before they were crawled.
+#[derive(Serialize)]
+pub struct WaitPercentiles {
+    /// The number of crawled URLs.
+    pub urls: usize,
+    /// The median wait, in milliseconds.
+    pub p50_ms: i64,
+    /// The 90th percentile of waits, in milliseconds.
+    pub p90_ms: i64,
+    /// The 99th percentile of waits, in milliseconds.
+    pub p99_ms: i64,
+    /// The longest wait, in milliseconds.
+    pub max_ms: i64,
+}
+
+impl WaitPercentiles {
+    /// Computes the nearest-rank percentiles of waits, or None if there are none.
+    fn from_waits(mut waits: Vec<i64>) -> Option<Self> {
+        if waits.is_empty() {
+            return None;
+        }
+        waits.sort_unstable();
+        let percentile = |p: usize| waits[(waits.len() * p).div_ceil(100).max(1) - 1];
+        Some(WaitPercentiles {
+            urls: waits.len(),
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms: waits[waits.len() - 1],
+        })
+    }
+}
+
+/// URLs still waiting in the frontier, which may be starved by other domains.
+#[derive(Default, Serialize)]
+pub struct WaitingUrls {
+    /// The number of waiting URLs.
+    pub urls: i64,
+    /// How long the URL queued first has been waiting, in seconds.
+    pub oldest_secs: f64,
+}
+
+/// When a crawl run started and how much memory it used.
+#[derive(Serialize)]
+pub struct RunSummary {
+    /// The id of the run.
+    pub id: i64,
+    /// When the run started, in UTC.
+    pub started: String,
+    /// When the run finished, in UTC, or None if it is still running or was aborted.
+    pub finished: Option<String>,
+    /// How the run ended: `completed` with an empty frontier, `incomplete` with URLs left, or `stopped` on request.
+    pub outcome: Option<String>,
+    /// The seed URLs of the run.
+    pub seeds: Vec<String>,
+    /// The highest approximate memory usage of the run, in bytes.
+    pub peak_memory: i64,
+    /// The id of the run this run is a recrawl of.
+    pub parent_id: Option<i64>,
+    /// The number of pages stored by the run that an earlier run had fetched.
+    pub carried_over: i64,
+    /// The number of pages first stored by the run.
+    pub discovered: i64,
+    /// The number of URLs that failed during the run.
+    pub failures: i64,
+}
+
+/// A domain that is banned from crawling.
+#[derive(Serialize)]
+pub struct BannedDomain {
+    /// The name of the domain.
+    pub name: String,
+    /// When the ban ends, in UTC.
+    pub banned_until: String,
+}
+
+impl CrawlStats {
+    /// Reads the statistics from the database.
+    ///
+    /// # Arguments
+    /// * `connection` - The database connection.
+    ///
+    /// # Returns
+    /// The statistics, or an error if the database could not be read.
+    pub fn load(connection: &Connection) -> Result<Self, CrawlerError> {
+        let count = |query: &str| connection.query_row(query, [], |row| row.get::<_, i64>(0));
+        let thin: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM Page WHERE WordCount < ? AND DuplicateOf IS NULL",
+            [THIN_CONTENT_WORDS],
+            |row| row.get(0),
+        )?;
+        let (nofollow_pages, suppressed_links): (i64, i64) = connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(SuppressedLinks), 0) FROM Page WHERE SuppressedLinks > 0",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let (fetches, average_fetch_ms): (i64, Option<f64>) = connection.query_row(
+            "SELECT COUNT(*), AVG(DurationMs) FROM PageFetch",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let mut per_domain = BTreeMap::<String, DomainStats>::new();
+        let domain_of = |url: &str| Url::parse(url).ok()?.host_str().map(str::to_string);
+        let mut stmt = connection.prepare("SELECT Url FROM Page")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(domain) = domain_of(&row.get::<_, String>(0)?) {
+                per_domain.entry(domain).or_default().pages += 1;
+            }
+        }
+        let mut stmt = connection.prepare("SELECT Url, DurationMs FROM PageFetch")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(domain) = domain_of(&row.get::<_, String>(0)?) {
+                let stats = per_domain.entry(domain).or_default();
+                stats.fetches += 1;
+                stats.total_fetch_ms += row.get::<_, i64>(1)?;
+            }
+        }
+        let mut stmt = connection.prepare(
+            "SELECT Name,
+                (SELECT COUNT(*) FROM FailedUrl WHERE DomainId = Domain.Id),
+                (SELECT COUNT(*) FROM RobotsBlockedUrl WHERE DomainId = Domain.Id)
+            FROM Domain",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let stats = per_domain.entry(row.get(0)?).or_default();
+            stats.failures = row.get(1)?;
+            stats.robots_blocked = row.get(2)?;
+        }
+        for stats in per_domain.values_mut() {
+            if stats.fetches > 0 {
+                stats.average_fetch_ms = Some(stats.total_fetch_ms as f64 / stats.fetches as f64);
+            }
+        }
+
+        let mut all_waits = Vec::new();
+        let mut domain_waits = BTreeMap::<String, Vec<i64>>::new();
+        let mut depth_waits = BTreeMap::<u32, Vec<i64>>::new();
+        let mut stmt = connection.prepare(
+            "SELECT Domain.Name, FrontierWait.Depth, FrontierWait.WaitMs
+            FROM FrontierWait JOIN Domain ON Domain.Id = FrontierWait.DomainId",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let wait: i64 = row.get(2)?;
+            all_waits.push(wait);
+            domain_waits.entry(row.get(0)?).or_default().push(wait);
+            depth_waits.entry(row.get(1)?).or_default().push(wait);
+        }
+        let mut waiting = BTreeMap::<String, WaitingUrls>::new();
+        let mut stmt = connection.prepare(
+            "SELECT Url, (julianday('now') - julianday(Created)) * 86400 FROM FrontierUrl WHERE Status != 'done'",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(domain) = domain_of(&row.get::<_, String>(0)?) {
+                let urls = waiting.entry(domain).or_default();
+                urls.urls += 1;
+                urls.oldest_secs = urls
+                    .oldest_secs
+                    .max(row.get::<_, Option<f64>>(1)?.unwrap_or(0.0));
+            }
+        }
+        let frontier = FrontierStats {
+            wait: WaitPercentiles::from_waits(all_waits),
+            wait_per_domain: domain_waits
+                .into_iter()
+                .filter_map(|(domain, waits)| Some((domain, WaitPercentiles::from_waits(waits)?)))
+                .collect(),
+            wait_per_depth: depth_waits
+                .into_iter()
+                .filter_map(|(depth, waits)| Some((depth, WaitPercentiles::from_waits(waits)?)))
+                .collect(),
+            waiting,
+        };
+
+        let recent_runs = connection
+            .prepare(
+                "SELECT Id, Started, PeakMemory, ParentRunId,
+                    (SELECT COUNT(*) FROM RunPage WHERE RunId = CrawlRun.Id AND CarriedOver = 1),
+                    (SELECT COUNT(*) FROM RunPage WHERE RunId = CrawlRun.Id AND CarriedOver = 0),
+                    Finished, Outcome, Seeds, Failures
+                FROM CrawlRun ORDER BY Id DESC LIMIT 5",
+            )?
+            .query_map([], |row| {
+                Ok(RunSummary {
+                    id: row.get(0)?,
+                    started: row.get(1)?,
+                    finished: row.get(6)?,
+                    outcome: row.get(7)?,
+                    seeds: serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or_default(),
+                    peak_memory: row.get(2)?,
+                    parent_id: row.get(3)?,
+                    carried_over: row.get(4)?,
+                    discovered: row.get(5)?,
+                    failures: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let banned = connection
+            .prepare(
+                "SELECT Name, BannedUntil FROM Domain WHERE BannedUntil > CURRENT_TIMESTAMP ORDER BY Name",
+            )?
+            .query_map([], |row| {
+                Ok(BannedDomain {
+                    name: row.get(0)?,
+                    banned_until: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CrawlStats {
+            pages: count("SELECT COUNT(*) FROM Page")?,
+            duplicates: count("SELECT COUNT(*) FROM Page WHERE DuplicateOf IS NOT NULL")?,
+            thin_pages: thin,
+            fetches,
+            average_fetch_ms,
+            domains: count("SELECT COUNT(*) FROM Domain")?,
+            links: count("SELECT COUNT(DISTINCT Url) FROM PageLink")?,
+            suppressed_links,
+            nofollow_pages,
+            failures: count("SELECT COUNT(*) FROM FailedUrl")?,
+            robots_blocked: count("SELECT COUNT(*) FROM RobotsBlockedUrl")?,
+            per_domain,
+            frontier,
+            recent_runs,
+            banned,
+        })
+    }
+}
+
+/// Writes a summary of the crawl database.
+///
+/// Includes the number of stored pages, requests, domains and links, how many pages are thin content, how many links
+/// were not followed because their page is marked nofollow, how many URLs failed or were disallowed by robots.txt, a
+/// table of these counts per domain, percentiles of the time URLs waited in the frontier, the URLs left in the
+/// frontier, and the domains that are currently banned.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `writer` - The destination of the summary.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub fn write_stats(connection: &Connection, writer: &mut impl Write) -> Result<(), CrawlerError> {
+    let stats = CrawlStats::load(connection)?;
+    writeln!(
+        writer,
+        "Pages:    {} ({} duplicates, {} with fewer than {} words)",
+        stats.pages, stats.duplicates, stats.thin_pages, THIN_CONTENT_WORDS
+    )?;
+    writeln!(
+        writer,
+        "Requests: {} (average {:.0} ms)",
+        stats.fetches,
+        stats.average_fetch_ms.unwrap_or(0.0)
+    )?;
+    writeln!(writer, "Domains:  {}", stats.domains)?;
+    writeln!(
+        writer,
+        "Links:    {} discovered, {} not followed on {} pages marked nofollow",
+        stats.links, stats.suppressed_links, stats.nofollow_pages
+    )?;
+    writeln!(
+        writer,
+        "Failures: {} URLs, {} disallowed by robots.txt",
+        stats.failures, stats.robots_blocked
+    )?;
+
+    if !stats.per_domain.is_empty() {
+        let width = stats
+            .per_domain
+            .keys()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max("Domain".len());
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "  {:<width$}  {:>7}  {:>8}  {:>7}  {:>7}  {:>7}",
+            "Domain", "Pages", "Requests", "Avg ms", "Failed", "Blocked"
+        )?;
+        for (name, domain) in &stats.per_domain {
+            writeln!(
+                writer,
+                "  {:<width$}  {:>7}  {:>8}  {:>7.0}  {:>7}  {:>7}",
+                name,
+                domain.pages,
+                domain.fetches,
+                domain.average_fetch_ms.unwrap_or(0.0),
+                domain.failures,
+                domain.robots_blocked
+            )?;
+        }
+    }
+
+    if let Some(wait) = &stats.frontier.wait {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "  {:<20}  {:>7}  {:>8}  {:>8}  {:>8}  {:>8}",
+            "Frontier wait", "URLs", "p50 s", "p90 s", "p99 s", "max s"
+        )?;
+        write_wait_row(writer, "all", wait)?;
+        for (name, wait) in &stats.frontier.wait_per_domain {
+            write_wait_row(writer, name, wait)?;
+        }
+        for (depth, wait) in &stats.frontier.wait_per_depth {
+            write_wait_row(writer, &format!("depth {}", depth), wait)?;
+        }
+    }
+    if !stats.frontier.waiting.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Left in the frontier:")?;
+        for (name, urls) in &stats.frontier.waiting {
+            writeln!(
+                writer,
+                "  {} URLs of {}, oldest queued {:.0} s ago",
+                urls.urls, name, urls.oldest_secs
+            )?;
+        }
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "Recent runs:")?;
+    for run in &stats.recent_runs {
+        let parent = run
+            .parent_id
+            .map(|id| format!(" from #{}", id))
+            .unwrap_or_default();
+        let finished = match (&run.finished, &run.outcome) {
+            (Some(finished), Some(outcome)) => format!("finished {} UTC ({})", finished, outcome),
+            _ => "not finished".to_string(),
+        };
+        writeln!(
+            writer,
+            "  #{}{} started {} UTC, {}, peak memory {:.1} MiB, {} pages carried over, {} new, {} failed",
+            run.id,
+            parent,
+            run.started,
+            finished,
+            run.peak_memory as f64 / (1024.0 * 1024.0),
+            run.carried_over,
+            run.discovered,
+            run.failures
+        )?;
+        if !run.seeds.is_empty() {
+            writeln!(writer, "    seeds: {}", run.seeds.join(" "))?;
+        }
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "Banned domains: {}", stats.banned.len())?;
+    for domain in &stats.banned {
+        writeln!(
+            writer,
+            "  {} (until {} UTC)",
+            domain.name, domain.banned_until
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a row of the frontier wait table, in seconds.
+fn write_wait_row(
+    writer: &mut impl Write,
+    name: &str,
+    wait: &WaitPercentiles,
+) -> Result<(), CrawlerError> {
+    let secs = |ms: i64| ms as f64 / 1000.0;
+    writeln!(
+        writer,
+        "  {:<20}  {:>7}  {:>8.1}  {:>8.1}  {:>8.1}  {:>8.1}",
+        name,
+        wait.urls,
+        secs(wait.p50_ms),
+        secs(wait.p90_ms),
+        secs(wait.p99_ms),
+        secs(wait.max_ms)
+    )?;
+    Ok(())
+}
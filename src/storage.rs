@@ -0,0 +1,745 @@
+//! Saving page bodies as HTML files, WARC records, BLOBs in the database or objects in object storage.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use reqwest::header::{
+    HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, HOST, TRANSFER_ENCODING, USER_AGENT,
+};
+use reqwest::{StatusCode, Version};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+use crate::disk_writer::DiskWriter;
+use crate::error::CrawlerError;
+use crate::object_store::BodyStore;
+use crate::routing::{mirror_path, unique_mirror_path, StorageRoute, StorageTarget};
+use crate::warc::{WarcField, WarcWriter};
+
+/// How page bodies are saved.
+#[derive(Clone, Copy)]
+pub enum StorageFormat {
+    /// One `<hash>.html` file per distinct body, optionally compressed.
+    Html {
+        /// The compression of the files.
+        compression: PageCompression,
+    },
+    /// Request and response records in rotating `.warc.gz` files.
+    Warc {
+        /// The size in bytes after which a new file is started.
+        max_file_size: u64,
+    },
+    /// One row of the `PageBody` table per distinct body, optionally compressed, keeping the crawl in one file.
+    Blob {
+        /// The compression of the bodies.
+        compression: PageCompression,
+    },
+    /// One object per distinct body, optionally compressed, in the [`BodyStore`] of the crawler, named like the HTML
+    /// files.
+    Object {
+        /// The compression of the objects.
+        compression: PageCompression,
+    },
+    /// Bodies are not saved. Pages are still parsed and recorded in the database.
+    Discard,
+}
+
+/// How HTML files and BLOBs are compressed.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageCompression {
+    /// Plain `.html` files.
+    #[default]
+    None,
+    /// `.html.gz` files.
+    Gzip,
+    /// `.html.zst` files.
+    Zstd,
+}
+
+impl PageCompression {
+    /// Every compression, in the order files are looked for when reading.
+    const ALL: [PageCompression; 3] = [
+        PageCompression::None,
+        PageCompression::Gzip,
+        PageCompression::Zstd,
+    ];
+
+    /// Returns the file extension of pages saved with this compression.
+    fn extension(self) -> &'static str {
+        match self {
+            PageCompression::None => "html",
+            PageCompression::Gzip => "html.gz",
+            PageCompression::Zstd => "html.zst",
+        }
+    }
+
+    /// Returns the name of the compression, as recorded with BLOBs.
+    fn name(self) -> &'static str {
+        match self {
+            PageCompression::None => "none",
+            PageCompression::Gzip => "gzip",
+            PageCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Returns the compression of a BLOB, by its recorded name.
+    fn of_name(name: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|compression| compression.name() == name)
+            .unwrap_or_default()
+    }
+
+    /// Compresses a body.
+    fn compress(self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            PageCompression::None => Ok(body.to_vec()),
+            PageCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            PageCompression::Zstd => zstd::encode_all(body, 0),
+        }
+    }
+
+    /// Returns the compression of a file, by its extension.
+    fn of_file(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => PageCompression::Gzip,
+            Some("zst") => PageCompression::Zstd,
+            _ => PageCompression::None,
+        }
+    }
+
+    /// Decompresses a saved file.
+    fn decompress(self, contents: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            PageCompression::None => Ok(contents),
+            PageCompression::Gzip => {
+                let mut body = Vec::new();
+                GzDecoder::new(contents.as_slice()).read_to_end(&mut body)?;
+                Ok(body)
+            }
+            PageCompression::Zstd => zstd::decode_all(contents.as_slice()),
+        }
+    }
+}
+
+/// Reads a page body saved as an HTML file, decompressing it whichever compression it was saved with.
+///
+/// # Arguments
+/// * `save_dir` - The directory pages are saved in.
+/// * `hash` - The hash of the body.
+///
+/// # Returns
+/// The body, or a `NotFound` error if no file of the body exists.
+pub fn read_page(save_dir: &Path, hash: &str) -> io::Result<Vec<u8>> {
+    for compression in PageCompression::ALL {
+        let path = save_dir.join(format!("{}.{}", hash, compression.extension()));
+        match fs::read(&path) {
+            Ok(contents) => return compression.decompress(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No saved file for page {}", hash),
+    ))
+}
+
+/// Finds the HTML file a page body is saved in, whichever compression it was saved with.
+///
+/// # Arguments
+/// * `save_dir` - The directory pages are saved in.
+/// * `hash` - The hash of the body.
+///
+/// # Returns
+/// The path of the file, or None if no file of the body exists.
+pub fn find_page_file(save_dir: &Path, hash: &str) -> Option<PathBuf> {
+    PageCompression::ALL
+        .into_iter()
+        .map(|compression| save_dir.join(format!("{}.{}", hash, compression.extension())))
+        .find(|path| path.is_file())
+}
+
+/// Returns the hash a file in the pages directory is named after.
+///
+/// # Arguments
+/// * `path` - The path of the file.
+///
+/// # Returns
+/// The hash, or None if the file is not a page body saved under its hash, e.g. a WARC file.
+pub fn page_file_hash(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    PageCompression::ALL
+        .into_iter()
+        .find_map(|compression| name.strip_suffix(&format!(".{}", compression.extension())))
+        .filter(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Reads a page body saved in the `PageBody` table, decompressing it.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `hash` - The hash of the body.
+///
+/// # Returns
+/// The body, or None if the table holds no body with the hash.
+pub fn read_page_blob(
+    connection: &Connection,
+    hash: &str,
+) -> Result<Option<Vec<u8>>, CrawlerError> {
+    let blob = connection
+        .prepare_cached("SELECT Compression, Body FROM PageBody WHERE Hash = ?")?
+        .query_row([hash], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })
+        .optional()?;
+    match blob {
+        Some((compression, body)) => Ok(Some(
+            PageCompression::of_name(&compression).decompress(body)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Reads a page body by its hash, from the `PageBody` table or else from an HTML file.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `save_dir` - The directory pages are saved in.
+/// * `hash` - The hash of the body.
+///
+/// # Returns
+/// The body, or a `NotFound` error if the body was saved in neither.
+pub fn read_saved_page(
+    connection: &Connection,
+    save_dir: &Path,
+    hash: &str,
+) -> io::Result<Vec<u8>> {
+    match read_page_blob(connection, hash).map_err(io::Error::other)? {
+        Some(body) => Ok(body),
+        None => read_page(save_dir, hash),
+    }
+}
+
+/// Reads a page body saved at a known path, decompressing it according to its extension.
+///
+/// # Arguments
+/// * `path` - The path of the file.
+///
+/// # Returns
+/// The body, or an error if the file cannot be read.
+pub fn read_page_file(path: &Path) -> io::Result<Vec<u8>> {
+    PageCompression::of_file(path).decompress(fs::read(path)?)
+}
+
+/// Decompresses the contents of an object read from a [`BodyStore`], according to the extension of its location.
+pub(crate) fn decode_object(location: &str, contents: Vec<u8>) -> io::Result<Vec<u8>> {
+    PageCompression::of_file(Path::new(location)).decompress(contents)
+}
+
+/// A response to be saved.
+pub struct StoredResponse<'a> {
+    /// The final URL of the page.
+    pub url: &'a Url,
+    /// The user agent the page was requested with.
+    pub user_agent: &'a str,
+    /// The extra headers the page was requested with.
+    pub request_headers: &'a HeaderMap,
+    /// The HTTP version of the response.
+    pub version: Version,
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: &'a HeaderMap,
+    /// The decoded body of the response.
+    pub body: &'a [u8],
+    /// The hash of the body, used to name files.
+    pub hash: &'a str,
+    /// Whether another page is saved at the mirror path of the URL, so the file is named after the URL as well, see
+    /// [`unique_mirror_path`].
+    pub mirror_taken: bool,
+}
+
+/// Where a response was saved, when it cannot be derived from its hash.
+pub enum StoredLocation {
+    /// A record in a WARC file.
+    Warc {
+        /// The name of the file in the pages directory.
+        file: String,
+        /// The offset of the record in the file.
+        offset: u64,
+    },
+    /// A file chosen by a storage route.
+    File(PathBuf),
+    /// An object in the [`BodyStore`], at the location it reported.
+    Object(String),
+    /// A BLOB, to be saved in the `PageBody` table along with the page.
+    Blob {
+        /// The compression of the contents.
+        compression: &'static str,
+        /// The compressed body.
+        contents: Vec<u8>,
+    },
+}
+
+/// Saves page bodies in the configured format.
+pub struct PageStore {
+    save_dir: PathBuf,
+    format: StorageFormat,
+    warc: Option<WarcWriter>,
+    routes: Vec<StorageRoute>,
+    writer: DiskWriter,
+    body_store: Option<Arc<dyn BodyStore>>,
+}
+
+impl PageStore {
+    /// Creates a new `PageStore`.
+    ///
+    /// # Arguments
+    /// * `save_dir` - The directory to save pages to.
+    /// * `format` - The format to save pages in.
+    /// * `run_id` - The id of the crawl run, used to name WARC files.
+    /// * `writer` - The writer files are written with.
+    pub fn new(
+        save_dir: impl Into<PathBuf>,
+        format: StorageFormat,
+        run_id: i64,
+        writer: DiskWriter,
+    ) -> Self {
+        let save_dir = save_dir.into();
+        let warc = match format {
+            StorageFormat::Html { .. }
+            | StorageFormat::Blob { .. }
+            | StorageFormat::Object { .. }
+            | StorageFormat::Discard => None,
+            StorageFormat::Warc { max_file_size } => Some(WarcWriter::new(
+                save_dir.clone(),
+                &format!("crawl-{:05}", run_id),
+                max_file_size,
+                writer.clone(),
+            )),
+        };
+        PageStore {
+            save_dir,
+            format,
+            warc,
+            routes: Vec::new(),
+            writer,
+            body_store: None,
+        }
+    }
+
+    /// Routes the pages matching URL patterns to other storage targets than the format.
+    ///
+    /// # Arguments
+    /// * `routes` - The routes, in order of precedence.
+    pub fn set_routes(&mut self, routes: Vec<StorageRoute>) {
+        self.routes = routes;
+    }
+
+    /// Sets the object store bodies are saved to with [`StorageFormat::Object`].
+    ///
+    /// # Arguments
+    /// * `body_store` - The object store.
+    pub fn set_body_store(&mut self, body_store: Option<Arc<dyn BodyStore>>) {
+        self.body_store = body_store;
+    }
+
+    /// Returns the object store bodies are saved to, if any.
+    pub fn body_store(&self) -> Option<Arc<dyn BodyStore>> {
+        self.body_store.clone()
+    }
+
+    /// Returns the path a page is saved at in a mirror tree, if the first route matching its URL is a mirror.
+    ///
+    /// # Arguments
+    /// * `url` - The URL of the page.
+    pub fn mirror_file(&self, url: &Url) -> Option<PathBuf> {
+        match &self.routes.iter().find(|route| route.matches(url))?.target {
+            StorageTarget::Mirror { dir } => Some(mirror_path(dir, url)),
+            _ => None,
+        }
+    }
+
+    /// Saves a response according to the first route matching its URL.
+    ///
+    /// # Returns
+    /// None if no route matches, otherwise the location the response was saved at, if any.
+    async fn store_routed(
+        &self,
+        response: &StoredResponse<'_>,
+    ) -> io::Result<Option<Option<StoredLocation>>> {
+        let Some(route) = self.routes.iter().find(|route| route.matches(response.url)) else {
+            return Ok(None);
+        };
+        let path = match &route.target {
+            StorageTarget::Html { dir, compression } => {
+                let path = dir.join(format!("{}.{}", response.hash, compression.extension()));
+                self.writer
+                    .write(path.clone(), compression.compress(response.body)?)
+                    .await?;
+                path
+            }
+            StorageTarget::Mirror { dir } => {
+                let path = match response.mirror_taken {
+                    true => unique_mirror_path(dir, response.url),
+                    false => mirror_path(dir, response.url),
+                };
+                self.writer
+                    .write(path.clone(), response.body.to_vec())
+                    .await?;
+                path
+            }
+            StorageTarget::Jsonl { file } => {
+                let record = serde_json::json!({
+                    "url": response.url.as_str(),
+                    "status": response.status.as_u16(),
+                    "hash": response.hash,
+                    "fetched": WarcWriter::date(),
+                    "body": String::from_utf8_lossy(response.body),
+                });
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                self.writer.append(file.clone(), line).await?;
+                file.clone()
+            }
+            StorageTarget::Discard => return Ok(Some(None)),
+        };
+        Ok(Some(Some(StoredLocation::File(path))))
+    }
+
+    /// Saves a response.
+    ///
+    /// # Arguments
+    /// * `response` - The response to save.
+    ///
+    /// # Returns
+    /// The location of the response when it was routed, written to a WARC file or the object store, or is to be saved
+    /// as a BLOB, None otherwise.
+    pub async fn store(
+        &mut self,
+        response: &StoredResponse<'_>,
+    ) -> io::Result<Option<StoredLocation>> {
+        if let Some(location) = self.store_routed(response).await? {
+            return Ok(location);
+        }
+        let warc = match (&mut self.warc, self.format) {
+            (Some(warc), _) => warc,
+            (None, StorageFormat::Html { compression }) => {
+                let filepath =
+                    self.save_dir
+                        .join(format!("{}.{}", response.hash, compression.extension()));
+                self.writer
+                    .write(filepath, compression.compress(response.body)?)
+                    .await?;
+                return Ok(None);
+            }
+            (None, StorageFormat::Blob { compression }) => {
+                return Ok(Some(StoredLocation::Blob {
+                    compression: compression.name(),
+                    contents: compression.compress(response.body)?,
+                }));
+            }
+            (None, StorageFormat::Object { compression }) => {
+                let Some(body_store) = &self.body_store else {
+                    return Err(io::Error::other("No object store to save pages to"));
+                };
+                let key = format!("{}.{}", response.hash, compression.extension());
+                body_store
+                    .put(&key, compression.compress(response.body)?)
+                    .await
+                    .map_err(io::Error::other)?;
+                return Ok(Some(StoredLocation::Object(body_store.location(&key))));
+            }
+            (None, _) => return Ok(None),
+        };
+
+        let date = WarcWriter::date();
+        let response_id = WarcWriter::record_id();
+        let fields: Vec<WarcField> = vec![
+            ("WARC-Type", "response".to_string()),
+            ("WARC-Record-ID", response_id.clone()),
+            ("WARC-Date", date.clone()),
+            ("WARC-Target-URI", response.url.to_string()),
+            ("WARC-Payload-Digest", payload_digest(response.body)),
+            (
+                "Content-Type",
+                "application/http; msgtype=response".to_string(),
+            ),
+        ];
+        let (file, offset) = warc
+            .write_record(&fields, &response_block(response))
+            .await?;
+        write_request_record(warc, response, &date, &response_id).await?;
+        Ok(Some(StoredLocation::Warc { file, offset }))
+    }
+
+    /// Saves a response whose body is identical to an already saved one.
+    ///
+    /// Nothing is written for HTML files, BLOBs and objects, while WARC files receive a `revisit` record referring to
+    /// the original. Routed responses are saved in full, as routes may keep a file per URL.
+    ///
+    /// # Arguments
+    /// * `response` - The response to save.
+    /// * `original_url` - The URL the identical body was saved under.
+    ///
+    /// # Returns
+    /// The location of the response when it was routed or the revisit record when writing WARC files, None
+    /// otherwise.
+    pub async fn store_duplicate(
+        &mut self,
+        response: &StoredResponse<'_>,
+        original_url: &str,
+    ) -> io::Result<Option<StoredLocation>> {
+        if let Some(location) = self.store_routed(response).await? {
+            return Ok(location);
+        }
+        let Some(warc) = &mut self.warc else {
+            return Ok(None);
+        };
+
+        let date = WarcWriter::date();
+        let revisit_id = WarcWriter::record_id();
+        let fields: Vec<WarcField> = vec![
+            ("WARC-Type", "revisit".to_string()),
+            ("WARC-Record-ID", revisit_id.clone()),
+            ("WARC-Date", date.clone()),
+            ("WARC-Target-URI", response.url.to_string()),
+            (
+                "WARC-Profile",
+                "http://netpreserve.org/warc/1.0/revisit/identical-payload-digest".to_string(),
+            ),
+            ("WARC-Refers-To-Target-URI", original_url.to_string()),
+            ("WARC-Payload-Digest", payload_digest(response.body)),
+            (
+                "Content-Type",
+                "application/http; msgtype=response".to_string(),
+            ),
+        ];
+        let (file, offset) = warc.write_record(&fields, &response_head(response)).await?;
+        write_request_record(warc, response, &date, &revisit_id).await?;
+        Ok(Some(StoredLocation::Warc { file, offset }))
+    }
+}
+
+/// Computes the `WARC-Payload-Digest` of a body.
+fn payload_digest(body: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(body)))
+}
+
+/// Serializes the status line and headers of a response.
+///
+/// The body has already been de-chunked and decompressed, so `Transfer-Encoding` and `Content-Encoding` are dropped
+/// and `Content-Length` set to its length.
+fn response_head(response: &StoredResponse) -> Vec<u8> {
+    let mut head = format!(
+        "{:?} {} {}\r\n",
+        response.version,
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in response.headers {
+        if name == TRANSFER_ENCODING || name == CONTENT_ENCODING || name == CONTENT_LENGTH {
+            continue;
+        }
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(format!("content-length: {}\r\n\r\n", response.body.len()).as_bytes());
+    head
+}
+
+/// Serializes a full HTTP response.
+fn response_block(response: &StoredResponse) -> Vec<u8> {
+    let mut block = response_head(response);
+    block.extend_from_slice(response.body);
+    block
+}
+
+/// Writes the `request` record that belongs to a response or revisit record.
+async fn write_request_record(
+    warc: &mut WarcWriter,
+    response: &StoredResponse<'_>,
+    date: &str,
+    concurrent_to: &str,
+) -> io::Result<()> {
+    let url = response.url;
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target = format!("{}?{}", target, query);
+    }
+    let mut block = format!(
+        "GET {} {:?}\r\n{}: {}\r\n{}: {}\r\naccept: */*\r\n",
+        target,
+        response.version,
+        HOST,
+        url.host_str().unwrap_or(""),
+        USER_AGENT,
+        response.user_agent
+    )
+    .into_bytes();
+    for (name, value) in response.request_headers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    block.extend_from_slice(b"\r\n");
+
+    let fields: Vec<WarcField> = vec![
+        ("WARC-Type", "request".to_string()),
+        ("WARC-Date", date.to_string()),
+        ("WARC-Target-URI", url.to_string()),
+        ("WARC-Concurrent-To", concurrent_to.to_string()),
+        (
+            "Content-Type",
+            "application/http; msgtype=request".to_string(),
+        ),
+    ];
+    warc.write_record(&fields, &block).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response<'a>(url: &'a Url, headers: &'a HeaderMap, body: &'a [u8]) -> StoredResponse<'a> {
+        StoredResponse {
+            url,
+            user_agent: "test",
+            request_headers: headers,
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            headers,
+            body,
+            hash: "0123abcd",
+            mirror_taken: false,
+        }
+    }
+
+    #[test]
+    fn compresses_and_decompresses() {
+        let body = "<p>Page</p>".repeat(100);
+        for compression in PageCompression::ALL {
+            let contents = compression.compress(body.as_bytes()).unwrap();
+            assert_eq!(
+                compression.decompress(contents).unwrap(),
+                body.as_bytes(),
+                "{}",
+                compression.name()
+            );
+            assert_eq!(
+                PageCompression::of_name(compression.name()).name(),
+                compression.name()
+            );
+            let file = format!("0123abcd.{}", compression.extension());
+            assert_eq!(
+                PageCompression::of_file(Path::new(&file)).name(),
+                compression.name()
+            );
+            assert_eq!(page_file_hash(Path::new(&file)), Some("0123abcd"));
+        }
+        assert!(PageCompression::Gzip.decompress(b"<p>".to_vec()).is_err());
+        assert_eq!(page_file_hash(Path::new("crawl-00001-00000.warc.gz")), None);
+    }
+
+    #[tokio::test]
+    async fn saves_bodies_as_blobs() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        let (writer, handle) = DiskWriter::start(1);
+        let url = Url::parse("https://example.com/").unwrap();
+        let headers = HeaderMap::new();
+        let body = "<p>Page</p>".repeat(100);
+        for compression in PageCompression::ALL {
+            let mut store = PageStore::new(
+                "pages",
+                StorageFormat::Blob { compression },
+                1,
+                writer.clone(),
+            );
+            let hash = format!("{}{}", compression.name(), "0123abcd");
+            let Some(StoredLocation::Blob {
+                compression,
+                contents,
+            }) = store
+                .store(&response(&url, &headers, body.as_bytes()))
+                .await
+                .unwrap()
+            else {
+                panic!("no BLOB for {}", compression.name());
+            };
+            connection
+                .execute(
+                    "INSERT INTO PageBody (Hash, Compression, Body) VALUES (?, ?, ?)",
+                    rusqlite::params![hash, compression, contents],
+                )
+                .unwrap();
+            assert_eq!(
+                read_page_blob(&connection, &hash).unwrap().unwrap(),
+                body.as_bytes()
+            );
+        }
+        assert!(read_page_blob(&connection, "missing").unwrap().is_none());
+        let kind: String = connection
+            .query_row(
+                "SELECT type FROM pragma_table_info('PageBody') WHERE name = 'Hash'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kind, "TEXT");
+
+        drop(writer);
+        handle.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn saves_bodies_as_files() {
+        let dir = std::env::temp_dir().join(format!("web_crawler_storage_{}", std::process::id()));
+        let (writer, handle) = DiskWriter::start(4);
+        let url = Url::parse("https://example.com/").unwrap();
+        let headers = HeaderMap::new();
+        let body = "<p>Page</p>".repeat(100);
+        for compression in PageCompression::ALL {
+            let save_dir = dir.join(compression.name());
+            let mut store = PageStore::new(
+                &save_dir,
+                StorageFormat::Html { compression },
+                1,
+                writer.clone(),
+            );
+            let location = store
+                .store(&response(&url, &headers, body.as_bytes()))
+                .await
+                .unwrap();
+            assert!(location.is_none());
+        }
+        drop(writer);
+        handle.finish().await.unwrap();
+
+        for compression in PageCompression::ALL {
+            let save_dir = dir.join(compression.name());
+            assert_eq!(read_page(&save_dir, "0123abcd").unwrap(), body.as_bytes());
+            let path = find_page_file(&save_dir, "0123abcd").unwrap();
+            assert_eq!(read_page_file(&path).unwrap(), body.as_bytes());
+        }
+        let missing = read_page(&dir, "0123abcd").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,878 @@
+//! The records crawl workers share: robots.txt rules, stored pages and their links, and the frontier.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use tokio::runtime::{Builder, Handle, Runtime, RuntimeFlavor};
+use tokio_postgres::{Client, NoTls};
+use tracing::error;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::links::{FoundLink, LinkElement};
+use crate::robots::{disallowed_patterns, RobotsRules};
+
+/// Where the records that crawl workers share are kept.
+///
+/// The crawl database is itself a store, for a crawl run by one process or by workers on one machine. A
+/// [`PostgresStore`] lets workers on several machines cooperate. Each worker still keeps every other record in its own
+/// crawl database, along with the robots.txt rules, pages and links it recorded itself, so that its reports and
+/// exports keep working. The crawl database therefore records those before they are passed to the store, and its own
+/// `save_*` methods do nothing.
+pub trait CrawlStore {
+    /// Returns the robots.txt rules of a domain if any worker recorded them less than a day ago.
+    ///
+    /// # Arguments
+    /// * `domain` - The name of the domain.
+    fn fresh_robots_rules(&self, domain: &str) -> Result<Option<RobotsRules>, CrawlerError>;
+
+    /// Records the robots.txt rules of a domain, fetched just now, replacing its previous rules.
+    ///
+    /// # Arguments
+    /// * `domain` - The name of the domain.
+    /// * `rules` - The rules, which are empty if the domain has no robots.txt file.
+    fn save_robots_rules(&self, domain: &str, rules: &RobotsRules) -> Result<(), CrawlerError>;
+
+    /// Marks the robots.txt rules of every domain as outdated, so that they are fetched again.
+    ///
+    /// # Returns
+    /// The number of domains whose rules were outdated.
+    fn expire_robots_rules(&self) -> Result<usize, CrawlerError>;
+
+    /// Returns whether a page is stored under a URL.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    fn is_page_stored(&self, url: &str) -> Result<bool, CrawlerError>;
+
+    /// Records a stored page.
+    ///
+    /// # Arguments
+    /// * `url` - The URL the page is stored under.
+    /// * `hash` - The hash of its contents.
+    /// * `nofollow` - Whether none of its links are followed.
+    fn save_page(&self, url: &str, hash: &str, nofollow: bool) -> Result<(), CrawlerError>;
+
+    /// Records the links found on a stored page, replacing those of a previous fetch.
+    ///
+    /// # Arguments
+    /// * `page_url` - The URL the page is stored under.
+    /// * `links` - The resolved URL of every link, and the link as found on the page.
+    fn save_links(&self, page_url: &str, links: &[(Url, &FoundLink)]) -> Result<(), CrawlerError>;
+
+    /// Adds a URL to the frontier, unless it is in the frontier already, also as crawled.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `queued` - When the URL was first queued.
+    /// * `score` - The score of the URL.
+    ///
+    /// # Returns
+    /// Whether the URL was added.
+    fn frontier_push(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError>;
+
+    /// Adds a URL to the frontier to be fetched again, also if it is in the frontier as crawled. A URL waiting in the
+    /// frontier or claimed by a worker is left as it is.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `queued` - When the URL is queued.
+    /// * `score` - The score of the URL.
+    ///
+    /// # Returns
+    /// Whether the URL was added or queued again.
+    fn frontier_requeue(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError>;
+
+    /// Removes the URLs with the highest scores from the frontier.
+    ///
+    /// # Arguments
+    /// * `limit` - The largest number of URLs removed.
+    ///
+    /// # Returns
+    /// The URLs, highest score first and in the order they were queued among equal scores, with the time they were
+    /// queued and their score.
+    fn frontier_take(&self, limit: usize) -> Result<Vec<(String, SystemTime, f64)>, CrawlerError>;
+
+    /// Claims the URL with the highest score, or one whose lease expired, for a worker. No two workers claim the same
+    /// URL.
+    ///
+    /// # Arguments
+    /// * `worker_id` - The id of the worker.
+    /// * `lease` - How long the URL is reserved for the worker before others may claim it.
+    ///
+    /// # Returns
+    /// The URL, the time it was queued and its score, or None if no URL can be claimed.
+    fn frontier_claim(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<(String, SystemTime, f64)>, CrawlerError>;
+
+    /// Returns a URL claimed by a worker to the queue, for any worker to claim.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `worker_id` - The id of the worker.
+    ///
+    /// # Returns
+    /// Whether the worker had claimed the URL.
+    fn frontier_release(&self, url: &str, worker_id: &str) -> Result<bool, CrawlerError>;
+
+    /// Returns every URL claimed by a worker to the queue.
+    ///
+    /// # Arguments
+    /// * `worker_id` - The id of the worker.
+    fn frontier_release_all(&self, worker_id: &str) -> Result<(), CrawlerError>;
+
+    /// Marks a URL claimed by a worker as crawled, so that no worker queues it again.
+    ///
+    /// # Arguments
+    /// * `url` - The URL.
+    /// * `worker_id` - The id of the worker.
+    fn frontier_ack(&self, url: &str, worker_id: &str) -> Result<(), CrawlerError>;
+
+    /// Returns the number of URLs in the frontier that are not crawled.
+    ///
+    /// # Arguments
+    /// * `claimed` - Whether URLs claimed by workers are counted, and not only those waiting to be claimed.
+    fn frontier_count(&self, claimed: bool) -> Result<usize, CrawlerError>;
+
+    /// Removes the URLs of a host waiting in the frontier that match a predicate. Only the URLs of the host are read.
+    ///
+    /// # Arguments
+    /// * `host` - The host name of the URLs, on any port.
+    /// * `matches` - Returns whether to remove a URL.
+    ///
+    /// # Returns
+    /// The removed URLs.
+    fn frontier_remove(
+        &self,
+        host: &str,
+        matches: &dyn Fn(&str) -> bool,
+    ) -> Result<Vec<String>, CrawlerError>;
+
+    /// Removes every URL from the frontier.
+    fn frontier_clear(&self) -> Result<(), CrawlerError>;
+
+    /// Adds the links recorded on stored pages that were never crawled to the frontier. Links that were not followed
+    /// because of `nofollow` are left out, unless robots directives are ignored.
+    ///
+    /// # Arguments
+    /// * `ignore_robots` - Whether links marked nofollow, and the links of pages marked nofollow, are added too.
+    ///
+    /// # Returns
+    /// The number of URLs added.
+    fn frontier_add_uncrawled_links(&self, ignore_robots: bool) -> Result<usize, CrawlerError>;
+}
+
+impl CrawlStore for Connection {
+    fn fresh_robots_rules(&self, domain: &str) -> Result<Option<RobotsRules>, CrawlerError> {
+        let fresh = self
+            .query_row(
+                "SELECT Id, CrawlDelay FROM Domain WHERE Name = ? AND RobotsFetched > datetime('now', '-1 day')",
+                [domain],
+                |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((domain_id, crawl_delay)) = fresh else {
+            return Ok(None);
+        };
+        Ok(Some(RobotsRules {
+            disallowed: disallowed_patterns(self, domain_id)?,
+            crawl_delay,
+            sitemaps: Vec::new(),
+        }))
+    }
+
+    fn save_robots_rules(&self, _domain: &str, _rules: &RobotsRules) -> Result<(), CrawlerError> {
+        Ok(())
+    }
+
+    fn expire_robots_rules(&self) -> Result<usize, CrawlerError> {
+        Ok(0)
+    }
+
+    /// Pages recorded by a dry run have no stored body, and are not taken as stored.
+    fn is_page_stored(&self, url: &str) -> Result<bool, CrawlerError> {
+        let stored = self
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM Page WHERE Url = ? AND DryRun = 0)")?
+            .query_row([url], |row| row.get(0))?;
+        Ok(stored)
+    }
+
+    fn save_page(&self, _url: &str, _hash: &str, _nofollow: bool) -> Result<(), CrawlerError> {
+        Ok(())
+    }
+
+    fn save_links(
+        &self,
+        _page_url: &str,
+        _links: &[(Url, &FoundLink)],
+    ) -> Result<(), CrawlerError> {
+        Ok(())
+    }
+
+    fn frontier_push(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError> {
+        let added = self
+            .prepare_cached(
+                "INSERT OR IGNORE INTO FrontierUrl (Url, Created, Score) VALUES (?, ?, ?)",
+            )?
+            .execute(params![url, format_time(queued), score])?;
+        Ok(added > 0)
+    }
+
+    fn frontier_requeue(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError> {
+        let queued = self
+            .prepare_cached(
+                "INSERT INTO FrontierUrl (Url, Created, Score) VALUES (?1, ?2, ?3)
+                ON CONFLICT (Url) DO UPDATE SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL,
+                    Created = ?2, Score = ?3
+                WHERE Status = 'done'",
+            )?
+            .execute(params![url, format_time(queued), score])?;
+        Ok(queued > 0)
+    }
+
+    fn frontier_take(&self, limit: usize) -> Result<Vec<(String, SystemTime, f64)>, CrawlerError> {
+        let mut stmt = self.prepare(
+            "DELETE FROM FrontierUrl WHERE rowid IN (
+                SELECT rowid FROM FrontierUrl WHERE Status = 'queued' ORDER BY Score DESC, rowid LIMIT ?
+            )
+            RETURNING rowid, Url, Created, Score",
+        )?;
+        let mut rows = stmt
+            .query_map([limit], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, f64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.sort_by(|a, b| b.3.total_cmp(&a.3).then(a.0.cmp(&b.0)));
+        Ok(rows
+            .into_iter()
+            .map(|(_, url, created, score)| (url, queued_time(created.as_deref()), score))
+            .collect())
+    }
+
+    fn frontier_claim(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<(String, SystemTime, f64)>, CrawlerError> {
+        let claimed = self
+            .query_row(
+                "UPDATE FrontierUrl SET Status = 'claimed', WorkerId = ?1, LeaseExpires = datetime('now', ?2)
+                WHERE rowid = (
+                    SELECT rowid FROM FrontierUrl
+                    WHERE Status = 'queued' OR (Status = 'claimed' AND LeaseExpires < datetime('now'))
+                    ORDER BY Score DESC, rowid LIMIT 1
+                )
+                RETURNING Url, Created, Score",
+                [worker_id, &format!("+{} seconds", lease.as_secs())],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, f64>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        Ok(claimed.map(|(url, created, score)| (url, queued_time(created.as_deref()), score)))
+    }
+
+    fn frontier_release(&self, url: &str, worker_id: &str) -> Result<bool, CrawlerError> {
+        let released = self.execute(
+            "UPDATE FrontierUrl SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL
+            WHERE Url = ? AND WorkerId = ? AND Status = 'claimed'",
+            [url, worker_id],
+        )?;
+        Ok(released > 0)
+    }
+
+    fn frontier_release_all(&self, worker_id: &str) -> Result<(), CrawlerError> {
+        self.execute(
+            "UPDATE FrontierUrl SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL
+            WHERE WorkerId = ? AND Status = 'claimed'",
+            [worker_id],
+        )?;
+        Ok(())
+    }
+
+    fn frontier_ack(&self, url: &str, worker_id: &str) -> Result<(), CrawlerError> {
+        self.execute(
+            "UPDATE FrontierUrl SET Status = 'done', LeaseExpires = NULL WHERE Url = ? AND WorkerId = ?",
+            [url, worker_id],
+        )?;
+        Ok(())
+    }
+
+    fn frontier_count(&self, claimed: bool) -> Result<usize, CrawlerError> {
+        let count = self.query_row(
+            "SELECT COUNT(*) FROM FrontierUrl WHERE Status = 'queued' OR (? AND Status = 'claimed')",
+            [claimed],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// The URLs are looked up by prefix in the index of the `Url` column.
+    fn frontier_remove(
+        &self,
+        host: &str,
+        matches: &dyn Fn(&str) -> bool,
+    ) -> Result<Vec<String>, CrawlerError> {
+        // Brackets, e.g. of IPv6 hosts, are the only special characters of GLOB that a host can hold
+        let host = host.replace('[', "[[]");
+        let mut stmt = self.prepare(
+            "SELECT rowid, Url FROM FrontierUrl WHERE Status = 'queued' AND (Url GLOB ? OR Url GLOB ?)",
+        )?;
+        let rows = stmt
+            .query_map(
+                [
+                    format!("http://{}[/:]*", host),
+                    format!("https://{}[/:]*", host),
+                ],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut removed = Vec::new();
+        for (rowid, url) in rows {
+            // Another worker may have claimed the URL since it was read
+            if matches(&url)
+                && self.execute(
+                    "DELETE FROM FrontierUrl WHERE rowid = ? AND Status = 'queued'",
+                    [rowid],
+                )? > 0
+            {
+                removed.push(url);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn frontier_clear(&self) -> Result<(), CrawlerError> {
+        self.execute("DELETE FROM FrontierUrl", [])?;
+        Ok(())
+    }
+
+    /// Links that are the requested URL of a stored page, or failed before, are left out too.
+    fn frontier_add_uncrawled_links(&self, ignore_robots: bool) -> Result<usize, CrawlerError> {
+        let added = self.execute(
+            &format!("INSERT OR IGNORE INTO FrontierUrl (Url)
+            SELECT DISTINCT PageLink.Url FROM PageLink JOIN Page AS Source ON Source.Id = PageLink.PageId
+            WHERE (PageLink.Url LIKE 'http://%' OR PageLink.Url LIKE 'https://%')
+                AND {}
+                AND (?1 OR (PageLink.Nofollow = 0 AND Source.NoFollow = 0))
+                AND NOT EXISTS (SELECT 1 FROM Page WHERE Page.Url = PageLink.Url OR Page.RequestedUrl = PageLink.Url)
+                AND NOT EXISTS (SELECT 1 FROM FailedUrl WHERE FailedUrl.Url = PageLink.Url)",
+                LinkElement::navigable_sql("PageLink.Element")
+            ),
+            [ignore_robots],
+        )?;
+        Ok(added)
+    }
+}
+
+/// A Postgres database shared by crawl workers on several machines, see `scripts/create_postgres.sql`.
+///
+/// It holds the records workers must agree on, not a whole crawl: it is a shared cache of robots.txt rules, stored
+/// pages and links, and a shared frontier, next to the crawl database each worker still needs.
+///
+/// The connection is driven by a runtime of its own, and queries only exchange messages with it, so the thread the
+/// methods are called on waits for them without entering a runtime. They can therefore be called from any runtime,
+/// including a current-thread one.
+pub struct PostgresStore {
+    client: Client,
+    /// The runtime of the connection, taken when the store is dropped.
+    runtime: Option<Runtime>,
+}
+
+impl PostgresStore {
+    /// Connects to a Postgres database, creating the tables of the shared records if they do not exist.
+    ///
+    /// # Arguments
+    /// * `url` - The connection URL, e.g. `postgres://crawler@db.example.com/crawl`.
+    ///
+    /// # Returns
+    /// The store, or an error if the database cannot be connected to.
+    pub fn connect(url: &str) -> Result<Self, CrawlerError> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("postgres")
+            .enable_all()
+            .build()?;
+        let url = url.to_string();
+        let client = block_on(runtime.spawn(async move {
+            let (client, connection) = tokio_postgres::connect(&url, NoTls).await?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    error!("Lost the connection to the Postgres database: {}", e);
+                }
+            });
+            client
+                .batch_execute(include_str!("../scripts/create_postgres.sql"))
+                .await?;
+            Ok::<_, CrawlerError>(client)
+        }))
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        match client {
+            Ok(client) => Ok(PostgresStore {
+                client,
+                runtime: Some(runtime),
+            }),
+            Err(e) => {
+                runtime.shutdown_background();
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs statements in a transaction, which is rolled back if one of them fails.
+    async fn transaction<T>(
+        &self,
+        statements: impl Future<Output = Result<T, tokio_postgres::Error>>,
+    ) -> Result<T, CrawlerError> {
+        self.client.batch_execute("BEGIN").await?;
+        match statements.await {
+            Ok(result) => {
+                self.client.batch_execute("COMMIT").await?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.client.batch_execute("ROLLBACK").await?;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl CrawlStore for PostgresStore {
+    fn fresh_robots_rules(&self, domain: &str) -> Result<Option<RobotsRules>, CrawlerError> {
+        block_on(async {
+            let Some(row) = self
+                .client
+                .query_opt(
+                    "SELECT Id, CrawlDelay FROM Domain WHERE Name = $1 AND RobotsFetched > now() - interval '1 day'",
+                    &[&domain],
+                )
+                .await?
+            else {
+                return Ok(None);
+            };
+            let domain_id: i64 = row.get(0);
+            let disallowed = self
+                .client
+                .query(
+                    "SELECT Pattern FROM DisallowedPattern WHERE DomainId = $1",
+                    &[&domain_id],
+                )
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            Ok(Some(RobotsRules {
+                disallowed,
+                crawl_delay: row.get(1),
+                sitemaps: Vec::new(),
+            }))
+        })
+    }
+
+    fn save_robots_rules(&self, domain: &str, rules: &RobotsRules) -> Result<(), CrawlerError> {
+        block_on(self.transaction(async {
+            let domain_id: i64 = self
+                .client
+                .query_one(
+                    "INSERT INTO Domain (Name, CrawlDelay, RobotsFetched) VALUES ($1, $2, now())
+                    ON CONFLICT (Name) DO UPDATE SET CrawlDelay = excluded.CrawlDelay, RobotsFetched = excluded.RobotsFetched
+                    RETURNING Id",
+                    &[&domain, &rules.crawl_delay],
+                )
+                .await?
+                .get(0);
+            self.client
+                .execute(
+                    "DELETE FROM DisallowedPattern WHERE DomainId = $1",
+                    &[&domain_id],
+                )
+                .await?;
+            self.client
+                .execute(
+                    "INSERT INTO DisallowedPattern (DomainId, Pattern) SELECT $1, unnest($2::TEXT[])
+                    ON CONFLICT DO NOTHING",
+                    &[&domain_id, &rules.disallowed],
+                )
+                .await?;
+            Ok(())
+        }))
+    }
+
+    fn expire_robots_rules(&self) -> Result<usize, CrawlerError> {
+        let expired = block_on(self.client.execute(
+            "UPDATE Domain SET RobotsFetched = NULL WHERE RobotsFetched IS NOT NULL",
+            &[],
+        ))?;
+        Ok(expired as usize)
+    }
+
+    fn is_page_stored(&self, url: &str) -> Result<bool, CrawlerError> {
+        let row = block_on(
+            self.client
+                .query_one("SELECT EXISTS (SELECT 1 FROM Page WHERE Url = $1)", &[&url]),
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn save_page(&self, url: &str, hash: &str, nofollow: bool) -> Result<(), CrawlerError> {
+        block_on(self.client.execute(
+            "INSERT INTO Page (Url, Hash, NoFollow) VALUES ($1, $2, $3)
+            ON CONFLICT (Url) DO UPDATE SET Hash = excluded.Hash, NoFollow = excluded.NoFollow,
+                Updated = CASE WHEN Page.Hash IS DISTINCT FROM excluded.Hash THEN now() ELSE Page.Updated END",
+            &[&url, &hash, &nofollow],
+        ))?;
+        Ok(())
+    }
+
+    fn save_links(&self, page_url: &str, links: &[(Url, &FoundLink)]) -> Result<(), CrawlerError> {
+        let urls: Vec<&str> = links.iter().map(|(url, _)| url.as_str()).collect();
+        let anchors: Vec<&str> = links
+            .iter()
+            .map(|(_, link)| link.anchor_text.as_str())
+            .collect();
+        let nofollow: Vec<bool> = links.iter().map(|(_, link)| link.nofollow).collect();
+        let rels: Vec<&str> = links.iter().map(|(_, link)| link.rel.as_str()).collect();
+        let sections: Vec<&str> = links.iter().map(|(_, link)| link.section.name()).collect();
+        block_on(self.transaction(async {
+            let Some(page) = self
+                .client
+                .query_opt("SELECT Id FROM Page WHERE Url = $1", &[&page_url])
+                .await?
+            else {
+                return Ok(());
+            };
+            let page_id: i64 = page.get(0);
+            self.client
+                .execute("DELETE FROM PageLink WHERE PageId = $1", &[&page_id])
+                .await?;
+            self.client
+                .execute(
+                    "INSERT INTO PageLink (PageId, Url, AnchorText, Nofollow, Rel, Section)
+                    SELECT $1, Url, NULLIF(AnchorText, ''), Nofollow, NULLIF(Rel, ''), Section
+                    FROM unnest($2::TEXT[], $3::TEXT[], $4::BOOLEAN[], $5::TEXT[], $6::TEXT[])
+                        AS Link (Url, AnchorText, Nofollow, Rel, Section)
+                    ON CONFLICT DO NOTHING",
+                    &[&page_id, &urls, &anchors, &nofollow, &rels, &sections],
+                )
+                .await?;
+            Ok(())
+        }))
+    }
+
+    fn frontier_push(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError> {
+        let added = block_on(self.client.execute(
+            "INSERT INTO FrontierUrl (Url, Created, Score) VALUES ($1, $2, $3) ON CONFLICT (Url) DO NOTHING",
+            &[&url, &queued, &score],
+        ))?;
+        Ok(added > 0)
+    }
+
+    fn frontier_requeue(
+        &self,
+        url: &str,
+        queued: SystemTime,
+        score: f64,
+    ) -> Result<bool, CrawlerError> {
+        let queued = block_on(self.client.execute(
+            "INSERT INTO FrontierUrl (Url, Created, Score) VALUES ($1, $2, $3)
+            ON CONFLICT (Url) DO UPDATE SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL,
+                Created = excluded.Created, Score = excluded.Score
+            WHERE FrontierUrl.Status = 'done'",
+            &[&url, &queued, &score],
+        ))?;
+        Ok(queued > 0)
+    }
+
+    fn frontier_take(&self, limit: usize) -> Result<Vec<(String, SystemTime, f64)>, CrawlerError> {
+        let rows = block_on(self.client.query(
+            "DELETE FROM FrontierUrl WHERE Id IN (
+                SELECT Id FROM FrontierUrl WHERE Status = 'queued'
+                ORDER BY Score DESC, Id LIMIT $1 FOR UPDATE SKIP LOCKED
+            )
+            RETURNING Id, Url, Created, Score",
+            &[&(limit as i64)],
+        ))?;
+        let mut taken: Vec<(i64, String, SystemTime, f64)> = rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+            .collect();
+        taken.sort_by(|a, b| b.3.total_cmp(&a.3).then(a.0.cmp(&b.0)));
+        Ok(taken
+            .into_iter()
+            .map(|(_, url, queued, score)| (url, queued, score))
+            .collect())
+    }
+
+    fn frontier_claim(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<(String, SystemTime, f64)>, CrawlerError> {
+        let row = block_on(self.client.query_opt(
+            "UPDATE FrontierUrl SET Status = 'claimed', WorkerId = $1, LeaseExpires = now() + make_interval(secs => $2)
+            WHERE Id = (
+                SELECT Id FROM FrontierUrl
+                WHERE Status = 'queued' OR (Status = 'claimed' AND LeaseExpires < now())
+                ORDER BY Score DESC, Id LIMIT 1 FOR UPDATE SKIP LOCKED
+            )
+            RETURNING Url, Created, Score",
+            &[&worker_id, &lease.as_secs_f64()],
+        ))?;
+        Ok(row.map(|row| (row.get(0), row.get(1), row.get(2))))
+    }
+
+    fn frontier_release(&self, url: &str, worker_id: &str) -> Result<bool, CrawlerError> {
+        let released = block_on(self.client.execute(
+            "UPDATE FrontierUrl SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL
+            WHERE Url = $1 AND WorkerId = $2 AND Status = 'claimed'",
+            &[&url, &worker_id],
+        ))?;
+        Ok(released > 0)
+    }
+
+    fn frontier_release_all(&self, worker_id: &str) -> Result<(), CrawlerError> {
+        block_on(self.client.execute(
+            "UPDATE FrontierUrl SET Status = 'queued', WorkerId = NULL, LeaseExpires = NULL
+            WHERE WorkerId = $1 AND Status = 'claimed'",
+            &[&worker_id],
+        ))?;
+        Ok(())
+    }
+
+    fn frontier_ack(&self, url: &str, worker_id: &str) -> Result<(), CrawlerError> {
+        block_on(self.client.execute(
+            "UPDATE FrontierUrl SET Status = 'done', LeaseExpires = NULL WHERE Url = $1 AND WorkerId = $2",
+            &[&url, &worker_id],
+        ))?;
+        Ok(())
+    }
+
+    fn frontier_count(&self, claimed: bool) -> Result<usize, CrawlerError> {
+        let row = block_on(self.client.query_one(
+            "SELECT COUNT(*) FROM FrontierUrl WHERE Status = 'queued' OR ($1 AND Status = 'claimed')",
+            &[&claimed],
+        ))?;
+        Ok(row.get::<_, i64>(0) as usize)
+    }
+
+    fn frontier_remove(
+        &self,
+        host: &str,
+        matches: &dyn Fn(&str) -> bool,
+    ) -> Result<Vec<String>, CrawlerError> {
+        let rows = block_on(self.client.query(
+            "SELECT Id, Url FROM FrontierUrl WHERE Status = 'queued' AND Url ~ $1",
+            &[&format!("^https?://{}[/:]", regex::escape(host))],
+        ))?;
+        let ids: Vec<i64> = rows
+            .iter()
+            .filter(|row| matches(row.get(1)))
+            .map(|row| row.get(0))
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Other workers may have claimed some of the URLs since they were read
+        let removed = block_on(self.client.query(
+            "DELETE FROM FrontierUrl WHERE Id = ANY($1) AND Status = 'queued' RETURNING Url",
+            &[&ids],
+        ))?;
+        Ok(removed.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn frontier_clear(&self) -> Result<(), CrawlerError> {
+        block_on(self.client.execute("DELETE FROM FrontierUrl", &[]))?;
+        Ok(())
+    }
+
+    /// Only links that are the URL of a stored page are left out, as failed URLs are not shared.
+    fn frontier_add_uncrawled_links(&self, ignore_robots: bool) -> Result<usize, CrawlerError> {
+        let added = block_on(self.client.execute(
+            "INSERT INTO FrontierUrl (Url)
+            SELECT DISTINCT PageLink.Url FROM PageLink JOIN Page AS Source ON Source.Id = PageLink.PageId
+            WHERE (PageLink.Url LIKE 'http://%' OR PageLink.Url LIKE 'https://%')
+                AND ($1 OR (NOT PageLink.Nofollow AND NOT Source.NoFollow))
+                AND NOT EXISTS (SELECT 1 FROM Page WHERE Page.Url = PageLink.Url)
+            ON CONFLICT (Url) DO NOTHING",
+            &[&ignore_robots],
+        ))?;
+        Ok(added as usize)
+    }
+}
+
+impl Drop for PostgresStore {
+    /// Shuts the runtime of the connection down without waiting for it, which may be done inside another runtime.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Waits for a future on the calling thread, which needs no runtime of its own: queries and spawned tasks are driven by
+/// the runtime of the connection. On a multi-threaded runtime, the other tasks of the worker are handed to other
+/// workers while it waits.
+fn block_on<F: Future>(future: F) -> F::Output {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => {
+            tokio::task::block_in_place(|| futures::executor::block_on(future))
+        }
+        _ => futures::executor::block_on(future),
+    }
+}
+
+/// Formats a time like SQLite's `CURRENT_TIMESTAMP`, with milliseconds.
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y-%m-%d %H:%M:%S%.3f")
+        .to_string()
+}
+
+/// Parses a time stored in the `Created` column of `FrontierUrl`, with or without fractional seconds. Missing and
+/// unreadable times are taken as now.
+fn queued_time(value: Option<&str>) -> SystemTime {
+    value
+        .and_then(|value| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+                .ok()
+        })
+        .map_or_else(SystemTime::now, |time| time.and_utc().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::migrate;
+    use std::net::TcpListener;
+
+    #[test]
+    fn claims_and_removes_queued_urls_only() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        let store: &dyn CrawlStore = &connection;
+        let now = SystemTime::now();
+        for (url, score) in [
+            ("https://a.test/", 1.0),
+            ("https://b.test/", 3.0),
+            ("https://c.test/", 2.0),
+            ("http://a.test:8080/page", 0.5),
+            ("https://a.test.example/", 0.4),
+        ] {
+            assert!(store.frontier_push(url, now, score).unwrap());
+        }
+        assert!(!store.frontier_push("https://a.test/", now, 5.0).unwrap());
+
+        let (claimed, _, score) = store
+            .frontier_claim("worker", Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!((claimed.as_str(), score), ("https://b.test/", 3.0));
+        assert_eq!(store.frontier_count(false).unwrap(), 4);
+        assert_eq!(store.frontier_count(true).unwrap(), 5);
+
+        // Only the URLs of the host are removed, on any port
+        let removed = store.frontier_remove("a.test", &|_| true).unwrap();
+        assert_eq!(removed, ["https://a.test/", "http://a.test:8080/page"]);
+        let removed = store
+            .frontier_remove("c.test", &|url| url.ends_with("/other"))
+            .unwrap();
+        assert!(removed.is_empty());
+        // A claimed URL belongs to its worker until it is released
+        assert!(store
+            .frontier_remove("b.test", &|_| true)
+            .unwrap()
+            .is_empty());
+        assert!(!store.frontier_release("https://b.test/", "other").unwrap());
+        assert!(store.frontier_release("https://b.test/", "worker").unwrap());
+        let taken = store
+            .frontier_take(10)
+            .unwrap()
+            .into_iter()
+            .map(|(url, ..)| url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            taken,
+            [
+                "https://b.test/",
+                "https://c.test/",
+                "https://a.test.example/"
+            ]
+        );
+    }
+
+    /// Connects to a port nobody listens on.
+    fn connect_to_closed_port() -> Result<PostgresStore, CrawlerError> {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        PostgresStore::connect(&format!("postgres://crawler@127.0.0.1:{}/crawl", port))
+    }
+
+    #[tokio::test]
+    async fn connects_from_a_current_thread_runtime() {
+        assert_eq!(block_on(async { 1 + 1 }), 2);
+        assert!(matches!(
+            connect_to_closed_port(),
+            Err(CrawlerError::Postgres(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn connects_from_a_multi_thread_runtime() {
+        assert_eq!(block_on(async { 1 + 1 }), 2);
+        assert!(matches!(
+            connect_to_closed_port(),
+            Err(CrawlerError::Postgres(_))
+        ));
+    }
+
+    #[test]
+    fn connects_outside_a_runtime() {
+        assert!(matches!(
+            connect_to_closed_port(),
+            Err(CrawlerError::Postgres(_))
+        ));
+    }
+}
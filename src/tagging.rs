@@ -0,0 +1,210 @@
+//! Classification of pages with tags, by rules on their URL, markup and text.
+
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::CrawlerError;
+use crate::routing::UrlPattern;
+
+/// A rule of the rules file assigning a tag to the pages that meet all of its conditions.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagRule {
+    /// The tag, e.g. `product` or `legal`.
+    pub tag: String,
+    /// A glob the URL must match, as in storage rules.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// A CSS selector that must match at least one element of the page.
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// Words or phrases of which at least one must appear in the text of the page as whole words, ignoring case.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// A tag rule with its pattern and selector compiled.
+struct CompiledTagRule {
+    tag: String,
+    pattern: Option<UrlPattern>,
+    selector: Option<Selector>,
+    /// Matches any of the keywords as whole words, if the rule has any.
+    keywords: Option<Regex>,
+}
+
+/// Assigns tags to pages by the tag rules.
+#[derive(Default)]
+pub struct PageTagger {
+    rules: Vec<CompiledTagRule>,
+}
+
+impl PageTagger {
+    /// Compiles tag rules.
+    ///
+    /// # Arguments
+    /// * `rules` - The rules.
+    ///
+    /// # Returns
+    /// The tagger, or an error if a rule has no condition or an invalid pattern or selector.
+    pub fn new(rules: &[TagRule]) -> Result<Self, CrawlerError> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                if rule.pattern.is_none() && rule.selector.is_none() && rule.keywords.is_empty() {
+                    return Err(CrawlerError::Config(format!(
+                        "The rule of tag {} has no condition",
+                        rule.tag
+                    )));
+                }
+                let selector = match &rule.selector {
+                    Some(selector) => Some(Selector::parse(selector).map_err(|e| {
+                        CrawlerError::Config(format!(
+                            "Invalid selector {} of tag {}: {}",
+                            selector, rule.tag, e
+                        ))
+                    })?),
+                    None => None,
+                };
+                Ok(CompiledTagRule {
+                    tag: rule.tag.clone(),
+                    pattern: rule.pattern.as_deref().map(UrlPattern::new).transpose()?,
+                    selector,
+                    keywords: keywords_regex(&rule.keywords)?,
+                })
+            })
+            .collect::<Result<Vec<_>, CrawlerError>>()?;
+        Ok(PageTagger { rules })
+    }
+
+    /// Returns whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the tags of a page.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    /// * `document` - The parsed contents of the page.
+    /// * `text` - The plain text rendition of the page.
+    ///
+    /// # Returns
+    /// The distinct tags of the rules the page meets, in the order of the rules.
+    pub fn tags(&self, url: &Url, document: &Html, text: &str) -> Vec<&str> {
+        let mut tags: Vec<&str> = Vec::new();
+        for rule in &self.rules {
+            if tags.contains(&rule.tag.as_str()) {
+                continue;
+            }
+            if rule.pattern.as_ref().is_some_and(|p| !p.matches(url)) {
+                continue;
+            }
+            if let Some(selector) = &rule.selector {
+                if document.select(selector).next().is_none() {
+                    continue;
+                }
+            }
+            if rule.keywords.as_ref().is_some_and(|k| !k.is_match(text)) {
+                continue;
+            }
+            tags.push(&rule.tag);
+        }
+        tags
+    }
+}
+
+/// Builds a regular expression matching any of the keywords as whole words, ignoring case. The words of a phrase may
+/// be separated by any whitespace.
+///
+/// # Returns
+/// The regular expression, or None if there are no keywords.
+fn keywords_regex(keywords: &[String]) -> Result<Option<Regex>, CrawlerError> {
+    if keywords.is_empty() {
+        return Ok(None);
+    }
+    let alternatives: Vec<String> = keywords
+        .iter()
+        .map(|keyword| {
+            keyword
+                .split_whitespace()
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(r"\s+")
+        })
+        .collect();
+    // Consuming the characters around a keyword also bounds keywords that start or end with punctuation, e.g. C++
+    Ok(Some(Regex::new(&format!(
+        r"(?i)(?:^|\W)(?:{})(?:\W|$)",
+        alternatives.join("|")
+    ))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_whole_keywords() {
+        let rules = [TagRule {
+            tag: "legal".to_string(),
+            pattern: None,
+            selector: None,
+            keywords: vec![
+                "legal".to_string(),
+                "terms of service".to_string(),
+                "C++".to_string(),
+            ],
+        }];
+        let tagger = PageTagger::new(&rules).unwrap();
+        let url = Url::parse("https://example.com/").unwrap();
+        let document = Html::parse_document("<p>Page</p>");
+        let cases = [
+            ("Legal notice", true),
+            ("See our legal.", true),
+            ("(legal)", true),
+            ("Read the Terms\nof  Service first", true),
+            ("Learn c++ today", true),
+            ("This is illegal", false),
+            ("legally speaking", false),
+            ("paralegal services", false),
+            ("terms of services", false),
+            ("c+", false),
+            ("", false),
+        ];
+        for (text, tagged) in cases {
+            assert_eq!(
+                tagger.tags(&url, &document, text) == ["legal"],
+                tagged,
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn requires_every_condition() {
+        let rules: Vec<TagRule> = serde_json::from_str(
+            r#"[
+                {"tag": "docs", "pattern": "/docs/**", "selector": "article"},
+                {"tag": "docs", "keywords": ["documentation"]},
+                {"tag": "empty"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(PageTagger::new(&rules).is_err());
+        let tagger = PageTagger::new(&rules[..2]).unwrap();
+        let article = Html::parse_document("<article>Guide</article>");
+        let paragraph = Html::parse_document("<p>Guide</p>");
+        let docs = Url::parse("https://example.com/docs/guide").unwrap();
+        let blog = Url::parse("https://example.com/blog/guide").unwrap();
+        assert_eq!(tagger.tags(&docs, &article, "Guide"), ["docs"]);
+        assert!(tagger.tags(&docs, &paragraph, "Guide").is_empty());
+        assert!(tagger.tags(&blog, &article, "Guide").is_empty());
+        assert_eq!(
+            tagger.tags(&blog, &paragraph, "The documentation"),
+            ["docs"]
+        );
+    }
+}
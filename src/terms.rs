@@ -0,0 +1,396 @@
+//! Term frequencies of pages, for keyword statistics alongside the full-text search index.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+
+use crate::error::CrawlerError;
+
+/// The number of most frequent terms recorded per page.
+pub const TOP_TERMS: usize = 50;
+
+/// Common English words that say nothing about the topic of a page.
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "do", "for", "from", "had", "has", "have", "he", "her", "his", "how", "if",
+    "in", "into", "is", "it", "its", "more", "no", "not", "of", "on", "one", "or", "our", "out",
+    "she", "so", "some", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "to", "up", "was", "we", "were", "what", "which", "who", "will", "with",
+];
+
+/// The common words of other languages, by ISO 639-1 code. Pages in languages without a list, or whose language is
+/// not known, are counted with the English list.
+const STOP_WORDS: [(&str, &[&str]); 6] = [
+    (
+        "de",
+        &[
+            "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bis", "das", "dass", "dem",
+            "den", "der", "des", "die", "du", "ein", "eine", "einem", "einen", "einer", "er", "es",
+            "für", "hat", "ich", "ihr", "im", "in", "ist", "mit", "nach", "nicht", "noch", "nur",
+            "oder", "sich", "sie", "sind", "so", "um", "und", "uns", "von", "vor", "war", "wie",
+            "wir", "wird", "zu", "zum", "zur",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este", "la", "las", "le",
+            "lo", "los", "más", "no", "o", "para", "pero", "por", "que", "se", "si", "sin",
+            "sobre", "su", "sus", "un", "una", "y", "ya",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "est", "et",
+            "il", "ils", "la", "le", "les", "leur", "mais", "nous", "ou", "par", "pas", "plus",
+            "pour", "qui", "que", "sa", "se", "son", "sont", "sur", "un", "une", "vous",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "al", "alla", "che", "come", "con", "da", "del", "della", "di", "e", "gli", "il", "in",
+            "la", "le", "lo", "ma", "nel", "non", "per", "più", "se", "si", "sono", "su", "un",
+            "una",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "aan", "als", "bij", "dat", "de", "die", "een", "en", "er", "het", "hij", "in", "is",
+            "met", "niet", "of", "om", "ook", "op", "te", "van", "voor", "was", "wat", "we",
+            "zijn",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "ela", "ele", "em", "era",
+            "é", "mais", "mas", "na", "no", "nos", "não", "o", "os", "para", "pela", "pelo", "por",
+            "que", "se", "seu", "sua", "um", "uma",
+        ],
+    ),
+];
+
+/// Returns the common words of a language.
+fn stop_words(language: Option<&str>) -> &'static [&'static str] {
+    STOP_WORDS
+        .iter()
+        .find(|(code, _)| Some(*code) == language)
+        .map_or(ENGLISH_STOP_WORDS, |(_, words)| words)
+}
+
+/// Returns whether a character is written in a script whose words are not separated by spaces: Chinese characters,
+/// and the Japanese kana.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2fa1f}')
+}
+
+/// A term and the number of times it occurs.
+pub struct TermCount {
+    /// The lowercase term.
+    pub term: String,
+    /// The number of occurrences.
+    pub count: i64,
+}
+
+/// A page mentioning a term.
+pub struct TermMatch {
+    /// The URL of the page.
+    pub url: String,
+    /// The number of times the page mentions the term.
+    pub count: i64,
+}
+
+/// Splits text into lowercase terms, leaving out numbers, single characters and the stop words of its language.
+///
+/// Chinese and Japanese text, whose words are not separated, is split into overlapping pairs of characters, as
+/// full-text indexes do, so `東京都` gives `東京` and `京都`. A character standing alone is a term of its own.
+///
+/// # Arguments
+/// * `text` - The plain text of a page.
+/// * `language` - The ISO 639-1 code of the language of the page, which chooses the stop words.
+///
+/// # Returns
+/// The terms, in the order they occur.
+pub fn tokenize<'a>(text: &'a str, language: Option<&str>) -> impl Iterator<Item = String> + 'a {
+    let stop_words = stop_words(language);
+    text.split(|c: char| !c.is_alphanumeric())
+        .flat_map(split_cjk)
+        .filter_map(move |(word, cjk)| {
+            if cjk {
+                return Some(word.to_string());
+            }
+            let word = word.to_lowercase();
+            (word.chars().nth(1).is_some()
+                && !word.chars().all(|c| c.is_numeric())
+                && !stop_words.contains(&word.as_str()))
+            .then_some(word)
+        })
+}
+
+/// Splits a word at the changes between Chinese or Japanese characters and other characters, and the Chinese or
+/// Japanese runs into overlapping pairs of characters.
+///
+/// # Returns
+/// The parts, and whether each is Chinese or Japanese.
+fn split_cjk(word: &str) -> Vec<(&str, bool)> {
+    let mut parts = Vec::new();
+    let mut rest = word;
+    while let Some(first) = rest.chars().next() {
+        let cjk = is_cjk(first);
+        let end = rest.find(|c: char| is_cjk(c) != cjk).unwrap_or(rest.len());
+        let (run, next) = rest.split_at(end);
+        if !cjk {
+            parts.push((run, false));
+        } else {
+            let starts = run
+                .char_indices()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            match starts.len() {
+                1 => parts.push((run, true)),
+                count => parts.extend((0..count - 1).map(|i| {
+                    let end = starts.get(i + 2).copied().unwrap_or(run.len());
+                    (&run[starts[i]..end], true)
+                })),
+            }
+        }
+        rest = next;
+    }
+    parts
+}
+
+/// Counts the terms of a text.
+///
+/// # Arguments
+/// * `text` - The plain text of a page.
+/// * `language` - The ISO 639-1 code of the language of the page.
+/// * `limit` - The maximum number of terms to return.
+///
+/// # Returns
+/// The most frequent terms, most frequent first, and alphabetically among terms occurring equally often.
+pub fn top_terms(text: &str, language: Option<&str>, limit: usize) -> Vec<TermCount> {
+    let mut counts: HashMap<String, i64> = HashMap::new();
+    for term in tokenize(text, language) {
+        *counts.entry(term).or_default() += 1;
+    }
+    let mut terms: Vec<TermCount> = counts
+        .into_iter()
+        .map(|(term, count)| TermCount { term, count })
+        .collect();
+    terms.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    terms.truncate(limit);
+    terms
+}
+
+/// Replaces the recorded terms of a page with the most frequent terms of its text.
+///
+/// Duplicate pages and pages whose contents are not indexed are skipped, like their text.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `page_id` - The id of the page entity.
+/// * `text` - The plain text of the page.
+/// * `language` - The ISO 639-1 code of the language of the page.
+///
+/// # Returns
+/// A Result indicating success or failure.
+pub fn record_terms(
+    connection: &Connection,
+    page_id: i64,
+    text: &str,
+    language: Option<&str>,
+) -> Result<(), CrawlerError> {
+    connection.execute("DELETE FROM PageTerm WHERE PageId = ?", [page_id])?;
+    let mut stmt = connection.prepare_cached(
+        "INSERT INTO PageTerm (PageId, Term, Count)
+        SELECT Id, ?, ? FROM Page WHERE Id = ? AND DuplicateOf IS NULL AND Indexed = 1",
+    )?;
+    for term in top_terms(text, language, TOP_TERMS) {
+        stmt.execute(params![term.term, term.count, page_id])?;
+    }
+    Ok(())
+}
+
+/// Reads the recorded terms of a page.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `url` - The final or requested URL of the page.
+/// * `limit` - The maximum number of terms to return.
+///
+/// # Returns
+/// The most frequent terms of the page, most frequent first, or None if no page has the URL.
+pub fn page_keywords(
+    connection: &Connection,
+    url: &str,
+    limit: usize,
+) -> Result<Option<Vec<TermCount>>, CrawlerError> {
+    let page_id: Option<i64> = connection
+        .query_row(
+            "SELECT COALESCE(DuplicateOf, Id) FROM Page WHERE Url = ?1 OR RequestedUrl = ?1
+            ORDER BY Url = ?1 DESC LIMIT 1",
+            [url],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(page_id) = page_id else {
+        return Ok(None);
+    };
+    let terms = connection
+        .prepare(
+            "SELECT Term, Count FROM PageTerm WHERE PageId = ? ORDER BY Count DESC, Term LIMIT ?",
+        )?
+        .query_map(params![page_id, limit], |row| {
+            Ok(TermCount {
+                term: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(terms))
+}
+
+/// Finds the pages mentioning a term among their most frequent terms.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `term` - The term, matched case-insensitively.
+/// * `limit` - The maximum number of pages to return.
+///
+/// # Returns
+/// The pages, those mentioning the term most often first.
+pub fn pages_with_term(
+    connection: &Connection,
+    term: &str,
+    limit: usize,
+) -> Result<Vec<TermMatch>, CrawlerError> {
+    let matches = connection
+        .prepare(
+            "SELECT Page.Url, PageTerm.Count FROM PageTerm JOIN Page ON Page.Id = PageTerm.PageId
+            WHERE PageTerm.Term = ?
+            ORDER BY PageTerm.Count DESC, Page.Url
+            LIMIT ?",
+        )?
+        .query_map(params![term.to_lowercase(), limit], |row| {
+            Ok(TermMatch {
+                url: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_terms() {
+        let cases: [(&str, Option<&str>, &[&str]); 8] = [
+            (
+                "The Crawler crawls: the web's pages, in 2024!",
+                None,
+                &["crawler", "crawls", "web", "pages"],
+            ),
+            ("a b c 42 1.5 x2 ÄRGER", Some("en"), &["x2", "ärger"]),
+            // Stop words are those of the language of the page
+            ("Der Hund und die Katze", Some("de"), &["hund", "katze"]),
+            (
+                "Der Hund und die Katze",
+                Some("en"),
+                &["der", "hund", "und", "die", "katze"],
+            ),
+            ("Le chat est sur la table", Some("fr"), &["chat", "table"]),
+            ("The cat is on the table", Some("xx"), &["cat", "table"]),
+            // Chinese and Japanese text is split into pairs of characters
+            (
+                "東京都に行く",
+                Some("ja"),
+                &["東京", "京都", "都に", "に行", "行く"],
+            ),
+            ("Rust语言 和 Go", Some("zh"), &["rust", "语言", "和", "go"]),
+        ];
+        for (text, language, terms) in cases {
+            assert_eq!(
+                tokenize(text, language).collect::<Vec<_>>(),
+                terms,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn counts_the_most_frequent_terms() {
+        let terms = top_terms(
+            "Rust crawler. The crawler stores pages; the crawler reads pages. Rust!",
+            Some("en"),
+            3,
+        );
+        let terms = terms
+            .iter()
+            .map(|term| (term.term.as_str(), term.count))
+            .collect::<Vec<_>>();
+        assert_eq!(terms, [("crawler", 3), ("pages", 2), ("rust", 2)]);
+    }
+
+    #[test]
+    fn records_and_finds_terms() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO Page (Id, Url, RequestedUrl, Hash, DuplicateOf, Indexed) VALUES
+                    (1, 'https://example.com/', 'https://example.com/', 'a', NULL, 1),
+                    (2, 'https://example.com/b', 'https://example.com/b', 'b', NULL, 1),
+                    (3, 'https://example.com/copy', 'https://example.com/copy', 'a', 1, 1),
+                    (4, 'https://example.com/hidden', 'https://example.com/hidden', 'c', NULL, 0);",
+            )
+            .unwrap();
+        record_terms(&connection, 1, "Rust rust crawler", None).unwrap();
+        // Terms recorded again replace those of the previous fetch
+        record_terms(&connection, 2, "python", None).unwrap();
+        record_terms(&connection, 2, "Rust tokio", None).unwrap();
+        record_terms(&connection, 3, "Rust", None).unwrap();
+        record_terms(&connection, 4, "Rust", None).unwrap();
+
+        let keywords = |url| {
+            page_keywords(&connection, url, 10).unwrap().map(|terms| {
+                terms
+                    .into_iter()
+                    .map(|term| (term.term, term.count))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let expected = vec![("rust".to_string(), 2), ("crawler".to_string(), 1)];
+        assert_eq!(keywords("https://example.com/"), Some(expected.clone()));
+        // A duplicate has the terms of the page it duplicates
+        assert_eq!(keywords("https://example.com/copy"), Some(expected));
+        assert_eq!(keywords("https://example.com/hidden"), Some(Vec::new()));
+        assert_eq!(keywords("https://example.com/missing"), None);
+
+        let pages = pages_with_term(&connection, "RUST", 10)
+            .unwrap()
+            .into_iter()
+            .map(|page| (page.url, page.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pages,
+            [
+                ("https://example.com/".to_string(), 2),
+                ("https://example.com/b".to_string(), 1),
+            ]
+        );
+        assert!(pages_with_term(&connection, "python", 10)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Adaptive throttling of domains that respond with 429 Too Many Requests or 503 Service Unavailable.
+
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+
+/// The number of times a throttled URL is queued again before its response is recorded as a failure.
+pub const MAX_THROTTLE_RETRIES: u32 = 5;
+/// The delay between requests that throttling starts doubling from, for domains without a longer crawl delay.
+pub const MIN_THROTTLE_DELAY: Duration = Duration::from_secs(1);
+/// The longest delay between requests throttling grows to.
+pub const MAX_THROTTLE_DELAY: Duration = Duration::from_secs(60);
+/// The longest pause honoured, however late a `Retry-After` header asks to come back.
+pub const MAX_THROTTLE_PAUSE: Duration = Duration::from_secs(60 * 60);
+
+/// How a throttled domain is slowed down for the rest of a run.
+#[derive(Clone, Copy)]
+pub struct Throttle {
+    /// The minimum time between requests to the domain.
+    pub delay: Duration,
+    /// The time before which no request is sent to the domain.
+    pub paused_until: Instant,
+}
+
+impl Throttle {
+    /// Throttles a domain that responded with 429 or 503.
+    ///
+    /// The delay between requests is doubled every time the domain throttles the crawler, up to
+    /// [`MAX_THROTTLE_DELAY`]. The domain is paused for the time given by `Retry-After`, or else for the new delay.
+    ///
+    /// # Arguments
+    /// * `previous` - The throttle of an earlier response of the domain, if any.
+    /// * `crawl_delay` - The crawl delay of the domain before it was first throttled, if any.
+    /// * `retry_after` - The time given by the `Retry-After` header of the response, if any.
+    ///
+    /// # Returns
+    /// The new throttle of the domain.
+    pub fn next(
+        previous: Option<&Throttle>,
+        crawl_delay: Option<Duration>,
+        retry_after: Option<Duration>,
+    ) -> Throttle {
+        let base = previous
+            .map(|throttle| throttle.delay)
+            .or(crawl_delay)
+            .unwrap_or_default()
+            .max(MIN_THROTTLE_DELAY);
+        let delay = (base * 2).min(MAX_THROTTLE_DELAY);
+        let pause = retry_after.unwrap_or(delay).min(MAX_THROTTLE_PAUSE);
+        Throttle {
+            delay,
+            paused_until: Instant::now() + pause,
+        }
+    }
+
+    /// Returns the time left until the domain may be requested again, or None if it is not paused.
+    pub fn remaining(&self) -> Option<Duration> {
+        Some(self.paused_until.saturating_duration_since(Instant::now())).filter(|d| !d.is_zero())
+    }
+}
+
+/// Parses the value of a `Retry-After` header, either a number of seconds or an HTTP date.
+///
+/// # Arguments
+/// * `value` - The value of the header.
+/// * `now` - The current time, which an HTTP date is relative to.
+///
+/// # Returns
+/// The time to wait, zero for dates in the past, or None if the value is invalid.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let cases = [
+            ("120", Some(Duration::from_secs(120))),
+            (" 0 ", Some(Duration::ZERO)),
+            (
+                "Wed, 21 Oct 2015 07:29:30 GMT",
+                Some(Duration::from_secs(90)),
+            ),
+            ("Wed, 21 Oct 2015 07:00:00 GMT", Some(Duration::ZERO)),
+            ("-5", None),
+            ("soon", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_retry_after(value, now), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn doubles_delay_up_to_limit() {
+        let first = Throttle::next(None, None, None);
+        assert_eq!(first.delay, MIN_THROTTLE_DELAY * 2);
+        assert!(first.remaining().unwrap() <= first.delay);
+
+        let slow = Throttle::next(None, Some(Duration::from_secs(10)), None);
+        assert_eq!(slow.delay, Duration::from_secs(20));
+        assert_eq!(
+            Throttle::next(Some(&slow), None, None).delay,
+            Duration::from_secs(40)
+        );
+
+        let mut throttle = first;
+        for _ in 0..10 {
+            throttle = Throttle::next(Some(&throttle), None, None);
+        }
+        assert_eq!(throttle.delay, MAX_THROTTLE_DELAY);
+
+        let told = Throttle::next(None, None, Some(Duration::from_secs(24 * 60 * 60)));
+        assert!(told.remaining().unwrap() > MAX_THROTTLE_DELAY);
+        assert!(told.remaining().unwrap() <= MAX_THROTTLE_PAUSE);
+    }
+}
@@ -0,0 +1,74 @@
+//! TLS settings shared by every client the crawler makes requests with.
+
+use reqwest::{Certificate, ClientBuilder};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::CrawlerError;
+
+/// Which certificates the clients of the crawler trust: the system roots, the certificates of extra CA files, or any
+/// certificate at all.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// PEM files whose certificates are trusted as roots besides the system roots.
+    pub ca_certs: Vec<PathBuf>,
+    /// Whether invalid certificates are accepted, including expired, self-signed and mismatched ones.
+    pub accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    /// Adds the TLS settings to the builder of a client.
+    ///
+    /// # Arguments
+    /// * `client` - The builder of the client.
+    ///
+    /// # Returns
+    /// The client builder, or an error if a CA file cannot be read or holds no valid certificate.
+    pub fn configure(&self, mut client: ClientBuilder) -> Result<ClientBuilder, CrawlerError> {
+        for path in &self.ca_certs {
+            let invalid = |e: &dyn std::fmt::Display| {
+                CrawlerError::Config(format!("Invalid CA file {}: {}", path.display(), e))
+            };
+            let certificates =
+                Certificate::from_pem_bundle(&fs::read(path).map_err(|e| invalid(&e))?)
+                    .map_err(|e| invalid(&e))?;
+            if certificates.is_empty() {
+                return Err(invalid(&"no certificates found"));
+            }
+            for certificate in certificates {
+                client = client.add_root_certificate(certificate);
+            }
+        }
+        Ok(client.danger_accept_invalid_certs(self.accept_invalid_certs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+    fn configure(files: &[&str]) -> Result<ClientBuilder, CrawlerError> {
+        let tls = TlsConfig {
+            ca_certs: files
+                .iter()
+                .map(|file| PathBuf::from(FIXTURES).join(file))
+                .collect(),
+            accept_invalid_certs: false,
+        };
+        tls.configure(Client::builder())
+    }
+
+    #[test]
+    fn loads_pem_bundles() {
+        assert!(configure(&["bundle.pem"]).unwrap().build().is_ok());
+        assert!(configure(&[]).unwrap().build().is_ok());
+
+        let error = configure(&["bundle.pem", "empty.pem"]).err().unwrap();
+        assert!(error.to_string().contains("no certificates found"));
+        let error = configure(&["missing.pem"]).err().unwrap();
+        assert!(error.to_string().contains("missing.pem"));
+    }
+}
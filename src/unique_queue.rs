@@ -1,3 +1,5 @@
+//! A queue without duplicates.
+
 use std::collections::HashSet;
 
 /// A queue that maintains unique elements.
@@ -6,6 +8,12 @@ pub struct UniqueQueue<T> {
     set: HashSet<T>,
 }
 
+impl<T: Eq + std::hash::Hash + Clone> Default for UniqueQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Eq + std::hash::Hash + Clone> UniqueQueue<T> {
     /// Creates a new `UniqueQueue`.
     pub fn new() -> Self {
@@ -19,9 +27,15 @@ impl<T: Eq + std::hash::Hash + Clone> UniqueQueue<T> {
     ///
     /// # Arguments
     /// `item` - The item to be pushed into the queue.
-    pub fn push(&mut self, item: T) {
+    ///
+    /// # Returns
+    /// `true` if the item was added, `false` if it was already queued.
+    pub fn push(&mut self, item: T) -> bool {
         if self.set.insert(item.clone()) {
             self.queue.push(item);
+            true
+        } else {
+            false
         }
     }
 
@@ -38,8 +52,34 @@ impl<T: Eq + std::hash::Hash + Clone> UniqueQueue<T> {
         }
     }
 
+    /// Removes the items that do not satisfy a predicate.
+    ///
+    /// # Arguments
+    /// `keep` - Returns whether to keep an item.
+    ///
+    /// # Returns
+    /// The removed items, in queue order.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut removed = Vec::new();
+        self.queue.retain(|item| {
+            if keep(item) {
+                true
+            } else {
+                self.set.remove(item);
+                removed.push(item.clone());
+                false
+            }
+        });
+        removed
+    }
+
     /// Returns whether the queue is empty.
     pub fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
 }
@@ -0,0 +1,253 @@
+//! Checks to run before a crawl.
+
+use reqwest::Client;
+use rusqlite::{Connection, OpenFlags};
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+use crate::config::CrawlConfig;
+use crate::dns::Resolver;
+use crate::error::CrawlerError;
+use crate::normalize::display_host;
+use crate::schema;
+
+/// The outcome of a single pre-crawl check.
+pub struct Check {
+    /// What was checked.
+    pub name: String,
+    /// A description of the problem, if the check failed.
+    pub problem: Option<String>,
+}
+
+impl Check {
+    fn passed(name: String) -> Self {
+        Check {
+            name,
+            problem: None,
+        }
+    }
+
+    fn failed(name: String, problem: String) -> Self {
+        Check {
+            name,
+            problem: Some(problem),
+        }
+    }
+}
+
+/// Checks that the configuration file given with `--config` can be read and is valid.
+///
+/// # Arguments
+/// * `path` - The path of the configuration file.
+///
+/// # Returns
+/// The outcome of the check.
+pub fn check_config(path: &Path) -> Check {
+    let name = format!("Configuration file {}", path.display());
+    match CrawlConfig::load(path) {
+        Ok(_) => Check::passed(name),
+        Err(e) => Check::failed(name, e.to_string()),
+    }
+}
+
+/// Checks that a seed URL can be crawled.
+///
+/// The URL must be a valid http(s) URL whose host resolves, and which responds successfully. A missing robots.txt is
+/// not a problem, but an unreachable one is.
+///
+/// # Arguments
+/// * `client` - The HTTP client to send requests with.
+/// * `resolver` - The resolver the host of the seed is looked up with.
+/// * `seed` - The seed URL.
+///
+/// # Returns
+/// The outcome of each check.
+pub async fn check_seed(client: &Client, resolver: &Resolver, seed: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let url = match Url::parse(seed) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        Ok(url) => {
+            let problem = format!("unsupported scheme \"{}\"", url.scheme());
+            return vec![Check::failed(format!("Seed {}", seed), problem)];
+        }
+        Err(e) => return vec![Check::failed(format!("Seed {}", seed), e.to_string())],
+    };
+    let Some(host) = url.host_str() else {
+        let problem = "the URL has no host".to_string();
+        return vec![Check::failed(format!("Seed {}", seed), problem)];
+    };
+    let domain = display_host(host);
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let name = format!("DNS resolution of {}", domain);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match resolver.lookup(host, port).await.map(|a| a.len()) {
+        Ok(0) => checks.push(Check::failed(name, "no addresses found".to_string())),
+        Ok(_) => checks.push(Check::passed(name)),
+        Err(e) => {
+            checks.push(Check::failed(name, e.to_string()));
+            return checks;
+        }
+    }
+
+    let robots_url = url.join("/robots.txt").unwrap();
+    let name = format!("Robots.txt of {}", domain);
+    match client.get(robots_url).send().await {
+        Ok(response) if response.status().is_server_error() => {
+            let problem = format!("responded with {}", response.status());
+            checks.push(Check::failed(name, problem));
+        }
+        Ok(_) => checks.push(Check::passed(name)),
+        Err(e) => checks.push(Check::failed(name, e.to_string())),
+    }
+
+    let name = format!("Reachability of {}", seed);
+    match client.get(url.as_str()).send().await {
+        Ok(response) if response.status().is_success() || response.status().is_redirection() => {
+            checks.push(Check::passed(name))
+        }
+        Ok(response) => {
+            let problem = format!("responded with {}", response.status());
+            checks.push(Check::failed(name, problem));
+        }
+        Err(e) => checks.push(Check::failed(name, e.to_string())),
+    }
+    checks
+}
+
+/// Checks that pages can be saved to a directory.
+///
+/// A missing directory is not a problem, as it is created when the database is initialized.
+///
+/// # Arguments
+/// * `save_dir` - The directory pages are saved to.
+///
+/// # Returns
+/// The outcome of the check.
+pub fn check_save_dir(save_dir: &str) -> Check {
+    let name = format!("Storage directory {}", save_dir);
+    let path = Path::new(save_dir);
+    if !path.exists() {
+        return Check::passed(name);
+    }
+    if !path.is_dir() {
+        return Check::failed(name, "not a directory".to_string());
+    }
+    let probe = path.join(".validate");
+    match fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)) {
+        Ok(()) => Check::passed(name),
+        Err(e) => Check::failed(name, format!("not writable: {}", e)),
+    }
+}
+
+/// Checks that the crawler can use the database, whose schema is upgraded when it is opened.
+///
+/// A missing database is not a problem, as the crawl creates it, and neither is an older schema version. Databases
+/// created by a newer version of the crawler are.
+///
+/// # Arguments
+/// * `db_name` - The path of the database.
+/// * `schema_version` - The schema version the crawler expects.
+///
+/// # Returns
+/// The outcome of the check.
+pub fn check_database(db_name: &str, schema_version: i64) -> Check {
+    let name = format!("Database {}", db_name);
+    if !Path::new(db_name).exists() {
+        return Check::passed(name);
+    }
+
+    let version = Connection::open_with_flags(db_name, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(CrawlerError::from)
+        .and_then(|connection| schema::schema_version(&connection));
+    match version {
+        Ok(version) if version <= schema_version => Check::passed(name),
+        Ok(version) => Check::failed(
+            name,
+            format!(
+                "schema version is {}, newer than version {} of this crawler",
+                version, schema_version
+            ),
+        ),
+        Err(e) => Check::failed(name, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "web_crawler_validate_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn checks_configuration_files() {
+        let dir = test_dir("config");
+        let valid = dir.join("valid.toml");
+        fs::write(&valid, "seeds = [\"https://example.com/\"]\n").unwrap();
+        let invalid = dir.join("invalid.toml");
+        fs::write(&invalid, "seeds = [\n").unwrap();
+
+        assert!(check_config(&valid).problem.is_none());
+        assert!(check_config(&invalid)
+            .problem
+            .unwrap()
+            .contains("Invalid configuration file"));
+        assert!(check_config(&dir.join("missing.toml")).problem.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_storage_directories() {
+        let dir = test_dir("save_dir");
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+
+        assert!(check_save_dir(&dir.join("missing").to_string_lossy())
+            .problem
+            .is_none());
+        assert_eq!(
+            check_save_dir(&file.to_string_lossy()).problem.as_deref(),
+            Some("not a directory")
+        );
+        assert!(check_save_dir(&dir.to_string_lossy()).problem.is_none());
+        assert!(!dir.join(".validate").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_database_versions() {
+        let dir = test_dir("database");
+        let database = |version: i64| {
+            let path = dir.join(format!("version_{}.db", version));
+            Connection::open(&path)
+                .unwrap()
+                .pragma_update(None, "user_version", version)
+                .unwrap();
+            path.to_string_lossy().into_owned()
+        };
+
+        let missing = dir.join("missing.db");
+        assert!(check_database(&missing.to_string_lossy(), 3)
+            .problem
+            .is_none());
+        assert!(!missing.exists());
+        assert!(check_database(&database(2), 3).problem.is_none());
+        assert!(check_database(&database(3), 3).problem.is_none());
+        assert_eq!(
+            check_database(&database(4), 3).problem.as_deref(),
+            Some("schema version is 4, newer than version 3 of this crawler")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
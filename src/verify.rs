@@ -0,0 +1,317 @@
+//! Auditing of the saved page bodies against the database, and repair of what disagrees.
+
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::digest::HashAlgorithm;
+use crate::error::CrawlerError;
+use crate::storage::{find_page_file, page_file_hash, read_page_blob, read_page_file};
+
+/// Where a page body is expected to be saved.
+pub enum SavedBody {
+    /// A file, named by its hash in the pages directory or chosen by a storage route.
+    File(PathBuf),
+    /// A row of the `PageBody` table.
+    Blob,
+}
+
+/// What is wrong with the saved body of a page.
+pub enum BodyProblem {
+    /// No file or BLOB of the body exists.
+    Missing,
+    /// The body does not hash to the recorded hash.
+    Mismatch {
+        /// The hash of the saved body.
+        actual: String,
+    },
+    /// The body exists but cannot be read or decompressed.
+    Unreadable(String),
+}
+
+/// A page whose saved body disagrees with the database.
+pub struct BrokenPage {
+    /// The URL of the page.
+    pub url: String,
+    /// The recorded hash of the body.
+    pub hash: String,
+    /// Where the body was looked for, or None if it was found nowhere.
+    pub location: Option<SavedBody>,
+    /// What is wrong with the body.
+    pub problem: BodyProblem,
+}
+
+/// The outcome of an audit.
+#[derive(Default)]
+pub struct VerifyReport {
+    /// The number of saved bodies checked.
+    pub checked: usize,
+    /// The pages whose body is missing, corrupt or unreadable.
+    pub broken: Vec<BrokenPage>,
+    /// The files of the pages directory that no page or revision refers to.
+    pub orphan_files: Vec<PathBuf>,
+    /// The hashes of the BLOBs that no page or revision refers to.
+    pub orphan_blobs: Vec<String>,
+}
+
+/// Re-hashes the saved body of every distinct page, and looks for saved bodies no page refers to.
+///
+/// Bodies saved in WARC and JSONL files or in object storage are not checked, nor are pages whose contents were not
+/// saved. Pages of crawls run with `--no-store` are reported as missing, as nothing tells them apart.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `save_dir` - The directory pages are saved in.
+///
+/// # Returns
+/// The report, or an error if the database or the pages directory cannot be read.
+pub fn verify(connection: &Connection, save_dir: &Path) -> Result<VerifyReport, CrawlerError> {
+    let pages = connection
+        .prepare(
+            "SELECT Url, Hash, HashAlgorithm, StoredFile FROM Page
+            WHERE DuplicateOf IS NULL AND Indexed = 1 AND WarcFile IS NULL
+                AND COALESCE(StoredFile NOT LIKE '%.jsonl' AND StoredFile NOT LIKE '%://%', 1)
+            ORDER BY Id",
+        )?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut report = VerifyReport::default();
+    for (url, hash, algorithm, stored_file) in pages {
+        let algorithm = HashAlgorithm::from_name(&algorithm).unwrap_or_default();
+        let (location, body) = match stored_file {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let body = read_page_file(&path);
+                (Some(SavedBody::File(path)), body)
+            }
+            None => match read_page_blob(connection, &hash) {
+                Ok(Some(body)) => (Some(SavedBody::Blob), Ok(body)),
+                Ok(None) => match find_page_file(save_dir, &hash) {
+                    Some(path) => {
+                        let body = read_page_file(&path);
+                        (Some(SavedBody::File(path)), body)
+                    }
+                    None => (None, Err(io::ErrorKind::NotFound.into())),
+                },
+                Err(e) => (Some(SavedBody::Blob), Err(io::Error::other(e))),
+            },
+        };
+        report.checked += 1;
+        let problem = match body {
+            Ok(body) => {
+                let actual = algorithm.digest(&body);
+                if actual == hash {
+                    continue;
+                }
+                BodyProblem::Mismatch { actual }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BodyProblem::Missing,
+            Err(e) => BodyProblem::Unreadable(e.to_string()),
+        };
+        report.broken.push(BrokenPage {
+            url,
+            hash,
+            location,
+            problem,
+        });
+    }
+
+    let referenced: HashSet<String> = connection
+        .prepare("SELECT Hash FROM Page UNION SELECT Hash FROM PageRevision")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if save_dir.is_dir() {
+        for entry in fs::read_dir(save_dir)? {
+            let path = entry?.path();
+            if page_file_hash(&path).is_some_and(|hash| !referenced.contains(hash)) {
+                report.orphan_files.push(path);
+            }
+        }
+        report.orphan_files.sort();
+    }
+    report.orphan_blobs = connection
+        .prepare("SELECT Hash FROM PageBody ORDER BY Hash")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter(|hash| {
+            hash.as_ref()
+                .map_or(true, |hash| !referenced.contains(hash))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(report)
+}
+
+/// Repairs what an audit found.
+///
+/// The corrupt bodies of broken pages are deleted and the pages queued in the frontier, with their validators
+/// cleared, so `resume --revalidate` fetches them again in full. Orphan files and BLOBs are deleted. Files are only
+/// deleted once the changes to the database are committed, so no page is left referring to a deleted file, and files
+/// that cannot be deleted are logged and left for the next audit.
+///
+/// # Arguments
+/// * `connection` - The database connection.
+/// * `report` - The report of the audit.
+///
+/// # Returns
+/// The number of pages queued, and the number of files and BLOBs deleted.
+pub fn repair(
+    connection: &mut Connection,
+    report: &VerifyReport,
+) -> Result<(usize, usize), CrawlerError> {
+    let transaction = connection.transaction()?;
+    let (mut queued, mut deleted) = (0, 0);
+    let mut files = Vec::new();
+    for page in &report.broken {
+        if !matches!(page.problem, BodyProblem::Missing) {
+            match &page.location {
+                Some(SavedBody::File(path)) => files.push(path),
+                Some(SavedBody::Blob) => {
+                    deleted +=
+                        transaction.execute("DELETE FROM PageBody WHERE Hash = ?", [&page.hash])?;
+                }
+                None => {}
+            }
+        }
+        transaction.execute(
+            "UPDATE Page SET ETag = NULL, LastModified = NULL WHERE Url = ?",
+            [&page.url],
+        )?;
+        queued += transaction.execute(
+            "INSERT OR IGNORE INTO FrontierUrl (Url) VALUES (?)",
+            params![page.url],
+        )?;
+    }
+    for hash in &report.orphan_blobs {
+        deleted += transaction.execute("DELETE FROM PageBody WHERE Hash = ?", [hash])?;
+    }
+    transaction.commit()?;
+    for path in files.into_iter().chain(&report.orphan_files) {
+        match fs::remove_file(path) {
+            Ok(()) => deleted += 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Cannot delete {}: {}", path.display(), e),
+        }
+    }
+    Ok((queued, deleted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the URLs of the broken pages of a report, and whether their body is missing.
+    fn broken(report: &VerifyReport) -> Vec<(&str, bool)> {
+        report
+            .broken
+            .iter()
+            .map(|page| {
+                (
+                    page.url.as_str(),
+                    matches!(page.problem, BodyProblem::Missing),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repairs_broken_and_orphan_bodies() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&mut connection).unwrap();
+        let save_dir =
+            std::env::temp_dir().join(format!("web_crawler_verify_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&save_dir);
+        fs::create_dir_all(&save_dir).unwrap();
+        let hash = |body: &str| HashAlgorithm::default().digest(body.as_bytes());
+        let save = |body: &str, contents: &str| {
+            let path = save_dir.join(format!("{}.html", hash(body)));
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let good = save("good", "good");
+        let corrupt = save("original", "tampered");
+        let orphan = save("orphan", "orphan");
+        let pages = [
+            ("https://example.com/good", hash("good"), None),
+            ("https://example.com/corrupt", hash("original"), None),
+            ("https://example.com/missing", hash("missing"), None),
+            ("https://example.com/blob", hash("blob"), None),
+            (
+                "https://example.com/object",
+                hash("object"),
+                Some("s3://bucket/pages/object.html"),
+            ),
+        ];
+        for (url, hash, stored_file) in &pages {
+            connection
+                .execute(
+                    "INSERT INTO Page (Url, RequestedUrl, Hash, StoredFile) VALUES (?1, ?1, ?2, ?3)",
+                    params![url, hash, stored_file],
+                )
+                .unwrap();
+        }
+        for (hash, body) in [(hash("blob"), "other"), (hash("loose"), "loose")] {
+            connection
+                .execute(
+                    "INSERT INTO PageBody (Hash, Body) VALUES (?, ?)",
+                    params![hash, body.as_bytes()],
+                )
+                .unwrap();
+        }
+
+        let report = verify(&connection, &save_dir).unwrap();
+        assert_eq!(report.checked, 4);
+        assert_eq!(
+            broken(&report),
+            [
+                ("https://example.com/corrupt", false),
+                ("https://example.com/missing", true),
+                ("https://example.com/blob", false),
+            ]
+        );
+        assert_eq!(report.orphan_files, std::slice::from_ref(&orphan));
+        assert_eq!(report.orphan_blobs, [hash("loose")]);
+
+        assert_eq!(repair(&mut connection, &report).unwrap(), (3, 4));
+        assert!(good.is_file());
+        assert!(!corrupt.exists());
+        assert!(!orphan.exists());
+        let queued: Vec<String> = connection
+            .prepare("SELECT Url FROM FrontierUrl ORDER BY Url")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            queued,
+            [
+                "https://example.com/blob",
+                "https://example.com/corrupt",
+                "https://example.com/missing"
+            ]
+        );
+
+        let report = verify(&connection, &save_dir).unwrap();
+        assert_eq!(
+            broken(&report),
+            [
+                ("https://example.com/corrupt", true),
+                ("https://example.com/missing", true),
+                ("https://example.com/blob", true),
+            ]
+        );
+        assert!(report.orphan_files.is_empty());
+        assert!(report.orphan_blobs.is_empty());
+        fs::remove_dir_all(&save_dir).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! The set of URLs visited during a run, exact or, for large crawls, a Bloom filter of fixed size in front of a
+//! temporary database.
+
+use rusqlite::Connection;
+use std::collections::HashSet;
+
+use crate::error::CrawlerError;
+use crate::memory::MemoryUsage;
+
+/// The size of a Bloom filter.
+#[derive(Clone, Copy)]
+pub struct BloomSettings {
+    /// The number of URLs the filter is sized for. More can be added, at a higher false positive rate.
+    pub capacity: usize,
+    /// The share of URLs that were not added that the filter reports as added, once it holds `capacity` URLs.
+    pub false_positive_rate: f64,
+}
+
+/// A Bloom filter of strings, which tells for certain that a string was not added, but may report a string that was
+/// not added as added.
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u32,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter with the optimal number of bits and hash functions for its settings.
+    ///
+    /// # Arguments
+    /// * `settings` - The capacity and false positive rate.
+    pub fn new(settings: BloomSettings) -> Self {
+        let capacity = settings.capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-capacity * settings.false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hash_count = ((bit_count as f64 / capacity) * ln2).round().max(1.0) as u32;
+        BloomFilter {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+        }
+    }
+
+    /// Adds a string.
+    ///
+    /// # Returns
+    /// `true` if the string was not reported as added before.
+    pub fn insert(&mut self, item: &str) -> bool {
+        let mut added = false;
+        for position in Self::positions(item, self.bit_count, self.hash_count) {
+            let (word, bit) = ((position / 64) as usize, 1 << (position % 64));
+            added |= self.bits[word] & bit == 0;
+            self.bits[word] |= bit;
+        }
+        added
+    }
+
+    /// Returns whether a string may have been added. `false` is always right.
+    pub fn contains(&self, item: &str) -> bool {
+        Self::positions(item, self.bit_count, self.hash_count)
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+
+    /// Returns the memory used by the bits of the filter, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.bits.len() * size_of::<u64>()
+    }
+
+    /// Returns the bits of a string, derived from two halves of its hash by double hashing.
+    fn positions(item: &str, bit_count: u64, hash_count: u32) -> impl Iterator<Item = u64> {
+        let hash = blake3::hash(item.as_bytes());
+        let bytes = hash.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        (0..u64::from(hash_count)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+    }
+}
+
+/// The URLs visited during a run.
+pub(crate) enum VisitedSet {
+    /// Every URL, exactly, in memory.
+    Exact { urls: HashSet<String>, bytes: usize },
+    /// A Bloom filter of the URLs in memory, in front of a temporary database that holds every URL exactly, so that
+    /// the database is only looked up for URLs the filter reports as visited.
+    Bloom {
+        filter: BloomFilter,
+        /// The temporary database, deleted when the set is dropped.
+        urls: Connection,
+        /// Whether the filter also holds the URLs of the stored pages, see [`VisitedSet::add_stored`].
+        covers_stored: bool,
+    },
+}
+
+impl VisitedSet {
+    /// Creates an empty set, a Bloom filter if it has settings.
+    ///
+    /// # Returns
+    /// The set, or an error if the temporary database of a Bloom filter cannot be created.
+    pub(crate) fn new(bloom: Option<BloomSettings>) -> Result<Self, CrawlerError> {
+        let Some(settings) = bloom else {
+            return Ok(VisitedSet::Exact {
+                urls: HashSet::new(),
+                bytes: 0,
+            });
+        };
+        // An empty path opens a private database on disk, which is deleted when it is closed
+        let urls = Connection::open("")?;
+        urls.execute(
+            "CREATE TABLE VisitedUrl (Url TEXT PRIMARY KEY) WITHOUT ROWID",
+            [],
+        )?;
+        Ok(VisitedSet::Bloom {
+            filter: BloomFilter::new(settings),
+            urls,
+            covers_stored: false,
+        })
+    }
+
+    /// Marks a URL as visited.
+    pub(crate) fn insert(&mut self, url: String) -> Result<(), CrawlerError> {
+        match self {
+            VisitedSet::Exact { urls, bytes } => {
+                let size = MemoryUsage::url_size(&url);
+                if urls.insert(url) {
+                    *bytes += size;
+                }
+            }
+            VisitedSet::Bloom { filter, urls, .. } => {
+                filter.insert(&url);
+                urls.prepare_cached("INSERT OR IGNORE INTO VisitedUrl (Url) VALUES (?)")?
+                    .execute([url])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether a URL was visited.
+    pub(crate) fn contains(&self, url: &str) -> Result<bool, CrawlerError> {
+        match self {
+            VisitedSet::Exact { urls, .. } => Ok(urls.contains(url)),
+            VisitedSet::Bloom { filter, urls, .. } => {
+                if !filter.contains(url) {
+                    return Ok(false);
+                }
+                let visited = urls
+                    .prepare_cached("SELECT EXISTS (SELECT 1 FROM VisitedUrl WHERE Url = ?)")?
+                    .query_row([url], |row| row.get(0))?;
+                Ok(visited)
+            }
+        }
+    }
+
+    /// Marks a visited URL as not visited.
+    pub(crate) fn remove(&mut self, url: &str) -> Result<(), CrawlerError> {
+        match self {
+            VisitedSet::Exact { urls, bytes } => {
+                if urls.remove(url) {
+                    *bytes -= MemoryUsage::url_size(url);
+                }
+            }
+            VisitedSet::Bloom { urls, .. } => {
+                // The URL stays in the filter, and is found to be not visited in the database
+                urls.prepare_cached("DELETE FROM VisitedUrl WHERE Url = ?")?
+                    .execute([url])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the URLs of the stored pages to a Bloom filter, without marking them as visited, so that a URL the filter
+    /// reports as not visited is known not to be stored either. An exact set does nothing.
+    ///
+    /// # Arguments
+    /// * `stored` - The URLs of every stored page.
+    pub(crate) fn add_stored(&mut self, stored: impl IntoIterator<Item = String>) {
+        if let VisitedSet::Bloom {
+            filter,
+            covers_stored,
+            ..
+        } = self
+        {
+            for url in stored {
+                filter.insert(&url);
+            }
+            *covers_stored = true;
+        }
+    }
+
+    /// Returns whether a URL may belong to a stored page, `false` only if the filter holds the stored pages and has
+    /// not seen the URL.
+    pub(crate) fn may_be_stored(&self, url: &str) -> bool {
+        match self {
+            VisitedSet::Bloom {
+                filter,
+                covers_stored: true,
+                ..
+            } => filter.contains(url),
+            _ => true,
+        }
+    }
+
+    /// Returns the approximate memory used by the set, in bytes. The database of a Bloom filter is kept on disk.
+    pub(crate) fn memory_bytes(&self) -> usize {
+        match self {
+            VisitedSet::Exact { bytes, .. } => *bytes,
+            VisitedSet::Bloom { filter, .. } => filter.memory_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter_has_no_false_negatives() {
+        let mut filter = BloomFilter::new(BloomSettings {
+            capacity: 1000,
+            false_positive_rate: 0.01,
+        });
+        assert!(!filter.contains("https://example.com/"));
+        assert!(filter.insert("https://example.com/"));
+        assert!(!filter.insert("https://example.com/"));
+        for i in 0..1000 {
+            filter.insert(&format!("https://example.com/{}", i));
+        }
+        assert!((0..1000).all(|i| filter.contains(&format!("https://example.com/{}", i))));
+        assert!(filter.contains("https://example.com/"));
+    }
+
+    #[test]
+    fn bloom_filter_keeps_false_positive_rate() {
+        let settings = BloomSettings {
+            capacity: 10_000,
+            false_positive_rate: 0.01,
+        };
+        let mut filter = BloomFilter::new(settings);
+        for i in 0..settings.capacity {
+            filter.insert(&format!("https://example.com/page/{}", i));
+        }
+        let false_positives = (0..100_000)
+            .filter(|i| filter.contains(&format!("https://other.test/page/{}", i)))
+            .count();
+        let rate = false_positives as f64 / 100_000.0;
+        assert!(rate < settings.false_positive_rate * 1.5, "{}", rate);
+        // About 1.2 bytes per URL at 1%
+        assert!(filter.memory_bytes() < settings.capacity * 2);
+    }
+
+    #[test]
+    fn bloom_set_checks_positives_exactly() {
+        let mut visited = VisitedSet::new(Some(BloomSettings {
+            capacity: 10,
+            false_positive_rate: 0.01,
+        }))
+        .unwrap();
+        visited.insert("https://example.com/".to_string()).unwrap();
+        visited.add_stored(["https://example.com/stored".to_string()]);
+        assert!(visited.contains("https://example.com/").unwrap());
+        // In the filter, but never visited
+        assert!(!visited.contains("https://example.com/stored").unwrap());
+        assert!(visited.may_be_stored("https://example.com/stored"));
+        assert!(!visited.may_be_stored("https://example.com/other"));
+
+        visited.remove("https://example.com/").unwrap();
+        assert!(!visited.contains("https://example.com/").unwrap());
+        visited.insert("https://example.com/".to_string()).unwrap();
+        assert!(visited.contains("https://example.com/").unwrap());
+    }
+}
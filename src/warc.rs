@@ -0,0 +1,170 @@
+use chrono::{SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::disk_writer::DiskWriter;
+
+/// Writes WARC 1.0 records into rotating, per-record gzipped `.warc.gz` files.
+///
+/// A new file is started once the current one exceeds the maximum size, and every file begins with a `warcinfo`
+/// record.
+pub struct WarcWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_file_size: u64,
+    sequence: u32,
+    file: Option<String>,
+    written: u64,
+    writer: DiskWriter,
+}
+
+/// A WARC record header field.
+pub type WarcField = (&'static str, String);
+
+impl WarcWriter {
+    /// Creates a new `WarcWriter`. No file is created until the first record is written.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to write files to.
+    /// * `prefix` - The prefix of the file names, followed by a sequence number.
+    /// * `max_file_size` - The size in bytes after which a new file is started.
+    /// * `writer` - The writer the records are handed to.
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        prefix: &str,
+        max_file_size: u64,
+        writer: DiskWriter,
+    ) -> Self {
+        WarcWriter {
+            dir: dir.into(),
+            prefix: prefix.to_string(),
+            max_file_size,
+            sequence: 0,
+            file: None,
+            written: 0,
+            writer,
+        }
+    }
+
+    /// Generates a new record id.
+    pub fn record_id() -> String {
+        format!("<urn:uuid:{}>", Uuid::new_v4())
+    }
+
+    /// Returns the current time in the format of the `WARC-Date` field.
+    pub fn date() -> String {
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    /// Writes a record, starting a new file first if necessary.
+    ///
+    /// `WARC/1.0`, `Content-Length`, and a `WARC-Record-ID` if none is given, are added to the fields.
+    ///
+    /// # Arguments
+    /// * `fields` - The header fields of the record.
+    /// * `block` - The content block of the record.
+    ///
+    /// # Returns
+    /// The name of the file and the offset the record was written at.
+    pub async fn write_record(
+        &mut self,
+        fields: &[WarcField],
+        block: &[u8],
+    ) -> io::Result<(String, u64)> {
+        if self.file.is_none() || self.written >= self.max_file_size {
+            self.rotate().await?;
+        }
+        let name = self.file.clone().unwrap();
+        let offset = self.written;
+        self.append(&name, Self::encode_record(fields, block)?)
+            .await?;
+        Ok((name, offset))
+    }
+
+    /// Queues an encoded record to be appended to a file.
+    async fn append(&mut self, name: &str, record: Vec<u8>) -> io::Result<()> {
+        self.written += record.len() as u64;
+        self.writer.append(self.dir.join(name), record).await
+    }
+
+    /// Starts a new file and writes its `warcinfo` record.
+    async fn rotate(&mut self) -> io::Result<()> {
+        // A file of the same name may be left by another database, and records appended to it would be recorded at the
+        // wrong offsets, so its name is skipped
+        let name = loop {
+            self.sequence += 1;
+            let name = format!("{}-{:05}.warc.gz", self.prefix, self.sequence);
+            if !self.dir.join(&name).exists() {
+                break name;
+            }
+            warn!("Not appending to the existing WARC file {}", name);
+        };
+        self.written = 0;
+        self.file = Some(name.clone());
+
+        let info = format!(
+            "software: {}/{}\r\nformat: WARC File Format 1.0\r\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let fields = [
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Date", Self::date()),
+            ("WARC-Filename", name.clone()),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ];
+        let record = Self::encode_record(&fields, info.as_bytes())?;
+        self.append(&name, record).await
+    }
+
+    /// Serializes and gzips a single record.
+    fn encode_record(fields: &[WarcField], block: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        write!(encoder, "WARC/1.0\r\n")?;
+        if !fields.iter().any(|(name, _)| *name == "WARC-Record-ID") {
+            write!(encoder, "WARC-Record-ID: {}\r\n", Self::record_id())?;
+        }
+        for (name, value) in fields {
+            write!(encoder, "{}: {}\r\n", name, value)?;
+        }
+        write!(encoder, "Content-Length: {}\r\n\r\n", block.len())?;
+        encoder.write_all(block)?;
+        write!(encoder, "\r\n\r\n")?;
+        encoder.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn does_not_append_to_existing_files() {
+        let dir = std::env::temp_dir().join(format!("web_crawler_warc_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("crawl-00001-00001.warc.gz"), "old").unwrap();
+        let (writer, handle) = DiskWriter::start(4);
+        let mut warc = WarcWriter::new(&dir, "crawl-00001", 1 << 20, writer);
+
+        let (name, offset) = warc
+            .write_record(&[("WARC-Type", "resource".to_string())], b"body")
+            .await
+            .unwrap();
+        drop(warc);
+        handle.finish().await.unwrap();
+        assert_eq!(name, "crawl-00001-00002.warc.gz");
+        assert!(offset > 0);
+        assert_eq!(
+            fs::read(dir.join("crawl-00001-00001.warc.gz")).unwrap(),
+            b"old"
+        );
+        assert!(fs::metadata(dir.join(&name)).unwrap().len() > offset);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+//! Delivery of crawl events to a webhook, as they happen.
+
+use chrono::{SecondsFormat, Utc};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tracing::{error, warn};
+use url::Url;
+
+/// The number of times the delivery of an event is attempted before it is dropped.
+const MAX_ATTEMPTS: u32 = 5;
+/// The wait before the first retry of a delivery, doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// How long the end of a crawl waits for queued events to be delivered.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Something that happened during a crawl, posted to the webhook.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A page was recorded in the database.
+    PageStored {
+        /// The final URL of the page.
+        url: String,
+        /// The id of the page entity.
+        page_id: i64,
+        /// The hash of the contents of the page.
+        hash: String,
+        /// The id of the page with the same contents, if the page is a duplicate.
+        duplicate_of: Option<i64>,
+        /// Whether the contents of the page were saved.
+        indexed: bool,
+    },
+    /// A domain was seen for the first time.
+    DomainDiscovered {
+        /// The name of the domain.
+        domain: String,
+        /// The id of the domain entity.
+        domain_id: i64,
+    },
+    /// The crawl run finished or was stopped.
+    CrawlCompleted {
+        /// The number of pages stored by the run.
+        pages: i64,
+        /// The number of requests sent by the run.
+        fetches: i64,
+        /// The number of URLs that could not be fetched during the run.
+        failed: i64,
+        /// The number of URLs left in the frontier.
+        frontier: usize,
+    },
+}
+
+/// An event with the context shared by every event.
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    time: String,
+    run_id: i64,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// Posts crawl events as JSON to a webhook, from a background task so that slow or failing deliveries do not stall
+/// the crawl.
+///
+/// Events are delivered one at a time, in order. A failed delivery is retried with exponential backoff, unless the
+/// webhook rejects the event with a client error other than 408 or 429.
+#[derive(Clone)]
+pub struct EventWebhook {
+    sender: UnboundedSender<(String, WebhookEvent)>,
+}
+
+/// Owns the task of an `EventWebhook`, and waits for it to deliver the queued events.
+pub struct EventWebhookHandle {
+    task: JoinHandle<usize>,
+}
+
+impl EventWebhook {
+    /// Starts the delivery task.
+    ///
+    /// # Arguments
+    /// * `client` - The HTTP client to post events with.
+    /// * `url` - The URL of the webhook.
+    /// * `run_id` - The id of the crawl run, included in every event.
+    ///
+    /// # Returns
+    /// The webhook, and the handle used to wait for its task once every clone of the webhook is dropped.
+    pub fn start(client: Client, url: Url, run_id: i64) -> (Self, EventWebhookHandle) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(Self::run(client, url, run_id, receiver));
+        (EventWebhook { sender }, EventWebhookHandle { task })
+    }
+
+    /// Queues an event to be posted, with the current time.
+    ///
+    /// # Arguments
+    /// * `event` - The event.
+    pub fn send(&self, event: WebhookEvent) {
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        if self.sender.send((time, event)).is_err() {
+            error!("Webhook delivery has stopped, dropping an event");
+        }
+    }
+
+    /// Delivers queued events until every sender is dropped.
+    ///
+    /// # Returns
+    /// The number of events that could not be delivered.
+    async fn run(
+        client: Client,
+        url: Url,
+        run_id: i64,
+        mut receiver: UnboundedReceiver<(String, WebhookEvent)>,
+    ) -> usize {
+        let mut failures = 0;
+        while let Some((time, event)) = receiver.recv().await {
+            let payload = WebhookPayload {
+                time,
+                run_id,
+                event: &event,
+            };
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => body,
+                Err(e) => {
+                    error!("Failed to serialize a webhook event: {}", e);
+                    failures += 1;
+                    continue;
+                }
+            };
+            if let Err(e) = Self::deliver(&client, &url, body).await {
+                error!("Failed to deliver an event to {}: {}", url, e);
+                failures += 1;
+            }
+        }
+        failures
+    }
+
+    /// Posts an event, retrying with exponential backoff.
+    async fn deliver(client: &Client, url: &Url, body: Vec<u8>) -> Result<(), String> {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let response = client
+                .post(url.as_str())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+            let error = match response {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if !is_retryable(response.status()) => {
+                    return Err(format!("rejected with {}", response.status()));
+                }
+                Ok(response) => format!("responded with {}", response.status()),
+                Err(e) => e.to_string(),
+            };
+            if attempt == MAX_ATTEMPTS {
+                return Err(format!("{} after {} attempts", error, attempt));
+            }
+            warn!(
+                "Delivering an event to {} failed: {}, retrying in {}s",
+                url,
+                error,
+                backoff.as_secs()
+            );
+            sleep(backoff).await;
+            backoff *= 2;
+        }
+        unreachable!()
+    }
+}
+
+impl EventWebhookHandle {
+    /// Waits for the queued events to be delivered, for up to a minute. Every `EventWebhook` must be dropped first.
+    /// Failed deliveries are logged, and do not fail the crawl.
+    pub async fn finish(self) {
+        match timeout(FINISH_TIMEOUT, self.task).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(failures)) => warn!("{} events could not be delivered to the webhook", failures),
+            Ok(Err(e)) => error!("Webhook delivery task failed: {}", e),
+            Err(_) => warn!("Gave up waiting for webhook events to be delivered"),
+        }
+    }
+}
+
+/// Returns whether a failed delivery may succeed when retried.
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
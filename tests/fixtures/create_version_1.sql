@@ -0,0 +1,99 @@
+-- Sqlite
+
+BEGIN TRANSACTION;
+
+PRAGMA user_version = 1;
+
+DROP TABLE IF EXISTS CrawlRun;
+CREATE TABLE CrawlRun (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    PeakMemory INTEGER NOT NULL DEFAULT 0,
+    Started DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+DROP TABLE IF EXISTS Page;
+CREATE TABLE Page (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER,
+    Url TEXT UNIQUE NOT NULL,
+    RequestedUrl TEXT NOT NULL,
+    Hash BLOB NOT NULL,
+    DuplicateOf INTEGER,
+    ETag TEXT,
+    LastModified TEXT,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    Updated DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (DuplicateOf) REFERENCES Page(Id)
+);
+
+DROP TABLE IF EXISTS PageFetch;
+CREATE TABLE PageFetch (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    RunId INTEGER NOT NULL,
+    PageId INTEGER,
+    Url TEXT NOT NULL,
+    FinalUrl TEXT NOT NULL,
+    Status INTEGER NOT NULL,
+    ContentType TEXT,
+    ContentLength INTEGER,
+    DurationMs INTEGER NOT NULL,
+    Headers TEXT NOT NULL,
+    Fetched DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (RunId) REFERENCES CrawlRun(Id),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE SET NULL
+);
+
+DROP TABLE IF EXISTS Domain;
+CREATE TABLE Domain (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Name TEXT UNIQUE NOT NULL,
+    RefusedCount INTEGER NOT NULL DEFAULT 0,
+    BannedUntil DATETIME,
+    CrawlDelay REAL,
+    RobotsFetched DATETIME,
+    Pinned INTEGER NOT NULL DEFAULT 0,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+Drop TABLE IF EXISTS DisallowedPattern;
+CREATE TABLE DisallowedPattern (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    DomainId INTEGER NOT NULL,
+    Pattern TEXT NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (DomainId, Pattern),
+    FOREIGN KEY (DomainId) REFERENCES Domain(Id) ON DELETE CASCADE
+);
+
+DROP TABLE IF EXISTS Redirect;
+CREATE TABLE Redirect (
+    SourceUrl TEXT PRIMARY KEY,
+    TargetUrl TEXT NOT NULL,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+DROP TABLE IF EXISTS RedirectHop;
+CREATE TABLE RedirectHop (
+    SourceUrl TEXT NOT NULL,
+    Position INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    Status INTEGER NOT NULL,
+    PRIMARY KEY (SourceUrl, Position)
+);
+
+DROP TABLE IF EXISTS FrontierUrl;
+CREATE TABLE FrontierUrl (
+    Url TEXT PRIMARY KEY,
+    Created DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+DROP TABLE IF EXISTS PageLink;
+CREATE TABLE PageLink (
+    PageId INTEGER NOT NULL,
+    Url TEXT NOT NULL,
+    PRIMARY KEY (PageId, Url),
+    FOREIGN KEY (PageId) REFERENCES Page(Id) ON DELETE CASCADE
+);
+
+COMMIT;
\ No newline at end of file